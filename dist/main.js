@@ -21,6 +21,19 @@ const bossOverlay = document.getElementById("boss-overlay");
 const bossLogEl = document.getElementById("boss-log");
 const bossExitBtn = document.getElementById("boss-exit");
 const restoreUiBtn = document.getElementById("restore-ui");
+const archivePicker = document.getElementById("archive-picker");
+const archiveEntriesEl = document.getElementById("archive-entries");
+const archiveCancelBtn = document.getElementById("archive-cancel");
+const unlockPanel = document.getElementById("unlock-panel");
+const unlockInput = document.getElementById("unlock-passphrase");
+const unlockErrorEl = document.getElementById("unlock-error");
+const librarySearchPanel = document.getElementById("library-search-panel");
+const librarySearchTitleEl = document.getElementById("library-search-title");
+const librarySearchEntriesEl = document.getElementById("library-search-entries");
+const tocPanel = document.getElementById("toc-panel");
+const tocEntriesEl = document.getElementById("toc-entries");
+const bookmarkPanel = document.getElementById("bookmark-panel");
+const bookmarkEntriesEl = document.getElementById("bookmark-entries");
 
 let bossMode = false;
 let hiddenTimeout = null;
@@ -43,6 +56,7 @@ let autoSaveMode = "instant";
 let autoFadeEnabled = false;
 let fadeDelayMs = 5000;
 let autoSaveTimer = null;
+let tocEntries = [];
 
 if (!invoke) {
   console.error("Tauri invoke API 未注入，界面将无法与后端通信。");
@@ -93,7 +107,18 @@ function selectFileDialog() {
     console.error("未启用对话框插件或权限，无法打开文件。", dialogApi);
     return null;
   }
-  return openDialog({ multiple: false, filters: [{ name: "Text", extensions: ["txt"] }] });
+  return openDialog({
+    multiple: false,
+    filters: [{ name: "Text", extensions: ["txt", "fb2", "mobi", "azw3", "azw", "umd", "docx", "epub", "pdf", "html", "htm", "md", "zip", "7z", "gz"] }],
+  });
+}
+
+function selectFolderDialog() {
+  if (!openDialog) {
+    console.error("未启用对话框插件或权限，无法打开目录。", dialogApi);
+    return null;
+  }
+  return openDialog({ multiple: false, directory: true });
 }
 
 function measureFits(start, end) {
@@ -215,7 +240,7 @@ function ensureReaderFits() {
 
 function performProgressSave(offset = currentOffset) {
   if (!invoke || !fullText) return;
-  invoke("update_progress", { offset }).catch((err) => {
+  invoke("update_progress", { offset: indexToCharOffset(offset) }).catch((err) => {
     console.debug("保存阅读进度失败", err);
   });
 }
@@ -285,6 +310,16 @@ async function loadDocument(path) {
   }
   if (!invoke) return;
   try {
+    if (isArchivePath(path)) {
+      const entries = await invoke("list_archive_entries", { path });
+      if (entries.length > 1) {
+        showArchivePicker(entries);
+        return;
+      }
+      if (entries.length === 1) {
+        path = entries[0].path;
+      }
+    }
     const payload = await invoke("load_file", { path });
     applyDocumentPayload(payload);
   } catch (error) {
@@ -292,14 +327,38 @@ async function loadDocument(path) {
   }
 }
 
+function isArchivePath(path) {
+  return /\.(zip|7z|gz)$/i.test(path);
+}
+
+// 压缩包中有多个文本文件时列出供选择，选中项以“压缩包!/文件名”路径打开
+function showArchivePicker(entries) {
+  if (!archivePicker || !archiveEntriesEl) return;
+  archiveEntriesEl.replaceChildren(
+    ...entries.map((entry) => {
+      const item = document.createElement("button");
+      item.type = "button";
+      item.textContent = entry.name;
+      item.addEventListener("click", async () => {
+        archivePicker.hidden = true;
+        await loadDocument(entry.path);
+      });
+      return item;
+    })
+  );
+  archivePicker.hidden = false;
+}
+
 function applyDocumentPayload(payload) {
   if (!payload) return;
   fullText = payload.content ?? "";
   fullLength = fullText.length;
-  currentOffset = Math.min(payload.offset ?? 0, fullLength);
+  currentOffset = charOffsetToIndex(payload.offset ?? 0);
   nextOffset = currentOffset;
   history = [];
   historyIndex = -1;
+  tocEntries = (payload.toc ?? []).slice().sort((a, b) => a.offset - b.offset);
+  showListPanel(null);
   const label = getFileName(payload.file_path);
   fileInfoEl.textContent = label;
   lastFileLabel = fileInfoEl.textContent;
@@ -336,10 +395,191 @@ function jumpToOffset(offset) {
   renderPage(offset, { pushHistory: true });
 }
 
+// 后端的偏移按字符（码点）计，JS 字符串按 UTF-16 码元计，扩展区汉字、emoji 等占两个码元
+function charOffsetToIndex(offset) {
+  let index = 0;
+  for (let count = 0; count < offset && index < fullText.length; count += 1) {
+    index += fullText.codePointAt(index) > 0xffff ? 2 : 1;
+  }
+  return index;
+}
+
+function indexToCharOffset(index) {
+  let count = 0;
+  for (let position = 0; position < index && position < fullText.length; count += 1) {
+    position += fullText.codePointAt(position) > 0xffff ? 2 : 1;
+  }
+  return count;
+}
+
+// 列表面板的一行：点击主按钮跳转，可带一个附加按钮（如删除）
+function buildListEntry(label, onOpen, extra) {
+  const row = document.createElement("div");
+  row.className = "list-entry";
+  const main = document.createElement("button");
+  main.type = "button";
+  main.className = "entry-main";
+  main.textContent = label;
+  main.title = label;
+  main.addEventListener("click", onOpen);
+  row.append(main);
+  if (extra) {
+    const button = document.createElement("button");
+    button.type = "button";
+    button.textContent = extra.label;
+    button.addEventListener("click", extra.onClick);
+    row.append(button);
+  }
+  return row;
+}
+
+function renderListEntries(container, rows, emptyText) {
+  if (rows.length) {
+    container.replaceChildren(...rows);
+  } else {
+    const empty = document.createElement("div");
+    empty.className = "list-empty";
+    empty.textContent = emptyText;
+    container.replaceChildren(empty);
+  }
+}
+
+// 书库搜索、目录和书签共用一块区域，同时只显示一个；传 null 全部关闭
+function showListPanel(panel) {
+  for (const other of [librarySearchPanel, tocPanel, bookmarkPanel]) {
+    if (other) other.hidden = other !== panel;
+  }
+}
+
+// 在书库所有已建立索引的书中查找，结果按最近阅读排列
+async function searchLibrary() {
+  if (!invoke || !librarySearchPanel || !librarySearchEntriesEl) return;
+  const query = searchInput.value.trim();
+  if (!query) return;
+  let hits = [];
+  try {
+    hits = await invoke("search_library", { query, limit: 100 });
+  } catch (error) {
+    console.error("书库搜索失败", error);
+    return;
+  }
+  if (librarySearchTitleEl) {
+    librarySearchTitleEl.textContent = `书库搜索“${query}”：${hits.length} 处`;
+  }
+  const rows = hits.map((hit) =>
+    buildListEntry(`${hit.title}：${hit.snippet}`, async () => {
+      librarySearchPanel.hidden = true;
+      try {
+        applyDocumentPayload(
+          await invoke("open_search_hit", { path: hit.file_path, offset: hit.offset }),
+        );
+      } catch (error) {
+        console.error("打开搜索结果失败", error);
+      }
+    }),
+  );
+  renderListEntries(librarySearchEntriesEl, rows, "没有找到，尚未建立索引的书不会出现在结果中");
+  showListPanel(librarySearchPanel);
+}
+
+// 一次遍历换算一组已排序的字符偏移，章节很多时避免逐个从头数
+function charOffsetsToIndexes(offsets) {
+  let index = 0;
+  let count = 0;
+  return offsets.map((offset) => {
+    for (; count < offset && index < fullText.length; count += 1) {
+      index += fullText.codePointAt(index) > 0xffff ? 2 : 1;
+    }
+    return index;
+  });
+}
+
+function toggleTocPanel() {
+  if (!tocPanel || !tocEntriesEl) return;
+  if (!tocPanel.hidden) {
+    tocPanel.hidden = true;
+    return;
+  }
+  if (!fullText) return;
+  const starts = charOffsetsToIndexes(tocEntries.map((chapter) => chapter.offset));
+  // 当前所在的章节：起点不超过当前位置的最后一章
+  let current = -1;
+  starts.forEach((start, index) => {
+    if (start <= currentOffset) current = index;
+  });
+  const rows = tocEntries.map((chapter, index) => {
+    const row = buildListEntry(chapter.title || `第 ${index + 1} 章`, () => {
+      tocPanel.hidden = true;
+      jumpToOffset(starts[index]);
+    });
+    row.classList.toggle("current", index === current);
+    return row;
+  });
+  renderListEntries(tocEntriesEl, rows, "这本书没有目录");
+  showListPanel(tocPanel);
+  rows[current]?.scrollIntoView({ block: "center" });
+}
+
+function renderBookmarks(bookmarks) {
+  if (!bookmarkEntriesEl) return;
+  const rows = bookmarks
+    .slice()
+    .sort((a, b) => a.offset - b.offset)
+    .map((bookmark) => {
+      const percent = fullLength ? (charOffsetToIndex(bookmark.offset) / fullLength) * 100 : 0;
+      const label = `${percent.toFixed(1)}%  ${bookmark.excerpt || "（空白）"}`;
+      return buildListEntry(
+        label,
+        () => {
+          bookmarkPanel.hidden = true;
+          jumpToOffset(charOffsetToIndex(bookmark.offset));
+        },
+        {
+          label: "删除",
+          onClick: () => runBookmarkCommand("remove_bookmark", { id: bookmark.id }),
+        },
+      );
+    });
+  renderListEntries(bookmarkEntriesEl, rows, "还没有书签");
+}
+
+async function runBookmarkCommand(command, args = {}) {
+  if (!invoke) return;
+  try {
+    renderBookmarks(await invoke(command, args));
+  } catch (error) {
+    console.error("书签操作失败", error);
+  }
+}
+
+async function toggleBookmarkPanel() {
+  if (!bookmarkPanel) return;
+  if (!bookmarkPanel.hidden) {
+    bookmarkPanel.hidden = true;
+    return;
+  }
+  if (!fullText) return;
+  await runBookmarkCommand("get_bookmarks");
+  showListPanel(bookmarkPanel);
+}
+
+// “p120” 或 “第120页” 按书上印刷的页码跳转（仅 PDF 有页码信息）
+const PAGE_QUERY = /^(?:p\s*(\S+)|第\s*(\S+?)\s*页)$/i;
+
 async function handleSearch(backwards = false) {
   if (!fullText) return;
   const query = searchInput.value.trim();
   if (!query) return;
+  const pageMatch = query.match(PAGE_QUERY);
+  if (pageMatch) {
+    try {
+      const offset = await invoke("go_to_page", { label: pageMatch[1] || pageMatch[2] });
+      jumpToOffset(charOffsetToIndex(offset));
+      return;
+    } catch {
+      // 没有页码信息或找不到该页时，按普通文本搜索
+    }
+  }
   const textLower = fullText.toLowerCase();
   const queryLower = query.toLowerCase();
   let index;
@@ -496,6 +736,14 @@ function setupEventListeners() {
     if (!selected) return;
     await loadDocument(selected);
   });
+  document.getElementById("open-folder")?.addEventListener("click", async () => {
+    const selected = await selectFolderDialog();
+    if (!selected) return;
+    await loadDocument(selected);
+  });
+  archiveCancelBtn?.addEventListener("click", () => {
+    archivePicker.hidden = true;
+  });
   document.getElementById("prev-page").addEventListener("click", goToPreviousPage);
   document.getElementById("next-page").addEventListener("click", goToNextPage);
   bossKeyBtn.addEventListener("click", () => toggleBossMode(true));
@@ -504,6 +752,21 @@ function setupEventListeners() {
   }
 
   searchBtn.addEventListener("click", () => handleSearch(false));
+  document.getElementById("library-search-btn")?.addEventListener("click", searchLibrary);
+  document.getElementById("library-search-close")?.addEventListener("click", () => {
+    librarySearchPanel.hidden = true;
+  });
+  document.getElementById("toc-btn")?.addEventListener("click", toggleTocPanel);
+  document.getElementById("toc-close")?.addEventListener("click", () => {
+    tocPanel.hidden = true;
+  });
+  document.getElementById("bookmarks-btn")?.addEventListener("click", toggleBookmarkPanel);
+  document.getElementById("bookmark-add")?.addEventListener("click", () =>
+    runBookmarkCommand("add_bookmark", { offset: indexToCharOffset(currentOffset) }),
+  );
+  document.getElementById("bookmark-close")?.addEventListener("click", () => {
+    bookmarkPanel.hidden = true;
+  });
   searchInput.addEventListener("keydown", (event) => {
     if (event.key === "Enter") {
       handleSearch(event.shiftKey);
@@ -629,6 +892,27 @@ async function setupWindowHooks() {
       }
     }),
   );
+  // 文本处理设置变化后，后端会按新规则重新生成文档
+  unlistenFns.push(
+    await appWindow.listen("document-reloaded", (event) => {
+      applyDocumentPayload(event?.payload);
+    }),
+  );
+  // 其他设备的进度较新却更靠前时，确认后才回退
+  unlistenFns.push(
+    await appWindow.listen("sync-conflicts", async (event) => {
+      for (const conflict of event?.payload || []) {
+        const accept = confirm(
+          `《${conflict.title}》在“${conflict.remoteDevice}”上的进度比本机靠前，要回到那里吗？`,
+        );
+        try {
+          await invoke("resolve_sync_conflict", { bookId: conflict.bookId, accept });
+        } catch (error) {
+          console.error("处理进度冲突失败:", error);
+        }
+      }
+    }),
+  );
   // 监听设置变更事件
   const handleSettingsChanged = (event) => {
     const settings = event?.payload;
@@ -811,9 +1095,38 @@ async function hydrateSettings() {
   }
 }
 
+// 阅读数据已加密时，先输入口令解锁，再读取设置和恢复文档
+async function unlockIfNeeded() {
+  if (!invoke || !unlockPanel) return;
+  try {
+    if ((await invoke("data_lock_status")) !== "locked") return;
+  } catch (error) {
+    console.debug("读取加密状态失败", error);
+    return;
+  }
+  unlockPanel.hidden = false;
+  unlockInput?.focus();
+  await new Promise((resolve) => {
+    unlockPanel.addEventListener("submit", async (event) => {
+      event.preventDefault();
+      unlockErrorEl.textContent = "";
+      try {
+        await invoke("unlock_data", { passphrase: unlockInput.value });
+        unlockInput.value = "";
+        unlockPanel.hidden = true;
+        resolve();
+      } catch (error) {
+        unlockErrorEl.textContent = `${error}`;
+        unlockInput.select();
+      }
+    });
+  });
+}
+
 async function init() {
   setupEventListeners();
   await setupWindowHooks();
+  await unlockIfNeeded();
   await hydrateSettings();
   readerEl.focus();
   await restoreDocument();
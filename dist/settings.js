@@ -46,6 +46,45 @@ function initRangeInputs() {
   });
 }
 
+function renderProfiles(list) {
+  const select = document.getElementById('profile-select');
+  if (!select || !list) return;
+  select.innerHTML = '';
+  list.profiles.forEach(name => {
+    const option = document.createElement('option');
+    option.value = name;
+    option.textContent = name === list.active ? `${name}（使用中）` : name;
+    select.appendChild(option);
+  });
+  select.value = list.active;
+}
+
+async function loadProfiles() {
+  if (!invoke) return;
+  try {
+    renderProfiles(await invoke('list_profiles'));
+  } catch (error) {
+    console.error('加载配置方案失败', error);
+  }
+}
+
+async function loadLockStatus() {
+  if (!invoke) return;
+  try {
+    const status = await invoke('data_lock_status');
+    const labels = { off: '未启用', locked: '已加密，尚未解锁', unlocked: '已加密' };
+    const statusEl = document.getElementById('vault-status');
+    if (statusEl) {
+      statusEl.textContent = labels[status] || status;
+    }
+    document.getElementById('enable-encryption')?.toggleAttribute('hidden', status !== 'off');
+    document.getElementById('change-passphrase')?.toggleAttribute('hidden', status === 'off');
+    document.getElementById('disable-encryption')?.toggleAttribute('hidden', status === 'off');
+  } catch (error) {
+    console.error('读取加密状态失败', error);
+  }
+}
+
 async function loadSettings() {
   if (!invoke) {
     console.error("Tauri invoke API 未注入");
@@ -55,6 +94,8 @@ async function loadSettings() {
   try {
     currentSettings = await invoke("get_all_settings");
     applySettingsToUI(currentSettings);
+    await loadProfiles();
+    await loadLockStatus();
   } catch (error) {
     console.error("加载设置失败", error);
     currentSettings = getDefaultSettings();
@@ -76,7 +117,15 @@ function getDefaultSettings() {
     },
     reading: {
       smart_break: true,
-      auto_save_interval: "instant"
+      auto_save_interval: "instant",
+      reflow: true,
+      normalize: {
+        unify_line_endings: true,
+        strip_zero_width: true,
+        normalize_indent: true,
+        collapse_blank_lines: true,
+        dedupe_paragraphs: false
+      }
     },
     privacy: {
       boss_action: "disguise",
@@ -92,6 +141,12 @@ function getDefaultSettings() {
       auto_start: false,
       restore_reading: true,
       dev_mode: false
+    },
+    opds_server: {
+      enabled: false,
+      port: 8732,
+      lan: false,
+      token: ""
     }
   };
 }
@@ -112,6 +167,13 @@ function applySettingsToUI(settings) {
   const reading = settings.reading || {};
   setInputValue('max-chars', settings.max_chars_per_page || 900);
   setInputValue('smart-break', reading.smart_break !== false);
+  setInputValue('reflow', reading.reflow !== false);
+  const normalize = reading.normalize || {};
+  setInputValue('normalize-line-endings', normalize.unify_line_endings !== false);
+  setInputValue('normalize-zero-width', normalize.strip_zero_width !== false);
+  setInputValue('normalize-indent', normalize.normalize_indent !== false);
+  setInputValue('normalize-blank-lines', normalize.collapse_blank_lines !== false);
+  setInputValue('normalize-dedupe', normalize.dedupe_paragraphs === true);
   setInputValue('auto-save-interval', reading.auto_save_interval || 'instant');
 
   // 隐私设置
@@ -141,6 +203,25 @@ function applySettingsToUI(settings) {
   setInputValue('auto-start', system.auto_start || false);
   setInputValue('restore-reading', system.restore_reading !== false);
   setInputValue('dev-mode', system.dev_mode || false);
+
+  // OPDS 书库服务
+  const opdsServer = settings.opds_server || {};
+  setInputValue('opds-server-enabled', opdsServer.enabled || false);
+  setInputValue('opds-server-port', opdsServer.port || 8732);
+  setInputValue('opds-server-lan', opdsServer.lan || false);
+
+  // 多设备同步
+  const sync = settings.sync || {};
+  const webdav = sync.webdav || {};
+  setInputValue('sync-folder-enabled', sync.folder?.enabled || false);
+  setInputValue('sync-folder-path', sync.folder?.path || '');
+  setInputValue('sync-webdav-enabled', webdav.enabled || false);
+  setInputValue('sync-webdav-url', webdav.url || '');
+  setInputValue('sync-webdav-username', webdav.username || '');
+  setInputValue('sync-webdav-password', webdav.password || '');
+  setInputValue('sync-webdav-folder', webdav.folder || 'moyu-reader');
+  setInputValue('sync-interval', sync.interval_minutes ?? 10);
+  setInputValue('sync-device-name', sync.device_name || '');
 }
 
 function setInputValue(id, value) {
@@ -169,6 +250,7 @@ function collectSettingsFromUI() {
     boss_key: document.getElementById('boss-key')?.value || 'Ctrl+Alt+Space',
     max_chars_per_page: parseInt(document.getElementById('max-chars')?.value || '900'),
     appearance: {
+      ...(currentSettings?.appearance || {}),
       window_opacity: parseInt(document.getElementById('window-opacity')?.value || '90'),
       text_opacity: parseInt(document.getElementById('text-opacity')?.value || '100'),
       always_on_top: document.getElementById('always-on-top')?.checked || false,
@@ -179,23 +261,57 @@ function collectSettingsFromUI() {
       text_color: document.getElementById('text-color')?.value || '#d7dce2'
     },
     reading: {
+      ...(currentSettings?.reading || {}),
       smart_break: document.getElementById('smart-break')?.checked || false,
+      reflow: document.getElementById('reflow')?.checked || false,
+      normalize: {
+        unify_line_endings: document.getElementById('normalize-line-endings')?.checked || false,
+        strip_zero_width: document.getElementById('normalize-zero-width')?.checked || false,
+        normalize_indent: document.getElementById('normalize-indent')?.checked || false,
+        collapse_blank_lines: document.getElementById('normalize-blank-lines')?.checked || false,
+        dedupe_paragraphs: document.getElementById('normalize-dedupe')?.checked || false
+      },
       auto_save_interval: document.getElementById('auto-save-interval')?.value || 'instant'
     },
     privacy: {
+      ...(currentSettings?.privacy || {}),
       boss_action: document.getElementById('boss-action')?.value || 'disguise',
       auto_fade: document.getElementById('auto-fade')?.checked || false,
       fade_delay: parseInt(document.getElementById('fade-delay')?.value || '5')
     },
     keybindings: {
+      ...(currentSettings?.keybindings || {}),
       prev_page: getKeybindingValue('prev_page', 'PageUp'),
       next_page: getKeybindingValue('next_page', 'PageDown'),
       search: getKeybindingValue('search', 'Ctrl+F')
     },
     system: {
+      ...(currentSettings?.system || {}),
       auto_start: document.getElementById('auto-start')?.checked || false,
       restore_reading: document.getElementById('restore-reading')?.checked || false,
       dev_mode: document.getElementById('dev-mode')?.checked || false
+    },
+    opds_server: {
+      ...(currentSettings?.opds_server || {}),
+      enabled: document.getElementById('opds-server-enabled')?.checked || false,
+      port: parseInt(document.getElementById('opds-server-port')?.value || '8732'),
+      lan: document.getElementById('opds-server-lan')?.checked || false
+    },
+    sync: {
+      ...(currentSettings?.sync || {}),
+      device_name: document.getElementById('sync-device-name')?.value?.trim() || '',
+      interval_minutes: parseInt(document.getElementById('sync-interval')?.value || '10'),
+      folder: {
+        enabled: document.getElementById('sync-folder-enabled')?.checked || false,
+        path: document.getElementById('sync-folder-path')?.value?.trim() || ''
+      },
+      webdav: {
+        enabled: document.getElementById('sync-webdav-enabled')?.checked || false,
+        url: document.getElementById('sync-webdav-url')?.value?.trim() || '',
+        username: document.getElementById('sync-webdav-username')?.value?.trim() || '',
+        password: document.getElementById('sync-webdav-password')?.value || '',
+        folder: document.getElementById('sync-webdav-folder')?.value?.trim() || 'moyu-reader'
+      }
     }
   };
 }
@@ -292,6 +408,9 @@ function initSettingListeners() {
   // 监听所有设置变化
   document.querySelectorAll('input, select').forEach(element => {
     if (element.classList.contains('keybinding')) return; // 快捷键需要特殊处理
+    if (element.classList.contains('backup-option')) return; // 备份选项不属于设置
+    if (element.classList.contains('profile-option')) return; // 方案管理单独处理
+    if (element.classList.contains('vault-option')) return; // 口令不保存到设置
 
     const event = element.type === 'checkbox' ? 'change' : 'input';
     element.addEventListener(event, debounceSave);
@@ -332,6 +451,176 @@ function initSettingListeners() {
     });
   }
 
+  // 立即同步按钮
+  const syncNowBtn = document.getElementById('sync-now');
+  if (syncNowBtn) {
+    syncNowBtn.addEventListener('click', async () => {
+      if (!invoke) return;
+      syncNowBtn.disabled = true;
+      try {
+        await saveSettings();
+        const report = await invoke('sync_now');
+        const conflicts = report?.conflicts?.length || 0;
+        alert(conflicts > 0 ? `同步完成，有 ${conflicts} 本书的进度需要在阅读窗口确认` : '同步完成');
+        await loadSettings();
+      } catch (error) {
+        alert(`同步失败：${error}`);
+      } finally {
+        syncNowBtn.disabled = false;
+      }
+    });
+  }
+
+  // 配置方案
+  const profileSelect = document.getElementById('profile-select');
+  const profileNameInput = document.getElementById('profile-name');
+  const runProfileAction = async (command, args, reload = false) => {
+    if (!invoke) return;
+    try {
+      renderProfiles(await invoke(command, args));
+      if (reload) {
+        await loadSettings();
+      }
+      return true;
+    } catch (error) {
+      alert(`${error}`);
+      return false;
+    }
+  };
+
+  document.getElementById('switch-profile')?.addEventListener('click', async () => {
+    const name = profileSelect?.value;
+    if (!name) return;
+    if (pendingSave) {
+      clearTimeout(pendingSave);
+      pendingSave = null;
+      await saveSettings();
+    }
+    await runProfileAction('switch_profile', { name }, true);
+  });
+
+  document.getElementById('delete-profile')?.addEventListener('click', async () => {
+    const name = profileSelect?.value;
+    if (!name || !confirm(`确定删除方案“${name}”吗？`)) return;
+    await runProfileAction('delete_profile', { name });
+  });
+
+  document.getElementById('create-profile')?.addEventListener('click', async () => {
+    const name = profileNameInput?.value?.trim();
+    if (!name) return;
+    if (await runProfileAction('create_profile', { name })) {
+      profileNameInput.value = '';
+    }
+  });
+
+  document.getElementById('clone-profile')?.addEventListener('click', async () => {
+    const name = profileNameInput?.value?.trim();
+    const source = profileSelect?.value;
+    if (!name || !source) return;
+    if (await runProfileAction('clone_profile', { source, name })) {
+      profileNameInput.value = '';
+    }
+  });
+
+  // 数据加密
+  const vaultInputs = ['vault-current', 'vault-new', 'vault-confirm'].map(id => document.getElementById(id));
+  const newPassphrase = () => {
+    const [, next, confirmed] = vaultInputs;
+    if (!next?.value) {
+      alert('请输入新口令');
+      return null;
+    }
+    if (next.value !== confirmed?.value) {
+      alert('两次输入的新口令不一致');
+      return null;
+    }
+    return next.value;
+  };
+  const runVaultAction = async (command, args, message) => {
+    if (!invoke) return;
+    try {
+      await invoke(command, args);
+      vaultInputs.forEach(input => {
+        if (input) input.value = '';
+      });
+      alert(message);
+    } catch (error) {
+      alert(`${error}`);
+    }
+    await loadLockStatus();
+  };
+
+  document.getElementById('enable-encryption')?.addEventListener('click', async () => {
+    const passphrase = newPassphrase();
+    if (!passphrase) return;
+    if (!confirm('启用后每次启动都需要输入口令，忘记口令将无法恢复设置和书库；加密期间不能备份和同步。确定启用吗？')) return;
+    await runVaultAction('enable_encryption', { passphrase }, '已启用加密');
+  });
+
+  document.getElementById('change-passphrase')?.addEventListener('click', async () => {
+    const passphrase = newPassphrase();
+    if (!passphrase) return;
+    const current = vaultInputs[0]?.value || '';
+    await runVaultAction('change_passphrase', { current, passphrase }, '口令已更换，数据已重新加密');
+  });
+
+  document.getElementById('disable-encryption')?.addEventListener('click', async () => {
+    const passphrase = vaultInputs[0]?.value || '';
+    if (!confirm('关闭后设置和书库将以明文保存，确定关闭加密吗？')) return;
+    await runVaultAction('disable_encryption', { passphrase }, '已关闭加密');
+  });
+
+  // 备份与恢复
+  const dialogApi = tauriApi.dialog;
+  const createBackupBtn = document.getElementById('create-backup');
+  if (createBackupBtn) {
+    createBackupBtn.addEventListener('click', async () => {
+      if (!invoke || !dialogApi?.save) return;
+      const date = new Date().toISOString().slice(0, 10);
+      const path = await dialogApi.save({
+        defaultPath: `moyu-reader-backup-${date}.zip`,
+        filters: [{ name: 'Backup', extensions: ['zip'] }]
+      });
+      if (!path) return;
+      try {
+        const includeBooks = document.getElementById('backup-include-books')?.checked || false;
+        const info = await invoke('create_backup', { path, includeBooks });
+        alert(info.books > 0 ? `备份完成，包含 ${info.books} 本书` : '备份完成');
+      } catch (error) {
+        alert(`备份失败：${error}`);
+      }
+    });
+  }
+
+  const restoreBackupBtn = document.getElementById('restore-backup');
+  if (restoreBackupBtn) {
+    restoreBackupBtn.addEventListener('click', async () => {
+      if (!invoke || !dialogApi?.open) return;
+      const path = await dialogApi.open({
+        multiple: false,
+        filters: [{ name: 'Backup', extensions: ['zip'] }]
+      });
+      if (!path) return;
+      try {
+        const info = await invoke('inspect_backup', { path });
+        const sections = Array.from(document.querySelectorAll('.restore-section:checked'))
+          .map(input => input.value)
+          .filter(section => info.sections.includes(section));
+        if (sections.length === 0) {
+          alert('备份中没有选中的内容');
+          return;
+        }
+        const created = new Date(info.created * 1000).toLocaleString();
+        if (!confirm(`将用 ${created} 的备份覆盖所选内容，确定恢复吗？`)) return;
+        await invoke('restore_backup', { path, sections });
+        await loadSettings();
+        alert('恢复完成');
+      } catch (error) {
+        alert(`恢复失败：${error}`);
+      }
+    });
+  }
+
   // 检查更新按钮
   const checkUpdateBtn = document.getElementById('check-update');
   if (checkUpdateBtn) {
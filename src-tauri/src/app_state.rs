@@ -1,35 +1,142 @@
-use std::path::PathBuf;
-use std::sync::RwLock;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 
+use crate::book_source::{self, BookSource};
+use crate::layout::PageLayout;
+use crate::library::{self, Library};
+use crate::novel::{Chapter, OffsetMap, PageMark, PipelineOptions};
+use crate::opds::{self, Catalog};
+use crate::profiles;
+use crate::rules::{self, RuleSet};
 use crate::settings;
 use crate::settings::AppConfig;
+use crate::vault::{self, KdfParams, Keyring, LockStatus, VaultHeader};
 
 #[derive(Clone, Default)]
 pub struct StateSnapshot {
     pub file_path: Option<PathBuf>,
     pub text: String,
+    /// 展示文本的字符数；偏移都以字符计，不能用 `text.len()` 的字节数夹取
+    pub text_chars: usize,
+    pub offsets: OffsetMap,
+    pub chapters: Vec<Chapter>,
+    pub pages: Vec<PageMark>,
     pub current_offset: usize,
     pub config: AppConfig,
+    pub library: Library,
+    pub rules: RuleSet,
+    pub sources: Vec<BookSource>,
+    pub catalogs: Vec<Catalog>,
+    pub layout: PageLayout,
+}
+
+impl StateSnapshot {
+    /// 把展示文本中的偏移限制在文本末尾以内。
+    pub fn clamp_offset(&self, offset: usize) -> usize {
+        offset.min(self.text_chars)
+    }
+
+    /// 组装打开某本书时使用的处理管线参数。
+    pub fn pipeline_options(&self, path: &Path) -> PipelineOptions {
+        PipelineOptions {
+            reading: self.config.reading.clone(),
+            rules: self.rules.rules_for(&library::book_id(path)),
+            conversion: self
+                .library
+                .find_by_path(path)
+                .map(|book| book.conversion)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// 配置与书库的加密状态。
+#[derive(Clone)]
+enum Vault {
+    Off,
+    /// 密钥文件丢失或损坏时为 `None`，仍保持锁定，以免用默认数据覆盖加密文件
+    Locked(Option<VaultHeader>),
+    Unlocked(VaultHeader, Keyring),
+}
+
+impl Vault {
+    /// 写入配置和书库时使用的密钥；锁定时拒绝写入。
+    fn keyring(&self) -> Result<Option<&Keyring>> {
+        match self {
+            Vault::Off => Ok(None),
+            Vault::Locked(_) => bail!("阅读数据已加密，请先输入口令解锁"),
+            Vault::Unlocked(_, keyring) => Ok(Some(keyring)),
+        }
+    }
 }
 
 pub struct AppState {
     inner: RwLock<StateSnapshot>,
+    vault: RwLock<Vault>,
     config_path: PathBuf,
+    library_path: PathBuf,
+    rules_path: PathBuf,
+    sources_path: PathBuf,
+    catalogs_path: PathBuf,
+    profiles_path: PathBuf,
+    vault_path: PathBuf,
 }
 
 impl AppState {
     pub fn new(config_path: PathBuf) -> Self {
-        let config = settings::load_config(&config_path);
+        let config_dir = config_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let library_path = library::default_library_path(&config_dir);
+        let vault_path = vault::default_vault_path(&config_dir);
+        let vault = match vault::load_header(&vault_path) {
+            Ok(Some(header)) => Vault::Locked(Some(header)),
+            Ok(None)
+                if !vault::is_sealed_file(&config_path)
+                    && !vault::is_sealed_file(&library_path) =>
+            {
+                Vault::Off
+            }
+            _ => Vault::Locked(None),
+        };
+        // 加密的配置和书库在解锁后才读取
+        let (config, library) = match vault {
+            Vault::Off => (
+                settings::load_config(&config_path, None),
+                library::load_library(&library_path, None),
+            ),
+            _ => Default::default(),
+        };
+        let rules_path = rules::default_rules_path(&config_dir);
+        let sources_path = book_source::default_sources_path(&config_dir);
+        let catalogs_path = opds::default_catalogs_path(&config_dir);
+        let profiles_path = profiles::default_profiles_dir(&config_dir);
+        let rules = rules::load_rules(&rules_path);
+        let sources = book_source::load_sources(&sources_path);
+        let catalogs = opds::load_catalogs(&catalogs_path);
         let snapshot = StateSnapshot {
             text: String::new(),
             config,
+            library,
+            rules,
+            sources,
+            catalogs,
             ..StateSnapshot::default()
         };
         Self {
             inner: RwLock::new(snapshot),
+            vault: RwLock::new(vault),
             config_path,
+            library_path,
+            rules_path,
+            sources_path,
+            catalogs_path,
+            profiles_path,
+            vault_path,
         }
     }
 
@@ -45,9 +152,176 @@ impl AppState {
             .expect("failed to acquire reader state write lock")
     }
 
+    fn vault(&self) -> RwLockReadGuard<'_, Vault> {
+        self.vault
+            .read()
+            .expect("failed to acquire vault read lock")
+    }
+
+    fn vault_mut(&self) -> RwLockWriteGuard<'_, Vault> {
+        self.vault
+            .write()
+            .expect("failed to acquire vault write lock")
+    }
+
     pub fn save_config(&self) -> Result<()> {
+        let vault = self.vault();
+        let guard = self.read();
+        settings::save_config(&self.config_path, &guard.config, vault.keyring()?)
+    }
+
+    pub fn save_library(&self) -> Result<()> {
+        let vault = self.vault();
+        let guard = self.read();
+        library::save_library(&self.library_path, &guard.library, vault.keyring()?)
+    }
+
+    pub fn save_rules(&self) -> Result<()> {
+        let guard = self.read();
+        rules::save_rules(&self.rules_path, &guard.rules)
+    }
+
+    pub fn save_sources(&self) -> Result<()> {
         let guard = self.read();
-        settings::save_config(&self.config_path, &guard.config)
+        book_source::save_sources(&self.sources_path, &guard.sources)
+    }
+
+    pub fn save_catalogs(&self) -> Result<()> {
+        let guard = self.read();
+        opds::save_catalogs(&self.catalogs_path, &guard.catalogs)
+    }
+
+    pub fn config_dir(&self) -> PathBuf {
+        self.config_path
+            .parent()
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_default()
+    }
+
+    pub fn profiles_dir(&self) -> &Path {
+        &self.profiles_path
+    }
+
+    /// 在方案目录中读写方案时使用的密钥，与主配置相同。
+    pub fn with_keyring<T>(&self, action: impl FnOnce(Option<&Keyring>) -> Result<T>) -> Result<T> {
+        let vault = self.vault();
+        action(vault.keyring()?)
+    }
+
+    /// 切换到另一个配置方案并写入主配置，书库等共享数据不变。
+    pub fn switch_profile(&self, name: &str) -> Result<()> {
+        let vault = self.vault();
+        let keyring = vault.keyring()?;
+        let mut guard = self.write();
+        let config = profiles::switch_profile(
+            &self.profiles_path,
+            &guard.config,
+            name,
+            keyring,
+            |config| settings::save_config(&self.config_path, config, keyring),
+        )?;
+        guard.config = config;
+        Ok(())
+    }
+
+    pub fn lock_status(&self) -> LockStatus {
+        match &*self.vault() {
+            Vault::Off => LockStatus::Off,
+            Vault::Locked(_) => LockStatus::Locked,
+            Vault::Unlocked(..) => LockStatus::Unlocked,
+        }
+    }
+
+    /// 用口令解锁并读入加密的配置和书库。每次启动只需解锁一次，密钥只保存在内存中。
+    pub fn unlock(&self, passphrase: &str) -> Result<()> {
+        let mut vault = self.vault_mut();
+        let mut header = match &*vault {
+            Vault::Locked(Some(header)) => header.clone(),
+            Vault::Locked(None) => bail!("密钥文件丢失或已损坏，无法解锁"),
+            _ => return Ok(()),
+        };
+        let mut keyring = header.unlock(passphrase)?;
+        // 任何一个文件读不出都放弃解锁，以免把默认数据加密写回覆盖原文件
+        let config = settings::read_config(&self.config_path, Some(&keyring))?;
+        let library = library::read_library(&self.library_path, Some(&keyring))?;
+        for (name, path) in profiles::profile_files(&self.profiles_path) {
+            vault::read_file(&path, Some(&keyring))
+                .with_context(|| format!("方案“{}”无法解密", name))?;
+        }
+        {
+            let mut guard = self.write();
+            guard.config = config.unwrap_or_default();
+            guard.library = library.unwrap_or_default();
+        }
+        // 启用加密或更换口令中途退出时，在这里把剩下的文件加密完
+        self.reseal_all(Some(&keyring), Some(&keyring))?;
+        if header.has_old_keys() {
+            header.retire_old_keys();
+            keyring.retire_old_keys();
+            vault::save_header(&self.vault_path, &header)?;
+        }
+        *vault = Vault::Unlocked(header, keyring);
+        Ok(())
+    }
+
+    /// 启用加密：生成密钥文件后把配置、书库和所有方案加密写回。
+    pub fn enable_encryption(&self, passphrase: &str) -> Result<()> {
+        let mut vault = self.vault_mut();
+        if !matches!(*vault, Vault::Off) {
+            bail!("已经启用了加密");
+        }
+        let (header, keyring) = VaultHeader::create(passphrase, KdfParams::default())?;
+        // 先写密钥文件：中途退出时未加密的文件仍可读取，解锁后继续加密
+        vault::save_header(&self.vault_path, &header)?;
+        *vault = Vault::Unlocked(header, keyring.clone());
+        self.reseal_all(None, Some(&keyring))
+    }
+
+    /// 更换口令，并用新生成的密钥重新加密全部数据。
+    pub fn change_passphrase(&self, current: &str, new: &str) -> Result<()> {
+        let mut vault = self.vault_mut();
+        let Vault::Unlocked(header, _) = &*vault else {
+            bail!("尚未启用加密或尚未解锁");
+        };
+        let old_keyring = header.unlock(current)?;
+        let (mut header, mut keyring) =
+            VaultHeader::rekey(&old_keyring, new, KdfParams::default())?;
+        // 新密钥文件同时保留旧密钥，重新加密中途退出也不会有读不出的文件
+        vault::save_header(&self.vault_path, &header)?;
+        *vault = Vault::Unlocked(header.clone(), keyring.clone());
+        self.reseal_all(Some(&keyring), Some(&keyring))?;
+        header.retire_old_keys();
+        keyring.retire_old_keys();
+        vault::save_header(&self.vault_path, &header)?;
+        *vault = Vault::Unlocked(header, keyring);
+        Ok(())
+    }
+
+    /// 关闭加密：把数据解密写回后删除密钥文件。
+    pub fn disable_encryption(&self, passphrase: &str) -> Result<()> {
+        let mut vault = self.vault_mut();
+        let Vault::Unlocked(header, keyring) = &*vault else {
+            bail!("尚未启用加密或尚未解锁");
+        };
+        header.unlock(passphrase)?;
+        let keyring = keyring.clone();
+        self.reseal_all(Some(&keyring), None)?;
+        fs::remove_file(&self.vault_path)?;
+        *vault = Vault::Off;
+        Ok(())
+    }
+
+    /// 用 `to` 重新写入配置、书库和方案目录中的全部方案。调用方需持有 `vault` 写锁。
+    fn reseal_all(&self, from: Option<&Keyring>, to: Option<&Keyring>) -> Result<()> {
+        {
+            let guard = self.read();
+            settings::save_config(&self.config_path, &guard.config, to)?;
+            library::save_library(&self.library_path, &guard.library, to)?;
+        }
+        for (_, path) in profiles::profile_files(&self.profiles_path) {
+            vault::reseal_file(&path, from, to)?;
+        }
+        Ok(())
     }
 
     pub fn update_config<F>(&self, mutator: F) -> Result<()>
@@ -71,7 +345,73 @@ impl Clone for AppState {
         let snapshot = self.snapshot();
         Self {
             inner: RwLock::new(snapshot),
+            vault: RwLock::new(self.vault().clone()),
             config_path: self.config_path.clone(),
+            library_path: self.library_path.clone(),
+            rules_path: self.rules_path.clone(),
+            sources_path: self.sources_path.clone(),
+            catalogs_path: self.catalogs_path.clone(),
+            profiles_path: self.profiles_path.clone(),
+            vault_path: self.vault_path.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_data_stays_locked_until_unlocked() {
+        let dir =
+            std::env::temp_dir().join(format!("moyu-reader-app-vault-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config_path = settings::default_config_path(dir.clone());
+
+        let state = AppState::new(config_path.clone());
+        state
+            .update_config(|config| config.last_offset = 4200)
+            .unwrap();
+        state.write().library.add(Path::new("/books/雪中行.txt"));
+        state.save_library().unwrap();
+        state.enable_encryption("摸鱼口令").unwrap();
+        assert!(vault::is_sealed_file(&config_path));
+        let plain = fs::read(library::default_library_path(&dir)).unwrap();
+        assert!(!String::from_utf8_lossy(&plain).contains("雪中行"));
+
+        // 重新启动后在解锁前拒绝写入，不会用默认数据覆盖
+        let state = AppState::new(config_path.clone());
+        assert_eq!(state.lock_status(), LockStatus::Locked);
+        assert_eq!(state.read().config.last_offset, 0);
+        assert!(state.save_config().is_err());
+        assert!(state.unlock("别的口令").is_err());
+        state.unlock("摸鱼口令").unwrap();
+        assert_eq!(state.read().config.last_offset, 4200);
+        assert_eq!(state.read().library.books.len(), 1);
+
+        assert!(state.change_passphrase("别的口令", "新口令").is_err());
+        state.change_passphrase("摸鱼口令", "新口令").unwrap();
+        let state = AppState::new(config_path.clone());
+        assert!(state.unlock("摸鱼口令").is_err());
+
+        // 书库损坏时不解锁，也不改写任何文件
+        let library_path = library::default_library_path(&dir);
+        let sealed = fs::read(&library_path).unwrap();
+        let mut broken = sealed.clone();
+        *broken.last_mut().unwrap() ^= 1;
+        fs::write(&library_path, &broken).unwrap();
+        let config_bytes = fs::read(&config_path).unwrap();
+        assert!(state.unlock("新口令").is_err());
+        assert_eq!(state.lock_status(), LockStatus::Locked);
+        assert_eq!(fs::read(&library_path).unwrap(), broken);
+        assert_eq!(fs::read(&config_path).unwrap(), config_bytes);
+        fs::write(&library_path, &sealed).unwrap();
+        state.unlock("新口令").unwrap();
+
+        state.disable_encryption("新口令").unwrap();
+        let state = AppState::new(config_path);
+        assert_eq!(state.lock_status(), LockStatus::Off);
+        assert_eq!(state.read().config.last_offset, 4200);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
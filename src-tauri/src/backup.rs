@@ -0,0 +1,664 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::book_source::BookSource;
+use crate::library::{book_id, now_secs, safe_file_name, Library};
+use crate::opds::Catalog;
+use crate::rules::RuleSet;
+use crate::settings::AppConfig;
+
+/// 备份格式版本，读到更高版本的备份时拒绝恢复。
+const FORMAT_VERSION: u32 = 1;
+const FORMAT_NAME: &str = "moyu-reader-backup";
+const MANIFEST: &str = "manifest.json";
+
+/// 备份中可以单独恢复的部分。
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Section {
+    /// 设置
+    Config,
+    /// 书库：书籍列表、阅读进度与书签
+    Library,
+    /// 替换规则
+    Rules,
+    /// 网络书源
+    Sources,
+    /// OPDS 书库
+    Catalogs,
+    /// 书籍文件
+    Books,
+    /// 只恢复阅读进度：合并到书库中已有的同一本书，不增删书籍、不改书签
+    Progress,
+}
+
+impl Section {
+    fn entry(self) -> &'static str {
+        match self {
+            Section::Config => "config.json",
+            Section::Library => "library.json",
+            Section::Rules => "rules.json",
+            Section::Sources => "sources.json",
+            Section::Catalogs => "catalogs.json",
+            Section::Books => "books/",
+            Section::Progress => Section::Library.entry(),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Section::Config => "设置",
+            Section::Library => "书库",
+            Section::Rules => "替换规则",
+            Section::Sources => "书源",
+            Section::Catalogs => "OPDS 书库",
+            Section::Books => "书籍文件",
+            Section::Progress => "阅读进度",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    format: String,
+    version: u32,
+    app_version: String,
+    created: u64,
+    sections: Vec<Section>,
+    books: Vec<BookFile>,
+}
+
+/// 备份中的一个书籍文件。
+#[derive(Clone, Serialize, Deserialize)]
+struct BookFile {
+    book_id: String,
+    /// 备份时的原路径
+    path: PathBuf,
+    entry: String,
+    size: u64,
+}
+
+/// 要备份的数据，即 `AppState` 中持久化的各部分。
+pub struct BackupContents {
+    pub config: AppConfig,
+    pub library: Library,
+    pub rules: RuleSet,
+    pub sources: Vec<BookSource>,
+    pub catalogs: Vec<Catalog>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupInfo {
+    pub version: u32,
+    pub app_version: String,
+    pub created: u64,
+    pub sections: Vec<Section>,
+    pub books: usize,
+}
+
+/// 从备份中读出并校验过的数据，未选择的部分为 `None`。
+#[derive(Default)]
+pub struct Restored {
+    pub config: Option<AppConfig>,
+    pub library: Option<Library>,
+    pub rules: Option<RuleSet>,
+    pub sources: Option<Vec<BookSource>>,
+    pub catalogs: Option<Vec<Catalog>>,
+    /// 备份时的书库，只用其中的阅读进度
+    pub progress: Option<Library>,
+    /// 解压到新位置的书籍：原来的书库 ID 与新路径
+    pub relocated: Vec<(String, PathBuf)>,
+}
+
+/// 把数据写成一个 zip 备份，`include_books` 为真时一并打包书库中的书籍文件。
+///
+/// 先写到临时文件，完成后才替换目标文件。
+pub fn create_backup(
+    dest: &Path,
+    contents: &BackupContents,
+    include_books: bool,
+) -> Result<BackupInfo> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("创建备份目录失败: {}", parent.display()))?;
+    }
+    let tmp = temp_path(dest);
+    let result = write_archive(&tmp, contents, include_books)
+        .and_then(|info| fs::rename(&tmp, dest).map(|_| info).map_err(Into::into));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.with_context(|| format!("写入备份失败: {}", dest.display()))
+}
+
+fn write_archive(
+    path: &Path,
+    contents: &BackupContents,
+    include_books: bool,
+) -> Result<BackupInfo> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let sections = [
+        (
+            Section::Config,
+            serde_json::to_vec_pretty(&config_without_credentials(&contents.config))?,
+        ),
+        (
+            Section::Library,
+            serde_json::to_vec_pretty(&contents.library)?,
+        ),
+        (Section::Rules, serde_json::to_vec_pretty(&contents.rules)?),
+        (
+            Section::Sources,
+            serde_json::to_vec_pretty(&contents.sources)?,
+        ),
+        (
+            Section::Catalogs,
+            serde_json::to_vec_pretty(&catalogs_without_passwords(&contents.catalogs))?,
+        ),
+    ];
+    let mut manifest = Manifest {
+        format: FORMAT_NAME.to_string(),
+        version: FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created: now_secs(),
+        sections: sections.iter().map(|(section, _)| *section).collect(),
+        books: Vec::new(),
+    };
+    for (section, data) in &sections {
+        zip.start_file(section.entry(), options)?;
+        zip.write_all(data)?;
+    }
+
+    if include_books {
+        manifest.sections.push(Section::Books);
+        // 文件夹、网络书籍的章节缓存等不是单个文件，不打包
+        for book in contents
+            .library
+            .books
+            .iter()
+            .filter(|book| book.path.is_file())
+        {
+            let name = book
+                .path
+                .file_name()
+                .map(|name| safe_file_name(&name.to_string_lossy()))
+                .unwrap_or_default();
+            let entry = format!("{}{}/{}", Section::Books.entry(), book.id, name);
+            let mut file = File::open(&book.path)
+                .with_context(|| format!("读取书籍失败: {}", book.path.display()))?;
+            let size = file.metadata()?.len();
+            zip.start_file(
+                entry.as_str(),
+                options.large_file(size >= u64::from(u32::MAX)),
+            )?;
+            io::copy(&mut file, &mut zip)
+                .with_context(|| format!("读取书籍失败: {}", book.path.display()))?;
+            manifest.books.push(BookFile {
+                book_id: book.id.clone(),
+                path: book.path.clone(),
+                entry,
+                size,
+            });
+        }
+    }
+
+    zip.start_file(MANIFEST, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    zip.finish()?.flush()?;
+    Ok(info(&manifest))
+}
+
+/// 备份文件会被拷走或上传到别处，其中不保存密码、令牌等凭据。
+fn config_without_credentials(config: &AppConfig) -> AppConfig {
+    let mut config = config.clone();
+    config.opds_server.token.clear();
+    config.kosync.userkey.clear();
+    config.sync.webdav.password.clear();
+    config
+}
+
+fn catalogs_without_passwords(catalogs: &[Catalog]) -> Vec<Catalog> {
+    catalogs
+        .iter()
+        .map(|catalog| Catalog {
+            password: String::new(),
+            ..catalog.clone()
+        })
+        .collect()
+}
+
+/// 备份中没有凭据：服务令牌沿用本机的，同一账号沿用本机保存的密码，换了账号的需要重新填写。
+pub fn keep_credentials(config: &mut AppConfig, current: &AppConfig) {
+    config.opds_server.token = current.opds_server.token.clone();
+    if config.kosync.server == current.kosync.server
+        && config.kosync.username == current.kosync.username
+    {
+        config.kosync.userkey = current.kosync.userkey.clone();
+    }
+    let webdav = &mut config.sync.webdav;
+    if webdav.url == current.sync.webdav.url && webdav.username == current.sync.webdav.username {
+        webdav.password = current.sync.webdav.password.clone();
+    }
+}
+
+/// 恢复的 OPDS 书库按地址与用户名沿用本机保存的密码。
+pub fn keep_catalog_passwords(catalogs: &mut [Catalog], current: &[Catalog]) {
+    for catalog in catalogs {
+        if let Some(saved) = current
+            .iter()
+            .find(|saved| saved.url == catalog.url && saved.username == catalog.username)
+        {
+            catalog.password = saved.password.clone();
+        }
+    }
+}
+
+/// 读取备份的基本信息，不是有效备份时报错。
+pub fn inspect_backup(path: &Path) -> Result<BackupInfo> {
+    let (_, manifest) = open(path)?;
+    Ok(info(&manifest))
+}
+
+/// 读出并校验所选部分；选了书籍文件时解压到 `books_dir`，由调用方用 [`relocate_books`]
+/// 把书库中的路径指向解压后的文件。
+///
+/// 所有部分都校验通过、书籍文件全部解压成功后才返回，之前出错不会留下任何改动，
+/// 调用方可放心用结果覆盖现有数据。原路径上仍有文件的书不解压。
+pub fn read_backup(path: &Path, sections: &[Section], books_dir: &Path) -> Result<Restored> {
+    let (mut archive, manifest) = open(path)?;
+    if sections.is_empty() {
+        bail!("没有选择要恢复的内容");
+    }
+    let available = available_sections(&manifest);
+    if let Some(missing) = sections.iter().find(|section| !available.contains(section)) {
+        bail!("备份中没有{}", missing.label());
+    }
+    let wants = |section| sections.contains(&section);
+    let mut restored = Restored {
+        config: read_section(&mut archive, Section::Config, wants(Section::Config))?,
+        library: read_section(&mut archive, Section::Library, wants(Section::Library))?,
+        rules: read_section(&mut archive, Section::Rules, wants(Section::Rules))?,
+        sources: read_section(&mut archive, Section::Sources, wants(Section::Sources))?,
+        catalogs: read_section(&mut archive, Section::Catalogs, wants(Section::Catalogs))?,
+        progress: read_section(&mut archive, Section::Progress, wants(Section::Progress))?,
+        relocated: Vec::new(),
+    };
+    if wants(Section::Books) {
+        restored.relocated = restore_books(&mut archive, &manifest.books, books_dir)?;
+    }
+    Ok(restored)
+}
+
+/// 把备份中的阅读进度合并到书库中已有的同一本书，返回进度有变化的书的 ID。
+pub fn merge_progress(library: &mut Library, backup: &Library) -> Vec<String> {
+    let mut updated = Vec::new();
+    for saved in &backup.books {
+        let Some(record) = library.books.iter_mut().find(|book| book.id == saved.id) else {
+            continue;
+        };
+        if record.offset != saved.offset {
+            record.set_offset(saved.offset);
+            updated.push(record.id.clone());
+        }
+    }
+    updated
+}
+
+/// 让书库指向解压后的书籍文件。书库 ID 由路径计算，按书设置的替换规则随 ID 一起迁移；
+/// 返回不再使用的旧 ID，供调用方清理以旧 ID 保存的索引。
+pub fn relocate_books(
+    relocated: &[(String, PathBuf)],
+    library: &mut Library,
+    rules: &mut RuleSet,
+) -> Vec<String> {
+    let mut retired = Vec::new();
+    for (old_id, path) in relocated {
+        let new_id = book_id(path);
+        if *old_id == new_id {
+            continue;
+        }
+        if let Some(record) = library.books.iter_mut().find(|book| book.id == *old_id) {
+            record.id = new_id.clone();
+            record.path = path.clone();
+        }
+        if let Some(book_rules) = rules.books.remove(old_id) {
+            rules.books.insert(new_id, book_rules);
+        }
+        retired.push(old_id.clone());
+    }
+    retired
+}
+
+fn open(path: &Path) -> Result<(ZipArchive<File>, Manifest)> {
+    let file = File::open(path).with_context(|| format!("打开备份失败: {}", path.display()))?;
+    let mut archive =
+        ZipArchive::new(file).with_context(|| format!("不是有效的备份文件: {}", path.display()))?;
+    let manifest: Manifest = read_json(&mut archive, MANIFEST)
+        .with_context(|| format!("不是摸鱼阅读器的备份文件: {}", path.display()))?;
+    if manifest.format != FORMAT_NAME {
+        bail!("不是摸鱼阅读器的备份文件: {}", path.display());
+    }
+    if manifest.version > FORMAT_VERSION {
+        bail!(
+            "备份由更新版本的摸鱼阅读器（{}）创建，请先升级",
+            manifest.app_version
+        );
+    }
+    Ok((archive, manifest))
+}
+
+fn read_section<T: DeserializeOwned>(
+    archive: &mut ZipArchive<File>,
+    section: Section,
+    wanted: bool,
+) -> Result<Option<T>> {
+    if !wanted {
+        return Ok(None);
+    }
+    read_json(archive, section.entry()).map(Some)
+}
+
+/// 按类型严格解析，读取时同时校验 CRC。
+fn read_json<T: DeserializeOwned>(archive: &mut ZipArchive<File>, name: &str) -> Result<T> {
+    let mut data = Vec::new();
+    archive
+        .by_name(name)
+        .with_context(|| format!("备份中缺少 {}", name))?
+        .read_to_end(&mut data)
+        .with_context(|| format!("备份中的 {} 已损坏", name))?;
+    serde_json::from_slice(&data).with_context(|| format!("备份中的 {} 格式错误", name))
+}
+
+fn restore_books(
+    archive: &mut ZipArchive<File>,
+    books: &[BookFile],
+    dir: &Path,
+) -> Result<Vec<(String, PathBuf)>> {
+    // 先全部解压到临时文件，全部成功后再改名
+    let mut extracted: Vec<(&BookFile, PathBuf, PathBuf)> = Vec::new();
+    let result = (|| -> Result<()> {
+        for book in books {
+            if book.path.is_file() {
+                continue;
+            }
+            let name = Path::new(&book.entry)
+                .file_name()
+                .map(|name| safe_file_name(&name.to_string_lossy()))
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| book.book_id.clone());
+            let target = unique_target(dir, &name, book.size, &extracted);
+            if target.is_file() {
+                // 之前恢复过的同一个文件
+                extracted.push((book, PathBuf::new(), target));
+                continue;
+            }
+            fs::create_dir_all(dir).with_context(|| format!("创建目录失败: {}", dir.display()))?;
+            let tmp = temp_path(&target);
+            extracted.push((book, tmp.clone(), target));
+            let mut entry = archive
+                .by_name(&book.entry)
+                .with_context(|| format!("备份中缺少书籍文件: {}", book.entry))?;
+            let mut file = File::create(&tmp)?;
+            io::copy(&mut entry, &mut file)
+                .with_context(|| format!("备份中的书籍文件已损坏: {}", book.entry))?;
+            file.sync_all()?;
+        }
+        Ok(())
+    })();
+    if let Err(err) = result {
+        for (_, tmp, _) in &extracted {
+            let _ = fs::remove_file(tmp);
+        }
+        return Err(err);
+    }
+
+    let mut relocated = Vec::new();
+    for (book, tmp, target) in extracted {
+        if !tmp.as_os_str().is_empty() {
+            fs::rename(&tmp, &target)
+                .with_context(|| format!("写入书籍失败: {}", target.display()))?;
+        }
+        relocated.push((book.book_id.clone(), target));
+    }
+    Ok(relocated)
+}
+
+/// 目标目录中已有同名同大小的文件时视为同一本书，否则在文件名后加序号。
+fn unique_target(
+    dir: &Path,
+    name: &str,
+    size: u64,
+    taken: &[(&BookFile, PathBuf, PathBuf)],
+) -> PathBuf {
+    let path = Path::new(name);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    for index in 1.. {
+        let candidate = if index == 1 {
+            dir.join(name)
+        } else {
+            dir.join(format!("{} ({}){}", stem, index, ext))
+        };
+        if taken.iter().any(|(_, _, target)| *target == candidate) {
+            continue;
+        }
+        match fs::metadata(&candidate) {
+            Ok(meta) if meta.len() == size => return candidate,
+            Ok(_) => continue,
+            Err(_) => return candidate,
+        }
+    }
+    unreachable!()
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// 备份中可以恢复的部分：有书库时也可以只恢复其中的阅读进度。
+fn available_sections(manifest: &Manifest) -> Vec<Section> {
+    let mut sections = manifest.sections.clone();
+    if sections.contains(&Section::Library) && !sections.contains(&Section::Progress) {
+        sections.push(Section::Progress);
+    }
+    sections
+}
+
+fn info(manifest: &Manifest) -> BackupInfo {
+    BackupInfo {
+        version: manifest.version,
+        app_version: manifest.app_version.clone(),
+        created: manifest.created,
+        sections: available_sections(manifest),
+        books: manifest.books.len(),
+    }
+}
+
+pub fn default_restore_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("moyu-reader-restored")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::Bookmark;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("moyu-reader-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn restores_selected_sections_and_book_files() {
+        let dir = temp_dir("backup");
+        let book = dir.join("雪中行.txt");
+        fs::write(&book, "第一章 雪夜\n").unwrap();
+        let mut library = Library::default();
+        let record = library.add(&book);
+        record.offset = 3;
+        record.bookmarks.push(Bookmark::new(3, "雪夜".to_string()));
+        let mut config = AppConfig::default();
+        config.appearance.font_size = 22;
+        config.sync.webdav.url = "https://dav.example.com/".to_string();
+        config.sync.webdav.username = "reader".to_string();
+        config.sync.webdav.password = "dav-secret".to_string();
+        config.kosync.username = "reader".to_string();
+        config.kosync.userkey = "kosync-secret".to_string();
+        config.opds_server.token = "token-secret".to_string();
+        let catalog = Catalog {
+            name: "家里".to_string(),
+            url: "http://nas.local/opds".to_string(),
+            username: "reader".to_string(),
+            password: "opds-secret".to_string(),
+        };
+        let contents = BackupContents {
+            config: config.clone(),
+            library,
+            rules: RuleSet::default(),
+            sources: Vec::new(),
+            catalogs: vec![catalog.clone()],
+        };
+        let archive = dir.join("备份.zip");
+        let info = create_backup(&archive, &contents, true).unwrap();
+        assert_eq!(info.books, 1);
+        assert_eq!(inspect_backup(&archive).unwrap().sections.len(), 7);
+        let mut zip = ZipArchive::new(File::open(&archive).unwrap()).unwrap();
+        for entry in ["config.json", "catalogs.json"] {
+            let mut text = String::new();
+            zip.by_name(entry)
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            assert!(!text.contains("secret"), "{}", entry);
+        }
+
+        // 凭据沿用本机的；换了账号的密码不沿用
+        let sections = [Section::Config, Section::Catalogs];
+        let restored = read_backup(&archive, &sections, &dir).unwrap();
+        let mut restored_config = restored.config.unwrap();
+        let mut current = config.clone();
+        current.kosync.username = "someone-else".to_string();
+        keep_credentials(&mut restored_config, &current);
+        assert_eq!(restored_config.sync.webdav.password, "dav-secret");
+        assert_eq!(restored_config.opds_server.token, "token-secret");
+        assert!(restored_config.kosync.userkey.is_empty());
+        let mut catalogs = restored.catalogs.unwrap();
+        keep_catalog_passwords(&mut catalogs, &[catalog]);
+        assert_eq!(catalogs[0].password, "opds-secret");
+
+        // 只恢复设置与规则
+        let restore_dir = dir.join("restored");
+        let restored =
+            read_backup(&archive, &[Section::Config, Section::Rules], &restore_dir).unwrap();
+        assert_eq!(restored.config.unwrap().appearance.font_size, 22);
+        assert!(restored.rules.is_some());
+        assert!(restored.library.is_none());
+        assert!(!restore_dir.exists());
+
+        // 原文件还在时不解压
+        let all = [Section::Library, Section::Books];
+        let restored = read_backup(&archive, &all, &restore_dir).unwrap();
+        assert!(restored.relocated.is_empty());
+        assert_eq!(restored.library.unwrap().books[0].path, book);
+
+        // 原文件丢失后解压到恢复目录，书库指向新位置，进度、书签和按书的替换规则保留
+        fs::remove_file(&book).unwrap();
+        let restored = read_backup(&archive, &all, &restore_dir).unwrap();
+        assert_eq!(restored.relocated.len(), 1);
+        let mut library = restored.library.unwrap();
+        let old_id = library.books[0].id.clone();
+        let mut rules = RuleSet::default();
+        rules.books.insert(old_id.clone(), Vec::new());
+        let retired = relocate_books(&restored.relocated, &mut library, &mut rules);
+        assert_eq!(retired, vec![old_id]);
+        let record = &library.books[0];
+        assert_eq!(record.path, restore_dir.join("雪中行.txt"));
+        assert_eq!(record.id, book_id(&record.path));
+        assert!(rules.books.contains_key(&record.id));
+        assert_eq!((record.offset, record.bookmarks.len()), (3, 1));
+        assert_eq!(fs::read_to_string(&record.path).unwrap(), "第一章 雪夜\n");
+
+        // 只恢复进度：已有的书更新位置，其他书和书签不变
+        let mut current = Library::default();
+        current.add(&book).offset = 1;
+        current.add(&dir.join("雨夜.txt")).offset = 8;
+        let restored = read_backup(&archive, &[Section::Progress], &restore_dir).unwrap();
+        let updated = merge_progress(&mut current, &restored.progress.unwrap());
+        assert_eq!(updated, vec![book_id(&book)]);
+        assert_eq!(current.books.len(), 2);
+        assert_eq!((current.books[0].offset, current.books[1].offset), (3, 8));
+        assert!(current.books[0].bookmarks.is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_damaged_or_newer_backups() {
+        let dir = temp_dir("backup-invalid");
+        let write_zip = |name: &str, entries: &[(&str, &str)]| {
+            let path = dir.join(name);
+            let mut zip = ZipWriter::new(File::create(&path).unwrap());
+            for (entry, body) in entries {
+                zip.start_file(*entry, SimpleFileOptions::default())
+                    .unwrap();
+                zip.write_all(body.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+            path
+        };
+        let manifest = |version: u32| {
+            format!(
+                r#"{{"format":"moyu-reader-backup","version":{},"app_version":"9.0.0","created":0,"sections":["config","library"],"books":[]}}"#,
+                version
+            )
+        };
+        let config = r#"{"appearance":{"font_size":30}}"#;
+
+        let newer = write_zip(
+            "newer.zip",
+            &[(MANIFEST, &manifest(2)), ("config.json", config)],
+        );
+        let err = inspect_backup(&newer).err().unwrap().to_string();
+        assert!(err.contains("9.0.0"));
+
+        // 书库损坏时连设置也不恢复
+        let broken = write_zip(
+            "broken.zip",
+            &[
+                (MANIFEST, &manifest(1)),
+                ("config.json", config),
+                ("library.json", r#"{"books": [{"id": 1"#),
+            ],
+        );
+        let sections = [Section::Config, Section::Library];
+        assert!(read_backup(&broken, &sections, &dir).is_err());
+        assert!(read_backup(&broken, &[Section::Progress], &dir).is_err());
+        assert!(read_backup(&broken, &[Section::Rules], &dir).is_err());
+        let restored = read_backup(&broken, &[Section::Config], &dir).unwrap();
+        assert_eq!(restored.config.unwrap().appearance.font_size, 30);
+
+        fs::write(dir.join("plain.zip"), "not a zip").unwrap();
+        assert!(inspect_backup(&dir.join("plain.zip")).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,352 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use super::{merge_sources, parse_sources, BookSource};
+use crate::library::{self, BookRecord, Library};
+use crate::novel::{self, Chapter, SourceText};
+
+/// 阅读备份中书架或进度里的一本书，只保留导入进度用到的字段。
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LegadoBook {
+    pub name: String,
+    pub author: String,
+    pub dur_chapter_index: usize,
+    /// 章节内的字符位置
+    pub dur_chapter_pos: usize,
+    pub dur_chapter_time: i64,
+    pub dur_chapter_title: String,
+}
+
+pub struct LegadoBackup {
+    pub books: Vec<LegadoBook>,
+    pub sources: Vec<BookSource>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedProgress {
+    pub name: String,
+    pub author: String,
+    pub path: PathBuf,
+    pub chapter_title: String,
+    pub offset: usize,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnmatchedBook {
+    pub name: String,
+    pub author: String,
+    pub chapter_title: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupReport {
+    pub matched: Vec<ImportedProgress>,
+    pub unmatched: Vec<UnmatchedBook>,
+    pub sources_imported: usize,
+}
+
+/// 读取阅读的备份：可以是备份 zip，也可以是解压后的目录。
+///
+/// `bookshelf.json` 与 `bookProgress.json` 中的同一本书取最近阅读的一条。
+pub fn read_backup(path: &Path) -> Result<LegadoBackup> {
+    let mut files = BackupFiles::open(path)?;
+    let mut books: Vec<LegadoBook> = Vec::new();
+    for name in ["bookshelf.json", "bookProgress.json"] {
+        let Some(json) = files.read(name)? else {
+            continue;
+        };
+        let entries: Vec<LegadoBook> = serde_json::from_str(json.trim_start_matches('\u{feff}'))
+            .with_context(|| format!("无法解析备份中的 {}", name))?;
+        for entry in entries {
+            if entry.name.trim().is_empty() {
+                continue;
+            }
+            match books.iter_mut().find(|book| same_book(book, &entry)) {
+                Some(book) if entry.dur_chapter_time > book.dur_chapter_time => *book = entry,
+                Some(_) => {}
+                None => books.push(entry),
+            }
+        }
+    }
+    let sources = match files.read("bookSource.json")? {
+        Some(json) => parse_sources(&json)?,
+        None => Vec::new(),
+    };
+    if books.is_empty() && sources.is_empty() {
+        bail!("没有在备份中找到书架、阅读进度或书源: {}", path.display());
+    }
+    Ok(LegadoBackup { books, sources })
+}
+
+/// 已换算好进度、尚未写入书库的阅读备份。
+pub struct PreparedImport {
+    pub report: BackupReport,
+    sources: Vec<BookSource>,
+}
+
+/// 按书名与作者匹配书库中的书，把阅读的章节序号与章内位置换算为原文偏移。
+///
+/// 换算要读取每本书的文件，不修改书库，可以在锁外执行，之后用 [`PreparedImport::apply`] 写入。
+pub fn prepare_import(path: &Path, library: &Library) -> Result<PreparedImport> {
+    let backup = read_backup(path)?;
+    let mut report = BackupReport::default();
+    for book in backup.books {
+        let unmatched = |reason: String| UnmatchedBook {
+            name: book.name.clone(),
+            author: book.author.clone(),
+            chapter_title: book.dur_chapter_title.clone(),
+            reason,
+        };
+        let Some(record) = find_record(library, &book) else {
+            report
+                .unmatched
+                .push(unmatched("书库中没有同名的书".to_string()));
+            continue;
+        };
+        let path = record.path.clone();
+        let source = match novel::load_source(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                report
+                    .unmatched
+                    .push(unmatched(format!("无法读取本地文件: {}", err)));
+                continue;
+            }
+        };
+        let offset = progress_offset(&source, &book);
+        report.matched.push(ImportedProgress {
+            name: book.name,
+            author: book.author,
+            path,
+            chapter_title: book.dur_chapter_title,
+            offset,
+        });
+    }
+    Ok(PreparedImport {
+        report,
+        sources: backup.sources,
+    })
+}
+
+impl PreparedImport {
+    /// 只改写匹配到的书的进度并合并书源，书库中的其他修改不受影响。
+    pub fn apply(self, library: &mut Library, sources: &mut Vec<BookSource>) -> BackupReport {
+        for book in &self.report.matched {
+            library.update_offset(&book.path, book.offset);
+        }
+        BackupReport {
+            sources_imported: merge_sources(sources, self.sources),
+            ..self.report
+        }
+    }
+}
+
+/// 书名相同时优先选作者也相同的一本；两边都有作者且不同时视为不同的书。
+fn find_record<'a>(library: &'a Library, book: &LegadoBook) -> Option<&'a BookRecord> {
+    let name = normalize(&book.name);
+    let author = normalize(&book.author);
+    let candidates: Vec<&BookRecord> = library
+        .books
+        .iter()
+        .filter(|record| {
+            normalize(&record.title) == name
+                || normalize(&library::book_title(&record.path)) == name
+        })
+        .filter(|record| novel::source_exists(&record.path))
+        .collect();
+    candidates
+        .iter()
+        .find(|record| !author.is_empty() && normalize(&record.author) == author)
+        .or_else(|| {
+            candidates
+                .iter()
+                .find(|record| author.is_empty() || record.author.trim().is_empty())
+        })
+        .copied()
+}
+
+/// 先按章节名定位（两边的目录规则可能不同，序号未必一致），找不到再按序号，最后加上章内位置。
+pub fn progress_offset(source: &SourceText, book: &LegadoBook) -> usize {
+    let total = source.text.chars().count();
+    let chapters = if source.chapters.is_empty() {
+        heading_chapters(&source.text)
+    } else {
+        source.chapters.clone()
+    };
+    if chapters.is_empty() {
+        return book.dur_chapter_pos.min(total);
+    }
+    let title = normalize(&book.dur_chapter_title);
+    let index = chapters
+        .iter()
+        .enumerate()
+        .filter(|(_, chapter)| !title.is_empty() && normalize(&chapter.title) == title)
+        .min_by_key(|(index, _)| index.abs_diff(book.dur_chapter_index))
+        .map(|(index, _)| index)
+        .unwrap_or_else(|| book.dur_chapter_index.min(chapters.len() - 1));
+    let start = chapters[index].offset;
+    let end = chapters
+        .get(index + 1)
+        .map(|next| next.offset)
+        .unwrap_or(total);
+    (start + book.dur_chapter_pos).min(end.saturating_sub(1).max(start))
+}
+
+/// 纯文本没有目录时，按阅读默认的目录规则识别“第…章”等标题行。
+fn heading_chapters(text: &str) -> Vec<Chapter> {
+    let pattern = Regex::new(
+        r"^[ \t　]*(?:第[0-9０-９零〇一二两三四五六七八九十百千万壹贰叁肆伍陆柒捌玖拾佰仟]+[章节回卷集部篇]|序章|楔子|引子|尾声|后记|番外).{0,30}$",
+    )
+    .expect("章节标题正则无效");
+    let mut chapters = Vec::new();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if pattern.is_match(line.trim_end()) {
+            chapters.push(Chapter {
+                title: line.trim().to_string(),
+                offset,
+            });
+        }
+        offset += line.chars().count();
+    }
+    chapters
+}
+
+fn same_book(a: &LegadoBook, b: &LegadoBook) -> bool {
+    normalize(&a.name) == normalize(&b.name) && normalize(&a.author) == normalize(&b.author)
+}
+
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|ch| !ch.is_whitespace() && !matches!(ch, '《' | '》'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+enum BackupFiles {
+    Dir(PathBuf),
+    Zip(Box<ZipArchive<BufReader<File>>>),
+}
+
+impl BackupFiles {
+    fn open(path: &Path) -> Result<Self> {
+        if path.is_dir() {
+            return Ok(BackupFiles::Dir(path.to_path_buf()));
+        }
+        let file = File::open(path).with_context(|| format!("打开备份失败: {}", path.display()))?;
+        let archive = ZipArchive::new(BufReader::new(file))
+            .with_context(|| format!("读取备份压缩包失败: {}", path.display()))?;
+        Ok(BackupFiles::Zip(Box::new(archive)))
+    }
+
+    /// 按文件名查找，忽略备份中可能存在的上层目录。
+    fn read(&mut self, name: &str) -> Result<Option<String>> {
+        match self {
+            BackupFiles::Dir(dir) => {
+                let path = dir.join(name);
+                if !path.is_file() {
+                    return Ok(None);
+                }
+                let bytes =
+                    fs::read(&path).with_context(|| format!("读取失败: {}", path.display()))?;
+                Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+            }
+            BackupFiles::Zip(archive) => {
+                let Some(entry) = archive
+                    .file_names()
+                    .find(|entry| entry.rsplit(['/', '\\']).next() == Some(name))
+                    .map(str::to_string)
+                else {
+                    return Ok(None);
+                };
+                let mut bytes = Vec::new();
+                archive
+                    .by_name(&entry)?
+                    .read_to_end(&mut bytes)
+                    .with_context(|| format!("解压失败: {}", name))?;
+                Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn imports_progress_for_matching_local_books() {
+        let dir = std::env::temp_dir().join(format!("moyu-reader-legado-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let novel = dir.join("雪中行.txt");
+        fs::write(
+            &novel,
+            "第一章 雪夜\n那一夜的雪下得很大。\n第二章 归途\n归途漫长，终于到家。\n第三章 重逢\n故人相见。\n",
+        )
+        .unwrap();
+
+        let mut library = Library::default();
+        library.touch(&novel).author = "佚名".to_string();
+        library.touch(&dir.join("不存在.txt"));
+
+        let backup = dir.join("backup.zip");
+        let mut zip = ZipWriter::new(File::create(&backup).unwrap());
+        let options = SimpleFileOptions::default();
+        zip.start_file("backup/bookshelf.json", options).unwrap();
+        zip.write_all(
+            r#"[{"name":"雪中行","author":"佚名","origin":"loc_book","durChapterIndex":0,"durChapterPos":0,"durChapterTime":1},
+                {"name":"不存在","author":"","durChapterIndex":3,"durChapterTitle":"第四章"},
+                {"name":"雪满山","author":"无名","origin":"https://example.com","durChapterTitle":"第九章"}]"#
+                .as_bytes(),
+        )
+        .unwrap();
+        zip.start_file("backup/bookProgress.json", options).unwrap();
+        zip.write_all(
+            r#"[{"name":"《雪中行》","author":"佚名","durChapterIndex":5,"durChapterPos":3,"durChapterTime":9,"durChapterTitle":"第二章 归途"}]"#
+                .as_bytes(),
+        )
+        .unwrap();
+        zip.start_file("backup/bookSource.json", options).unwrap();
+        zip.write_all(
+            r#"[{"bookSourceUrl":"https://example.com","bookSourceName":"示例"}]"#.as_bytes(),
+        )
+        .unwrap();
+        zip.finish().unwrap();
+
+        let prepared = prepare_import(&backup, &library).unwrap();
+        assert_eq!(library.find_by_path(&novel).unwrap().offset, 0);
+        let mut sources = Vec::new();
+        let report = prepared.apply(&mut library, &mut sources);
+        assert_eq!(report.sources_imported, 1);
+        assert_eq!(sources[0].book_source_name, "示例");
+
+        assert_eq!(report.matched.len(), 1);
+        let source = novel::load_source(&novel).unwrap();
+        let second = source.text.find("第二章").unwrap();
+        let expected = source.text[..second].chars().count() + 3;
+        assert_eq!(report.matched[0].offset, expected);
+        assert_eq!(library.find_by_path(&novel).unwrap().offset, expected);
+        let text: String = source.text.chars().skip(expected).take(4).collect();
+        assert_eq!(text, " 归途\n");
+
+        let unmatched: Vec<&str> = report.unmatched.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(unmatched, vec!["不存在", "雪满山"]);
+        assert_eq!(report.unmatched[1].chapter_title, "第九章");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
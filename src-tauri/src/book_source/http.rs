@@ -0,0 +1,361 @@
+use std::io::Read;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use encoding_rs::{Encoding, UTF_8};
+use serde_json::Value;
+use url::Url;
+
+use crate::novel::decode_bytes;
+
+const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36";
+const TIMEOUT: Duration = Duration::from_secs(15);
+/// 单个页面的大小上限，防止误把大文件当网页读取。
+const MAX_BODY_BYTES: u64 = 16 * 1024 * 1024;
+
+/// 一次书源请求：由书源 URL 模板与其后的 `,{...}` 选项解析而来。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub url: String,
+    pub post: bool,
+    pub body: Option<String>,
+    pub charset: Option<&'static Encoding>,
+    pub headers: Vec<(String, String)>,
+}
+
+pub struct Response {
+    /// 跟随重定向后的最终地址，页面中的相对链接以它为基准。
+    pub url: String,
+    pub body: String,
+}
+
+/// 搜索地址中的模板变量。
+pub struct SearchVars<'a> {
+    pub key: &'a str,
+    pub page: u32,
+}
+
+/// 解析书源 URL：替换 `{{key}}`/`{{page}}` 等变量，拆出请求选项，并以 `base` 补全相对地址。
+///
+/// 选项格式与阅读（Legado）一致，例如 `/search,{"method":"POST","body":"q={{key}}","charset":"gbk"}`。
+pub fn build_request(
+    template: &str,
+    base: &str,
+    vars: Option<&SearchVars>,
+    source_header: &str,
+) -> Result<Request> {
+    let (url, options) = split_options(template.trim())?;
+    let charset = options
+        .get("charset")
+        .and_then(Value::as_str)
+        .and_then(|label| Encoding::for_label(label.trim().as_bytes()))
+        .filter(|encoding| *encoding != UTF_8);
+    let fill = |text: &str| match vars {
+        Some(vars) => fill_template(text, vars, charset.unwrap_or(UTF_8)),
+        None => Ok(text.to_string()),
+    };
+
+    let url = resolve_url(base, &fill(url)?)?;
+    let post = options
+        .get("method")
+        .and_then(Value::as_str)
+        .is_some_and(|method| method.eq_ignore_ascii_case("post"));
+    let body = match options.get("body") {
+        Some(Value::String(body)) => Some(fill(body)?),
+        Some(Value::Null) | None => None,
+        Some(other) => Some(fill(&other.to_string())?),
+    };
+
+    let mut headers = parse_headers(source_header);
+    if let Some(extra) = options.get("headers") {
+        headers.extend(parse_headers(&match extra {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        }));
+    }
+    Ok(Request {
+        url,
+        post,
+        body,
+        charset,
+        headers,
+    })
+}
+
+/// 按 `base` 把相对地址补全为绝对地址，已是绝对地址时原样返回。
+pub fn resolve_url(base: &str, url: &str) -> Result<String> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Ok(base.to_string());
+    }
+    if let Ok(absolute) = Url::parse(url) {
+        return Ok(absolute.to_string());
+    }
+    let base = Url::parse(base).with_context(|| format!("无效的地址: {}", base))?;
+    Ok(base
+        .join(url)
+        .with_context(|| format!("无效的地址: {}", url))?
+        .to_string())
+}
+
+/// 补全规则取到的相对地址，保留其后的 `,{...}` 请求选项；无法解析时原样返回。
+pub fn absolute_url(base: &str, template: &str) -> String {
+    let template = template.trim();
+    match split_options(template) {
+        Ok((url, options)) if !options.is_empty() => {
+            let url = resolve_url(base, url).unwrap_or_else(|_| url.to_string());
+            format!("{},{}", url, Value::Object(options))
+        }
+        _ => resolve_url(base, template).unwrap_or_else(|_| template.to_string()),
+    }
+}
+
+pub fn fetch(request: &Request) -> Result<Response> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let mut call = if request.post {
+        agent.post(&request.url)
+    } else {
+        agent.get(&request.url)
+    };
+    let has_header = |name: &str| {
+        request
+            .headers
+            .iter()
+            .any(|(key, _)| key.eq_ignore_ascii_case(name))
+    };
+    if !has_header("User-Agent") {
+        call = call.set("User-Agent", USER_AGENT);
+    }
+    if request.post && !has_header("Content-Type") {
+        let json = request
+            .body
+            .as_deref()
+            .is_some_and(|body| body.trim_start().starts_with(['{', '[']));
+        call = call.set(
+            "Content-Type",
+            if json {
+                "application/json"
+            } else {
+                "application/x-www-form-urlencoded"
+            },
+        );
+    }
+    for (name, value) in &request.headers {
+        call = call.set(name, value);
+    }
+
+    let result = match &request.body {
+        Some(body) if request.post => {
+            let bytes = encode_body(body, request.charset.unwrap_or(UTF_8));
+            call.send_bytes(&bytes)
+        }
+        _ => call.call(),
+    };
+    let response = result.map_err(|err| match err {
+        ureq::Error::Status(code, _) => anyhow!("请求失败（HTTP {}）: {}", code, request.url),
+        other => anyhow!("请求失败: {}: {}", request.url, other),
+    })?;
+
+    let url = response.get_url().to_string();
+    let header_charset = response
+        .header("Content-Type")
+        .and_then(charset_from_content_type);
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_BODY_BYTES)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("读取响应失败: {}", url))?;
+
+    let encoding = request
+        .charset
+        .or(header_charset)
+        .or_else(|| meta_charset(&bytes));
+    let body = match encoding {
+        Some(encoding) => encoding.decode(&bytes).0.into_owned(),
+        None => match std::str::from_utf8(&bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => decode_bytes(&bytes)?,
+        },
+    };
+    Ok(Response { url, body })
+}
+
+/// 拆分 `url,{options}`：逗号后能解析为 JSON 对象时才视为选项，兼容单引号写法。
+fn split_options(template: &str) -> Result<(&str, serde_json::Map<String, Value>)> {
+    for (index, _) in template.match_indices(',') {
+        let rest = template[index + 1..].trim();
+        if !rest.starts_with('{') || !rest.ends_with('}') {
+            continue;
+        }
+        let parsed = serde_json::from_str::<Value>(rest)
+            .or_else(|_| serde_json::from_str::<Value>(&rest.replace('\'', "\"")));
+        if let Ok(Value::Object(options)) = parsed {
+            return Ok((template[..index].trim(), options));
+        }
+    }
+    if template.is_empty() {
+        bail!("书源地址为空");
+    }
+    Ok((template, serde_json::Map::new()))
+}
+
+/// 替换 `{{key}}`、`{{page}}`、`{{page+1}}` 以及旧版的 `searchKey`/`searchPage`，
+/// 并展开按页取值的 `<第一页,第二页,…>`。关键词按请求编码做百分号转义。
+fn fill_template(template: &str, vars: &SearchVars, encoding: &'static Encoding) -> Result<String> {
+    let key = encode_component(vars.key, encoding);
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .map(|end| start + end)
+            .with_context(|| format!("URL 模板缺少 }}}}: {}", template))?;
+        let expression: String = rest[start + 2..end].split_whitespace().collect();
+        let value = match expression.as_str() {
+            "key" | "searchKey" => key.clone(),
+            page if page.starts_with("page") => {
+                let delta: i64 = match &page[4..] {
+                    "" => 0,
+                    offset => offset
+                        .parse()
+                        .with_context(|| format!("不支持的 URL 模板: {{{{{}}}}}", expression))?,
+                };
+                (i64::from(vars.page) + delta).to_string()
+            }
+            _ => bail!("不支持的 URL 模板（JavaScript）: {{{{{}}}}}", expression),
+        };
+        result.push_str(&value);
+        rest = &rest[end + 2..];
+    }
+    result.push_str(rest);
+
+    let result = result
+        .replace("searchKey", &key)
+        .replace("searchPage", &vars.page.to_string());
+    Ok(page_choices(&result, vars.page))
+}
+
+/// `<a,b,c>` 表示第 1、2、3 页分别使用 a、b、c，超出时沿用最后一项。
+fn page_choices(url: &str, page: u32) -> String {
+    let Some(start) = url.find('<') else {
+        return url.to_string();
+    };
+    let Some(end) = url[start..].find('>').map(|end| start + end) else {
+        return url.to_string();
+    };
+    let choices: Vec<&str> = url[start + 1..end].split(',').collect();
+    let index = (page.max(1) as usize - 1).min(choices.len() - 1);
+    format!(
+        "{}{}{}",
+        &url[..start],
+        choices[index].trim(),
+        &url[end + 1..]
+    )
+}
+
+fn encode_component(text: &str, encoding: &'static Encoding) -> String {
+    let (bytes, _, _) = encoding.encode(text);
+    let mut encoded = String::with_capacity(bytes.len() * 3);
+    for byte in bytes.iter() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(byte) {
+            encoded.push(char::from(*byte));
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn encode_body(body: &str, encoding: &'static Encoding) -> Vec<u8> {
+    encoding.encode(body).0.into_owned()
+}
+
+/// 书源的 `header` 字段是 JSON 字符串；以 `@js:` 开头的动态请求头不支持，直接忽略。
+fn parse_headers(header: &str) -> Vec<(String, String)> {
+    let header = header.trim();
+    if header.is_empty() || header.starts_with("@js:") || header.starts_with("<js>") {
+        return Vec::new();
+    }
+    let parsed = serde_json::from_str::<Value>(header)
+        .or_else(|_| serde_json::from_str::<Value>(&header.replace('\'', "\"")));
+    let Ok(Value::Object(map)) = parsed else {
+        return Vec::new();
+    };
+    map.into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::String(value) => value,
+                other => other.to_string(),
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    let lower = content_type.to_ascii_lowercase();
+    let label = lower[lower.find("charset=")? + "charset=".len()..]
+        .trim_matches(['"', '\'', ' '])
+        .split(';')
+        .next()?;
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+fn meta_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]).to_ascii_lowercase();
+    let rest = &head[head.find("charset=")? + "charset=".len()..];
+    let label: String = rest
+        .trim_start_matches(['"', '\''])
+        .chars()
+        .take_while(|ch| ch.is_ascii_alphanumeric() || *ch == '-' || *ch == '_')
+        .collect();
+    Encoding::for_label(label.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_post_request_with_gbk_key_and_headers() {
+        let request = build_request(
+            "/modules/article/search.php,{'method':'POST','body':'searchkey={{key}}&page={{page}}','charset':'gbk'}",
+            "https://www.example.com/",
+            Some(&SearchVars { key: "雪中", page: 2 }),
+            r#"{"Referer":"https://www.example.com/"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.url,
+            "https://www.example.com/modules/article/search.php"
+        );
+        assert!(request.post);
+        assert_eq!(
+            request.body.as_deref(),
+            Some("searchkey=%D1%A9%D6%D0&page=2")
+        );
+        assert_eq!(request.charset, Some(encoding_rs::GBK));
+        assert_eq!(
+            request.headers,
+            vec![(
+                "Referer".to_string(),
+                "https://www.example.com/".to_string()
+            )]
+        );
+
+        let request = build_request(
+            "https://api.example.com/s?q={{key}}&p={{page - 1}}&t=<new,old>",
+            "https://www.example.com/",
+            Some(&SearchVars {
+                key: "a b",
+                page: 3,
+            }),
+            "@js:java.getHeader()",
+        )
+        .unwrap();
+        assert_eq!(request.url, "https://api.example.com/s?q=a%20b&p=2&t=old");
+        assert!(!request.post && request.headers.is_empty());
+    }
+}
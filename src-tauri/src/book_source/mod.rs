@@ -0,0 +1,745 @@
+mod backup;
+mod http;
+mod rule;
+mod xpath;
+
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::library;
+use crate::novel;
+use crate::settings::write_atomic;
+use http::SearchVars;
+use rule::Page;
+
+pub use backup::{prepare_import, BackupReport};
+
+/// 目录翻页的上限，防止规则写错时在两页之间来回跳转。
+const MAX_TOC_PAGES: usize = 200;
+/// 单章正文分页的上限。
+const MAX_CONTENT_PAGES: usize = 50;
+
+/// 阅读（Legado）格式的书源，字段名与其导出的 JSON 一致。
+///
+/// 未用到的字段（发现页、登录等）保存在 `extra` 中，导出时原样写回。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BookSource {
+    pub book_source_url: String,
+    pub book_source_name: String,
+    pub book_source_group: String,
+    /// 0 为文字书源，音频、图片等类型不支持
+    pub book_source_type: i32,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub header: String,
+    pub search_url: String,
+    pub rule_search: SearchRule,
+    pub rule_book_info: BookInfoRule,
+    pub rule_toc: TocRule,
+    pub rule_content: ContentRule,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SearchRule {
+    pub book_list: String,
+    pub name: String,
+    pub author: String,
+    pub intro: String,
+    pub kind: String,
+    pub last_chapter: String,
+    pub book_url: String,
+    pub cover_url: String,
+    pub word_count: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BookInfoRule {
+    /// 先选出信息所在的区域，其余规则在该区域内执行
+    pub init: String,
+    pub name: String,
+    pub author: String,
+    pub intro: String,
+    pub kind: String,
+    pub last_chapter: String,
+    pub cover_url: String,
+    pub toc_url: String,
+    pub word_count: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TocRule {
+    pub chapter_list: String,
+    pub chapter_name: String,
+    pub chapter_url: String,
+    pub next_toc_url: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ContentRule {
+    pub content: String,
+    pub title: String,
+    pub next_content_url: String,
+    pub replace_regex: String,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// 搜索结果中的一本书。
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchBook {
+    pub source_url: String,
+    pub source_name: String,
+    pub name: String,
+    pub author: String,
+    pub intro: String,
+    pub kind: String,
+    pub last_chapter: String,
+    pub cover_url: String,
+    pub book_url: String,
+}
+
+/// 在线书籍的详情与目录，随章节缓存一起保存为 `book.json`。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OnlineBook {
+    pub source_url: String,
+    pub book_url: String,
+    pub toc_url: String,
+    pub name: String,
+    pub author: String,
+    pub intro: String,
+    pub kind: String,
+    pub cover_url: String,
+    pub last_chapter: String,
+    pub chapters: Vec<OnlineChapter>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OnlineChapter {
+    pub title: String,
+    /// 为空表示卷名等没有正文的目录项
+    pub url: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadReport {
+    pub dir: PathBuf,
+    pub downloaded: usize,
+    pub cached: usize,
+    /// 下载失败的章节名，下次打开时会重新尝试
+    pub failed: Vec<String>,
+}
+
+/// 解析书源 JSON：既可以是阅读导出的数组，也可以是单个书源对象。
+pub fn parse_sources(json: &str) -> Result<Vec<BookSource>> {
+    let value: Value =
+        serde_json::from_str(json.trim_start_matches('\u{feff}')).context("书源不是有效的 JSON")?;
+    let sources: Vec<BookSource> = match value {
+        Value::Array(_) => serde_json::from_value(value),
+        _ => serde_json::from_value(value).map(|source| vec![source]),
+    }
+    .context("无法识别的书源格式")?;
+    Ok(sources
+        .into_iter()
+        .filter(|source| !source.book_source_url.trim().is_empty())
+        .collect())
+}
+
+/// 按书源地址合并：已有的书源被覆盖，其余追加到末尾，返回合并的数量。
+pub fn merge_sources(sources: &mut Vec<BookSource>, imported: Vec<BookSource>) -> usize {
+    let count = imported.len();
+    for source in imported {
+        match sources
+            .iter_mut()
+            .find(|existing| existing.book_source_url == source.book_source_url)
+        {
+            Some(existing) => *existing = source,
+            None => sources.push(source),
+        }
+    }
+    count
+}
+
+pub fn load_sources(path: &Path) -> Vec<BookSource> {
+    let Ok(bytes) = fs::read(path) else {
+        return Vec::new();
+    };
+    match serde_json::from_slice::<Vec<BookSource>>(&bytes) {
+        Ok(sources) => sources,
+        Err(err) => {
+            eprintln!("书源文件已损坏，暂不加载 {}: {}", path.display(), err);
+            Vec::new()
+        }
+    }
+}
+
+/// 现有文件无法解析时拒绝保存：读取时已退回空列表，覆盖会丢掉用户的书源。
+pub fn save_sources(path: &Path, sources: &[BookSource]) -> Result<()> {
+    if let Ok(bytes) = fs::read(path) {
+        if let Err(err) = serde_json::from_slice::<Vec<BookSource>>(&bytes) {
+            bail!(
+                "书源文件已损坏，为免覆盖未保存，请修复或移走后重试: {}: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("创建书源目录失败: {}", parent.display()))?;
+    }
+    let data = serde_json::to_vec_pretty(sources)?;
+    write_atomic(path, &data).with_context(|| format!("写入书源失败: {}", path.display()))
+}
+
+pub fn default_sources_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("moyu-reader-sources.json")
+}
+
+/// 在线书籍的章节缓存目录，每本书一个子目录。
+pub fn default_cache_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("moyu-reader-online")
+}
+
+/// 按书源的搜索规则搜索书籍。
+pub fn search(source: &BookSource, key: &str, page: u32) -> Result<Vec<SearchBook>> {
+    check_supported(source)?;
+    if source.search_url.trim().is_empty() {
+        bail!("书源“{}”不支持搜索", source.book_source_name);
+    }
+    let request = http::build_request(
+        &source.search_url,
+        &source.book_source_url,
+        Some(&SearchVars { key, page }),
+        &source.header,
+    )?;
+    let response = http::fetch(&request)?;
+    let page = Page::parse(&response.body);
+    let root = page.root();
+    let rule = &source.rule_search;
+
+    let items = if rule.book_list.trim().is_empty() {
+        Vec::new()
+    } else {
+        rule::elements(&root, &rule.book_list)?
+    };
+    if items.is_empty() {
+        // 搜索结果唯一时不少网站会直接跳到详情页
+        let info = &source.rule_book_info;
+        let name = clean(&rule::string(&root, &info.name)?);
+        if name.is_empty() {
+            return Ok(Vec::new());
+        }
+        let author = clean(&rule::string(&root, &info.author)?);
+        return Ok(vec![SearchBook {
+            source_url: source.book_source_url.clone(),
+            source_name: source.book_source_name.clone(),
+            name,
+            author,
+            intro: rule::string(&root, &info.intro)?.trim().to_string(),
+            kind: clean(&rule::string(&root, &info.kind)?),
+            last_chapter: clean(&rule::string(&root, &info.last_chapter)?),
+            cover_url: optional_url(&response.url, &rule::string(&root, &info.cover_url)?),
+            book_url: response.url.clone(),
+        }]);
+    }
+
+    let mut books = Vec::new();
+    for item in &items {
+        let name = clean(&rule::string(item, &rule.name)?);
+        if name.is_empty() {
+            continue;
+        }
+        books.push(SearchBook {
+            source_url: source.book_source_url.clone(),
+            source_name: source.book_source_name.clone(),
+            name,
+            author: clean(&rule::string(item, &rule.author)?),
+            intro: rule::string(item, &rule.intro)?.trim().to_string(),
+            kind: clean(&rule::string(item, &rule.kind)?),
+            last_chapter: clean(&rule::string(item, &rule.last_chapter)?),
+            cover_url: optional_url(&response.url, &rule::string(item, &rule.cover_url)?),
+            book_url: http::absolute_url(&response.url, &rule::string(item, &rule.book_url)?),
+        });
+    }
+    Ok(books)
+}
+
+/// 同时在多个书源中搜索，返回合并的结果与各失败书源的错误。
+pub fn search_all(
+    sources: &[BookSource],
+    key: &str,
+) -> (Vec<SearchBook>, Vec<(String, anyhow::Error)>) {
+    let outcomes: Vec<Result<Vec<SearchBook>>> = thread::scope(|scope| {
+        let handles: Vec<_> = sources
+            .iter()
+            .map(|source| scope.spawn(move || search(source, key, 1)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("搜索线程异常退出")))
+            })
+            .collect()
+    });
+    let mut books = Vec::new();
+    let mut errors = Vec::new();
+    for (source, outcome) in sources.iter().zip(outcomes) {
+        match outcome {
+            Ok(found) => books.extend(found),
+            Err(err) => errors.push((source.book_source_name.clone(), err)),
+        }
+    }
+    (books, errors)
+}
+
+/// 读取书籍详情页；规则取不到的字段留空，由调用方用搜索结果补齐。
+pub fn book_info(source: &BookSource, book_url: &str) -> Result<OnlineBook> {
+    check_supported(source)?;
+    let response = get(source, book_url)?;
+    let page = Page::parse(&response.body);
+    let root = page.root();
+    let rule = &source.rule_book_info;
+    let scope = if rule.init.trim().is_empty() {
+        root
+    } else {
+        rule::elements(&root, &rule.init)?
+            .into_iter()
+            .next()
+            .unwrap_or(root)
+    };
+    let toc_url = rule::string(&scope, &rule.toc_url)?;
+    Ok(OnlineBook {
+        source_url: source.book_source_url.clone(),
+        book_url: book_url.to_string(),
+        toc_url: http::absolute_url(&response.url, &toc_url),
+        name: clean(&rule::string(&scope, &rule.name)?),
+        author: clean(&rule::string(&scope, &rule.author)?),
+        intro: rule::string(&scope, &rule.intro)?.trim().to_string(),
+        kind: clean(&rule::string(&scope, &rule.kind)?),
+        cover_url: optional_url(&response.url, &rule::string(&scope, &rule.cover_url)?),
+        last_chapter: clean(&rule::string(&scope, &rule.last_chapter)?),
+        chapters: Vec::new(),
+    })
+}
+
+/// 读取目录，跟随 `nextTocUrl` 翻页，结果写入 `book.chapters`。
+pub fn load_toc(source: &BookSource, book: &mut OnlineBook) -> Result<()> {
+    let rule = &source.rule_toc;
+    if rule.chapter_list.trim().is_empty() {
+        bail!("书源“{}”缺少目录规则", source.book_source_name);
+    }
+    let first = if book.toc_url.is_empty() {
+        book.book_url.clone()
+    } else {
+        book.toc_url.clone()
+    };
+    let mut queue = VecDeque::from([first.clone()]);
+    let mut visited = HashSet::from([first]);
+    let mut chapters = Vec::new();
+    let mut pages = 0;
+
+    while let Some(url) = queue.pop_front() {
+        pages += 1;
+        if pages > MAX_TOC_PAGES {
+            break;
+        }
+        let response = get(source, &url)?;
+        let page = Page::parse(&response.body);
+        let root = page.root();
+        for item in rule::elements(&root, &rule.chapter_list)? {
+            let title = clean(&rule::string(&item, &rule.chapter_name)?);
+            if title.is_empty() {
+                continue;
+            }
+            let chapter_url = rule::string(&item, &rule.chapter_url)?;
+            let url = if chapter_url.trim().is_empty() {
+                String::new()
+            } else {
+                http::absolute_url(&response.url, &chapter_url)
+            };
+            chapters.push(OnlineChapter { title, url });
+        }
+        if !rule.next_toc_url.trim().is_empty() {
+            for next in rule::strings(&root, &rule.next_toc_url)? {
+                let next = http::absolute_url(&response.url, &next);
+                if visited.insert(next.clone()) {
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    if chapters.is_empty() {
+        bail!("没有读取到目录: {}", book.name);
+    }
+    book.chapters = chapters;
+    Ok(())
+}
+
+/// 读取一章正文：跟随 `nextContentUrl` 合并分页，遇到下一章的地址即停止，最后执行净化规则。
+pub fn chapter_content(
+    source: &BookSource,
+    chapter: &OnlineChapter,
+    next_chapter_url: Option<&str>,
+) -> Result<String> {
+    let rule = &source.rule_content;
+    if rule.content.trim().is_empty() {
+        bail!("书源“{}”缺少正文规则", source.book_source_name);
+    }
+    let mut queue = VecDeque::from([chapter.url.clone()]);
+    let mut visited = HashSet::from([chapter.url.clone()]);
+    let mut parts = Vec::new();
+
+    while let Some(url) = queue.pop_front() {
+        if parts.len() >= MAX_CONTENT_PAGES {
+            break;
+        }
+        let response = get(source, &url)?;
+        let page = Page::parse(&response.body);
+        let root = page.root();
+        let content = rule::string(&root, &rule.content)?;
+        parts.push(if content.contains('<') {
+            novel::html_to_text(&content)
+        } else {
+            content
+        });
+        if rule.next_content_url.trim().is_empty() {
+            continue;
+        }
+        for next in rule::strings(&root, &rule.next_content_url)? {
+            let next = http::absolute_url(&response.url, &next);
+            if Some(next.as_str()) != next_chapter_url && visited.insert(next.clone()) {
+                queue.push_back(next);
+            }
+        }
+    }
+
+    let text = parts.join("\n");
+    let text = rule::apply_replace(&text, &rule.replace_regex);
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// 把整本书下载到 `cache_dir` 下的独立目录，每章一个文本文件，可直接按目录打开。
+///
+/// 已缓存的章节不再请求；目录变化后多余的旧章节文件会被删除。单章失败不会中断下载。
+pub fn download_book(
+    source: &BookSource,
+    book: &OnlineBook,
+    cache_dir: &Path,
+    on_progress: &mut dyn FnMut(usize, usize),
+) -> Result<DownloadReport> {
+    let dir = book_dir(cache_dir, book);
+    fs::create_dir_all(&dir).with_context(|| format!("创建缓存目录失败: {}", dir.display()))?;
+    let info = serde_json::to_vec_pretty(book)?;
+    write_atomic(&dir.join("book.json"), &info)
+        .with_context(|| format!("写入书籍信息失败: {}", dir.display()))?;
+
+    let mut report = DownloadReport {
+        dir: dir.clone(),
+        downloaded: 0,
+        cached: 0,
+        failed: Vec::new(),
+    };
+    let total = book.chapters.len();
+    let mut expected = HashSet::new();
+    for (index, chapter) in book.chapters.iter().enumerate() {
+        let name = chapter_file_name(index, &chapter.title);
+        let path = dir.join(&name);
+        expected.insert(name);
+        on_progress(index, total);
+
+        if chapter.url.is_empty() {
+            write_atomic(&path, b"")
+                .with_context(|| format!("写入章节失败: {}", path.display()))?;
+            continue;
+        }
+        if fs::metadata(&path).is_ok_and(|meta| meta.len() > 0) {
+            report.cached += 1;
+            continue;
+        }
+        let next_url = book.chapters[index + 1..]
+            .iter()
+            .find(|next| !next.url.is_empty())
+            .map(|next| next.url.as_str());
+        match chapter_content(source, chapter, next_url) {
+            Ok(text) => {
+                write_atomic(&path, text.as_bytes())
+                    .with_context(|| format!("写入章节失败: {}", path.display()))?;
+                report.downloaded += 1;
+            }
+            Err(err) => {
+                eprintln!("下载章节失败 {}: {:#}", chapter.title, err);
+                report.failed.push(chapter.title.clone());
+            }
+        }
+    }
+    on_progress(total, total);
+
+    for entry in fs::read_dir(&dir)?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // 目录中多出的旧章节，以及写入中断留下的临时文件
+        if (name.ends_with(".txt") && !expected.contains(&name)) || name.ends_with(".tmp") {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+    Ok(report)
+}
+
+/// 同一书源、同一本书总是落在同一个缓存目录。
+pub fn book_dir(cache_dir: &Path, book: &OnlineBook) -> PathBuf {
+    let key = format!("{}\n{}", book.source_url, book.book_url);
+    cache_dir.join(library::book_id(Path::new(&key)))
+}
+
+fn check_supported(source: &BookSource) -> Result<()> {
+    if source.book_source_type != 0 {
+        bail!("书源“{}”不是文字书源，暂不支持", source.book_source_name);
+    }
+    Ok(())
+}
+
+fn get(source: &BookSource, url: &str) -> Result<http::Response> {
+    let request = http::build_request(url, &source.book_source_url, None, &source.header)?;
+    http::fetch(&request)
+}
+
+fn optional_url(base: &str, url: &str) -> String {
+    if url.trim().is_empty() {
+        String::new()
+    } else {
+        http::absolute_url(base, url)
+    }
+}
+
+fn clean(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 章节文件名以五位序号开头保证顺序，打开时序号会从章节名中去掉。
+fn chapter_file_name(index: usize, title: &str) -> String {
+    format!("{:05} {}.txt", index + 1, library::safe_file_name(title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_http::{self, TestResponse};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// 按路径（忽略查询串）返回固定页面，并统计请求次数。
+    fn serve_pages(pages: Vec<(&'static str, String)>) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let base = test_http::serve(move |request| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            match pages.iter().find(|(path, _)| *path == request.path()) {
+                Some((_, body)) => TestResponse::ok("text/html; charset=utf-8", body.as_str()),
+                None => TestResponse::status(404),
+            }
+        });
+        (base, hits)
+    }
+
+    fn fixture_pages() -> Vec<(&'static str, String)> {
+        let page = |body: &str| format!("<html><body>{}</body></html>", body);
+        vec![
+            (
+                "/search",
+                page(
+                    r#"<ul class="list"><li><a class="name" href="/book/1/">雪中行</a><span class="author">作者：佚名</span></li>
+                       <li><a class="name" href="/book/2/">雪满山</a><span class="author">作者：无名</span></li></ul>"#,
+                ),
+            ),
+            (
+                "/book/1/",
+                page(
+                    r#"<div class="info"><h1>雪中行</h1><p class="author">作者：佚名</p><p class="intro">一个少年的故事。</p>
+                       <a class="toc" href="list/">目录</a></div>"#,
+                ),
+            ),
+            (
+                "/book/1/list/",
+                page(
+                    r#"<dl id="list"><dd><a href="../1.html">第一章 雪夜</a></dd><dd><a href="../2.html">第二章 归途</a></dd></dl>
+                       <a id="next" href="2">下一页</a>"#,
+                ),
+            ),
+            (
+                "/book/1/list/2",
+                page(
+                    r#"<dl id="list"><dd><a href="../3.html">第三章 重逢?</a></dd></dl><a id="next" href="/book/1/list/">上一页</a>"#,
+                ),
+            ),
+            (
+                "/book/1/1.html",
+                page(
+                    r#"<div id="content">　　那一夜的雪下得很大。<br>　　他独自上路。</div><a id="pager" href="1_2.html">下一页</a>"#,
+                ),
+            ),
+            (
+                "/book/1/1_2.html",
+                page(
+                    r#"<div id="content">　　天亮时，雪停了。</div><a id="pager" href="2.html">下一章</a>"#,
+                ),
+            ),
+            (
+                "/book/1/2.html",
+                page(
+                    r#"<div id="content">　　归途漫长。<br>本站广告：请收藏<br>　　终于到家。</div><a id="pager" href="3.html">下一章</a>"#,
+                ),
+            ),
+            (
+                "/book/1/3.html",
+                page(r#"<div id="content">　　故人相见。</div>"#),
+            ),
+        ]
+    }
+
+    fn fixture_source(base: &str) -> BookSource {
+        let json = format!(
+            r###"[{{
+                "bookSourceUrl": "{base}",
+                "bookSourceName": "测试书源",
+                "searchUrl": "/search?q={{{{key}}}}&page={{{{page}}}}",
+                "ruleSearch": {{
+                    "bookList": "class.list@tag.li",
+                    "name": "class.name@text",
+                    "author": "class.author@text##作者：",
+                    "bookUrl": "class.name@href"
+                }},
+                "ruleBookInfo": {{
+                    "init": "class.info",
+                    "name": "tag.h1@text",
+                    "author": "@css:.author@text##作者：",
+                    "intro": "class.intro@text",
+                    "tocUrl": "class.toc@href"
+                }},
+                "ruleToc": {{
+                    "chapterList": "id.list@tag.dd",
+                    "chapterName": "tag.a@text",
+                    "chapterUrl": "tag.a@href",
+                    "nextTocUrl": "id.next@href"
+                }},
+                "ruleContent": {{
+                    "content": "id.content@html",
+                    "nextContentUrl": "id.pager@href",
+                    "replaceRegex": "##本站广告[^\\n]*"
+                }},
+                "ruleExplore": {{"bookList": "class.rank"}}
+            }}]"###
+        );
+        let mut sources = parse_sources(&json).unwrap();
+        assert_eq!(sources.len(), 1);
+        assert!(sources[0].enabled);
+        sources.remove(0)
+    }
+
+    #[test]
+    fn searches_and_downloads_book_into_chapter_cache() {
+        let (base, hits) = serve_pages(fixture_pages());
+        let source = fixture_source(&base);
+
+        let mut broken = source.clone();
+        broken.book_source_name = "失效书源".to_string();
+        broken.search_url = "/missing?q={{key}}".to_string();
+        let (results, errors) = search_all(&[source.clone(), broken], "雪");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "失效书源");
+        let names: Vec<&str> = results.iter().map(|book| book.name.as_str()).collect();
+        assert_eq!(names, vec!["雪中行", "雪满山"]);
+        assert_eq!(results[1].author, "无名");
+        assert_eq!(results[0].book_url, format!("{}/book/1/", base));
+
+        let mut book = book_info(&source, &results[0].book_url).unwrap();
+        assert_eq!(book.author, "佚名");
+        assert_eq!(book.intro, "一个少年的故事。");
+        assert_eq!(book.toc_url, format!("{}/book/1/list/", base));
+        load_toc(&source, &mut book).unwrap();
+        let titles: Vec<&str> = book.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一章 雪夜", "第二章 归途", "第三章 重逢?"]);
+
+        let cache_dir =
+            std::env::temp_dir().join(format!("moyu-reader-online-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+        let mut progress = Vec::new();
+        let report = download_book(&source, &book, &cache_dir, &mut |done, total| {
+            progress.push((done, total))
+        })
+        .unwrap();
+        assert_eq!((report.downloaded, report.cached), (3, 0));
+        assert!(report.failed.is_empty());
+        assert_eq!(progress.last(), Some(&(3, 3)));
+
+        let loaded = novel::load_source(&report.dir).unwrap();
+        let titles: Vec<&str> = loaded.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一章 雪夜", "第二章 归途", "第三章 重逢？"]);
+        assert!(loaded
+            .text
+            .contains("那一夜的雪下得很大。\n他独自上路。\n天亮时，雪停了。\n"));
+        assert!(loaded.text.contains("归途漫长。\n终于到家。"));
+        assert!(!loaded.text.contains("广告"));
+        assert!(!loaded.text.contains("故人相见。\n\n第"));
+
+        let before = hits.load(Ordering::SeqCst);
+        let report = download_book(&source, &book, &cache_dir, &mut |_, _| {}).unwrap();
+        assert_eq!((report.downloaded, report.cached), (0, 3));
+        assert_eq!(hits.load(Ordering::SeqCst), before);
+
+        book.chapters.truncate(2);
+        download_book(&source, &book, &cache_dir, &mut |_, _| {}).unwrap();
+        assert_eq!(novel::load_source(&report.dir).unwrap().chapters.len(), 2);
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn refuses_to_overwrite_unreadable_sources_file() {
+        let dir = std::env::temp_dir().join(format!("moyu-reader-sources-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sources.json");
+        fs::write(&path, r#"[{"bookSourceUrl": "https://a.example""#).unwrap();
+
+        assert!(load_sources(&path).is_empty());
+        assert!(save_sources(&path, &[]).is_err());
+        assert!(fs::read_to_string(&path).unwrap().contains("a.example"));
+
+        fs::remove_file(&path).unwrap();
+        save_sources(&path, &[]).unwrap();
+        assert!(load_sources(&path).is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,742 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use scraper::{CaseSensitivity, ElementRef, Html, Node, Selector};
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+use super::xpath::{self, XValue};
+
+/// 解析后的页面：HTML（连同原始文本）或 JSON 接口返回值。
+pub enum Page {
+    Html(Html, String),
+    Json(Value),
+}
+
+impl Page {
+    pub fn parse(body: &str) -> Self {
+        let trimmed = body.trim_start();
+        if trimmed.starts_with(['{', '[']) {
+            if let Ok(value) = serde_json::from_str(trimmed) {
+                return Page::Json(value);
+            }
+        }
+        Page::Html(Html::parse_document(body), body.to_string())
+    }
+
+    pub fn root(&self) -> Content<'_> {
+        match self {
+            Page::Html(html, body) => Content::Page(html.root_element(), body),
+            Page::Json(value) => Content::Json(value),
+        }
+    }
+}
+
+/// 规则的作用对象：页面、列表规则选出的一项，或“:”开头的整页正则匹配出的分组。
+#[derive(Clone)]
+pub enum Content<'a> {
+    /// 整个网页；整页正则作用于原始文本，解析后再序列化会改变属性顺序
+    Page(ElementRef<'a>, &'a str),
+    Element(ElementRef<'a>),
+    Json(&'a Value),
+    Groups(Vec<String>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operator {
+    /// `&&`：合并各条规则的结果
+    And,
+    /// `||`：取第一条有结果的规则
+    Or,
+    /// `%%`：交替合并
+    Interleave,
+}
+
+/// 按列表规则选出多项；规则以 `-` 开头时结果倒序。
+pub fn elements<'a>(content: &Content<'a>, rule: &str) -> Result<Vec<Content<'a>>> {
+    let rule = rule.trim();
+    let (reverse, rule) = match rule.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, rule.strip_prefix('+').unwrap_or(rule)),
+    };
+    let (parts, operator) = split_operators(rule);
+    let mut groups = Vec::new();
+    for part in parts {
+        let items = single_elements(content, part.trim())?;
+        let found = !items.is_empty();
+        groups.push(items);
+        if operator == Operator::Or && found {
+            break;
+        }
+    }
+    let mut items = combine(groups, operator);
+    if reverse {
+        items.reverse();
+    }
+    Ok(items)
+}
+
+/// 取字符串结果，多个结果以换行连接，再执行 `##正则##替换`。
+pub fn string(content: &Content, rule: &str) -> Result<String> {
+    let (rule, replace) = split_replace(rule);
+    let text = raw_strings(content, rule)?.join("\n");
+    Ok(match replace {
+        Some(replace) => replace.apply(&text),
+        None => text,
+    })
+}
+
+/// 取多个字符串结果（如下一页地址），每项分别执行替换。
+pub fn strings(content: &Content, rule: &str) -> Result<Vec<String>> {
+    let (rule, replace) = split_replace(rule);
+    let values = raw_strings(content, rule)?;
+    Ok(match replace {
+        Some(replace) => values.iter().map(|value| replace.apply(value)).collect(),
+        None => values,
+    })
+}
+
+/// 对文本执行 `##正则##替换` 形式的净化规则，可以有多段。
+pub fn apply_replace(text: &str, rule: &str) -> String {
+    let rule = rule.trim();
+    if rule.is_empty() {
+        return text.to_string();
+    }
+    let rule = if rule.starts_with("##") {
+        rule.to_string()
+    } else {
+        format!("##{}", rule)
+    };
+    match split_replace(&rule).1 {
+        Some(replace) => replace.apply(text),
+        None => text.to_string(),
+    }
+}
+
+fn raw_strings(content: &Content, rule: &str) -> Result<Vec<String>> {
+    let rule = rule.trim();
+    if rule.is_empty() {
+        return Ok(Vec::new());
+    }
+    if rule.contains("{{") && rule.contains("}}") {
+        return Ok(vec![fill_template(content, rule)?]);
+    }
+    let (parts, operator) = split_operators(rule);
+    let mut groups = Vec::new();
+    for part in parts {
+        let values: Vec<String> = single_strings(content, part.trim())?
+            .into_iter()
+            .filter(|value| !value.is_empty())
+            .collect();
+        let found = !values.is_empty();
+        groups.push(values);
+        if operator == Operator::Or && found {
+            break;
+        }
+    }
+    Ok(combine(groups, operator))
+}
+
+/// `{{规则}}` 嵌在普通文本中，如 `/book/{{$.id}}/`。
+fn fill_template(content: &Content, rule: &str) -> Result<String> {
+    let mut result = String::new();
+    let mut rest = rule;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .map(|end| start + end)
+            .with_context(|| format!("规则缺少 }}}}: {}", rule))?;
+        result.push_str(&string(content, &rest[start + 2..end])?);
+        rest = &rest[end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn combine<T>(groups: Vec<Vec<T>>, operator: Operator) -> Vec<T> {
+    match operator {
+        Operator::And => groups.into_iter().flatten().collect(),
+        Operator::Or => groups
+            .into_iter()
+            .find(|group| !group.is_empty())
+            .unwrap_or_default(),
+        Operator::Interleave => {
+            let mut iterators: Vec<_> = groups.into_iter().map(Vec::into_iter).collect();
+            let mut result = Vec::new();
+            loop {
+                let before = result.len();
+                for iterator in iterators.iter_mut() {
+                    result.extend(iterator.next());
+                }
+                if result.len() == before {
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+fn split_operators(rule: &str) -> (Vec<&str>, Operator) {
+    for (separator, operator) in [
+        ("&&", Operator::And),
+        ("||", Operator::Or),
+        ("%%", Operator::Interleave),
+    ] {
+        if rule.contains(separator) {
+            return (rule.split(separator).collect(), operator);
+        }
+    }
+    (vec![rule], Operator::And)
+}
+
+struct Replace {
+    pattern: String,
+    replacement: String,
+    first_only: bool,
+}
+
+impl Replace {
+    fn apply(&self, text: &str) -> String {
+        // Java 正则中的前后断言等写法不受支持，无法编译时保持原文
+        let Ok(regex) = Regex::new(&self.pattern) else {
+            return text.to_string();
+        };
+        let replacement = java_replacement(&self.replacement);
+        if self.first_only {
+            match regex.find(text) {
+                Some(found) => regex
+                    .replace(found.as_str(), replacement.as_str())
+                    .into_owned(),
+                None => String::new(),
+            }
+        } else {
+            regex.replace_all(text, replacement.as_str()).into_owned()
+        }
+    }
+}
+
+/// Java 的 `$1` 紧跟字母数字时在 Rust 中会被当作命名分组，统一改写为 `${1}`。
+fn java_replacement(replacement: &str) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '$' && chars.peek().is_some_and(char::is_ascii_digit) {
+            let mut digits = String::new();
+            while let Some(digit) = chars.peek().copied().filter(char::is_ascii_digit) {
+                digits.push(digit);
+                chars.next();
+            }
+            result.push_str(&format!("${{{}}}", digits));
+        } else if ch == '$' {
+            result.push_str("$$");
+        } else {
+            result.push(ch);
+        }
+    }
+    result
+}
+
+/// 拆出规则末尾的 `##正则##替换###`；三个 `#` 结尾表示只取第一个匹配并替换。
+fn split_replace(rule: &str) -> (&str, Option<Replace>) {
+    let Some(index) = rule.find("##") else {
+        return (rule, None);
+    };
+    let mut tail = &rule[index + 2..];
+    let first_only = tail.ends_with("###");
+    if first_only {
+        tail = &tail[..tail.len() - 3];
+    }
+    let (pattern, replacement) = tail.split_once("##").unwrap_or((tail, ""));
+    (
+        &rule[..index],
+        Some(Replace {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            first_only,
+        }),
+    )
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Default,
+    Css,
+    XPath,
+    Json,
+    Regex,
+}
+
+fn mode<'r>(content: &Content, rule: &'r str) -> Result<(Mode, &'r str)> {
+    let lower = rule.get(..7).unwrap_or(rule).to_ascii_lowercase();
+    if lower.starts_with("@js:") || lower.starts_with("<js>") {
+        bail!("书源规则包含 JavaScript，暂不支持: {}", rule);
+    }
+    Ok(if lower.starts_with("@css:") {
+        (Mode::Css, &rule[5..])
+    } else if lower.starts_with("@xpath:") {
+        (Mode::XPath, &rule[7..])
+    } else if lower.starts_with("@json:") {
+        (Mode::Json, &rule[6..])
+    } else if rule.starts_with('/') {
+        (Mode::XPath, rule)
+    } else if rule.starts_with("$.") || rule.starts_with("$[") {
+        (Mode::Json, rule)
+    } else if let Some(pattern) = rule.strip_prefix(':') {
+        (Mode::Regex, pattern)
+    } else if matches!(content, Content::Json(_)) {
+        (Mode::Json, rule)
+    } else {
+        (Mode::Default, rule)
+    })
+}
+
+fn single_elements<'a>(content: &Content<'a>, rule: &str) -> Result<Vec<Content<'a>>> {
+    if rule.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (mode, rule) = mode(content, rule)?;
+    let page = as_element(content);
+    let content = if mode == Mode::Regex { content } else { &page };
+    match (mode, content) {
+        (Mode::Json, Content::Json(value)) => Ok(json_query(value, rule)?
+            .into_iter()
+            .flat_map(|value| match value {
+                // `$.list` 直接选中数组时展开为各项
+                Value::Array(items) => items.iter().collect(),
+                other => vec![other],
+            })
+            .map(Content::Json)
+            .collect()),
+        (Mode::Regex, _) => {
+            let source = content_source(content);
+            let regex = Regex::new(rule).with_context(|| format!("无效的正则规则: {}", rule))?;
+            Ok(regex
+                .captures_iter(&source)
+                .map(|captures| {
+                    Content::Groups(
+                        captures
+                            .iter()
+                            .map(|group| group.map(|g| g.as_str().to_string()).unwrap_or_default())
+                            .collect(),
+                    )
+                })
+                .collect())
+        }
+        (Mode::Css, Content::Element(element)) => Ok(css_select(*element, rule)?
+            .into_iter()
+            .map(Content::Element)
+            .collect()),
+        (Mode::XPath, Content::Element(element)) => Ok(xpath::evaluate(*element, rule)?
+            .into_iter()
+            .filter_map(|value| match value {
+                XValue::Element(element) => Some(Content::Element(element)),
+                XValue::Text(_) => None,
+            })
+            .collect()),
+        (Mode::Default, Content::Element(element)) => {
+            let mut current = vec![*element];
+            for segment in rule.split('@').filter(|segment| !segment.trim().is_empty()) {
+                let mut next = Vec::new();
+                for element in current {
+                    next.extend(select_segment(element, segment.trim())?);
+                }
+                current = next;
+            }
+            Ok(current.into_iter().map(Content::Element).collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+fn single_strings(content: &Content, rule: &str) -> Result<Vec<String>> {
+    if let Content::Groups(groups) = content {
+        return Ok(vec![substitute_groups(rule, groups)]);
+    }
+    let (mode, rule) = mode(content, rule)?;
+    match (mode, &as_element(content)) {
+        (Mode::Json, Content::Json(value)) => Ok(json_query(value, rule)?
+            .into_iter()
+            .flat_map(json_strings)
+            .collect()),
+        (Mode::Regex, _) => Ok(single_elements(content, &format!(":{}", rule))?
+            .into_iter()
+            .filter_map(|item| match item {
+                Content::Groups(groups) => groups.into_iter().next(),
+                _ => None,
+            })
+            .collect()),
+        (Mode::Css, Content::Element(element)) => {
+            let (selector, getter) = split_getter(rule);
+            let elements = if selector.is_empty() {
+                vec![*element]
+            } else {
+                css_select(*element, selector)?
+            };
+            Ok(elements.into_iter().map(|e| get(e, getter)).collect())
+        }
+        (Mode::XPath, Content::Element(element)) => Ok(xpath::evaluate(*element, rule)?
+            .into_iter()
+            .map(|value| match value {
+                XValue::Element(element) => element_text(element),
+                XValue::Text(text) => text,
+            })
+            .collect()),
+        (Mode::Default, Content::Element(element)) => {
+            let (path, getter) = split_getter(rule);
+            let elements = if path.is_empty() {
+                vec![Content::Element(*element)]
+            } else {
+                single_elements(content, path)?
+            };
+            Ok(elements
+                .into_iter()
+                .filter_map(|item| match item {
+                    Content::Element(element) => Some(get(element, getter)),
+                    _ => None,
+                })
+                .collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// 最后一个 `@` 之后是取值方式：text、ownText、textNodes、html、all 或属性名。
+fn split_getter(rule: &str) -> (&str, &str) {
+    match rule.rfind('@') {
+        Some(index) => (rule[..index].trim(), rule[index + 1..].trim()),
+        None => ("", rule.trim()),
+    }
+}
+
+fn get(element: ElementRef, getter: &str) -> String {
+    match getter {
+        "text" => element_text(element),
+        "ownText" => collapse_whitespace(&own_texts(element).join("")),
+        "textNodes" => own_texts(element)
+            .iter()
+            .map(|text| text.trim())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        "html" | "all" => element.html(),
+        attribute => element.attr(attribute).unwrap_or("").trim().to_string(),
+    }
+}
+
+fn element_text(element: ElementRef) -> String {
+    collapse_whitespace(&element.text().collect::<String>())
+}
+
+fn own_texts(element: ElementRef) -> Vec<String> {
+    element
+        .children()
+        .filter_map(|child| match child.value() {
+            Node::Text(text) => Some(text.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 与 Jsoup 一致，选择结果包含元素自身。
+fn css_select<'a>(element: ElementRef<'a>, selector: &str) -> Result<Vec<ElementRef<'a>>> {
+    let parsed = Selector::parse(selector)
+        .map_err(|err| anyhow::anyhow!("无效的 CSS 选择器 {}: {:?}", selector, err))?;
+    let mut elements = Vec::new();
+    if parsed.matches(&element) {
+        elements.push(element);
+    }
+    elements.extend(element.select(&parsed));
+    Ok(elements)
+}
+
+/// 阅读默认语法中的一段：`class.名称`、`id.名称`、`tag.名称`、`text.文字`、`children`，
+/// 后接 `.0`、`.-1`、`.0:2`（取下标）、`!0`（排除下标）或 `[1:3]`、`[!0,2]`；其余按 CSS 选择器处理。
+fn select_segment<'a>(element: ElementRef<'a>, segment: &str) -> Result<Vec<ElementRef<'a>>> {
+    let (segment, bracket) = match (segment.rfind('['), segment.ends_with(']')) {
+        (Some(start), true) if start > 0 => (
+            &segment[..start],
+            Some(&segment[start + 1..segment.len() - 1]),
+        ),
+        _ => (segment, None),
+    };
+    let mut parts = segment.splitn(3, '.');
+    let kind = parts.next().unwrap_or("");
+    let (name, index_spec) = match kind {
+        "class" | "id" | "tag" | "text" => {
+            let name = parts.next().unwrap_or("");
+            let rest = parts.next();
+            match name.split_once('!') {
+                Some((name, excluded)) => (name, Some(format!("!{}", excluded))),
+                None => (name, rest.map(str::to_string)),
+            }
+        }
+        "children" => ("", parts.next().map(str::to_string)),
+        _ => return css_select(element, segment),
+    };
+
+    let candidates: Vec<ElementRef<'a>> = match kind {
+        "children" => element.children().filter_map(ElementRef::wrap).collect(),
+        _ => element
+            .descendants()
+            .filter_map(ElementRef::wrap)
+            .filter(|candidate| match kind {
+                "class" => name.split_whitespace().all(|class| {
+                    candidate
+                        .value()
+                        .has_class(class, CaseSensitivity::CaseSensitive)
+                }),
+                "id" => candidate.value().id() == Some(name),
+                "tag" => candidate.value().name().eq_ignore_ascii_case(name),
+                _ => own_texts(*candidate).iter().any(|text| text.contains(name)),
+            })
+            .collect(),
+    };
+
+    let spec = bracket
+        .map(IndexSpec::bracket)
+        .or_else(|| index_spec.as_deref().map(IndexSpec::dotted))
+        .transpose()?;
+    Ok(match spec {
+        Some(spec) => spec.select(candidates),
+        None => candidates,
+    })
+}
+
+struct IndexSpec {
+    exclude: bool,
+    /// 闭区间，可为负数（从末尾计）
+    ranges: Vec<(i64, i64)>,
+}
+
+impl IndexSpec {
+    /// 旧写法：`0:2:5` 为下标列表，`!0:1` 为排除列表。
+    fn dotted(spec: &str) -> Result<Self> {
+        let (exclude, list) = match spec.strip_prefix('!') {
+            Some(list) => (true, list),
+            None => (false, spec),
+        };
+        let ranges = list
+            .split(':')
+            .map(|index| {
+                let index: i64 = index
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("无效的下标: {}", spec))?;
+                Ok((index, index))
+            })
+            .collect::<Result<_>>()?;
+        Ok(IndexSpec { exclude, ranges })
+    }
+
+    /// 新写法：`[0,2]`、`[1:3]`（闭区间）、`[!0]`、`[-1]`。
+    fn bracket(spec: &str) -> Result<Self> {
+        let (exclude, list) = match spec.trim().strip_prefix('!') {
+            Some(list) => (true, list),
+            None => (false, spec.trim()),
+        };
+        let parse = |text: &str| -> Result<i64> {
+            text.trim()
+                .parse()
+                .with_context(|| format!("无效的下标: [{}]", spec))
+        };
+        let ranges = list
+            .split(',')
+            .map(|item| match item.split_once(':') {
+                Some((start, end)) => Ok((parse(start)?, parse(end)?)),
+                None => parse(item).map(|index| (index, index)),
+            })
+            .collect::<Result<_>>()?;
+        Ok(IndexSpec { exclude, ranges })
+    }
+
+    fn select<T: Clone>(&self, items: Vec<T>) -> Vec<T> {
+        let len = items.len() as i64;
+        let resolve = |index: i64| if index < 0 { len + index } else { index };
+        let mut indices: Vec<usize> = Vec::new();
+        for &(start, end) in &self.ranges {
+            let (start, end) = (resolve(start), resolve(end));
+            let range: Vec<i64> = if start <= end {
+                (start..=end).collect()
+            } else {
+                (end..=start).rev().collect()
+            };
+            indices.extend(
+                range
+                    .into_iter()
+                    .filter(|index| (0..len).contains(index))
+                    .map(|index| index as usize),
+            );
+        }
+        if self.exclude {
+            items
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| !indices.contains(index))
+                .map(|(_, item)| item)
+                .collect()
+        } else {
+            indices
+                .into_iter()
+                .map(|index| items[index].clone())
+                .collect()
+        }
+    }
+}
+
+fn json_query<'a>(value: &'a Value, path: &str) -> Result<Vec<&'a Value>> {
+    let path = path.trim();
+    let path = if path.starts_with('$') {
+        path.to_string()
+    } else {
+        format!("$.{}", path)
+    };
+    let parsed = JsonPath::parse(&path).with_context(|| format!("无效的 JSONPath: {}", path))?;
+    Ok(parsed.query(value).all())
+}
+
+fn json_strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::Null => Vec::new(),
+        Value::String(text) => vec![text.clone()],
+        Value::Array(items) => items.iter().flat_map(json_strings).collect(),
+        other => vec![other.to_string()],
+    }
+}
+
+/// 整页正则的取值规则中以 `$1`、`$2` 引用分组，其余文字原样保留。
+fn substitute_groups(rule: &str, groups: &[String]) -> String {
+    let mut result = String::new();
+    let mut chars = rule.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '$' && chars.peek().is_some_and(char::is_ascii_digit) {
+            let mut digits = String::new();
+            while let Some(digit) = chars.peek().copied().filter(char::is_ascii_digit) {
+                digits.push(digit);
+                chars.next();
+            }
+            let index: usize = digits.parse().unwrap_or(0);
+            result.push_str(groups.get(index).map(String::as_str).unwrap_or(""));
+        } else {
+            result.push(ch);
+        }
+    }
+    result.trim().to_string()
+}
+
+/// 除整页正则外，各种规则都把页面当作根元素处理。
+fn as_element<'a>(content: &Content<'a>) -> Content<'a> {
+    match content {
+        Content::Page(element, _) => Content::Element(*element),
+        other => other.clone(),
+    }
+}
+
+fn content_source(content: &Content) -> String {
+    match content {
+        Content::Page(_, source) => source.to_string(),
+        Content::Element(element) => element.html(),
+        Content::Json(value) => value.to_string(),
+        Content::Groups(groups) => groups.first().cloned().unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><body>
+<div class="result-list">
+  <div class="result-item"><h3><a href="/book/1/" title="雪中行">雪中行</a></h3>
+    <p class="author">作者：<span>佚名</span></p><p class="intro">  一个 少年的  故事  </p></div>
+  <div class="result-item"><h3><a href="/book/2/">雪满山</a></h3>
+    <p class="author">作者：<span>无名</span></p></div>
+</div>
+<ul id="toc"><li>卷一</li><li><a href="1.html">第一章</a></li><li><a href="2.html">第二章</a></li></ul>
+<div id="content">第一段<br>第二段<script>ad()</script></div>
+</body></html>"#;
+
+    #[test]
+    fn evaluates_default_css_xpath_and_regex_rules() {
+        let page = Page::parse(PAGE);
+        let root = page.root();
+
+        let items = elements(&root, "class.result-item").unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(string(&items[0], "tag.h3@tag.a@text").unwrap(), "雪中行");
+        assert_eq!(string(&items[0], "tag.a.0@href").unwrap(), "/book/1/");
+        assert_eq!(
+            string(&items[1], "class.author@tag.span@text").unwrap(),
+            "无名"
+        );
+        assert_eq!(
+            string(&items[0], "class.author@ownText##作者：").unwrap(),
+            ""
+        );
+        assert_eq!(
+            string(&items[0], "class.intro@text").unwrap(),
+            "一个 少年的 故事"
+        );
+        assert_eq!(
+            string(&items[1], "class.intro@text||tag.a@text").unwrap(),
+            "雪满山"
+        );
+
+        let chapters = elements(&root, "id.toc@tag.li!0").unwrap();
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(string(&chapters[1], "a@href").unwrap(), "2.html");
+        let reversed = elements(&root, "-id.toc@tag.li[1:2]").unwrap();
+        assert_eq!(string(&reversed[0], "text").unwrap(), "第二章");
+        assert_eq!(
+            strings(&root, "@css:#toc a@text").unwrap(),
+            vec!["第一章", "第二章"]
+        );
+        assert_eq!(
+            string(&root, "//div[@class='result-item'][2]//span/text()").unwrap(),
+            "无名"
+        );
+        assert_eq!(
+            string(
+                &root,
+                "@css:.result-item:first-child h3 a@href&&tag.a.1@href##/book/(\\d+)/##$1号"
+            )
+            .unwrap(),
+            "1号\n2号"
+        );
+        assert!(string(&root, "id.content@html")
+            .unwrap()
+            .contains("第一段<br>第二段"));
+
+        let groups = elements(&root, r#":<a href="(/book/\d+/)"[^>]*>([^<]+)</a>"#).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(string(&groups[1], "$2").unwrap(), "雪满山");
+        assert_eq!(
+            string(&groups[1], "https://example.com$1").unwrap(),
+            "https://example.com/book/2/"
+        );
+
+        assert!(string(&root, "@js:result").is_err());
+    }
+
+    #[test]
+    fn evaluates_json_rules_and_templates() {
+        let page = Page::parse(
+            r#"{"code":0,"data":{"list":[{"id":7,"name":"雪中行","author":"佚名","tags":["武侠","江湖"]},{"id":8,"name":"雪满山","author":"无名"}]}}"#,
+        );
+        let root = page.root();
+        let items = elements(&root, "$.data.list[*]").unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(string(&items[0], "$.name").unwrap(), "雪中行");
+        assert_eq!(string(&items[0], "author").unwrap(), "佚名");
+        assert_eq!(string(&items[0], "$.tags").unwrap(), "武侠\n江湖");
+        assert_eq!(
+            string(&items[1], "/book/{{$.id}}/info").unwrap(),
+            "/book/8/info"
+        );
+        assert_eq!(elements(&root, "@json:$.data.list").unwrap().len(), 2);
+        assert_eq!(apply_replace("广告文字正文", "广告文字"), "正文");
+    }
+}
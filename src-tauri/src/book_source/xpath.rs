@@ -0,0 +1,461 @@
+use anyhow::{bail, Result};
+use scraper::{ElementRef, Node};
+
+/// XPath 求值结果：元素，或 `text()`/`@attr` 得到的字符串。
+#[derive(Clone)]
+pub enum XValue<'a> {
+    Element(ElementRef<'a>),
+    Text(String),
+}
+
+/// 书源中常见的 XPath 子集：`/`、`//`、`.`、`..`、`*`、`text()`、`@attr`、`|`，
+/// 谓词支持位置、`last()`、`@attr`、`text()`、`contains()`、`starts-with()`、`not()`
+/// 以及 `and`/`or` 组合。
+///
+/// 以 `/` 开头的路径相对于只含 `context` 一个元素的虚拟文档，与阅读对列表项的处理一致。
+pub fn evaluate<'a>(context: ElementRef<'a>, expression: &str) -> Result<Vec<XValue<'a>>> {
+    let mut results = Vec::new();
+    for path in split_top_level(expression, "|") {
+        results.extend(evaluate_path(context, path.trim())?);
+    }
+    Ok(results)
+}
+
+#[derive(Clone, Copy)]
+enum Context<'a> {
+    /// 虚拟文档节点，唯一的子元素是被包装的元素
+    Document(ElementRef<'a>),
+    Element(ElementRef<'a>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Axis {
+    Child,
+    Descendant,
+}
+
+fn evaluate_path<'a>(context: ElementRef<'a>, path: &str) -> Result<Vec<XValue<'a>>> {
+    if path.is_empty() {
+        bail!("XPath 为空");
+    }
+    let mut nodes = if path.starts_with('/') {
+        vec![Context::Document(context)]
+    } else {
+        vec![Context::Element(context)]
+    };
+    let mut rest = path;
+    let mut first = true;
+    while !rest.is_empty() {
+        let axis = if let Some(next) = rest.strip_prefix("//") {
+            rest = next;
+            Axis::Descendant
+        } else if let Some(next) = rest.strip_prefix('/') {
+            rest = next;
+            Axis::Child
+        } else if first {
+            Axis::Child
+        } else {
+            bail!("无法解析的 XPath: {}", path);
+        };
+        first = false;
+        let end = step_end(rest);
+        let step = rest[..end].trim();
+        rest = &rest[end..];
+
+        if step == "text()" {
+            return Ok(nodes
+                .iter()
+                .flat_map(|node| text_nodes(*node, axis))
+                .map(XValue::Text)
+                .collect());
+        }
+        if let Some(name) = step.strip_prefix('@') {
+            let mut values = Vec::new();
+            for node in &nodes {
+                let elements = match axis {
+                    Axis::Child => element_of(*node).into_iter().collect(),
+                    Axis::Descendant => descendants(*node, true),
+                };
+                for element in elements {
+                    if name == "*" {
+                        values.extend(element.value().attrs().map(|(_, v)| v.to_string()));
+                    } else if let Some(value) = element.attr(name) {
+                        values.push(value.to_string());
+                    }
+                }
+            }
+            return Ok(values.into_iter().map(XValue::Text).collect());
+        }
+
+        nodes = apply_step(&nodes, axis, step)?;
+    }
+    Ok(nodes
+        .into_iter()
+        .filter_map(element_of)
+        .map(XValue::Element)
+        .collect())
+}
+
+fn apply_step<'a>(nodes: &[Context<'a>], axis: Axis, step: &str) -> Result<Vec<Context<'a>>> {
+    if step == "." {
+        return Ok(nodes.to_vec());
+    }
+    if step == ".." {
+        return Ok(nodes
+            .iter()
+            .filter_map(|node| element_of(*node))
+            .filter_map(|element| element.parent().and_then(ElementRef::wrap))
+            .map(Context::Element)
+            .collect());
+    }
+    let (name, predicates) = match step.find('[') {
+        Some(index) => (&step[..index], parse_predicates(&step[index..])?),
+        None => (step, Vec::new()),
+    };
+    let name = name.trim().to_ascii_lowercase();
+
+    // `//x` 即 descendant-or-self::node()/child::x，位置谓词按各自的父节点分别计算
+    let parents: Vec<Context<'a>> = match axis {
+        Axis::Child => nodes.to_vec(),
+        Axis::Descendant => nodes
+            .iter()
+            .flat_map(|node| {
+                std::iter::once(*node)
+                    .chain(descendants(*node, false).into_iter().map(Context::Element))
+            })
+            .collect(),
+    };
+    let mut results: Vec<Context<'a>> = Vec::new();
+    for parent in parents {
+        let mut matched: Vec<ElementRef<'a>> = children(parent)
+            .into_iter()
+            .filter(|element| name == "*" || name == "node()" || element.value().name() == name)
+            .collect();
+        for predicate in &predicates {
+            let size = matched.len();
+            matched = matched
+                .into_iter()
+                .enumerate()
+                .filter(|(index, element)| test(*element, predicate, index + 1, size))
+                .map(|(_, element)| element)
+                .collect();
+        }
+        for element in matched {
+            if !results
+                .iter()
+                .any(|existing| element_of(*existing).is_some_and(|e| e.id() == element.id()))
+            {
+                results.push(Context::Element(element));
+            }
+        }
+    }
+    Ok(results)
+}
+
+fn element_of(node: Context) -> Option<ElementRef> {
+    match node {
+        Context::Document(_) => None,
+        Context::Element(element) => Some(element),
+    }
+}
+
+fn children(node: Context) -> Vec<ElementRef> {
+    match node {
+        Context::Document(root) => vec![root],
+        Context::Element(element) => element.children().filter_map(ElementRef::wrap).collect(),
+    }
+}
+
+/// `//` 选取的后代元素；`include_self` 用于 `//@attr` 这类自身也参与匹配的情形。
+fn descendants(node: Context, include_self: bool) -> Vec<ElementRef> {
+    match node {
+        Context::Document(root) => root.descendants().filter_map(ElementRef::wrap).collect(),
+        Context::Element(element) => element
+            .descendants()
+            .skip(usize::from(!include_self))
+            .filter_map(ElementRef::wrap)
+            .collect(),
+    }
+}
+
+fn text_nodes(node: Context, axis: Axis) -> Vec<String> {
+    let texts: Vec<String> = match (node, axis) {
+        (Context::Document(root), Axis::Child) => vec![root.text().collect()],
+        (Context::Element(element), Axis::Child) => element
+            .children()
+            .filter_map(|child| match child.value() {
+                Node::Text(text) => Some(text.to_string()),
+                _ => None,
+            })
+            .collect(),
+        (Context::Document(element) | Context::Element(element), Axis::Descendant) => {
+            element.text().map(str::to_string).collect()
+        }
+    };
+    texts
+        .into_iter()
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+        .collect()
+}
+
+/// 步骤在下一个不处于谓词或引号内的 `/` 处结束。
+fn step_end(rest: &str) -> usize {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    for (index, ch) in rest.char_indices() {
+        match (quote, ch) {
+            (Some(open), ch) if ch == open => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '[' | '(') => depth += 1,
+            (None, ']' | ')') => depth -= 1,
+            (None, '/') if depth == 0 => return index,
+            _ => {}
+        }
+    }
+    rest.len()
+}
+
+fn parse_predicates(text: &str) -> Result<Vec<String>> {
+    let mut predicates = Vec::new();
+    let mut rest = text.trim();
+    while let Some(body) = rest.strip_prefix('[') {
+        let mut depth = 1;
+        let mut quote: Option<char> = None;
+        let mut end = None;
+        for (index, ch) in body.char_indices() {
+            match (quote, ch) {
+                (Some(open), ch) if ch == open => quote = None,
+                (Some(_), _) => {}
+                (None, '\'' | '"') => quote = Some(ch),
+                (None, '[') => depth += 1,
+                (None, ']') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(index);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some(end) = end else {
+            bail!("XPath 谓词缺少 ]: {}", text);
+        };
+        predicates.push(body[..end].trim().to_string());
+        rest = body[end + 1..].trim_start();
+    }
+    if !rest.is_empty() {
+        bail!("无法解析的 XPath 谓词: {}", text);
+    }
+    Ok(predicates)
+}
+
+/// 按顶层（不在括号或引号内）的分隔符切分。
+fn split_top_level<'t>(text: &'t str, separator: &str) -> Vec<&'t str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    let mut index = 0;
+    while index < text.len() {
+        let ch = text[index..].chars().next().unwrap_or(' ');
+        match (quote, ch) {
+            (Some(open), ch) if ch == open => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '[' | '(') => depth += 1,
+            (None, ']' | ')') => depth -= 1,
+            (None, _) if depth == 0 && text[index..].starts_with(separator) => {
+                parts.push(&text[start..index]);
+                index += separator.len();
+                start = index;
+                continue;
+            }
+            _ => {}
+        }
+        index += ch.len_utf8();
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+fn test(element: ElementRef, predicate: &str, position: usize, size: usize) -> bool {
+    let predicate = predicate.trim();
+    if let Ok(index) = predicate.parse::<usize>() {
+        return position == index;
+    }
+    if let Some(rest) = predicate.strip_prefix("last()") {
+        let rest: String = rest.split_whitespace().collect();
+        let offset = rest
+            .strip_prefix('-')
+            .and_then(|offset| offset.parse::<usize>().ok())
+            .unwrap_or(0);
+        return size.checked_sub(offset) == Some(position);
+    }
+    let any = split_top_level(predicate, " or ");
+    if any.len() > 1 {
+        return any.iter().any(|part| test(element, part, position, size));
+    }
+    let all = split_top_level(predicate, " and ");
+    if all.len() > 1 {
+        return all.iter().all(|part| test(element, part, position, size));
+    }
+    if let Some(inner) = call_arguments(predicate, "not") {
+        return !test(element, inner, position, size);
+    }
+    for (function, check) in [
+        ("contains", (|a, b| a.contains(b)) as fn(&str, &str) -> bool),
+        ("starts-with", |a, b| a.starts_with(b)),
+    ] {
+        if let Some(arguments) = call_arguments(predicate, function) {
+            let arguments = split_top_level(arguments, ",");
+            if arguments.len() == 2 {
+                let haystack = value(element, arguments[0], position);
+                let needle = value(element, arguments[1], position);
+                return check(&haystack, &needle);
+            }
+            return false;
+        }
+    }
+    for operator in ["!=", "<=", ">=", "=", "<", ">"] {
+        let parts = split_top_level(predicate, operator);
+        if parts.len() == 2 {
+            let left = value(element, parts[0], position);
+            let right = value(element, parts[1], position);
+            let numbers = (left.trim().parse::<f64>(), right.trim().parse::<f64>());
+            return match (operator, numbers) {
+                ("=", _) => left == right,
+                ("!=", _) => left != right,
+                (_, (Ok(left), Ok(right))) => match operator {
+                    "<" => left < right,
+                    ">" => left > right,
+                    "<=" => left <= right,
+                    _ => left >= right,
+                },
+                _ => false,
+            };
+        }
+    }
+    // 只有路径时表示存在性判断
+    if let Some(name) = predicate.strip_prefix('@') {
+        return element.attr(name).is_some();
+    }
+    if predicate == "text()" {
+        return !own_text(element).trim().is_empty();
+    }
+    evaluate(element, predicate)
+        .map(|values| !values.is_empty())
+        .unwrap_or(false)
+}
+
+fn call_arguments<'t>(expression: &'t str, function: &str) -> Option<&'t str> {
+    expression
+        .strip_prefix(function)?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')
+}
+
+fn value(element: ElementRef, expression: &str, position: usize) -> String {
+    let expression = expression.trim();
+    if let Some(literal) = expression
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''))
+        .or_else(|| {
+            expression
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+        })
+    {
+        return literal.to_string();
+    }
+    if let Some(name) = expression.strip_prefix('@') {
+        return element.attr(name).unwrap_or("").to_string();
+    }
+    if let Some(inner) = call_arguments(expression, "normalize-space") {
+        let text = if inner.trim().is_empty() {
+            element.text().collect::<String>()
+        } else {
+            value(element, inner, position)
+        };
+        return text.split_whitespace().collect::<Vec<_>>().join(" ");
+    }
+    match expression {
+        "text()" => own_text(element),
+        "." | "string()" | "string(.)" => element.text().collect(),
+        "position()" => position.to_string(),
+        "name()" | "local-name()" => element.value().name().to_string(),
+        _ if expression.parse::<f64>().is_ok() => expression.to_string(),
+        _ => evaluate(element, expression)
+            .ok()
+            .and_then(|values| values.into_iter().next())
+            .map(|value| match value {
+                XValue::Element(element) => element.text().collect(),
+                XValue::Text(text) => text,
+            })
+            .unwrap_or_default(),
+    }
+}
+
+fn own_text(element: ElementRef) -> String {
+    element
+        .children()
+        .filter_map(|child| match child.value() {
+            Node::Text(text) => Some(text.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::Html;
+
+    fn texts(values: Vec<XValue>) -> Vec<String> {
+        values
+            .into_iter()
+            .map(|value| match value {
+                XValue::Element(element) => element.text().collect::<String>().trim().to_string(),
+                XValue::Text(text) => text,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn evaluates_common_book_source_paths() {
+        let html = Html::parse_document(
+            r#"<html><body><div id="list"><dl>
+<dt>最新章节</dt><dd><a href="/3.html">第三章</a></dd>
+<dt>正文</dt><dd><a href="/1.html">第一章</a></dd><dd><a href="/2.html" class="vip x">第二章</a></dd>
+</dl></div><p class="info">作者：<b>佚名</b></p></body></html>"#,
+        );
+        let root = html.root_element();
+        let eval = |path: &str| texts(evaluate(root, path).unwrap());
+
+        assert_eq!(
+            eval("//div[@id='list']//dd/a/@href"),
+            vec!["/3.html", "/1.html", "/2.html"]
+        );
+        assert_eq!(eval("//dd[last()]/a/text()"), vec!["第二章"]);
+        assert_eq!(eval("//dd[position()>1]/a"), vec!["第一章", "第二章"]);
+        assert_eq!(eval("//a[contains(@class,'vip')]/text()"), vec!["第二章"]);
+        assert_eq!(eval("//dt[text()='正文']"), vec!["正文"]);
+        assert_eq!(eval("//p[@class='info']/text()"), vec!["作者："]);
+        assert_eq!(
+            eval("//p/b/text() | //dt[1]/text()"),
+            vec!["佚名", "最新章节"]
+        );
+        assert_eq!(eval("/html/body/p/b"), vec!["佚名"]);
+
+        // 相对路径以列表项为起点
+        let item = evaluate(root, "//dd[2]").unwrap();
+        let XValue::Element(item) = item[0].clone() else {
+            panic!("应为元素");
+        };
+        assert_eq!(texts(evaluate(item, "a/@href").unwrap()), vec!["/1.html"]);
+        assert_eq!(texts(evaluate(item, "//a/text()").unwrap()), vec!["第一章"]);
+    }
+}
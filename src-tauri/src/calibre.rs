@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+
+use crate::library::{BookRecord, Library};
+
+/// 打开时的格式优先顺序：纯文本最忠实于原文，其次是 EPUB，其余按解析质量排列。
+const FORMATS: [&str; 13] = [
+    "txt", "epub", "fb2", "mobi", "azw3", "azw", "prc", "docx", "umd", "pdf", "html", "htm", "md",
+];
+
+/// Calibre 书库中的一本书。
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibreBook {
+    pub id: i64,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: f64,
+    pub tags: Vec<String>,
+    /// Calibre 中登记的全部格式（大写，如 `EPUB`）
+    pub formats: Vec<String>,
+    /// 可以打开的最佳格式文件，没有时为空
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedBook {
+    pub id: i64,
+    pub title: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibreImportReport {
+    pub imported: Vec<BookRecord>,
+    pub skipped: Vec<SkippedBook>,
+}
+
+/// 读取 Calibre 书库目录（含 `metadata.db` 与各书文件夹），按书名排序列出全部书籍。
+pub fn list_books(dir: &Path) -> Result<Vec<CalibreBook>> {
+    let db_path = dir.join("metadata.db");
+    if !db_path.is_file() {
+        bail!(
+            "不是 Calibre 书库目录（缺少 metadata.db）: {}",
+            dir.display()
+        );
+    }
+    // 只读打开，Calibre 正在运行时也不会改动它的数据库
+    let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("打开 Calibre 数据库失败: {}", db_path.display()))?;
+    read_books(&conn, dir).context("读取 Calibre 数据库失败")
+}
+
+fn read_books(conn: &Connection, dir: &Path) -> rusqlite::Result<Vec<CalibreBook>> {
+    let authors = linked_names(
+        conn,
+        "SELECT l.book, a.name FROM books_authors_link l JOIN authors a ON a.id = l.author ORDER BY l.id",
+    )?;
+    let tags = linked_names(
+        conn,
+        "SELECT l.book, t.name FROM books_tags_link l JOIN tags t ON t.id = l.tag ORDER BY t.name",
+    )?;
+    let series = linked_names(
+        conn,
+        "SELECT l.book, s.name FROM books_series_link l JOIN series s ON s.id = l.series",
+    )?;
+
+    let mut files: HashMap<i64, Vec<(String, String)>> = HashMap::new();
+    let mut statement = conn.prepare("SELECT book, format, name FROM data")?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })?;
+    for row in rows {
+        let (book, format, name) = row?;
+        files.entry(book).or_default().push((format, name));
+    }
+
+    let mut statement =
+        conn.prepare("SELECT id, title, path, series_index FROM books ORDER BY sort, id")?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<f64>>(3)?,
+        ))
+    })?;
+    let mut books = Vec::new();
+    for row in rows {
+        let (id, title, folder, series_index) = row?;
+        let files = files.remove(&id).unwrap_or_default();
+        books.push(CalibreBook {
+            id,
+            title,
+            authors: authors.get(&id).cloned().unwrap_or_default(),
+            series: series.get(&id).and_then(|names| names.first().cloned()),
+            series_index: series_index.unwrap_or(1.0),
+            tags: tags.get(&id).cloned().unwrap_or_default(),
+            formats: files.iter().map(|(format, _)| format.clone()).collect(),
+            path: best_format(&dir.join(&folder), &files),
+        });
+    }
+    Ok(books)
+}
+
+/// `sql` 返回（书籍 ID, 名称）两列，按书籍分组。
+fn linked_names(conn: &Connection, sql: &str) -> rusqlite::Result<HashMap<i64, Vec<String>>> {
+    let mut statement = conn.prepare(sql)?;
+    let rows = statement.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut names: HashMap<i64, Vec<String>> = HashMap::new();
+    for row in rows {
+        let (book, name) = row?;
+        names.entry(book).or_default().push(name);
+    }
+    Ok(names)
+}
+
+/// 格式文件位于 `书库/<books.path>/<data.name>.<小写格式>`，只选磁盘上确实存在的文件。
+fn best_format(folder: &Path, files: &[(String, String)]) -> Option<PathBuf> {
+    files
+        .iter()
+        .filter_map(|(format, name)| {
+            let ext = format.to_ascii_lowercase();
+            let rank = FORMATS.iter().position(|known| *known == ext)?;
+            let path = folder.join(format!("{}.{}", name, ext));
+            path.is_file().then_some((rank, path))
+        })
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, path)| path)
+}
+
+/// 已选好格式文件、尚未加入书库的 Calibre 书籍。
+pub struct PreparedImport {
+    /// 格式文件、书名与作者
+    books: Vec<(PathBuf, String, String)>,
+    skipped: Vec<SkippedBook>,
+}
+
+/// 为选中的书（`ids` 为空时为全部）挑选最佳格式，书名与作者取自 Calibre。
+///
+/// 要读取 Calibre 数据库并检查每个格式文件，不修改书库，可以在锁外执行，之后用
+/// [`PreparedImport::apply`] 加入书库。
+pub fn prepare_import(dir: &Path, ids: &[i64]) -> Result<PreparedImport> {
+    let mut prepared = PreparedImport {
+        books: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for book in list_books(dir)? {
+        if !ids.is_empty() && !ids.contains(&book.id) {
+            continue;
+        }
+        let Some(path) = book.path else {
+            let reason = if book.formats.is_empty() {
+                "没有书籍文件".to_string()
+            } else {
+                format!("没有可打开的格式（{}）", book.formats.join("、"))
+            };
+            prepared.skipped.push(SkippedBook {
+                id: book.id,
+                title: book.title,
+                reason,
+            });
+            continue;
+        };
+        prepared
+            .books
+            .push((path, book.title, book.authors.join("、")));
+    }
+    Ok(prepared)
+}
+
+impl PreparedImport {
+    /// 把挑好的书加入书库，已在书库中的书只更新书名与作者。
+    pub fn apply(self, library: &mut Library) -> CalibreImportReport {
+        let mut report = CalibreImportReport {
+            skipped: self.skipped,
+            ..CalibreImportReport::default()
+        };
+        for (path, title, author) in self.books {
+            let record = library.add(&path);
+            record.title = title;
+            record.author = author;
+            report.imported.push(record.clone());
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn lists_and_imports_books_preferring_txt() {
+        let dir = std::env::temp_dir().join(format!("moyu-reader-calibre-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for folder in ["佚名/雪中行 (1)", "无名/画册 (2)", "佚名/雨夜 (3)"] {
+            fs::create_dir_all(dir.join(folder)).unwrap();
+        }
+        fs::write(
+            dir.join("佚名/雪中行 (1)/雪中行 - 佚名.txt"),
+            "第一章 雪夜\n",
+        )
+        .unwrap();
+        fs::write(dir.join("佚名/雪中行 (1)/雪中行 - 佚名.epub"), "").unwrap();
+        fs::write(dir.join("无名/画册 (2)/画册 - 无名.cbz"), "").unwrap();
+        fs::write(dir.join("佚名/雨夜 (3)/雨夜 - 佚名.epub"), "").unwrap();
+
+        let conn = Connection::open(dir.join("metadata.db")).unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT, sort TEXT, path TEXT, series_index REAL);
+            CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER, author INTEGER);
+            CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER, tag INTEGER);
+            CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT);
+            CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER, series INTEGER);
+            CREATE TABLE data (id INTEGER PRIMARY KEY, book INTEGER, format TEXT, name TEXT);
+            INSERT INTO books VALUES (1, '雪中行', 'xue', '佚名/雪中行 (1)', 2.0),
+                                     (2, '画册', 'hua', '无名/画册 (2)', 1.0),
+                                     (3, '雨夜', 'yu', '佚名/雨夜 (3)', 1.0);
+            INSERT INTO authors VALUES (1, '佚名'), (2, '无名');
+            INSERT INTO books_authors_link VALUES (1, 1, 1), (2, 1, 2), (3, 2, 2), (4, 3, 1);
+            INSERT INTO tags VALUES (1, '武侠'), (2, '短篇');
+            INSERT INTO books_tags_link VALUES (1, 1, 2), (2, 1, 1);
+            INSERT INTO series VALUES (1, '四季');
+            INSERT INTO books_series_link VALUES (1, 1, 1);
+            INSERT INTO data VALUES (1, 1, 'EPUB', '雪中行 - 佚名'), (2, 1, 'TXT', '雪中行 - 佚名'),
+                                    (3, 2, 'CBZ', '画册 - 无名'), (4, 3, 'EPUB', '雨夜 - 佚名'),
+                                    (5, 3, 'TXT', '雨夜 - 佚名');
+            "#,
+        )
+        .unwrap();
+        drop(conn);
+
+        let books = list_books(&dir).unwrap();
+        let titles: Vec<&str> = books.iter().map(|book| book.title.as_str()).collect();
+        assert_eq!(titles, vec!["画册", "雪中行", "雨夜"]);
+        let snow = &books[1];
+        assert_eq!(snow.authors, vec!["佚名", "无名"]);
+        assert_eq!(snow.series.as_deref(), Some("四季"));
+        assert_eq!(snow.series_index, 2.0);
+        assert_eq!(snow.tags, vec!["武侠", "短篇"]);
+        assert_eq!(
+            snow.path.as_deref(),
+            Some(dir.join("佚名/雪中行 (1)/雪中行 - 佚名.txt").as_path())
+        );
+        // 登记了 TXT 但文件不在磁盘上时退回 EPUB
+        assert!(books[2]
+            .path
+            .as_ref()
+            .unwrap()
+            .ends_with("雨夜 - 佚名.epub"));
+
+        let mut library = Library::default();
+        let report = prepare_import(&dir, &[1, 2]).unwrap().apply(&mut library);
+        assert_eq!(report.imported.len(), 1);
+        assert_eq!(report.skipped[0].reason, "没有可打开的格式（CBZ）");
+        let record = &library.books[0];
+        assert_eq!(record.title, "雪中行");
+        assert_eq!(record.author, "佚名、无名");
+        assert_eq!(record.last_opened, 0);
+
+        assert!(list_books(&dir.join("佚名")).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,17 +1,43 @@
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use serde::Serialize;
 use tauri::{AppHandle, Emitter, Manager, State};
+use zeroize::Zeroizing;
 
 use crate::app_state::{AppState, StateSnapshot};
-use crate::novel::load_text;
+use crate::backup::{self, BackupContents, BackupInfo, Section};
+use crate::book_source::{self, BackupReport, BookSource, SearchBook};
+use crate::calibre::{self, CalibreBook, CalibreImportReport};
+use crate::kosync::{self, Positions, Progress};
+use crate::layout::{paginate, FontMetrics, LayoutOptions, PageLayout, Viewport};
+use crate::library::{now_secs, Bookmark};
+use crate::novel::{
+    self, load_document, ArchiveEntry, Chapter, PageMark, RuleReport, ScriptConversion,
+};
+use crate::opds::{self, Catalog, Entry, Feed, LibrarySource, OpdsServer, ServerStatus};
+use crate::profiles::{self, ProfileList};
+use crate::rules::{builtin_presets, ReplaceRule, RuleSet};
+use crate::search_index::{SearchHit, SearchIndex};
+use crate::sync::{self, ProgressConflict, SyncReport, SyncService};
 use crate::tray::TrayState;
+use crate::vault::{Keyring, LockStatus};
 
 #[derive(Serialize)]
 pub struct DocumentPayload {
     pub file_path: Option<String>,
     pub content: String,
     pub offset: usize,
+    pub toc: Vec<Chapter>,
+    pub pages: Vec<PageMark>,
+}
+
+#[derive(Serialize)]
+pub struct PaginationPayload {
+    pub page_starts: Vec<usize>,
+    pub current_page: usize,
+    pub total_pages: usize,
 }
 
 #[derive(Serialize)]
@@ -19,6 +45,12 @@ pub struct SettingsPayload {
     pub boss_key: String,
 }
 
+#[derive(Clone, Serialize)]
+pub struct DownloadProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
 fn snapshot_to_payload(snapshot: &StateSnapshot) -> DocumentPayload {
     DocumentPayload {
         file_path: snapshot
@@ -27,63 +59,208 @@ fn snapshot_to_payload(snapshot: &StateSnapshot) -> DocumentPayload {
             .map(|path| path.display().to_string()),
         content: snapshot.text.clone(),
         offset: snapshot.current_offset,
+        toc: snapshot.chapters.clone(),
+        pages: snapshot.pages.clone(),
     }
 }
 
 fn load_document_internal(state: &AppState, path_buf: PathBuf) -> Result<DocumentPayload, String> {
-    let text = load_text(&path_buf).map_err(|err| err.to_string())?;
+    load_document_at(state, path_buf, None)
+}
+
+/// 加载文档；`jump_to` 为空时恢复该书上次的阅读位置。
+///
+/// 保存的进度与 `jump_to` 均为原文偏移，需经 `offsets` 映射到展示文本。
+fn load_document_at(
+    state: &AppState,
+    path_buf: PathBuf,
+    jump_to: Option<usize>,
+) -> Result<DocumentPayload, String> {
+    let options = state.read().pipeline_options(&path_buf);
+    let document = load_document(&path_buf, &options).map_err(|err| err.to_string())?;
 
     let payload = {
         let mut guard = state.write();
         guard.file_path = Some(path_buf.clone());
-        guard.text = text;
+        guard.text = document.text;
+        guard.text_chars = guard.text.chars().count();
+        guard.offsets = document.offsets;
+        guard.chapters = document.chapters;
+        guard.pages = document.pages;
+        guard.layout = PageLayout::default();
         let same_file = guard
             .config
             .last_file
             .as_ref()
             .map(|p| p == &path_buf)
             .unwrap_or(false);
-        guard.current_offset = if same_file {
-            guard.config.last_offset.min(guard.text.len())
+        let saved_offset = if same_file {
+            Some(guard.config.last_offset)
         } else {
-            0
+            guard
+                .library
+                .find_by_path(&path_buf)
+                .map(|book| book.offset)
         };
+        let source_offset = jump_to.or(saved_offset).unwrap_or(0);
+        guard.current_offset = guard.clamp_offset(guard.offsets.to_display(source_offset));
+        let source_offset = guard.offsets.to_source(guard.current_offset);
+        let record = guard.library.touch(&path_buf);
+        record.set_offset(source_offset);
+        // FB2 等格式自带书名与作者，优先于文件名
+        if let Some(title) = document.meta.title {
+            record.title = title;
+        }
+        if let Some(author) = document.meta.author {
+            record.author = author;
+        }
         guard.config.last_file = Some(path_buf);
-        guard.config.last_offset = guard.current_offset;
+        guard.config.last_offset = source_offset;
         guard.config.last_page = 0;
 
-        DocumentPayload {
-            file_path: guard
-                .file_path
-                .as_ref()
-                .map(|path| path.display().to_string()),
-            content: guard.text.clone(),
-            offset: guard.current_offset,
-        }
+        snapshot_to_payload(&guard)
     };
 
     state
         .save_config()
         .map_err(|err| format!("保存配置失败: {}", err))?;
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
 
     Ok(payload)
 }
 
+/// 阅读设置或替换规则变化后，按新管线重新生成当前文档并保持阅读位置。
+fn reload_document_internal(state: &AppState) -> Result<Option<DocumentPayload>, String> {
+    let (path, options, source_offset) = {
+        let guard = state.read();
+        let Some(path) = guard.file_path.clone() else {
+            return Ok(None);
+        };
+        (
+            path.clone(),
+            guard.pipeline_options(&path),
+            guard.offsets.to_source(guard.current_offset),
+        )
+    };
+    let document = load_document(&path, &options).map_err(|err| err.to_string())?;
+
+    let mut guard = state.write();
+    guard.text = document.text;
+    guard.text_chars = guard.text.chars().count();
+    guard.offsets = document.offsets;
+    guard.chapters = document.chapters;
+    guard.pages = document.pages;
+    guard.layout = PageLayout::default();
+    guard.current_offset = guard.clamp_offset(guard.offsets.to_display(source_offset));
+    Ok(Some(snapshot_to_payload(&guard)))
+}
+
 fn update_progress_internal(state: &AppState, offset: usize) -> Result<(), String> {
     {
         let mut guard = state.write();
-        guard.current_offset = offset.min(guard.text.len());
-        guard.config.last_offset = guard.current_offset;
+        guard.current_offset = guard.clamp_offset(offset);
+        let source_offset = guard.offsets.to_source(guard.current_offset);
+        guard.config.last_offset = source_offset;
+        if guard.layout.total_pages() > 0 {
+            guard.config.last_page = guard.layout.page_of(guard.current_offset);
+        }
+        if let Some(path) = guard.file_path.clone() {
+            guard.library.update_offset(&path, source_offset);
+        }
     }
     state
         .save_config()
-        .map_err(|err| format!("保存配置失败: {}", err))
+        .map_err(|err| format!("保存配置失败: {}", err))?;
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))
+}
+
+fn paginate_internal(
+    state: &AppState,
+    metrics: Option<FontMetrics>,
+    viewport: Viewport,
+) -> Result<PaginationPayload, String> {
+    let mut guard = state.write();
+    if guard.text.is_empty() {
+        return Err("尚未加载任何文件".to_string());
+    }
+    let metrics =
+        metrics.unwrap_or_else(|| FontMetrics::from_font_size(guard.config.appearance.font_size));
+    let options = LayoutOptions::from_config(&guard.config);
+    let layout = paginate(&guard.text, &metrics, viewport, options);
+    let current_page = layout.page_of(guard.current_offset);
+    guard.config.last_page = current_page;
+
+    let payload = PaginationPayload {
+        page_starts: layout.page_starts.clone(),
+        current_page,
+        total_pages: layout.total_pages(),
+    };
+    guard.layout = layout;
+    Ok(payload)
+}
+
+fn schedule_indexing(state: &AppState, index: &SearchIndex) {
+    let snapshot = state.read();
+    let Some(path) = snapshot.file_path.as_ref() else {
+        return;
+    };
+    if let Some(book) = snapshot.library.find_by_path(path) {
+        index.schedule(vec![book.clone()]);
+    }
 }
 
 #[tauri::command]
-pub fn load_file(path: String, state: State<'_, AppState>) -> Result<DocumentPayload, String> {
+pub fn load_file(
+    path: String,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+) -> Result<DocumentPayload, String> {
     let path_buf = PathBuf::from(path);
-    load_document_internal(state.inner(), path_buf)
+    let payload = load_document_internal(state.inner(), path_buf)?;
+    schedule_indexing(state.inner(), index.inner());
+    Ok(payload)
+}
+
+/// 列出压缩包中的文本文件，包含多个时由用户选择要打开的一个。
+#[tauri::command]
+pub fn list_archive_entries(path: String) -> Result<Vec<ArchiveEntry>, String> {
+    novel::list_archive_entries(PathBuf::from(path)).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn paginate_document(
+    metrics: Option<FontMetrics>,
+    viewport: Viewport,
+    state: State<'_, AppState>,
+) -> Result<PaginationPayload, String> {
+    paginate_internal(state.inner(), metrics, viewport)
+}
+
+#[tauri::command]
+pub fn search_library(
+    query: String,
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+) -> Vec<SearchHit> {
+    let library = state.read().library.clone();
+    index.search(&library, &query, limit.unwrap_or(50))
+}
+
+#[tauri::command]
+pub fn open_search_hit(
+    path: String,
+    offset: usize,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+) -> Result<DocumentPayload, String> {
+    let payload = load_document_at(state.inner(), PathBuf::from(path), Some(offset))?;
+    schedule_indexing(state.inner(), index.inner());
+    Ok(payload)
 }
 
 #[tauri::command]
@@ -96,11 +273,1112 @@ pub fn current_document(state: State<'_, AppState>) -> Result<DocumentPayload, S
     }
 }
 
+/// 按书上印刷的页码跳转（PDF），返回该页起始的展示偏移。
+#[tauri::command]
+pub fn go_to_page(label: String, state: State<'_, AppState>) -> Result<usize, String> {
+    let guard = state.read();
+    if guard.pages.is_empty() {
+        return Err("当前书籍没有页码信息".to_string());
+    }
+    novel::find_page(&guard.pages, &label).ok_or_else(|| format!("找不到第 {} 页", label.trim()))
+}
+
 #[tauri::command]
 pub fn update_progress(offset: usize, state: State<'_, AppState>) -> Result<(), String> {
     update_progress_internal(state.inner(), offset)
 }
 
+fn emit_reloaded_document(state: &AppState, app: &AppHandle) -> Result<(), String> {
+    if let Some(payload) = reload_document_internal(state)? {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.emit("document-reloaded", payload);
+        }
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_rules(state: State<'_, AppState>) -> RuleSet {
+    state.read().rules.clone()
+}
+
+#[tauri::command]
+pub fn rule_presets() -> Vec<ReplaceRule> {
+    builtin_presets()
+}
+
+#[tauri::command]
+pub fn update_rules(
+    rules: RuleSet,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    state.write().rules = rules;
+    state
+        .save_rules()
+        .map_err(|err| format!("保存替换规则失败: {}", err))?;
+    emit_reloaded_document(state.inner(), &app)
+}
+
+/// 试运行规则；未传入 `rules` 时使用当前书籍已生效的规则。
+#[tauri::command]
+pub fn dry_run_rules(
+    rules: Option<Vec<ReplaceRule>>,
+    state: State<'_, AppState>,
+) -> Result<Vec<RuleReport>, String> {
+    let snapshot = state.read();
+    let Some(path) = snapshot.file_path.clone() else {
+        return Err("尚未加载任何文件".to_string());
+    };
+    let rules = rules.unwrap_or_else(|| snapshot.pipeline_options(&path).rules);
+    let reading = snapshot.config.reading.clone();
+    drop(snapshot);
+    novel::dry_run_rules(&path, &reading, &rules).map_err(|err| err.to_string())
+}
+
+/// 设置当前书籍的简繁转换目标，进度按原文偏移保存，切换后位置不变。
+#[tauri::command]
+pub fn set_book_conversion(
+    conversion: ScriptConversion,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<(), String> {
+    {
+        let mut guard = state.write();
+        let Some(path) = guard.file_path.clone() else {
+            return Err("尚未加载任何文件".to_string());
+        };
+        guard.library.touch(&path).conversion = conversion;
+    }
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+    emit_reloaded_document(state.inner(), &app)
+}
+
+#[tauri::command]
+pub fn get_book_sources(state: State<'_, AppState>) -> Vec<BookSource> {
+    state.read().sources.clone()
+}
+
+/// 导入阅读（Legado）格式的书源 JSON，地址相同的书源会被覆盖，返回导入数量。
+#[tauri::command]
+pub fn import_book_sources(json: String, state: State<'_, AppState>) -> Result<usize, String> {
+    let imported = book_source::parse_sources(&json).map_err(|err| err.to_string())?;
+    let count = book_source::merge_sources(&mut state.write().sources, imported);
+    state
+        .save_sources()
+        .map_err(|err| format!("保存书源失败: {}", err))?;
+    Ok(count)
+}
+
+#[tauri::command]
+pub fn set_book_source_enabled(
+    url: String,
+    enabled: bool,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    {
+        let mut guard = state.write();
+        let source = guard
+            .sources
+            .iter_mut()
+            .find(|source| source.book_source_url == url)
+            .ok_or_else(|| format!("找不到书源: {}", url))?;
+        source.enabled = enabled;
+    }
+    state
+        .save_sources()
+        .map_err(|err| format!("保存书源失败: {}", err))
+}
+
+#[tauri::command]
+pub fn delete_book_source(url: String, state: State<'_, AppState>) -> Result<(), String> {
+    state
+        .write()
+        .sources
+        .retain(|source| source.book_source_url != url);
+    state
+        .save_sources()
+        .map_err(|err| format!("保存书源失败: {}", err))
+}
+
+/// 在指定书源或全部启用的书源中搜索；部分书源失败时只记录日志，全部失败才报错。
+#[tauri::command(async)]
+pub fn search_online(
+    key: String,
+    source_url: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchBook>, String> {
+    let sources: Vec<BookSource> = state
+        .read()
+        .sources
+        .iter()
+        .filter(|source| match &source_url {
+            Some(url) => &source.book_source_url == url,
+            None => source.enabled,
+        })
+        .cloned()
+        .collect();
+    if sources.is_empty() {
+        return Err("没有可用的书源".to_string());
+    }
+    let (books, errors) = book_source::search_all(&sources, key.trim());
+    for (name, err) in &errors {
+        eprintln!("书源“{}”搜索失败: {:#}", name, err);
+    }
+    if errors.len() == sources.len() {
+        let (name, err) = &errors[0];
+        return Err(format!("书源“{}”搜索失败: {}", name, err));
+    }
+    Ok(books)
+}
+
+/// 读取在线书籍的详情与目录，下载未缓存的章节后按目录打开。
+///
+/// 下载进度通过 `online-download-progress` 事件通知，完成后发送 `online-download-finished`。
+#[tauri::command(async)]
+pub fn open_online_book(
+    source_url: String,
+    book_url: String,
+    name: Option<String>,
+    author: Option<String>,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+    app: AppHandle,
+) -> Result<DocumentPayload, String> {
+    let source = state
+        .read()
+        .sources
+        .iter()
+        .find(|source| source.book_source_url == source_url)
+        .cloned()
+        .ok_or_else(|| format!("找不到书源: {}", source_url))?;
+    let mut book = book_source::book_info(&source, &book_url).map_err(|err| err.to_string())?;
+    if book.name.is_empty() {
+        book.name = name.unwrap_or_default();
+    }
+    if book.author.is_empty() {
+        book.author = author.unwrap_or_default();
+    }
+    book_source::load_toc(&source, &mut book).map_err(|err| err.to_string())?;
+
+    let cache_dir = book_source::default_cache_dir(&state.config_dir());
+    let report = book_source::download_book(&source, &book, &cache_dir, &mut |done, total| {
+        let _ = app.emit("online-download-progress", DownloadProgress { done, total });
+    })
+    .map_err(|err| err.to_string())?;
+    let _ = app.emit("online-download-finished", report.clone());
+
+    let payload = load_document_internal(state.inner(), report.dir.clone())?;
+    {
+        let mut guard = state.write();
+        let record = guard.library.touch(&report.dir);
+        if !book.name.is_empty() {
+            record.title = book.name;
+        }
+        record.author = book.author;
+    }
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+    schedule_indexing(state.inner(), index.inner());
+    Ok(payload)
+}
+
+/// 导入阅读（Legado）的备份：合并书源，并把匹配到的本地书的阅读进度写入书库。
+///
+/// 当前打开的书进度有变化时直接跳到新位置。
+#[tauri::command(async)]
+pub fn import_legado_backup(
+    path: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<BackupReport, String> {
+    let library = state.read().library.clone();
+    let prepared = book_source::prepare_import(&PathBuf::from(path), &library)
+        .map_err(|err| err.to_string())?;
+
+    let (report, current) = {
+        let mut guard = state.write();
+        let snapshot = &mut *guard;
+        let report = prepared.apply(&mut snapshot.library, &mut snapshot.sources);
+        let current = guard.file_path.clone().and_then(|path| {
+            report
+                .matched
+                .iter()
+                .find(|book| book.path == path)
+                .map(|book| book.offset)
+        });
+        if let Some(offset) = current {
+            guard.current_offset = guard.clamp_offset(guard.offsets.to_display(offset));
+            guard.config.last_offset = offset;
+        }
+        (report, current.map(|_| snapshot_to_payload(&guard)))
+    };
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+    state
+        .save_sources()
+        .map_err(|err| format!("保存书源失败: {}", err))?;
+    if let Some(payload) = current {
+        state
+            .save_config()
+            .map_err(|err| format!("保存配置失败: {}", err))?;
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.emit("document-reloaded", payload);
+        }
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn get_opds_catalogs(state: State<'_, AppState>) -> Vec<Catalog> {
+    state.read().catalogs.clone()
+}
+
+/// 添加 OPDS 书库；地址相同的书库会被更新。
+#[tauri::command]
+pub fn save_opds_catalog(catalog: Catalog, state: State<'_, AppState>) -> Result<(), String> {
+    let url = catalog.url.trim();
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("无效的 OPDS 地址: {}", catalog.url));
+    }
+    let catalog = Catalog {
+        url: url.to_string(),
+        ..catalog
+    };
+    {
+        let mut guard = state.write();
+        match guard
+            .catalogs
+            .iter_mut()
+            .find(|existing| existing.url == catalog.url)
+        {
+            Some(existing) => *existing = catalog,
+            None => guard.catalogs.push(catalog),
+        }
+    }
+    state
+        .save_catalogs()
+        .map_err(|err| format!("保存 OPDS 书库失败: {}", err))
+}
+
+#[tauri::command]
+pub fn delete_opds_catalog(url: String, state: State<'_, AppState>) -> Result<(), String> {
+    state.write().catalogs.retain(|catalog| catalog.url != url);
+    state
+        .save_catalogs()
+        .map_err(|err| format!("保存 OPDS 书库失败: {}", err))
+}
+
+/// 浏览 OPDS 目录；`url` 为空时打开书库首页，翻页与进入子目录时传入目录给出的链接。
+#[tauri::command(async)]
+pub fn browse_opds(
+    catalog_url: String,
+    url: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Feed, String> {
+    let catalog = find_catalog(&state, &catalog_url)?;
+    opds::browse(&catalog, url.as_deref()).map_err(|err| err.to_string())
+}
+
+/// 使用当前目录的搜索链接搜索；未传入时使用书库首页的搜索链接。
+#[tauri::command(async)]
+pub fn search_opds(
+    catalog_url: String,
+    query: String,
+    search_link: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Feed, String> {
+    let catalog = find_catalog(&state, &catalog_url)?;
+    opds::search(&catalog, search_link.as_deref(), &query).map_err(|err| err.to_string())
+}
+
+/// 下载书籍的最佳格式（txt 优先，其次 epub）到下载目录，加入书库并打开。
+#[tauri::command(async)]
+pub fn open_opds_book(
+    catalog_url: String,
+    entry: Entry,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+) -> Result<DocumentPayload, String> {
+    let catalog = find_catalog(&state, &catalog_url)?;
+    let dir = opds::default_download_dir(&state.config_dir());
+    let path = opds::download(&catalog, &entry, &dir).map_err(|err| err.to_string())?;
+
+    let payload = load_document_internal(state.inner(), path.clone())?;
+    {
+        let mut guard = state.write();
+        let record = guard.library.touch(&path);
+        if !entry.title.is_empty() {
+            record.title = entry.title;
+        }
+        if !entry.authors.is_empty() {
+            record.author = entry.authors.join("、");
+        }
+    }
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+    schedule_indexing(state.inner(), index.inner());
+    Ok(payload)
+}
+
+/// 内置 OPDS 服务的运行状态与可供阅读器使用的地址。
+#[tauri::command]
+pub fn opds_server_status(server: State<'_, OpdsServer>) -> ServerStatus {
+    server.status()
+}
+
+/// 按当前配置启动或停止内置 OPDS 服务，首次启用时生成访问令牌。
+pub fn apply_opds_server(app: &AppHandle) -> Result<(), String> {
+    let state = app.state::<AppState>();
+    let mut config = state.read().config.opds_server.clone();
+    if config.enabled && config.token.is_empty() {
+        config.token = opds::new_token().map_err(|err| err.to_string())?;
+        let token = config.token.clone();
+        state
+            .update_config(|current| current.opds_server.token = token)
+            .map_err(|err| format!("保存配置失败: {}", err))?;
+    }
+    let handle = app.clone();
+    let library: LibrarySource =
+        Arc::new(move || handle.state::<AppState>().read().library.clone());
+    app.state::<OpdsServer>()
+        .apply(&config, library)
+        .map_err(|err| err.to_string())
+}
+
+fn find_catalog(state: &AppState, url: &str) -> Result<Catalog, String> {
+    state
+        .read()
+        .catalogs
+        .iter()
+        .find(|catalog| catalog.url == url)
+        .cloned()
+        .ok_or_else(|| format!("找不到 OPDS 书库: {}", url))
+}
+
+/// 列出 Calibre 书库中的书籍（书名、作者、系列、标签与可打开的格式）。
+#[tauri::command(async)]
+pub fn list_calibre_books(dir: String) -> Result<Vec<CalibreBook>, String> {
+    calibre::list_books(&PathBuf::from(dir)).map_err(|err| err.to_string())
+}
+
+/// 把选中的 Calibre 书籍加入书库（`ids` 为空时导入全部）并建立搜索索引。
+#[tauri::command(async)]
+pub fn import_calibre_books(
+    dir: String,
+    ids: Vec<i64>,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+) -> Result<CalibreImportReport, String> {
+    // 读取 Calibre 数据库与检查文件在锁外完成，加锁只为加入书库
+    let prepared =
+        calibre::prepare_import(&PathBuf::from(dir), &ids).map_err(|err| err.to_string())?;
+    let report = prepared.apply(&mut state.write().library);
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+    index.schedule(report.imported.clone());
+    Ok(report)
+}
+
+/// 导入一本 Calibre 书籍并以最佳格式（txt 优先，其次 epub）打开。
+#[tauri::command(async)]
+pub fn open_calibre_book(
+    dir: String,
+    id: i64,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+) -> Result<DocumentPayload, String> {
+    let prepared =
+        calibre::prepare_import(&PathBuf::from(dir), &[id]).map_err(|err| err.to_string())?;
+    let report = prepared.apply(&mut state.write().library);
+    let Some(record) = report.imported.first() else {
+        return Err(match report.skipped.first() {
+            Some(book) => format!("无法打开《{}》: {}", book.title, book.reason),
+            None => format!("Calibre 书库中没有这本书: {}", id),
+        });
+    };
+    let payload = load_document_internal(state.inner(), record.path.clone())?;
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+    schedule_indexing(state.inner(), index.inner());
+    Ok(payload)
+}
+
+/// 设置 KOReader 同步账号，`register` 为真时先在服务器上注册，验证通过后才保存。
+#[tauri::command(async)]
+pub fn set_kosync_account(
+    server: String,
+    username: String,
+    password: String,
+    register: bool,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let server = server.trim().trim_end_matches('/').to_string();
+    if !server.starts_with("http://") && !server.starts_with("https://") {
+        return Err(format!("无效的同步服务器地址: {}", server));
+    }
+    let config = crate::settings::KosyncConfig {
+        server,
+        username: username.trim().to_string(),
+        userkey: kosync::user_key(&password),
+        ..state.read().config.kosync.clone()
+    };
+    if register {
+        kosync::register(&config).map_err(|err| err.to_string())?;
+    }
+    kosync::authorize(&config).map_err(|err| err.to_string())?;
+    state
+        .update_config(|app_config| app_config.kosync = config)
+        .map_err(|err| format!("保存配置失败: {}", err))
+}
+
+/// 把当前书的阅读位置推送到 KOReader 同步服务器。
+#[tauri::command(async)]
+pub fn kosync_push(state: State<'_, AppState>) -> Result<(), String> {
+    let config = kosync_config(state.inner())?;
+    let (path, offset) = {
+        let guard = state.read();
+        let path = guard.file_path.clone().ok_or("没有打开的书")?;
+        (path, guard.offsets.to_source(guard.current_offset))
+    };
+    let document =
+        kosync::document_hash(&path, &config.document_matching).map_err(|err| err.to_string())?;
+    let positions = Positions::load(&path).map_err(|err| err.to_string())?;
+    let (progress, percentage) = positions.to_progress(offset);
+    kosync::push(
+        &config,
+        &Progress {
+            document,
+            progress,
+            percentage,
+            device: config.device.clone(),
+            device_id: config.device_id.clone(),
+            timestamp: None,
+        },
+    )
+    .map_err(|err| err.to_string())
+}
+
+/// 拉取当前书在 KOReader 同步服务器上的进度并跳转，服务器上没有记录时返回 `None`。
+#[tauri::command(async)]
+pub fn kosync_pull(state: State<'_, AppState>, app: AppHandle) -> Result<Option<Progress>, String> {
+    let config = kosync_config(state.inner())?;
+    let path = state.read().file_path.clone().ok_or("没有打开的书")?;
+    let document =
+        kosync::document_hash(&path, &config.document_matching).map_err(|err| err.to_string())?;
+    let Some(remote) = kosync::pull(&config, &document).map_err(|err| err.to_string())? else {
+        return Ok(None);
+    };
+    let positions = Positions::load(&path).map_err(|err| err.to_string())?;
+    let offset = positions.to_offset(&remote.progress, remote.percentage);
+
+    let payload = {
+        let mut guard = state.write();
+        // 拉取期间换了书就不再跳转
+        if guard.file_path.as_ref() != Some(&path) {
+            return Ok(Some(remote));
+        }
+        guard.current_offset = guard.clamp_offset(guard.offsets.to_display(offset));
+        guard.config.last_offset = offset;
+        guard.library.update_offset(&path, offset);
+        snapshot_to_payload(&guard)
+    };
+    state
+        .save_config()
+        .map_err(|err| format!("保存配置失败: {}", err))?;
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.emit("document-reloaded", payload);
+    }
+    Ok(Some(remote))
+}
+
+/// 读取同步配置，首次同步时生成并保存设备 ID。
+fn kosync_config(state: &AppState) -> Result<crate::settings::KosyncConfig, String> {
+    let mut config = state.read().config.kosync.clone();
+    if config.username.is_empty() {
+        return Err("尚未设置 KOReader 同步账号".to_string());
+    }
+    if config.device_id.is_empty() {
+        config.device_id = kosync::new_device_id().map_err(|err| err.to_string())?;
+        let device_id = config.device_id.clone();
+        state
+            .update_config(|app_config| app_config.kosync.device_id = device_id)
+            .map_err(|err| format!("保存配置失败: {}", err))?;
+    }
+    Ok(config)
+}
+
+/// 当前书的书签，偏移为展示偏移。
+#[tauri::command]
+pub fn get_bookmarks(state: State<'_, AppState>) -> Vec<Bookmark> {
+    current_bookmarks(&state.read())
+}
+
+/// 在界面当前显示的位置添加书签，返回更新后的书签列表。
+///
+/// 阅读进度可能还没保存（如手动保存模式），所以由界面传入位置而不用 `current_offset`。
+#[tauri::command]
+pub fn add_bookmark(offset: usize, state: State<'_, AppState>) -> Result<Vec<Bookmark>, String> {
+    let bookmarks = {
+        let mut guard = state.write();
+        let path = guard.file_path.clone().ok_or("没有打开的书")?;
+        let display = guard.clamp_offset(offset);
+        let offset = guard.offsets.to_source(display);
+        let excerpt: String = guard
+            .text
+            .chars()
+            .skip(display)
+            .take(60)
+            .collect::<String>()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(30)
+            .collect();
+        let record = guard.library.find_mut(&path).ok_or("当前书不在书库中")?;
+        record.bookmarks.push(Bookmark::new(offset, excerpt));
+        current_bookmarks(&guard)
+    };
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+    Ok(bookmarks)
+}
+
+/// 删除书签（保留删除标记以便同步到其他设备），返回更新后的书签列表。
+#[tauri::command]
+pub fn remove_bookmark(id: String, state: State<'_, AppState>) -> Result<Vec<Bookmark>, String> {
+    let bookmarks = {
+        let mut guard = state.write();
+        let path = guard.file_path.clone().ok_or("没有打开的书")?;
+        let bookmark = guard
+            .library
+            .find_mut(&path)
+            .and_then(|record| {
+                record
+                    .bookmarks
+                    .iter_mut()
+                    .find(|bookmark| bookmark.id == id)
+            })
+            .ok_or("找不到这个书签")?;
+        bookmark.deleted = true;
+        bookmark.updated = now_secs();
+        current_bookmarks(&guard)
+    };
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+    Ok(bookmarks)
+}
+
+fn current_bookmarks(snapshot: &StateSnapshot) -> Vec<Bookmark> {
+    let Some(record) = snapshot
+        .file_path
+        .as_ref()
+        .and_then(|path| snapshot.library.find_by_path(path))
+    else {
+        return Vec::new();
+    };
+    record
+        .active_bookmarks()
+        .into_iter()
+        .map(|bookmark| Bookmark {
+            offset: snapshot.offsets.to_display(bookmark.offset),
+            ..bookmark
+        })
+        .collect()
+}
+
+/// 立即通过共享文件夹和 WebDAV 同步设置、阅读进度和书签。
+#[tauri::command(async)]
+pub fn sync_now(app: AppHandle) -> Result<SyncReport, String> {
+    run_sync(&app)
+}
+
+/// 等待确认的进度冲突：其他设备较新的进度比本机靠前。
+#[tauri::command]
+pub fn get_sync_conflicts(service: State<'_, SyncService>) -> Vec<ProgressConflict> {
+    service.conflicts()
+}
+
+/// 处理进度冲突：`accept` 为真时回到其他设备的位置，否则保留本机位置并覆盖其他设备。
+#[tauri::command(async)]
+pub fn resolve_sync_conflict(
+    book_id: String,
+    accept: bool,
+    state: State<'_, AppState>,
+    service: State<'_, SyncService>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let conflict = service
+        .take_conflict(&book_id)
+        .ok_or("没有这本书的进度冲突")?;
+    sync::resolve_conflict(&mut state.write().library, &conflict, accept);
+    let report = SyncReport {
+        books_updated: vec![book_id],
+        ..SyncReport::default()
+    };
+    apply_sync_report(state.inner(), &app, &report)?;
+    if !accept {
+        run_sync(&app)?;
+    }
+    Ok(())
+}
+
+/// 按设置的间隔在后台同步，启动后先同步一次。
+pub fn start_background_sync(app: &AppHandle) {
+    let handle = app.clone();
+    std::thread::spawn(move || {
+        let mut last_run: Option<Instant> = None;
+        loop {
+            let config = handle.state::<AppState>().read().config.sync.clone();
+            let interval = Duration::from_secs(u64::from(config.interval_minutes) * 60);
+            let due = (config.webdav.enabled || config.folder.enabled)
+                && config.interval_minutes > 0
+                && handle.state::<AppState>().lock_status() == LockStatus::Off
+                && last_run.is_none_or(|time| time.elapsed() >= interval);
+            if due {
+                last_run = Some(Instant::now());
+                if let Err(err) = run_sync(&handle) {
+                    eprintln!("后台同步失败: {}", err);
+                }
+            }
+            std::thread::sleep(Duration::from_secs(30));
+        }
+    });
+}
+
+fn run_sync(app: &AppHandle) -> Result<SyncReport, String> {
+    let state = app.state::<AppState>();
+    let service = app.state::<SyncService>();
+    service.exclusive(|| {
+        let (mut config, library) = {
+            let guard = state.read();
+            (guard.config.sync.clone(), guard.library.clone())
+        };
+        if !config.webdav.enabled && !config.folder.enabled {
+            return Err("尚未启用同步".to_string());
+        }
+        ensure_unencrypted(state.inner(), "同步")?;
+        if config.device_id.is_empty() {
+            config.device_id = kosync::new_device_id().map_err(|err| err.to_string())?;
+            let device_id = config.device_id.clone();
+            state
+                .update_config(|app_config| app_config.sync.device_id = device_id)
+                .map_err(|err| format!("保存配置失败: {}", err))?;
+        }
+        let device = sync::device_name(&config);
+        // 计算同步键要读取书籍文件，放在锁外
+        let keys = sync::book_keys(&library);
+        let merge = |remote: &mut sync::SyncData| {
+            let mut guard = state.write();
+            let snapshot = &mut *guard;
+            sync::merge(
+                &mut snapshot.config,
+                &mut snapshot.library,
+                &keys,
+                remote,
+                &device,
+            )
+        };
+
+        let mut report = SyncReport::default();
+        if config.folder.enabled {
+            let root = PathBuf::from(config.folder.path.trim());
+            report.combine(
+                sync::sync_folder(&root, &config.device_id, merge)
+                    .map_err(|err| err.to_string())?,
+            );
+        }
+        if config.webdav.enabled {
+            report
+                .combine(sync::sync_webdav(&config.webdav, merge).map_err(|err| err.to_string())?);
+        }
+        // 只提醒新出现的冲突，未处理的冲突不在每次后台同步时重复弹出
+        let known = service.conflicts();
+        let fresh: Vec<&ProgressConflict> = report
+            .conflicts
+            .iter()
+            .filter(|conflict| {
+                !known.iter().any(|old| {
+                    old.book_id == conflict.book_id && old.remote_updated == conflict.remote_updated
+                })
+            })
+            .collect();
+        if !fresh.is_empty() {
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.emit("sync-conflicts", &fresh);
+            }
+        }
+        service.set_conflicts(report.conflicts.clone());
+        apply_sync_report(state.inner(), app, &report)?;
+        Ok(report)
+    })
+}
+
+/// 保存同步结果；当前书的进度有变化时跳转，采用了其他设备的设置时通知界面刷新。
+fn apply_sync_report(state: &AppState, app: &AppHandle, report: &SyncReport) -> Result<(), String> {
+    let current = {
+        let mut guard = state.write();
+        let offset = guard.file_path.as_ref().and_then(|path| {
+            guard
+                .library
+                .find_by_path(path)
+                .filter(|book| report.books_updated.contains(&book.id))
+                .map(|book| book.offset)
+        });
+        if let Some(offset) = offset {
+            guard.current_offset = guard.clamp_offset(guard.offsets.to_display(offset));
+            guard.config.last_offset = offset;
+        }
+        offset.map(|_| snapshot_to_payload(&guard))
+    };
+    state
+        .save_config()
+        .map_err(|err| format!("保存配置失败: {}", err))?;
+    state
+        .save_library()
+        .map_err(|err| format!("保存书库失败: {}", err))?;
+
+    if report.settings_applied {
+        let config = state.read().config.clone();
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.emit("settings-changed", config);
+        }
+        apply_shortcuts(state, app);
+        // 排版设置可能变了，按新设置重新生成文档
+        emit_reloaded_document(state, app)?;
+    } else if let Some(payload) = current {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.emit("document-reloaded", payload);
+        }
+    }
+    Ok(())
+}
+
+/// 备份文件和同步数据要在其他设备上读取，无法用本机密钥加密；启用加密期间拒绝写出，
+/// 以免加密的设置和书库以明文离开本机。
+fn ensure_unencrypted(state: &AppState, action: &str) -> Result<(), String> {
+    if state.lock_status() == LockStatus::Off {
+        Ok(())
+    } else {
+        Err(format!(
+            "已启用数据加密，{}会以明文写出设置和书库，请先关闭加密",
+            action
+        ))
+    }
+}
+
+/// 把设置、书库（含书签）、替换规则、书源和 OPDS 书库备份成一个文件，可选一并打包书籍文件。
+#[tauri::command(async)]
+pub fn create_backup(
+    path: String,
+    include_books: bool,
+    state: State<'_, AppState>,
+) -> Result<BackupInfo, String> {
+    ensure_unencrypted(state.inner(), "备份")?;
+    let contents = {
+        let guard = state.read();
+        BackupContents {
+            config: guard.config.clone(),
+            library: guard.library.clone(),
+            rules: guard.rules.clone(),
+            sources: guard.sources.clone(),
+            catalogs: guard.catalogs.clone(),
+        }
+    };
+    backup::create_backup(&PathBuf::from(path), &contents, include_books)
+        .map_err(|err| err.to_string())
+}
+
+/// 读取备份中包含哪些内容，供恢复前选择。
+#[tauri::command(async)]
+pub fn inspect_backup(path: String) -> Result<BackupInfo, String> {
+    backup::inspect_backup(&PathBuf::from(path)).map_err(|err| err.to_string())
+}
+
+/// 从备份中恢复选中的部分。备份全部校验通过后才覆盖现有数据。
+#[tauri::command(async)]
+pub fn restore_backup(
+    path: String,
+    sections: Vec<Section>,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+    app: AppHandle,
+) -> Result<(), String> {
+    // 锁定时书库尚未读入，不能在此基础上恢复
+    state
+        .with_keyring(|_| Ok(()))
+        .map_err(|err| err.to_string())?;
+    let restored = backup::read_backup(
+        &PathBuf::from(path),
+        &sections,
+        &backup::default_restore_dir(&state.config_dir()),
+    )
+    .map_err(|err| err.to_string())?;
+
+    let config_restored = restored.config.is_some();
+    let library_restored = restored.library.is_some();
+    let progress_restored = restored.progress.is_some();
+    let retired = {
+        let mut guard = state.write();
+        if let Some(mut config) = restored.config {
+            backup::keep_credentials(&mut config, &guard.config);
+            // 保留当前方案、打开的文件和阅读位置；同步设备 ID 属于本机，不随备份恢复
+            guard.config = crate::settings::AppConfig {
+                profile: guard.config.profile.clone(),
+                last_file: guard.config.last_file.clone(),
+                last_page: guard.config.last_page,
+                last_offset: guard.config.last_offset,
+                sync: crate::settings::SyncConfig {
+                    device_id: guard.config.sync.device_id.clone(),
+                    ..config.sync
+                },
+                settings_updated: now_secs(),
+                ..config
+            };
+        }
+        if let Some(library) = restored.library {
+            guard.library = library;
+        }
+        if let Some(rules) = restored.rules {
+            guard.rules = rules;
+        }
+        if let Some(sources) = restored.sources {
+            guard.sources = sources;
+        }
+        if let Some(mut catalogs) = restored.catalogs {
+            backup::keep_catalog_passwords(&mut catalogs, &guard.catalogs);
+            guard.catalogs = catalogs;
+        }
+        if let Some(progress) = restored.progress {
+            backup::merge_progress(&mut guard.library, &progress);
+        }
+        let snapshot = &mut *guard;
+        backup::relocate_books(
+            &restored.relocated,
+            &mut snapshot.library,
+            &mut snapshot.rules,
+        )
+    };
+    for book_id in &retired {
+        index.remove(book_id);
+    }
+
+    state
+        .save_config()
+        .map_err(|err| format!("保存配置失败: {}", err))?;
+    if library_restored || progress_restored || !retired.is_empty() {
+        state
+            .save_library()
+            .map_err(|err| format!("保存书库失败: {}", err))?;
+        index.schedule(state.read().library.books.clone());
+    }
+    if sections.contains(&Section::Rules) || !retired.is_empty() {
+        state
+            .save_rules()
+            .map_err(|err| format!("保存替换规则失败: {}", err))?;
+    }
+    if sections.contains(&Section::Sources) {
+        state
+            .save_sources()
+            .map_err(|err| format!("保存书源失败: {}", err))?;
+    }
+    if sections.contains(&Section::Catalogs) {
+        state
+            .save_catalogs()
+            .map_err(|err| format!("保存 OPDS 书库失败: {}", err))?;
+    }
+
+    if config_restored {
+        apply_config(state.inner(), &app)?;
+    }
+    emit_reloaded_document(state.inner(), &app)
+}
+
+/// 整份配置被替换后（切换方案、恢复备份），通知主窗口并重新应用窗口、托盘、快捷键和服务设置。
+fn apply_config(state: &AppState, app: &AppHandle) -> Result<(), String> {
+    let config = state.read().config.clone();
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.emit("settings-changed", &config);
+        let _ = window.set_always_on_top(config.appearance.always_on_top);
+        let _ = window.set_skip_taskbar(!config.appearance.show_in_taskbar);
+    }
+    app.state::<TrayState>()
+        .update_dev_mode(config.system.dev_mode, app)
+        .map_err(|err| format!("更新托盘菜单失败: {}", err))?;
+    apply_shortcuts(state, app);
+    apply_opds_server(app)
+}
+
+#[tauri::command]
+pub fn list_profiles(state: State<'_, AppState>) -> ProfileList {
+    profiles::list_profiles(state.profiles_dir(), &state.read().config)
+}
+
+/// 以默认设置新建配置方案。
+#[tauri::command]
+pub fn create_profile(name: String, state: State<'_, AppState>) -> Result<ProfileList, String> {
+    let active = state.read().config.clone();
+    state
+        .with_keyring(|keyring| {
+            profiles::create_profile(
+                state.profiles_dir(),
+                &active,
+                &name,
+                &crate::settings::AppConfig::default(),
+                keyring,
+            )
+        })
+        .map_err(|err| err.to_string())?;
+    Ok(profiles::list_profiles(state.profiles_dir(), &active))
+}
+
+/// 复制已有方案（包括正在使用的方案）为新方案。
+#[tauri::command]
+pub fn clone_profile(
+    source: String,
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<ProfileList, String> {
+    let active = state.read().config.clone();
+    let dir = state.profiles_dir();
+    state
+        .with_keyring(|keyring| {
+            let config = profiles::load_profile(dir, &active, &source, keyring)?;
+            profiles::create_profile(dir, &active, &name, &config, keyring)
+        })
+        .map_err(|err| err.to_string())?;
+    Ok(profiles::list_profiles(dir, &active))
+}
+
+#[tauri::command]
+pub fn delete_profile(name: String, state: State<'_, AppState>) -> Result<ProfileList, String> {
+    let active = state.read().config.clone();
+    profiles::delete_profile(state.profiles_dir(), &active, &name)
+        .map_err(|err| err.to_string())?;
+    Ok(profiles::list_profiles(state.profiles_dir(), &active))
+}
+
+/// 切换配置方案，立即重新注册快捷键并应用窗口设置；正在读的书和进度不变。
+#[tauri::command]
+pub fn switch_profile(
+    name: String,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<ProfileList, String> {
+    state
+        .switch_profile(&name)
+        .map_err(|err| format!("切换配置方案失败: {}", err))?;
+    apply_config(state.inner(), &app)?;
+    // 不同方案的排版和文本清理设置可能不同
+    emit_reloaded_document(state.inner(), &app)?;
+    Ok(profiles::list_profiles(
+        state.profiles_dir(),
+        &state.read().config,
+    ))
+}
+
+#[tauri::command]
+pub fn data_lock_status(state: State<'_, AppState>) -> LockStatus {
+    state.lock_status()
+}
+
+/// 用口令解锁加密的配置和书库，随后应用设置并恢复上次阅读的书。
+#[tauri::command(async)]
+pub fn unlock_data(
+    passphrase: String,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+    app: AppHandle,
+) -> Result<(), String> {
+    let passphrase = Zeroizing::new(passphrase);
+    state.unlock(&passphrase).map_err(|err| err.to_string())?;
+    index.set_keyring(current_keyring(state.inner())?);
+    if let Some(name) = profiles::launch_profile() {
+        if let Err(err) = state.switch_profile(&name) {
+            eprintln!("切换到配置方案“{}”失败: {}", name, err);
+        }
+    }
+    index.schedule(state.read().library.books.clone());
+    apply_config(state.inner(), &app)?;
+
+    let config = state.read().config.clone();
+    if config.system.restore_reading {
+        if let Some(path) = config.last_file.filter(|path| novel::source_exists(path)) {
+            load_document_internal(state.inner(), path)?;
+        }
+    }
+    Ok(())
+}
+
+fn current_keyring(state: &AppState) -> Result<Option<Keyring>, String> {
+    state
+        .with_keyring(|keyring| Ok(keyring.cloned()))
+        .map_err(|err| err.to_string())
+}
+
+/// 密钥变化后旧索引无法再读取，清空后按新密钥重建。
+fn rebuild_index(state: &AppState, index: &SearchIndex) -> Result<(), String> {
+    index.reset(current_keyring(state)?);
+    index.schedule(state.read().library.books.clone());
+    Ok(())
+}
+
+/// 启用加密：此后配置、书库、各配置方案和全文索引以口令派生的密钥加密保存。
+#[tauri::command(async)]
+pub fn enable_encryption(
+    passphrase: String,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+) -> Result<(), String> {
+    let passphrase = Zeroizing::new(passphrase);
+    state
+        .enable_encryption(&passphrase)
+        .map_err(|err| format!("启用加密失败: {}", err))?;
+    rebuild_index(state.inner(), index.inner())
+}
+
+/// 更换口令并用新密钥重新加密全部数据。
+#[tauri::command(async)]
+pub fn change_passphrase(
+    current: String,
+    passphrase: String,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+) -> Result<(), String> {
+    let current = Zeroizing::new(current);
+    let passphrase = Zeroizing::new(passphrase);
+    state
+        .change_passphrase(&current, &passphrase)
+        .map_err(|err| format!("更换口令失败: {}", err))?;
+    rebuild_index(state.inner(), index.inner())
+}
+
+#[tauri::command(async)]
+pub fn disable_encryption(
+    passphrase: String,
+    state: State<'_, AppState>,
+    index: State<'_, SearchIndex>,
+) -> Result<(), String> {
+    let passphrase = Zeroizing::new(passphrase);
+    state
+        .disable_encryption(&passphrase)
+        .map_err(|err| format!("关闭加密失败: {}", err))?;
+    rebuild_index(state.inner(), index.inner())
+}
+
 #[tauri::command]
 pub fn app_settings(state: State<'_, AppState>) -> SettingsPayload {
     let snapshot = state.snapshot();
@@ -122,10 +1400,19 @@ pub fn update_settings(
     tray_state: State<'_, TrayState>,
     app: AppHandle,
 ) -> Result<(), String> {
-    let dev_mode_changed = {
+    let (dev_mode_changed, pipeline_changed, server_changed) = {
         let mut guard = state.write();
         let current = &mut guard.config;
         let changed = current.system.dev_mode != settings.system.dev_mode;
+        let pipeline_changed = current.reading.reflow != settings.reading.reflow
+            || current.reading.normalize != settings.reading.normalize;
+        let mut opds_server = settings.opds_server.clone();
+        // 设置页面不编辑令牌，传入为空时沿用已生成的令牌
+        if opds_server.token.is_empty() {
+            opds_server.token = current.opds_server.token.clone();
+        }
+        let server_changed = current.opds_server != opds_server;
+        let synced_before = sync::synced_settings(current);
 
         current.boss_key = settings.boss_key.clone();
         current.max_chars_per_page = settings.max_chars_per_page;
@@ -134,8 +1421,17 @@ pub fn update_settings(
         current.privacy = settings.privacy.clone();
         current.keybindings = settings.keybindings.clone();
         current.system = settings.system.clone();
+        current.opds_server = opds_server;
+        // 同步设备 ID 由本机生成，设置页面读到的可能是生成之前的空值
+        current.sync = crate::settings::SyncConfig {
+            device_id: current.sync.device_id.clone(),
+            ..settings.sync.clone()
+        };
+        if sync::synced_settings(current) != synced_before {
+            current.settings_updated = now_secs();
+        }
 
-        changed
+        (changed, pipeline_changed, server_changed)
     };
 
     state
@@ -148,25 +1444,38 @@ pub fn update_settings(
             .map_err(|err| format!("更新托盘菜单失败: {}", err))?;
     }
 
+    if pipeline_changed {
+        emit_reloaded_document(state.inner(), &app)?;
+    }
+
+    if server_changed {
+        apply_opds_server(&app)?;
+    }
+
     Ok(())
 }
 
 #[tauri::command]
-pub fn reset_settings(state: State<'_, AppState>) -> Result<(), String> {
+pub fn reset_settings(state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
     {
         let mut guard = state.write();
         let default_config = crate::settings::AppConfig::default();
-        // 保留当前打开的文件和阅读位置
+        // 保留当前方案名、打开的文件、阅读位置和同步账号
         guard.config = crate::settings::AppConfig {
+            profile: guard.config.profile.clone(),
             last_file: guard.config.last_file.clone(),
             last_page: guard.config.last_page,
             last_offset: guard.config.last_offset,
+            kosync: guard.config.kosync.clone(),
+            sync: guard.config.sync.clone(),
+            settings_updated: now_secs(),
             ..default_config
         };
     }
     state
         .save_config()
-        .map_err(|err| format!("重置设置失败: {}", err))
+        .map_err(|err| format!("重置设置失败: {}", err))?;
+    apply_opds_server(&app)
 }
 
 #[tauri::command]
@@ -216,6 +1525,12 @@ pub fn unregister_global_shortcut(shortcut: String, app: AppHandle) -> Result<()
 
 #[tauri::command]
 pub fn update_all_shortcuts(state: State<'_, AppState>, app: AppHandle) -> Result<(), String> {
+    apply_shortcuts(state.inner(), &app);
+    Ok(())
+}
+
+/// 按当前配置重新注册全部全局快捷键。
+fn apply_shortcuts(state: &AppState, app: &AppHandle) {
     use tauri_plugin_global_shortcut::GlobalShortcutExt;
 
     let snapshot = state.snapshot();
@@ -248,8 +1563,6 @@ pub fn update_all_shortcuts(state: State<'_, AppState>, app: AppHandle) -> Resul
             eprintln!("注册快捷键 {} 失败: {}", shortcut, err);
         }
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
@@ -294,6 +1607,6 @@ mod tests {
         load_document_internal(&state, novel_path).expect("reload failed");
         let snapshot = state.snapshot();
         assert!(snapshot.text.starts_with("001\n002"));
-        assert_eq!(snapshot.current_offset, 150.min(snapshot.text.len()));
+        assert_eq!(snapshot.current_offset, 150.min(snapshot.text_chars));
     }
 }
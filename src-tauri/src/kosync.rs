@@ -0,0 +1,428 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use md5::{Digest, Md5};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::novel::{self, PageMark};
+use crate::settings::KosyncConfig;
+
+const TIMEOUT: Duration = Duration::from_secs(15);
+const ACCEPT: &str = "application/vnd.koreader.v1+json";
+
+/// 服务器上保存的一条阅读进度，字段与 KOReader 的 kosync 插件一致。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Progress {
+    pub document: String,
+    /// EPUB 等流式文档为 xpointer，PDF 等分页文档为页码
+    pub progress: String,
+    pub percentage: f64,
+    pub device: String,
+    pub device_id: String,
+    /// 服务器记录的更新时间（Unix 秒），推送时不填
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+}
+
+/// 按 KOReader 的方式计算书籍标识：`binary` 为文件内容的部分 MD5，`filename` 为文件名的 MD5。
+pub fn document_hash(path: &Path, matching: &str) -> Result<String> {
+    if !path.is_file() {
+        bail!("只能同步单个书籍文件的进度: {}", path.display());
+    }
+    if matching == "filename" {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        return Ok(md5_hex(name.as_bytes()));
+    }
+    let mut file = File::open(path).with_context(|| format!("打开文件失败: {}", path.display()))?;
+    partial_md5(&mut file).with_context(|| format!("读取文件失败: {}", path.display()))
+}
+
+/// KOReader 的 `util.partialMD5`：在偏移 1024 << 2i（i = -1..=10）处各取 1024 字节。
+///
+/// LuaJIT 的移位只取低 5 位，i = -1 时偏移实际为 0。读到文件末尾即停止。
+fn partial_md5(file: &mut (impl Read + Seek)) -> std::io::Result<String> {
+    const STEP: u64 = 1024;
+    let mut hasher = Md5::new();
+    let mut buffer = [0u8; 1024];
+    for i in -1i32..=10 {
+        let offset = if i < 0 { 0 } else { STEP << (2 * i) };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut read = 0;
+        while read < buffer.len() {
+            match file.read(&mut buffer[read..])? {
+                0 => break,
+                count => read += count,
+            }
+        }
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+/// 服务器要求的 userkey：密码的 MD5。
+pub fn user_key(password: &str) -> String {
+    md5_hex(password.as_bytes())
+}
+
+/// 生成设备 ID：与 KOReader 相同，为 32 位十六进制串。
+pub fn new_device_id() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|err| anyhow!("生成设备 ID 失败: {}", err))?;
+    Ok(hex(&bytes).to_uppercase())
+}
+
+fn md5_hex(bytes: &[u8]) -> String {
+    hex(&Md5::digest(bytes))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// 注册账号；用户名已存在时报错。
+pub fn register(config: &KosyncConfig) -> Result<()> {
+    let body = json!({ "username": config.username, "password": config.userkey });
+    send(config, "POST", "/users/create", Some(body)).map(|_| ())
+}
+
+/// 验证账号密码。
+pub fn authorize(config: &KosyncConfig) -> Result<()> {
+    send(config, "GET", "/users/auth", None).map(|_| ())
+}
+
+pub fn push(config: &KosyncConfig, progress: &Progress) -> Result<()> {
+    let body = serde_json::to_value(progress)?;
+    send(config, "PUT", "/syncs/progress", Some(body)).map(|_| ())
+}
+
+/// 拉取某本书的进度，服务器上没有记录时返回 `None`。
+pub fn pull(config: &KosyncConfig, document: &str) -> Result<Option<Progress>> {
+    let value = send(
+        config,
+        "GET",
+        &format!("/syncs/progress/{}", document),
+        None,
+    )?;
+    if value.get("percentage").is_none() {
+        return Ok(None);
+    }
+    let progress: Progress =
+        serde_json::from_value(value).context("同步服务器返回的进度格式错误")?;
+    Ok(Some(progress))
+}
+
+fn send(config: &KosyncConfig, method: &str, path: &str, body: Option<Value>) -> Result<Value> {
+    if config.username.is_empty() {
+        bail!("尚未设置 KOReader 同步账号");
+    }
+    let url = format!("{}{}", config.server.trim_end_matches('/'), path);
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let request = agent
+        .request(method, &url)
+        .set("Accept", ACCEPT)
+        .set("x-auth-user", &config.username)
+        .set("x-auth-key", &config.userkey);
+    let result = match body {
+        Some(body) => request
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string()),
+        None => request.call(),
+    };
+    let response = result.map_err(|err| match err {
+        ureq::Error::Status(code, response) => {
+            let message = response
+                .into_string()
+                .ok()
+                .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+                .and_then(|value| value["message"].as_str().map(str::to_string));
+            match (code, message) {
+                (401, _) => anyhow!("KOReader 同步账号或密码错误"),
+                (_, Some(message)) => anyhow!("同步服务器返回错误（HTTP {}）: {}", code, message),
+                _ => anyhow!("同步服务器返回错误（HTTP {}）: {}", code, url),
+            }
+        }
+        other => anyhow!("连接同步服务器失败: {}: {}", url, other),
+    })?;
+    let text = response
+        .into_string()
+        .with_context(|| format!("读取同步服务器响应失败: {}", url))?;
+    if text.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    serde_json::from_str(&text).with_context(|| format!("同步服务器返回的不是 JSON: {}", url))
+}
+
+/// 书中位置与 KOReader 进度的换算依据。
+pub struct Positions {
+    total: usize,
+    locator: Locator,
+}
+
+enum Locator {
+    /// EPUB：书脊中各文件的起始偏移，对应 xpointer 中的 `DocFragment[n]`
+    Fragments(Vec<usize>),
+    /// PDF：各页的起始偏移
+    Pages(Vec<PageMark>),
+    /// 其他格式只能按百分比换算
+    Plain,
+}
+
+impl Positions {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = novel::load_source(path)?;
+        let total = source.text.chars().count();
+        let is_epub = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("epub"));
+        let locator = if is_epub {
+            Locator::Fragments(novel::epub_spine_offsets(path)?)
+        } else if !source.pages.is_empty() {
+            Locator::Pages(source.pages)
+        } else {
+            Locator::Plain
+        };
+        Ok(Positions { total, locator })
+    }
+
+    /// 原文偏移 → (progress, percentage)。
+    ///
+    /// EPUB 只能定位到所在文件的开头（KOReader 按 xpointer 跳转），其他流式格式没有对应的
+    /// xpointer，progress 记为原文偏移，KOReader 无法解析时会停留在原处。
+    pub fn to_progress(&self, offset: usize) -> (String, f64) {
+        let offset = offset.min(self.total);
+        let percentage = if self.total == 0 {
+            0.0
+        } else {
+            offset as f64 / self.total as f64
+        };
+        let progress = match &self.locator {
+            Locator::Fragments(starts) => {
+                let index = starts.partition_point(|start| *start <= offset).max(1);
+                format!("/body/DocFragment[{}]/body", index)
+            }
+            Locator::Pages(pages) => {
+                let index = pages.partition_point(|page| page.offset <= offset).max(1);
+                (pages[index - 1].index + 1).to_string()
+            }
+            Locator::Plain => offset.to_string(),
+        };
+        (progress, percentage)
+    }
+
+    /// (progress, percentage) → 原文偏移：以百分比为准，能从 progress 确定所在文件或页时
+    /// 把结果限制在其范围内。
+    pub fn to_offset(&self, progress: &str, percentage: f64) -> usize {
+        let estimate = (percentage.clamp(0.0, 1.0) * self.total as f64).round() as usize;
+        let (start, end) = match &self.locator {
+            Locator::Fragments(starts) => {
+                let fragment = Regex::new(r"DocFragment\[(\d+)\]")
+                    .expect("xpointer 正则无效")
+                    .captures(progress)
+                    .and_then(|captures| captures[1].parse::<usize>().ok())
+                    .filter(|index| (1..=starts.len()).contains(index));
+                let Some(index) = fragment else {
+                    return estimate;
+                };
+                let end = starts.get(index).copied().unwrap_or(self.total);
+                (starts[index - 1], end)
+            }
+            Locator::Pages(pages) => {
+                let page = progress
+                    .trim()
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|number| pages.iter().position(|page| page.index + 1 == number));
+                let Some(position) = page else {
+                    return estimate;
+                };
+                let end = pages
+                    .get(position + 1)
+                    .map(|next| next.offset)
+                    .unwrap_or(self.total);
+                (pages[position].offset, end)
+            }
+            Locator::Plain => return estimate,
+        };
+        if (start..end.max(start + 1)).contains(&estimate) {
+            estimate
+        } else {
+            start
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_http::{self, TestResponse};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn partial_md5_samples_like_koreader() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&data[0..1024]);
+        expected.extend_from_slice(&data[1024..2048]);
+        expected.extend_from_slice(&data[4096..5000]);
+        assert_eq!(
+            partial_md5(&mut Cursor::new(&data)).unwrap(),
+            md5_hex(&expected)
+        );
+        assert_eq!(user_key("123456"), "e10adc3949ba59abbe56e057f20f883e");
+    }
+
+    #[test]
+    fn maps_offsets_to_fragments_and_pages() {
+        let epub = Positions {
+            total: 300,
+            locator: Locator::Fragments(vec![0, 100, 100, 250]),
+        };
+        assert_eq!(epub.to_progress(120).0, "/body/DocFragment[3]/body");
+        assert_eq!(
+            epub.to_offset("/body/DocFragment[3]/body/p[2]/text().4", 0.5),
+            150
+        );
+        // 百分比落在所指文件之外时取文件开头
+        assert_eq!(epub.to_offset("/body/DocFragment[4]/body", 0.5), 250);
+
+        let pdf = Positions {
+            total: 90,
+            locator: Locator::Pages(
+                [(0, 0), (1, 30), (2, 60)]
+                    .into_iter()
+                    .map(|(index, offset)| PageMark {
+                        index,
+                        label: (index + 1).to_string(),
+                        offset,
+                    })
+                    .collect(),
+            ),
+        };
+        assert_eq!(pdf.to_progress(45), ("2".to_string(), 0.5));
+        assert_eq!(pdf.to_offset("3", 0.1), 60);
+
+        let plain = Positions {
+            total: 7,
+            locator: Locator::Plain,
+        };
+        let (progress, percentage) = plain.to_progress(3);
+        assert_eq!(plain.to_offset(&progress, percentage), 3);
+    }
+
+    #[test]
+    fn pushes_and_pulls_against_kosync_server() {
+        let records: Arc<Mutex<HashMap<String, Value>>> = Arc::default();
+        let store = Arc::clone(&records);
+        let base = test_http::serve(move |request| {
+            let json = |value: Value| TestResponse::ok(ACCEPT, value.to_string());
+            let authorized = request.header("x-auth-user") == Some("reader")
+                && request.header("x-auth-key") == Some(user_key("secret").as_str());
+            match (request.method.as_str(), request.path()) {
+                ("POST", "/users/create") => {
+                    let body: Value = serde_json::from_slice(&request.body).unwrap();
+                    if body["username"] == "reader" {
+                        TestResponse {
+                            status: 201,
+                            ..json(json!({ "username": "reader" }))
+                        }
+                    } else {
+                        TestResponse {
+                            status: 402,
+                            ..json(
+                                json!({ "code": 2002, "message": "Username is already registered." }),
+                            )
+                        }
+                    }
+                }
+                _ if !authorized => TestResponse::status(401),
+                ("GET", "/users/auth") => json(json!({ "authorized": "OK" })),
+                ("PUT", "/syncs/progress") => {
+                    let mut body: Value = serde_json::from_slice(&request.body).unwrap();
+                    body["timestamp"] = json!(1_700_000_000);
+                    let document = body["document"].as_str().unwrap().to_string();
+                    store.lock().unwrap().insert(document.clone(), body);
+                    json(json!({ "document": document, "timestamp": 1_700_000_000 }))
+                }
+                ("GET", path) => {
+                    let document = path.trim_start_matches("/syncs/progress/");
+                    json(
+                        store
+                            .lock()
+                            .unwrap()
+                            .get(document)
+                            .cloned()
+                            .unwrap_or(json!({})),
+                    )
+                }
+                _ => TestResponse::status(404),
+            }
+        });
+
+        let mut config = KosyncConfig {
+            server: format!("{}/", base),
+            username: "reader".to_string(),
+            userkey: user_key("wrong"),
+            device_id: "moyu-1".to_string(),
+            ..KosyncConfig::default()
+        };
+        register(&config).unwrap();
+        assert!(authorize(&config)
+            .unwrap_err()
+            .to_string()
+            .contains("密码错误"));
+        config.userkey = user_key("secret");
+        authorize(&config).unwrap();
+        config.username = "taken".to_string();
+        assert!(register(&config)
+            .unwrap_err()
+            .to_string()
+            .contains("already registered"));
+        config.username = "reader".to_string();
+
+        let dir = std::env::temp_dir().join(format!("moyu-reader-kosync-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("雪中行.txt");
+        fs::write(&path, "第一章 雪夜\n那一夜的雪下得很大。\n").unwrap();
+        let document = document_hash(&path, "binary").unwrap();
+        assert_eq!(
+            document_hash(&path, "filename").unwrap(),
+            md5_hex("雪中行.txt".as_bytes())
+        );
+
+        assert!(pull(&config, &document).unwrap().is_none());
+        let positions = Positions::load(&path).unwrap();
+        let (progress, percentage) = positions.to_progress(9);
+        push(
+            &config,
+            &Progress {
+                document: document.clone(),
+                progress,
+                percentage,
+                device: config.device.clone(),
+                device_id: config.device_id.clone(),
+                timestamp: None,
+            },
+        )
+        .unwrap();
+        let remote = pull(&config, &document).unwrap().unwrap();
+        assert_eq!(remote.timestamp, Some(1_700_000_000));
+        assert_eq!(remote.device, "moyu-reader");
+        assert_eq!(positions.to_offset(&remote.progress, remote.percentage), 9);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::settings::AppConfig;
+
+/// 不能出现在行首的标点（避头）。
+const NO_LINE_START: &str = "，。！？；：、）」』》〉】〕］｝”’…—～·,.!?;:)]}%";
+/// 不能出现在行尾的标点（避尾）。
+const NO_LINE_END: &str = "（「『《〈【〔［｛“‘([{";
+/// 在中文字体下按全角绘制的常见标点。
+const WIDE_PUNCTUATION: &str = "“”‘’…—·";
+
+/// 前端测得的字体度量，宽度单位均为像素。
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FontMetrics {
+    pub cjk_width: f32,
+    pub latin_width: f32,
+    /// 单个字形的实测宽度，键为单字符字符串。
+    pub glyph_widths: HashMap<String, f32>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Viewport {
+    pub width: f32,
+    pub height: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct LayoutOptions {
+    pub line_height: f32,
+    pub max_chars_per_page: usize,
+    pub smart_break: bool,
+}
+
+/// 分页结果：每页起始位置（字符偏移），第一页总是从 0 开始。
+#[derive(Clone, Default, Serialize)]
+pub struct PageLayout {
+    pub page_starts: Vec<usize>,
+    pub total_chars: usize,
+}
+
+impl FontMetrics {
+    pub fn from_font_size(font_size: u32) -> Self {
+        let size = font_size.max(1) as f32;
+        Self {
+            cjk_width: size,
+            latin_width: size * 0.55,
+            glyph_widths: HashMap::new(),
+        }
+    }
+
+    fn width_of(&self, ch: char, glyphs: &HashMap<char, f32>) -> f32 {
+        match ch {
+            '\n' | '\r' => 0.0,
+            _ if is_wide(ch) => self.cjk_width,
+            _ => glyphs.get(&ch).copied().unwrap_or(self.latin_width),
+        }
+    }
+}
+
+impl Default for FontMetrics {
+    fn default() -> Self {
+        Self::from_font_size(16)
+    }
+}
+
+impl LayoutOptions {
+    pub fn from_config(config: &AppConfig) -> Self {
+        let appearance = &config.appearance;
+        // line_height 以十分之一倍行距保存，与前端 `lineHeightRaw / 10` 保持一致
+        let ratio = if appearance.line_height == 0 {
+            1.8
+        } else {
+            appearance.line_height as f32 / 10.0
+        };
+        Self {
+            line_height: appearance.font_size.max(1) as f32 * ratio,
+            max_chars_per_page: config.max_chars_per_page,
+            smart_break: config.reading.smart_break,
+        }
+    }
+}
+
+impl PageLayout {
+    pub fn total_pages(&self) -> usize {
+        self.page_starts.len()
+    }
+
+    /// 返回偏移所在页的下标（从 0 开始）。
+    pub fn page_of(&self, offset: usize) -> usize {
+        match self.page_starts.binary_search(&offset) {
+            Ok(index) => index,
+            Err(index) => index.saturating_sub(1),
+        }
+    }
+
+    pub fn page_range(&self, page: usize) -> Option<(usize, usize)> {
+        let start = *self.page_starts.get(page)?;
+        let end = self
+            .page_starts
+            .get(page + 1)
+            .copied()
+            .unwrap_or(self.total_chars);
+        Some((start, end))
+    }
+}
+
+/// 按视口尺寸与字体度量对全文分页，结果只取决于输入，可重复计算。
+pub fn paginate(
+    text: &str,
+    metrics: &FontMetrics,
+    viewport: Viewport,
+    options: LayoutOptions,
+) -> PageLayout {
+    let chars: Vec<char> = text.chars().collect();
+    let glyphs: HashMap<char, f32> = metrics
+        .glyph_widths
+        .iter()
+        .filter_map(|(key, width)| {
+            let mut iter = key.chars();
+            match (iter.next(), iter.next()) {
+                (Some(ch), None) => Some((ch, *width)),
+                _ => None,
+            }
+        })
+        .collect();
+
+    let max_width = viewport.width.max(metrics.cjk_width).max(1.0);
+    let lines_per_page = if options.line_height > 0.0 {
+        ((viewport.height / options.line_height).floor() as usize).max(1)
+    } else {
+        1
+    };
+    let max_chars = if options.max_chars_per_page == 0 {
+        usize::MAX
+    } else {
+        options.max_chars_per_page
+    };
+
+    let mut page_starts = vec![0];
+    let mut page_start = 0;
+    let mut lines_on_page = 0;
+    let mut line_start = 0;
+    let mut line_width = 0.0;
+    let mut index = 0;
+
+    while index < chars.len() {
+        let ch = chars[index];
+
+        if index - page_start >= max_chars {
+            page_starts.push(index);
+            page_start = index;
+            lines_on_page = 0;
+            line_start = index;
+            line_width = 0.0;
+        }
+
+        if ch == '\n' {
+            index += 1;
+            lines_on_page += 1;
+            line_start = index;
+            line_width = 0.0;
+        } else {
+            let width = metrics.width_of(ch, &glyphs);
+            if line_width + width <= max_width || index == line_start {
+                line_width += width;
+                index += 1;
+                continue;
+            }
+
+            let break_at = if options.smart_break {
+                find_line_break(&chars, line_start, index)
+            } else {
+                index
+            };
+            lines_on_page += 1;
+            line_start = break_at;
+            line_width = 0.0;
+            if break_at > index {
+                index = break_at;
+            } else {
+                line_width = chars[break_at..index]
+                    .iter()
+                    .map(|ch| metrics.width_of(*ch, &glyphs))
+                    .sum();
+            }
+        }
+
+        if lines_on_page >= lines_per_page && line_start < chars.len() {
+            page_starts.push(line_start);
+            page_start = line_start;
+            lines_on_page = 0;
+        }
+    }
+
+    PageLayout {
+        page_starts,
+        total_chars: chars.len(),
+    }
+}
+
+/// 在 `overflow` 处溢出时寻找实际换行点：英文单词整体下移，并遵守避头尾规则。
+fn find_line_break(chars: &[char], line_start: usize, overflow: usize) -> usize {
+    let mut break_at = overflow;
+
+    // 行首的空格直接挂在上一行末尾
+    while break_at < chars.len() && chars[break_at] == ' ' {
+        break_at += 1;
+    }
+    if break_at > overflow {
+        return break_at;
+    }
+
+    if is_word_char(chars[break_at]) && is_word_char(chars[break_at - 1]) {
+        let mut word_start = break_at - 1;
+        while word_start > line_start && is_word_char(chars[word_start - 1]) {
+            word_start -= 1;
+        }
+        if word_start > line_start {
+            break_at = word_start;
+        }
+    }
+
+    while break_at > line_start + 1
+        && (NO_LINE_START.contains(chars[break_at]) || NO_LINE_END.contains(chars[break_at - 1]))
+    {
+        break_at -= 1;
+    }
+    break_at
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '\'' || ch == '-'
+}
+
+pub fn is_wide(ch: char) -> bool {
+    matches!(ch as u32,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA960..=0xA97F
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x20000..=0x3FFFD)
+        || WIDE_PUNCTUATION.contains(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(lines: f32) -> (Viewport, LayoutOptions) {
+        (
+            Viewport {
+                width: 100.0,
+                height: 20.0 * lines,
+            },
+            LayoutOptions {
+                line_height: 20.0,
+                max_chars_per_page: 0,
+                smart_break: true,
+            },
+        )
+    }
+
+    fn line_starts(text: &str, metrics: &FontMetrics, width: f32) -> Vec<usize> {
+        // 每页一行时，页起点就是行起点
+        let (_, layout_options) = options(1.0);
+        let viewport = Viewport {
+            width,
+            height: 20.0,
+        };
+        paginate(text, metrics, viewport, layout_options).page_starts
+    }
+
+    #[test]
+    fn kinsoku_keeps_punctuation_off_line_start() {
+        let metrics = FontMetrics::from_font_size(10);
+        // 每行 10 个汉字，第 11 个字符是逗号
+        let text = "一二三四五六七八九十，十一十二";
+        let starts = line_starts(text, &metrics, 100.0);
+        let chars: Vec<char> = text.chars().collect();
+        for start in &starts[1..] {
+            assert!(!NO_LINE_START.contains(chars[*start]));
+        }
+        assert_eq!(starts, vec![0, 9]);
+    }
+
+    #[test]
+    fn latin_words_are_not_split() {
+        let metrics = FontMetrics {
+            cjk_width: 10.0,
+            latin_width: 10.0,
+            glyph_widths: HashMap::new(),
+        };
+        let starts = line_starts("hello world again", &metrics, 100.0);
+        assert_eq!(starts, vec![0, 6, 12]);
+    }
+
+    #[test]
+    fn pagination_is_deterministic_and_respects_limits() {
+        let metrics = FontMetrics::from_font_size(10);
+        let text = "天地玄黄宇宙洪荒。\n".repeat(50);
+        let (viewport, mut layout_options) = options(4.0);
+        let first = paginate(&text, &metrics, viewport, layout_options);
+        let second = paginate(&text, &metrics, viewport, layout_options);
+        assert_eq!(first.page_starts, second.page_starts);
+        assert_eq!(first.total_pages(), 13);
+        assert_eq!(first.page_of(45), 1);
+        assert_eq!(first.page_range(0), Some((0, 40)));
+
+        layout_options.max_chars_per_page = 25;
+        let capped = paginate(&text, &metrics, viewport, layout_options);
+        for page in 0..capped.total_pages() {
+            let (start, end) = capped.page_range(page).unwrap();
+            assert!(end - start <= 25);
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::novel::ScriptConversion;
+use crate::vault::{self, Keyring};
+
+/// 打开过的书籍列表，与配置文件放在同一目录下单独保存。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Library {
+    pub books: Vec<BookRecord>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookRecord {
+    pub id: String,
+    pub path: PathBuf,
+    pub title: String,
+    pub author: String,
+    pub last_opened: u64,
+    pub offset: usize,
+    /// 阅读位置最后一次变化的时间（Unix 秒），同步时据此判断哪一端较新
+    pub progress_at: u64,
+    pub conversion: ScriptConversion,
+    /// 含已删除的书签，删除标记用于在设备间同步删除操作
+    pub bookmarks: Vec<Bookmark>,
+}
+
+/// 书签，`offset` 为原文偏移。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bookmark {
+    pub id: String,
+    pub offset: usize,
+    /// 书签处的一小段原文
+    pub excerpt: String,
+    pub created: u64,
+    /// 最后修改（或删除）的时间（Unix 秒）
+    pub updated: u64,
+    pub deleted: bool,
+}
+
+impl Bookmark {
+    pub fn new(offset: usize, excerpt: String) -> Self {
+        let mut bytes = [0u8; 8];
+        // 随机数不可用时退回时间戳，同一设备上仍不会重复
+        if getrandom::getrandom(&mut bytes).is_err() {
+            bytes = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_nanos() as u64)
+                .unwrap_or(0)
+                .to_be_bytes();
+        }
+        let now = now_secs();
+        Bookmark {
+            id: bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+            offset,
+            excerpt,
+            created: now,
+            updated: now,
+            deleted: false,
+        }
+    }
+}
+
+impl BookRecord {
+    /// 更新阅读位置，位置确实变化时记下时间。
+    pub fn set_offset(&mut self, offset: usize) {
+        if self.offset != offset {
+            self.offset = offset;
+            self.progress_at = now_secs();
+        }
+    }
+
+    /// 未删除的书签，按位置排序。
+    pub fn active_bookmarks(&self) -> Vec<Bookmark> {
+        let mut bookmarks: Vec<Bookmark> = self
+            .bookmarks
+            .iter()
+            .filter(|bookmark| !bookmark.deleted)
+            .cloned()
+            .collect();
+        bookmarks.sort_by_key(|bookmark| bookmark.offset);
+        bookmarks
+    }
+}
+
+impl Library {
+    pub fn find(&self, id: &str) -> Option<&BookRecord> {
+        self.books.iter().find(|book| book.id == id)
+    }
+
+    pub fn find_by_path(&self, path: &Path) -> Option<&BookRecord> {
+        self.find(&book_id(path))
+    }
+
+    /// 记录一次打开操作，不存在时新建条目。
+    pub fn touch(&mut self, path: &Path) -> &mut BookRecord {
+        let record = self.add(path);
+        record.last_opened = now_secs();
+        record
+    }
+
+    /// 加入书库但不视为打开过，已存在时返回原条目。
+    pub fn add(&mut self, path: &Path) -> &mut BookRecord {
+        let id = book_id(path);
+        let index = match self.books.iter().position(|book| book.id == id) {
+            Some(index) => index,
+            None => {
+                self.books.push(BookRecord {
+                    id,
+                    path: path.to_path_buf(),
+                    title: book_title(path),
+                    ..BookRecord::default()
+                });
+                self.books.len() - 1
+            }
+        };
+        &mut self.books[index]
+    }
+
+    pub fn find_mut(&mut self, path: &Path) -> Option<&mut BookRecord> {
+        let id = book_id(path);
+        self.books.iter_mut().find(|book| book.id == id)
+    }
+
+    pub fn update_offset(&mut self, path: &Path, offset: usize) {
+        if let Some(book) = self.find_mut(path) {
+            book.set_offset(offset);
+        }
+    }
+}
+
+/// 以路径计算稳定的书籍 ID（FNV-1a），跨版本、跨平台保持一致。
+pub fn book_id(path: &Path) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in path.to_string_lossy().as_bytes() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+pub fn book_title(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// 把书名转成可用作文件名的形式：非法字符换成全角，长度截断到 80 个字符。
+pub fn safe_file_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|ch| match ch {
+            '/' => '／',
+            '\\' => '＼',
+            ':' => '：',
+            '*' => '＊',
+            '?' => '？',
+            '"' => '＂',
+            '<' => '＜',
+            '>' => '＞',
+            '|' => '｜',
+            ch if ch.is_control() => ' ',
+            ch => ch,
+        })
+        .take(80)
+        .collect();
+    name.trim().to_string()
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn load_library(path: &Path, keyring: Option<&Keyring>) -> Library {
+    read_library(path, keyring)
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// 读取书库，文件不存在时为 `None`；解密或解析失败时报错，不回退到空书库。
+pub fn read_library(path: &Path, keyring: Option<&Keyring>) -> Result<Option<Library>> {
+    let Some(bytes) = vault::read_file(path, keyring)? else {
+        return Ok(None);
+    };
+    let library = serde_json::from_slice(&bytes)
+        .with_context(|| format!("书库文件已损坏: {}", path.display()))?;
+    Ok(Some(library))
+}
+
+pub fn save_library(path: &Path, library: &Library, keyring: Option<&Keyring>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("创建书库目录失败: {}", parent.display()))?;
+    }
+    let data = Zeroizing::new(serde_json::to_vec_pretty(library)?);
+    vault::write_file(path, &data, keyring)
+        .with_context(|| format!("写入书库失败: {}", path.display()))
+}
+
+pub fn default_library_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("moyu-reader-library.json")
+}
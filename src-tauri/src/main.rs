@@ -1,19 +1,44 @@
 mod app_state;
+mod backup;
+mod book_source;
+mod calibre;
 mod commands;
+mod kosync;
+mod layout;
+mod library;
 mod novel;
+mod opds;
+mod profiles;
+mod rules;
+mod search_index;
 mod settings;
+mod sync;
+#[cfg(test)]
+mod test_http;
 mod tray;
+mod vault;
 
 use std::path::PathBuf;
 
 use anyhow::Result as AnyResult;
 use app_state::AppState;
 use commands::{
-    app_settings, current_document, get_all_settings, load_file, register_global_shortcut,
-    reset_settings, sync_tray_state, unregister_global_shortcut, update_all_shortcuts,
-    update_progress, update_settings,
+    add_bookmark, app_settings, apply_opds_server, browse_opds, change_passphrase, clone_profile,
+    create_backup, create_profile, current_document, data_lock_status, delete_book_source,
+    delete_opds_catalog, delete_profile, disable_encryption, dry_run_rules, enable_encryption,
+    get_all_settings, get_book_sources, get_bookmarks, get_opds_catalogs, get_rules,
+    get_sync_conflicts, go_to_page, import_book_sources, import_calibre_books,
+    import_legado_backup, inspect_backup, kosync_pull, kosync_push, list_archive_entries,
+    list_calibre_books, list_profiles, load_file, opds_server_status, open_calibre_book,
+    open_online_book, open_opds_book, open_search_hit, paginate_document, register_global_shortcut,
+    remove_bookmark, reset_settings, resolve_sync_conflict, restore_backup, rule_presets,
+    save_opds_catalog, search_library, search_online, search_opds, set_book_conversion,
+    set_book_source_enabled, set_kosync_account, start_background_sync, switch_profile, sync_now,
+    sync_tray_state, unlock_data, unregister_global_shortcut, update_all_shortcuts,
+    update_progress, update_rules, update_settings,
 };
-use novel::load_text;
+use novel::{load_document, source_exists};
+use search_index::{default_index_dir, SearchIndex};
 use settings::default_config_path;
 #[cfg(target_os = "macos")]
 use tauri::TitleBarStyle;
@@ -24,10 +49,23 @@ fn main() {
     let context = tauri::generate_context!();
     let config_path = resolve_config_path(&context.config().identifier);
     let app_state = AppState::new(config_path);
+    // 加密的数据在解锁后再切换方案
+    if app_state.lock_status() != vault::LockStatus::Locked {
+        if let Some(name) = profiles::launch_profile() {
+            if let Err(err) = app_state.switch_profile(&name) {
+                eprintln!("切换到配置方案“{}”失败: {}", name, err);
+            }
+        }
+    }
+    let search_index = SearchIndex::new(default_index_dir(&app_state.config_dir()));
+    search_index.schedule(app_state.snapshot().library.books);
 
     tauri::Builder::default()
         .manage(app_state)
+        .manage(search_index)
         .manage(tray::TrayState::default())
+        .manage(opds::OpdsServer::default())
+        .manage(sync::SyncService::default())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .setup(|app| {
@@ -37,6 +75,11 @@ fn main() {
             restore_last_session(app)
                 .map_err(|err| -> Box<dyn std::error::Error> { err.into() })?;
             register_boss_key(app).map_err(|err| -> Box<dyn std::error::Error> { err.into() })?;
+            // 端口被占用等问题不影响阅读，只记录下来
+            if let Err(err) = apply_opds_server(app.handle()) {
+                eprintln!("启动 OPDS 服务失败: {}", err);
+            }
+            start_background_sync(app.handle());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -50,7 +93,56 @@ fn main() {
             sync_tray_state,
             register_global_shortcut,
             unregister_global_shortcut,
-            update_all_shortcuts
+            update_all_shortcuts,
+            search_library,
+            open_search_hit,
+            paginate_document,
+            get_rules,
+            rule_presets,
+            update_rules,
+            dry_run_rules,
+            set_book_conversion,
+            list_archive_entries,
+            go_to_page,
+            get_book_sources,
+            import_book_sources,
+            set_book_source_enabled,
+            delete_book_source,
+            search_online,
+            open_online_book,
+            import_legado_backup,
+            get_opds_catalogs,
+            save_opds_catalog,
+            delete_opds_catalog,
+            browse_opds,
+            search_opds,
+            open_opds_book,
+            opds_server_status,
+            list_calibre_books,
+            import_calibre_books,
+            open_calibre_book,
+            set_kosync_account,
+            kosync_push,
+            kosync_pull,
+            get_bookmarks,
+            add_bookmark,
+            remove_bookmark,
+            sync_now,
+            get_sync_conflicts,
+            resolve_sync_conflict,
+            create_backup,
+            inspect_backup,
+            restore_backup,
+            list_profiles,
+            create_profile,
+            clone_profile,
+            delete_profile,
+            switch_profile,
+            data_lock_status,
+            unlock_data,
+            enable_encryption,
+            change_passphrase,
+            disable_encryption
         ])
         .run(context)
         .expect("运行 Tauri 应用时出错");
@@ -96,12 +188,17 @@ fn restore_last_session(app: &mut App) -> AnyResult<()> {
     }
 
     if let Some(path) = snapshot.config.last_file.clone() {
-        if path.exists() {
-            let text = load_text(&path)?;
+        if source_exists(&path) {
+            let document = load_document(&path, &snapshot.pipeline_options(&path))?;
             let mut guard = state.write();
             guard.file_path = Some(path);
-            guard.text = text;
-            guard.current_offset = snapshot.config.last_offset.min(guard.text.len());
+            guard.text = document.text;
+            guard.text_chars = guard.text.chars().count();
+            guard.offsets = document.offsets;
+            guard.chapters = document.chapters;
+            guard.pages = document.pages;
+            guard.current_offset =
+                guard.clamp_offset(guard.offsets.to_display(snapshot.config.last_offset));
         } else {
             let _ = state.update_config(|config| {
                 config.last_file = None;
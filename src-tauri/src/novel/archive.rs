@@ -0,0 +1,280 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use zip::ZipArchive;
+
+use super::folder::compare_chapter_files;
+use super::{is_book_file, parse_book, SourceText};
+
+/// 压缩包路径与包内文件名之间的分隔符，例如 `books.zip!/第一卷.txt`。
+///
+/// 拼接后的路径作为书库中的书籍路径，阅读进度因此按“压缩包 + 包内文件”分别记录。
+const ENTRY_SEPARATOR: &str = "!/";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ArchiveKind {
+    Zip,
+    SevenZ,
+    Gzip,
+}
+
+/// 压缩包内可供选择的书籍文件。
+#[derive(Clone, Serialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    /// 打开该文件时使用的完整路径。
+    pub path: PathBuf,
+}
+
+fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+    match ext.as_str() {
+        "zip" => Some(ArchiveKind::Zip),
+        "7z" => Some(ArchiveKind::SevenZ),
+        "gz" => Some(ArchiveKind::Gzip),
+        _ => None,
+    }
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    archive_kind(path).is_some() && path.is_file()
+}
+
+pub fn entry_path(archive: &Path, entry: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}{}", archive.display(), ENTRY_SEPARATOR, entry))
+}
+
+/// 将 `archive.zip!/entry.txt` 拆分为压缩包路径与包内文件名。
+pub fn split_entry_path(path: &Path) -> Option<(PathBuf, String)> {
+    let full = path.to_string_lossy();
+    full.match_indices(ENTRY_SEPARATOR).find_map(|(index, _)| {
+        let archive = PathBuf::from(&full[..index]);
+        let entry = &full[index + ENTRY_SEPARATOR.len()..];
+        (!entry.is_empty() && archive_kind(&archive).is_some())
+            .then(|| (archive, entry.to_string()))
+    })
+}
+
+/// 列出压缩包中可读取的书籍文件，按章节顺序排列。
+pub fn list_entries(path: &Path) -> Result<Vec<ArchiveEntry>> {
+    let kind =
+        archive_kind(path).with_context(|| format!("不支持的压缩格式: {}", path.display()))?;
+    let mut entries: Vec<(String, u64)> = match kind {
+        ArchiveKind::Zip => {
+            let mut archive = open_zip(path)?;
+            let mut entries = Vec::new();
+            for index in 0..archive.len() {
+                let file = archive.by_index_raw(index)?;
+                if file.is_file() {
+                    entries.push((decode_entry_name(file.name_raw()), file.size()));
+                }
+            }
+            entries
+        }
+        ArchiveKind::SevenZ => sevenz_rust::Archive::open(path)
+            .with_context(|| format!("读取 7z 压缩包失败: {}", path.display()))?
+            .files
+            .iter()
+            .filter(|entry| !entry.is_directory() && entry.has_stream())
+            .map(|entry| (entry.name().to_string(), entry.size()))
+            .collect(),
+        ArchiveKind::Gzip => {
+            // gzip 只包含单个文件，去掉 .gz 后缀即为原文件名
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            vec![(name, 0)]
+        }
+    };
+
+    if kind != ArchiveKind::Gzip {
+        entries.retain(|(name, _)| is_readable_entry(name));
+    }
+    entries.sort_by(|a, b| compare_chapter_files(&a.0, &b.0));
+    Ok(entries
+        .into_iter()
+        .map(|(name, size)| ArchiveEntry {
+            path: entry_path(path, &name),
+            name,
+            size,
+        })
+        .collect())
+}
+
+/// 打开压缩包：只有一个书籍文件时直接读取，多个时需要先选择。
+pub fn load_archive(path: &Path) -> Result<SourceText> {
+    let entries = list_entries(path)?;
+    match entries.as_slice() {
+        [] => bail!("压缩包中没有可读取的书籍文件: {}", path.display()),
+        [entry] => load_entry(path, &entry.name),
+        _ => bail!(
+            "压缩包中包含多个书籍文件，请选择要打开的文件: {}",
+            path.display()
+        ),
+    }
+}
+
+pub fn load_entry(archive: &Path, entry: &str) -> Result<SourceText> {
+    let bytes = read_entry(archive, entry)?;
+    parse_book(Path::new(entry), &bytes).with_context(|| format!("解析失败: {}", entry))
+}
+
+fn read_entry(path: &Path, entry: &str) -> Result<Vec<u8>> {
+    if !path.exists() {
+        bail!("文件不存在: {}", path.display());
+    }
+    let kind =
+        archive_kind(path).with_context(|| format!("不支持的压缩格式: {}", path.display()))?;
+    let mut buffer = Vec::new();
+    match kind {
+        ArchiveKind::Zip => {
+            let mut archive = open_zip(path)?;
+            let index = (0..archive.len())
+                .find(|&index| {
+                    archive
+                        .by_index_raw(index)
+                        .map(|file| decode_entry_name(file.name_raw()) == entry)
+                        .unwrap_or(false)
+                })
+                .with_context(|| format!("压缩包中找不到文件: {}", entry))?;
+            archive
+                .by_index(index)?
+                .read_to_end(&mut buffer)
+                .with_context(|| format!("解压失败: {}", entry))?;
+        }
+        ArchiveKind::SevenZ => {
+            let mut reader = sevenz_rust::SevenZReader::open(path, sevenz_rust::Password::empty())
+                .with_context(|| format!("读取 7z 压缩包失败: {}", path.display()))?;
+            let mut found = false;
+            reader
+                .for_each_entries(|item, data| {
+                    if item.name() != entry {
+                        return Ok(true);
+                    }
+                    data.read_to_end(&mut buffer)?;
+                    found = true;
+                    Ok(false)
+                })
+                .with_context(|| format!("解压失败: {}", entry))?;
+            if !found {
+                bail!("压缩包中找不到文件: {}", entry);
+            }
+        }
+        ArchiveKind::Gzip => {
+            GzDecoder::new(BufReader::new(File::open(path)?))
+                .read_to_end(&mut buffer)
+                .with_context(|| format!("解压失败: {}", path.display()))?;
+        }
+    }
+    Ok(buffer)
+}
+
+fn open_zip(path: &Path) -> Result<ZipArchive<BufReader<File>>> {
+    let file = File::open(path).with_context(|| format!("打开文件失败: {}", path.display()))?;
+    ZipArchive::new(BufReader::new(file))
+        .with_context(|| format!("读取 zip 压缩包失败: {}", path.display()))
+}
+
+fn is_readable_entry(name: &str) -> bool {
+    // 跳过 macOS 打包时附带的资源文件
+    !name.starts_with("__MACOSX/") && is_book_file(Path::new(name))
+}
+
+/// 解码 zip 内的文件名。中文 Windows 打包的 zip 通常未设置 UTF-8 标志，
+/// 文件名实际是 GBK 编码，不能按 CP437 解读。
+fn decode_entry_name(raw: &[u8]) -> String {
+    if let Ok(name) = std::str::from_utf8(raw) {
+        return name.to_string();
+    }
+    let (name, _, _) = encoding_rs::GBK.decode(raw);
+    name.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("moyu-reader-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn zip_with_gbk_names_lists_and_loads_entries() {
+        let dir = temp_dir("archive-zip");
+        let path = dir.join("合集.zip");
+        // ZipWriter 只接受 UTF-8 文件名，先用等长的 ASCII 占位名写入（不会设置 UTF-8 标志），
+        // 再把占位名替换成 GBK 字节，模拟中文 Windows 打包的压缩包。
+        let entries = [
+            ("第二卷.txt", "BBBBBB.txt", "第二卷正文"),
+            ("第一卷.txt", "AAAAAA.txt", "第一卷正文"),
+        ];
+        let mut writer = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (_, placeholder, content) in entries {
+            writer
+                .start_file(placeholder, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer
+            .start_file("cover.jpg", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&[0, 1, 2]).unwrap();
+        let mut bytes = writer.finish().unwrap().into_inner();
+        for (name, placeholder, _) in entries {
+            let (raw, _, _) = encoding_rs::GBK.encode(name);
+            assert_eq!(raw.len(), placeholder.len());
+            let mut start = 0;
+            while let Some(found) = bytes[start..]
+                .windows(placeholder.len())
+                .position(|window| window == placeholder.as_bytes())
+            {
+                let at = start + found;
+                bytes[at..at + raw.len()].copy_from_slice(&raw);
+                start = at + raw.len();
+            }
+        }
+        fs::write(&path, bytes).unwrap();
+
+        let entries = list_entries(&path).unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["第一卷.txt", "第二卷.txt"]);
+        assert!(load_archive(&path).is_err());
+
+        let (archive, entry) = split_entry_path(&entries[1].path).unwrap();
+        assert_eq!(archive, path);
+        assert_eq!(entry, "第二卷.txt");
+        assert_eq!(load_entry(&archive, &entry).unwrap().text, "第二卷正文");
+    }
+
+    #[test]
+    fn gzip_holds_single_entry() {
+        let dir = temp_dir("archive-gz");
+        let path = dir.join("book.txt.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            File::create(&path).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all("压缩的正文".as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let entries = list_entries(&path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "book.txt");
+        assert_eq!(load_archive(&path).unwrap().text, "压缩的正文");
+        assert!(split_entry_path(Path::new("/tmp/notes!/a.txt")).is_none());
+    }
+}
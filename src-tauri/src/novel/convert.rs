@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use zhconv::{zhconv, Variant};
+
+use super::offset_map::{Rewriter, Segment};
+
+/// 简繁转换目标，按书籍单独设置。
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScriptConversion {
+    #[default]
+    None,
+    /// 简体 → 繁体
+    S2t,
+    /// 繁体 → 简体
+    T2s,
+    /// 简体 → 台湾正体（含地区用词）
+    S2tw,
+    /// 简体 → 香港繁体（含地区用词）
+    S2hk,
+}
+
+/// 分块边界：词组不会跨越这些标点，按块转换即可保留词组级词典的效果。
+const CHUNK_BREAKS: &str = "\n。！？；，、：“”「」『』（）《》…";
+
+impl ScriptConversion {
+    fn variant(self) -> Option<Variant> {
+        match self {
+            ScriptConversion::None => None,
+            ScriptConversion::S2t => Some(Variant::ZhHant),
+            ScriptConversion::T2s => Some(Variant::ZhHans),
+            ScriptConversion::S2tw => Some(Variant::ZhTW),
+            ScriptConversion::S2hk => Some(Variant::ZhHK),
+        }
+    }
+}
+
+pub fn convert(text: &str, target: ScriptConversion) -> Option<(String, Vec<Segment>)> {
+    let variant = target.variant()?;
+    Some(convert_with(text, |chunk| zhconv(chunk, variant)))
+}
+
+/// 逐块转换并记录映射；长度不变的块逐字对应，词组长度变化的块整体映射。
+fn convert_with<F>(text: &str, converter: F) -> (String, Vec<Segment>)
+where
+    F: Fn(&str) -> String,
+{
+    let mut rewriter = Rewriter::with_capacity(text.len());
+    for chunk in text.split_inclusive(|ch| CHUNK_BREAKS.contains(ch)) {
+        let converted = converter(chunk);
+        rewriter.replace(chunk, &converted);
+    }
+    rewriter.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::novel::offset_map::OffsetMap;
+
+    #[test]
+    fn phrase_conversion_maps_offsets() {
+        let (text, segments) = convert("中国的头发。", ScriptConversion::S2t).unwrap();
+        assert_eq!(text, "中國的頭髮。");
+        let mut map = OffsetMap::default();
+        map.push_layer(segments);
+        assert_eq!(map.to_display(3), 3);
+        assert!(convert("中国", ScriptConversion::None).is_none());
+    }
+
+    #[test]
+    fn length_changing_chunks_stay_anchored() {
+        let source = "他用了鼠标。然后离开，回家。";
+        let (text, segments) = convert_with(source, |chunk| chunk.replace("鼠标", "滑鼠器"));
+        assert_eq!(text, "他用了滑鼠器。然后离开，回家。");
+
+        let mut map = OffsetMap::default();
+        map.push_layer(segments);
+        // “然”在原文第 6 位，转换后顺延一位
+        assert_eq!(map.to_display(6), 7);
+        assert_eq!(map.to_source(7), 6);
+        // 落在变长块内部的位置退回到块内的相对位置
+        assert_eq!(map.to_source(2), 2);
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+use anyhow::{bail, Context, Result};
+use roxmltree::{Document, Node};
+use zip::ZipArchive;
+
+use super::{BookMeta, Chapter, SourceText};
+
+/// 大纲级别小于该值的段落视为章节标题（标题 1–3）。
+const MAX_HEADING_LEVEL: u32 = 3;
+
+/// 解析 Word 文档：按段落输出纯文本，标题样式的段落记为章节，脚注与尾注以“（注：…）”
+/// 的形式插在引用处，其余格式全部丢弃。
+pub fn parse_docx(bytes: &[u8]) -> Result<SourceText> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).context("不是有效的 DOCX 文件")?;
+    let document_xml = read_part(&mut archive, "word/document.xml")?
+        .context("DOCX 文件中缺少 word/document.xml")?;
+
+    let headings = match read_part(&mut archive, "word/styles.xml")? {
+        Some(xml) => heading_styles(&xml)?,
+        None => HashMap::new(),
+    };
+    let mut notes = HashMap::new();
+    for (part, kind) in [
+        ("word/footnotes.xml", NoteKind::Footnote),
+        ("word/endnotes.xml", NoteKind::Endnote),
+    ] {
+        if let Some(xml) = read_part(&mut archive, part)? {
+            collect_notes(&xml, kind, &mut notes)?;
+        }
+    }
+    let meta = match read_part(&mut archive, "docProps/core.xml")? {
+        Some(xml) => core_properties(&xml)?,
+        None => BookMeta::default(),
+    };
+
+    let document = Document::parse(&document_xml).context("DOCX 正文格式错误")?;
+    let body = document
+        .root_element()
+        .children()
+        .find(|node| node.tag_name().name() == "body")
+        .context("DOCX 文件中没有正文")?;
+
+    let mut writer = DocxWriter {
+        headings: &headings,
+        notes: &notes,
+        text: String::new(),
+        len: 0,
+        chapters: Vec::new(),
+    };
+    writer.visit(body);
+    if writer.text.trim().is_empty() {
+        bail!("DOCX 文件中没有正文");
+    }
+    Ok(SourceText {
+        text: writer.text,
+        chapters: writer.chapters,
+        meta,
+        ..SourceText::default()
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum NoteKind {
+    Footnote,
+    Endnote,
+}
+
+struct DocxWriter<'a> {
+    /// 样式 ID → 大纲级别
+    headings: &'a HashMap<String, u32>,
+    notes: &'a HashMap<(NoteKind, String), String>,
+    text: String,
+    len: usize,
+    chapters: Vec<Chapter>,
+}
+
+impl DocxWriter<'_> {
+    fn visit(&mut self, node: Node) {
+        for child in node.children().filter(Node::is_element) {
+            match child.tag_name().name() {
+                "p" => self.paragraph(child),
+                // 已删除的修订内容不输出
+                "del" | "sectPr" => {}
+                _ => self.visit(child),
+            }
+        }
+    }
+
+    fn paragraph(&mut self, paragraph: Node) {
+        let mut line = String::new();
+        self.runs(paragraph, &mut line);
+        let line = line.trim();
+
+        if !line.is_empty() && self.is_heading(paragraph) {
+            self.chapters.push(Chapter {
+                title: line.to_string(),
+                offset: self.len,
+            });
+        }
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.len += line.chars().count() + 1;
+    }
+
+    fn runs(&self, node: Node, line: &mut String) {
+        for child in node.children().filter(Node::is_element) {
+            match child.tag_name().name() {
+                "t" => line.push_str(child.text().unwrap_or("")),
+                "tab" => line.push(' '),
+                "br" | "cr" => line.push(' '),
+                "noBreakHyphen" => line.push('-'),
+                "footnoteReference" | "endnoteReference" => {
+                    let kind = if child.tag_name().name() == "footnoteReference" {
+                        NoteKind::Footnote
+                    } else {
+                        NoteKind::Endnote
+                    };
+                    let note = attribute(child, "id")
+                        .and_then(|id| self.notes.get(&(kind, id.to_string())));
+                    if let Some(note) = note {
+                        line.push_str("（注：");
+                        line.push_str(note);
+                        line.push('）');
+                    }
+                }
+                // 段落属性、删除内容、域代码与文本框不属于正文行
+                "pPr" | "rPr" | "del" | "instrText" | "txbxContent" => {}
+                _ => self.runs(child, line),
+            }
+        }
+    }
+
+    fn is_heading(&self, paragraph: Node) -> bool {
+        let Some(properties) = child(paragraph, "pPr") else {
+            return false;
+        };
+        let level = child(properties, "outlineLvl")
+            .and_then(|node| attribute(node, "val"))
+            .and_then(|value| value.parse::<u32>().ok())
+            .or_else(|| {
+                child(properties, "pStyle")
+                    .and_then(|node| attribute(node, "val"))
+                    .and_then(|style| self.headings.get(style).copied())
+            });
+        level.is_some_and(|level| level < MAX_HEADING_LEVEL)
+    }
+}
+
+fn read_part(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Option<String>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("读取 {} 失败", name)),
+    };
+    let mut xml = String::new();
+    file.read_to_string(&mut xml)
+        .with_context(|| format!("读取 {} 失败", name))?;
+    Ok(Some(xml))
+}
+
+/// 从 styles.xml 中找出标题样式及其大纲级别：优先取样式自带的 outlineLvl，
+/// 否则按内置名称 “heading N” / “Title” 推断。
+fn heading_styles(xml: &str) -> Result<HashMap<String, u32>> {
+    let document = Document::parse(xml).context("DOCX 样式表格式错误")?;
+    let mut styles = HashMap::new();
+    for style in document
+        .descendants()
+        .filter(|node| node.tag_name().name() == "style")
+    {
+        let Some(id) = attribute(style, "styleId") else {
+            continue;
+        };
+        let outline = child(style, "pPr")
+            .and_then(|properties| child(properties, "outlineLvl"))
+            .and_then(|node| attribute(node, "val"))
+            .and_then(|value| value.parse::<u32>().ok());
+        let name = child(style, "name")
+            .and_then(|node| attribute(node, "val"))
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let by_name = if name == "title" {
+            Some(0)
+        } else {
+            name.strip_prefix("heading")
+                .and_then(|level| level.trim().parse::<u32>().ok())
+                .map(|level| level.saturating_sub(1))
+        };
+        if let Some(level) = outline.or(by_name) {
+            styles.insert(id.to_string(), level);
+        }
+    }
+    Ok(styles)
+}
+
+fn collect_notes(
+    xml: &str,
+    kind: NoteKind,
+    notes: &mut HashMap<(NoteKind, String), String>,
+) -> Result<()> {
+    let document = Document::parse(xml).context("DOCX 脚注格式错误")?;
+    let tag = match kind {
+        NoteKind::Footnote => "footnote",
+        NoteKind::Endnote => "endnote",
+    };
+    for note in document
+        .descendants()
+        .filter(|node| node.tag_name().name() == tag)
+    {
+        let Some(id) = attribute(note, "id") else {
+            continue;
+        };
+        let text: Vec<String> = note
+            .descendants()
+            .filter(|node| node.tag_name().name() == "p")
+            .map(|paragraph| {
+                paragraph
+                    .descendants()
+                    .filter(|node| node.tag_name().name() == "t")
+                    .filter_map(|node| node.text())
+                    .collect::<String>()
+                    .trim()
+                    .to_string()
+            })
+            .filter(|text| !text.is_empty())
+            .collect();
+        if !text.is_empty() {
+            notes.insert((kind, id.to_string()), text.join(" "));
+        }
+    }
+    Ok(())
+}
+
+fn core_properties(xml: &str) -> Result<BookMeta> {
+    let document = Document::parse(xml).context("DOCX 文档属性格式错误")?;
+    let value = |name: &str| {
+        document
+            .descendants()
+            .find(|node| node.tag_name().name() == name)
+            .and_then(|node| node.text())
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+    Ok(BookMeta {
+        title: value("title"),
+        author: value("creator"),
+    })
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// 按本地名取属性，忽略 `w:` 命名空间前缀。
+fn attribute<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|attribute| attribute.name() == name)
+        .map(|attribute| attribute.value())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main""#;
+
+    fn build_docx(parts: &[(&str, String)]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in parts {
+            writer
+                .start_file(name.to_string(), SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn headings_become_chapters_and_footnotes_stay_inline() {
+        let document = format!(
+            r#"<w:document {W}><w:body>
+<w:p><w:pPr><w:pStyle w:val="1"/></w:pPr><w:r><w:t>第一章 </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>初雪</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">那年冬天来得早</w:t></w:r><w:r><w:footnoteReference w:id="2"/></w:r><w:r><w:t>。</w:t></w:r><w:del><w:r><w:delText>删掉的话</w:delText></w:r></w:del></w:p>
+<w:p><w:pPr><w:pStyle w:val="BodyText"/></w:pPr><w:r><w:t>他推开门</w:t><w:tab/><w:t>走了出去。</w:t></w:r></w:p>
+<w:p><w:pPr><w:outlineLvl w:val="1"/></w:pPr><w:r><w:t>第二章 归途</w:t></w:r></w:p>
+<w:p><w:r><w:t>完</w:t></w:r></w:p>
+<w:sectPr/></w:body></w:document>"#
+        );
+        let styles = format!(
+            r#"<w:styles {W}><w:style w:type="paragraph" w:styleId="1"><w:name w:val="heading 1"/></w:style>
+<w:style w:type="paragraph" w:styleId="BodyText"><w:name w:val="Body Text"/></w:style></w:styles>"#
+        );
+        let footnotes = format!(
+            r#"<w:footnotes {W}><w:footnote w:id="2"><w:p><w:r><w:t>指 1998 年。</w:t></w:r></w:p></w:footnote></w:footnotes>"#
+        );
+        let bytes = build_docx(&[
+            ("word/document.xml", document),
+            ("word/styles.xml", styles),
+            ("word/footnotes.xml", footnotes),
+        ]);
+
+        let source = parse_docx(&bytes).unwrap();
+        assert_eq!(
+            source.text,
+            "第一章 初雪\n那年冬天来得早（注：指 1998 年。）。\n他推开门 走了出去。\n第二章 归途\n完\n"
+        );
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一章 初雪", "第二章 归途"]);
+        let second: String = source
+            .text
+            .chars()
+            .skip(source.chapters[1].offset)
+            .collect();
+        assert!(second.starts_with("第二章 归途"));
+    }
+}
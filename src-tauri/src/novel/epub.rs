@@ -0,0 +1,342 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+use anyhow::{bail, Context, Result};
+use roxmltree::{Document, Node, ParsingOptions};
+use zip::ZipArchive;
+
+use super::html::html_to_source;
+use super::{BookMeta, Chapter, SourceText};
+
+const OPS_NAMESPACE: &str = "http://www.idpf.org/2007/ops";
+
+/// 解析 EPUB：按书脊（spine）顺序合并各 XHTML 文件的文字。目录（EPUB 3 的 nav 或
+/// EPUB 2 的 NCX）中的条目记为章节，没有目录时使用正文中的标题元素。
+pub fn parse_epub(bytes: &[u8]) -> Result<SourceText> {
+    read_epub(bytes).map(|(source, _)| source)
+}
+
+/// 书脊中每个文件在合并后原文中的起始偏移，缺失或没有文字的文件与下一个文件起点相同。
+pub fn spine_offsets(bytes: &[u8]) -> Result<Vec<usize>> {
+    read_epub(bytes).map(|(_, offsets)| offsets)
+}
+
+fn read_epub(bytes: &[u8]) -> Result<(SourceText, Vec<usize>)> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).context("不是有效的 EPUB 文件")?;
+    let container = read_entry(&mut archive, "META-INF/container.xml")?
+        .context("EPUB 文件中缺少 META-INF/container.xml")?;
+    let package_path = parse_xml(&container, "container.xml")?
+        .descendants()
+        .find(|node| node.tag_name().name() == "rootfile")
+        .and_then(|node| node.attribute("full-path"))
+        .map(str::to_string)
+        .context("EPUB 文件中没有指明 OPF 文件")?;
+    let package_xml = read_entry(&mut archive, &package_path)?
+        .with_context(|| format!("EPUB 文件中缺少 {}", package_path))?;
+    let package = Package::parse(&package_xml, &package_path)?;
+
+    let toc = match &package.toc {
+        Some((path, kind)) => match read_entry(&mut archive, path)? {
+            // 目录格式不规范时退回按标题元素分章，不影响正文
+            Some(xml) => toc_titles(&xml, path, *kind).unwrap_or_default(),
+            None => HashMap::new(),
+        },
+        None => HashMap::new(),
+    };
+
+    let mut source = SourceText {
+        meta: package.meta,
+        ..SourceText::default()
+    };
+    let mut offset = 0;
+    let mut spine_offsets = Vec::with_capacity(package.spine.len());
+    for path in &package.spine {
+        spine_offsets.push(offset);
+        let Some(html) = read_entry(&mut archive, path)? else {
+            continue;
+        };
+        let part = html_to_source(&html);
+        if part.text.trim().is_empty() {
+            continue;
+        }
+        if let Some(title) = toc.get(path) {
+            source.chapters.push(Chapter {
+                title: title.clone(),
+                offset,
+            });
+        } else if toc.is_empty() {
+            source
+                .chapters
+                .extend(part.chapters.into_iter().map(|chapter| Chapter {
+                    offset: offset + chapter.offset,
+                    ..chapter
+                }));
+        }
+        let mut text = part.text;
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        offset += text.chars().count();
+        source.text.push_str(&text);
+    }
+    if source.text.trim().is_empty() {
+        bail!("EPUB 文件中没有正文");
+    }
+    Ok((source, spine_offsets))
+}
+
+#[derive(Clone, Copy)]
+enum TocKind {
+    Nav,
+    Ncx,
+}
+
+struct Package {
+    meta: BookMeta,
+    /// 书脊中各文件在压缩包内的路径
+    spine: Vec<String>,
+    toc: Option<(String, TocKind)>,
+}
+
+impl Package {
+    fn parse(xml: &str, path: &str) -> Result<Self> {
+        let document = parse_xml(xml, path)?;
+        let text_of = |name: &str| {
+            document
+                .descendants()
+                .find(|node| node.tag_name().name() == name)
+                .and_then(|node| node.text())
+                .map(|text| text.trim().to_string())
+                .filter(|text| !text.is_empty())
+        };
+        let meta = BookMeta {
+            title: text_of("title"),
+            author: text_of("creator"),
+        };
+
+        let mut manifest = HashMap::new();
+        let mut nav = None;
+        for item in document
+            .descendants()
+            .filter(|node| node.tag_name().name() == "item")
+        {
+            let (Some(id), Some(href)) = (item.attribute("id"), item.attribute("href")) else {
+                continue;
+            };
+            let href = resolve_href(path, href);
+            if item
+                .attribute("properties")
+                .is_some_and(|properties| properties.split_whitespace().any(|p| p == "nav"))
+            {
+                nav = Some(href.clone());
+            }
+            manifest.insert(id, href);
+        }
+
+        let spine_node = document
+            .descendants()
+            .find(|node| node.tag_name().name() == "spine")
+            .context("OPF 文件中没有书脊（spine）")?;
+        let spine = spine_node
+            .children()
+            .filter(|node| node.tag_name().name() == "itemref")
+            .filter_map(|node| manifest.get(node.attribute("idref")?).cloned())
+            .collect();
+        let ncx = spine_node
+            .attribute("toc")
+            .and_then(|id| manifest.get(id).cloned());
+        let toc = match (nav, ncx) {
+            (Some(nav), _) => Some((nav, TocKind::Nav)),
+            (None, Some(ncx)) => Some((ncx, TocKind::Ncx)),
+            (None, None) => None,
+        };
+        Ok(Package { meta, spine, toc })
+    }
+}
+
+/// 目录条目指向的文件 → 该文件第一个条目的标题。
+fn toc_titles(xml: &str, path: &str, kind: TocKind) -> Result<HashMap<String, String>> {
+    let document = parse_xml(xml, path)?;
+    let mut titles = HashMap::new();
+    let mut add = |href: &str, title: String| {
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !title.is_empty() {
+            titles.entry(resolve_href(path, href)).or_insert(title);
+        }
+    };
+    match kind {
+        TocKind::Ncx => {
+            for point in document
+                .descendants()
+                .filter(|node| node.tag_name().name() == "navPoint")
+            {
+                let label = point
+                    .children()
+                    .find(|node| node.tag_name().name() == "navLabel")
+                    .map(node_text)
+                    .unwrap_or_default();
+                let source = point
+                    .children()
+                    .find(|node| node.tag_name().name() == "content")
+                    .and_then(|node| node.attribute("src"));
+                if let Some(source) = source {
+                    add(source, label);
+                }
+            }
+        }
+        TocKind::Nav => {
+            let navs: Vec<Node> = document
+                .descendants()
+                .filter(|node| node.tag_name().name() == "nav")
+                .collect();
+            let toc = navs
+                .iter()
+                .find(|node| node.attribute((OPS_NAMESPACE, "type")) == Some("toc"))
+                .or(navs.first())
+                .context("目录文件中没有 nav 元素")?;
+            for link in toc
+                .descendants()
+                .filter(|node| node.tag_name().name() == "a")
+            {
+                if let Some(href) = link.attribute("href") {
+                    add(href, node_text(link));
+                }
+            }
+        }
+    }
+    Ok(titles)
+}
+
+fn node_text(node: Node) -> String {
+    node.descendants()
+        .filter(|node| node.is_text())
+        .filter_map(|node| node.text())
+        .collect()
+}
+
+fn parse_xml<'a>(xml: &'a str, name: &str) -> Result<Document<'a>> {
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    Document::parse_with_options(xml, options).with_context(|| format!("{} 格式错误", name))
+}
+
+/// 把相对于 `base`（OPF 或目录文件）的链接转为压缩包内路径：去掉锚点、百分号解码并处理 `..`。
+fn resolve_href(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or(href);
+    let href = percent_decode(href);
+    let mut parts: Vec<&str> = match base.rfind('/') {
+        Some(index) if !href.starts_with('/') => base[..index].split('/').collect(),
+        _ => Vec::new(),
+    };
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Option<String>> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("读取 {} 失败", name)),
+    };
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)
+        .with_context(|| format!("读取 {} 失败", name))?;
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    #[test]
+    fn reads_spine_in_order_with_ncx_titles() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        let mut add = |name: &str, content: &str| {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        };
+        add("mimetype", "application/epub+zip");
+        add(
+            "META-INF/container.xml",
+            r#"<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+        );
+        add(
+            "OEBPS/content.opf",
+            r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" version="2.0"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>雪夜行</dc:title><dc:creator>佚名</dc:creator></metadata>
+<manifest><item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/><item id="c2" href="Text/%E7%AC%AC%E4%BA%8C.xhtml" media-type="application/xhtml+xml"/><item id="c1" href="Text/one.xhtml" media-type="application/xhtml+xml"/><item id="css" href="Styles/main.css" media-type="text/css"/></manifest>
+<spine toc="ncx"><itemref idref="c1"/><itemref idref="c2"/></spine></package>"#,
+        );
+        add(
+            "OEBPS/toc.ncx",
+            r#"<?xml version="1.0"?><!DOCTYPE ncx PUBLIC "-//NISO//DTD ncx 2005-1//EN" "http://www.daisy.org/z3986/2005/ncx-2005-1.dtd"><ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1"><navMap>
+<navPoint id="n1"><navLabel><text>第一章 雪夜</text></navLabel><content src="Text/one.xhtml"/></navPoint>
+<navPoint id="n2"><navLabel><text>第二章 归人</text></navLabel><content src="Text/%E7%AC%AC%E4%BA%8C.xhtml#top"/></navPoint></navMap></ncx>"#,
+        );
+        add(
+            "OEBPS/Text/one.xhtml",
+            "<html><body><h2>第一章 雪夜</h2><p>那一夜的雪下得很大。</p></body></html>",
+        );
+        add(
+            "OEBPS/Text/第二.xhtml",
+            "<html><body><p class=\"title\">第二章 归人</p><p>清晨，村口站着一个人。</p></body></html>",
+        );
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let source = parse_epub(&bytes).unwrap();
+        assert_eq!(source.meta.title.as_deref(), Some("雪夜行"));
+        assert_eq!(source.meta.author.as_deref(), Some("佚名"));
+        assert_eq!(
+            source.text,
+            "第一章 雪夜\n那一夜的雪下得很大。\n第二章 归人\n清晨，村口站着一个人。\n"
+        );
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一章 雪夜", "第二章 归人"]);
+        let second: String = source
+            .text
+            .chars()
+            .skip(source.chapters[1].offset)
+            .collect();
+        assert!(second.starts_with("第二章 归人"));
+        assert_eq!(
+            spine_offsets(&bytes).unwrap(),
+            vec![0, source.chapters[1].offset]
+        );
+    }
+}
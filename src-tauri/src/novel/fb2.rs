@@ -0,0 +1,228 @@
+use anyhow::{bail, Context, Result};
+use encoding_rs::Encoding;
+use roxmltree::{Document, Node, ParsingOptions};
+
+use super::{decode_bytes, BookMeta, Chapter, SourceText};
+
+/// 解析 FB2（FictionBook 2）文件：各级 `<section>` 的标题进入目录，
+/// 书名与作者取自 `<description><title-info>`。
+pub fn parse_fb2(bytes: &[u8]) -> Result<SourceText> {
+    let xml = decode_xml(bytes)?;
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let document = Document::parse_with_options(&xml, options).context("FB2 文件格式错误")?;
+    let root = document.root_element();
+    if root.tag_name().name() != "FictionBook" {
+        bail!("不是有效的 FB2 文件");
+    }
+
+    let mut writer = Fb2Writer::default();
+    // 注释和脚注放在带 name 属性的独立 body 中，不属于正文
+    for body in elements(root).filter(|node| node.tag_name().name() == "body") {
+        if body.attribute("name").is_none() {
+            writer.block(body, false);
+        }
+    }
+    if writer.text.trim().is_empty() {
+        bail!("FB2 文件中没有正文");
+    }
+
+    Ok(SourceText {
+        text: writer.text,
+        chapters: writer.chapters,
+        meta: read_meta(root),
+        ..SourceText::default()
+    })
+}
+
+#[derive(Default)]
+struct Fb2Writer {
+    text: String,
+    len: usize,
+    chapters: Vec<Chapter>,
+}
+
+impl Fb2Writer {
+    /// 输出一个块级容器；只有 `<section>` 的标题才记为章节。
+    fn block(&mut self, node: Node, is_section: bool) {
+        for child in elements(node) {
+            match child.tag_name().name() {
+                "title" => {
+                    let lines: Vec<String> = elements(child)
+                        .map(inline_text)
+                        .filter(|line| !line.is_empty())
+                        .collect();
+                    if lines.is_empty() {
+                        continue;
+                    }
+                    self.blank_line();
+                    if is_section {
+                        self.chapters.push(Chapter {
+                            title: lines.join(" "),
+                            offset: self.len,
+                        });
+                    }
+                    for line in &lines {
+                        self.push_line(line);
+                    }
+                    self.blank_line();
+                }
+                "section" => self.block(child, true),
+                "epigraph" | "cite" | "poem" | "stanza" => {
+                    self.blank_line();
+                    self.block(child, false);
+                    self.blank_line();
+                }
+                "table" => {
+                    for row in elements(child) {
+                        let cells: Vec<String> = elements(row).map(inline_text).collect();
+                        self.push_line(&cells.join(" | "));
+                    }
+                }
+                "empty-line" => self.blank_line(),
+                "image" | "binary" => {}
+                _ => self.push_line(&inline_text(child)),
+            }
+        }
+    }
+
+    fn push_line(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.text.push_str(line);
+        self.text.push('\n');
+        self.len += line.chars().count() + 1;
+    }
+
+    fn blank_line(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with("\n\n") {
+            self.text.push('\n');
+            self.len += 1;
+        }
+    }
+}
+
+fn elements<'a, 'input>(node: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(Node::is_element)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    elements(node).find(|node| node.tag_name().name() == name)
+}
+
+/// 段落内的强调、链接等行内元素只保留文字，排版用的连续空白合并为一个空格。
+fn inline_text(node: Node) -> String {
+    let text: String = node
+        .descendants()
+        .filter(Node::is_text)
+        .filter_map(|node| node.text())
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn read_meta(root: Node) -> BookMeta {
+    let Some(info) = child(root, "description").and_then(|node| child(node, "title-info")) else {
+        return BookMeta::default();
+    };
+    let title = child(info, "book-title")
+        .map(inline_text)
+        .filter(|title| !title.is_empty());
+    let authors: Vec<String> = elements(info)
+        .filter(|node| node.tag_name().name() == "author")
+        .filter_map(|author| {
+            let name: Vec<String> = ["first-name", "middle-name", "last-name"]
+                .iter()
+                .filter_map(|part| child(author, part).map(inline_text))
+                .filter(|part| !part.is_empty())
+                .collect();
+            if name.is_empty() {
+                child(author, "nickname")
+                    .map(inline_text)
+                    .filter(|nickname| !nickname.is_empty())
+            } else {
+                Some(name.join(" "))
+            }
+        })
+        .collect();
+    BookMeta {
+        title,
+        author: (!authors.is_empty()).then(|| authors.join(", ")),
+    }
+}
+
+/// 按 XML 声明中的编码解码，俄文 FB2 常见 windows-1251；未声明时自动检测。
+fn decode_xml(bytes: &[u8]) -> Result<String> {
+    let head = &bytes[..bytes.len().min(200)];
+    let declared = String::from_utf8_lossy(head)
+        .split("?>")
+        .next()
+        .and_then(|declaration| {
+            let rest = &declaration[declaration.find("encoding=")? + "encoding=".len()..];
+            let quote = rest.chars().next()?;
+            let rest = &rest[quote.len_utf8()..];
+            Some(rest[..rest.find(quote)?].to_string())
+        })
+        .and_then(|label| Encoding::for_label(label.trim().as_bytes()));
+    match declared {
+        Some(encoding) => {
+            let (text, _, had_errors) = encoding.decode(bytes);
+            if had_errors {
+                bail!("FB2 文件编码与声明不符");
+            }
+            Ok(text.into_owned())
+        }
+        None => decode_bytes(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r##"<?xml version="1.0" encoding="windows-1251"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+  <description>
+    <title-info>
+      <author><first-name>Лев</first-name><last-name>Толстой</last-name></author>
+      <book-title>Война и мир</book-title>
+    </title-info>
+  </description>
+  <body>
+    <title><p>Война и мир</p></title>
+    <section>
+      <title><p>Том первый</p></title>
+      <section>
+        <title><p>Часть первая</p><p>I</p></title>
+        <p>— Eh bien, mon prince. <emphasis>Gênes</emphasis> et Lucques<a l:href="#n1" type="note">[1]</a>.</p>
+        <empty-line/>
+        <p>Так говорила   Анна Павловна.</p>
+      </section>
+    </section>
+  </body>
+  <body name="notes">
+    <section id="n1"><p>Генуя и Лукка.</p></section>
+  </body>
+</FictionBook>"##;
+
+    #[test]
+    fn parses_nested_sections_and_description() {
+        let (bytes, _, _) = encoding_rs::WINDOWS_1251.encode(SAMPLE);
+        let source = parse_fb2(&bytes).unwrap();
+        assert_eq!(source.meta.title.as_deref(), Some("Война и мир"));
+        assert_eq!(source.meta.author.as_deref(), Some("Лев Толстой"));
+
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["Том первый", "Часть первая I"]);
+        let second: String = source
+            .text
+            .chars()
+            .skip(source.chapters[1].offset)
+            .collect();
+        assert!(second.starts_with("Часть первая\nI\n\n— Eh bien, mon prince. Gênes et Lucques[1].\n\nТак говорила Анна Павловна."));
+        assert!(!source.text.contains("Генуя"));
+    }
+}
@@ -0,0 +1,234 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+use super::{decode_bytes, Chapter, SourceText};
+
+const TEXT_EXTENSIONS: [&str; 2] = ["txt", "text"];
+
+/// 将目录下的分章文件按章节顺序拼成一本书，文件名作为章节标题。
+pub fn load_folder(dir: &Path) -> Result<SourceText> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("读取目录失败: {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_text_file(path))
+        .collect();
+    if files.is_empty() {
+        bail!("目录中没有可读取的文本文件: {}", dir.display());
+    }
+    files.sort_by(|a, b| compare_chapter_files(&file_stem(a), &file_stem(b)));
+
+    let mut source = SourceText::default();
+    let mut offset = 0;
+    for file in &files {
+        let bytes = fs::read(file).with_context(|| format!("读取文件失败: {}", file.display()))?;
+        let content =
+            decode_bytes(&bytes).with_context(|| format!("解码失败: {}", file.display()))?;
+        let title = chapter_title(&file_stem(file));
+        let content = content.trim_end();
+
+        let mut section = String::new();
+        // 正文首行已经是章节名时不再重复插入
+        let first_line = content.lines().find(|line| !line.trim().is_empty());
+        if first_line.map(str::trim) != Some(title.trim()) {
+            section.push_str(&title);
+            section.push_str("\n\n");
+        }
+        section.push_str(content);
+        section.push_str("\n\n");
+
+        source.chapters.push(Chapter { title, offset });
+        offset += section.chars().count();
+        source.text.push_str(&section);
+    }
+    Ok(source)
+}
+
+fn is_text_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| {
+            let ext = ext.to_string_lossy().to_ascii_lowercase();
+            TEXT_EXTENSIONS.contains(&ext.as_str())
+        })
+        .unwrap_or(false)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// 去掉用于排序的编号前缀（“00012 第十二章”），文件名只有编号时原样保留。
+fn chapter_title(stem: &str) -> String {
+    let digits = stem.chars().take_while(char::is_ascii_digit).count();
+    if digits >= 3 {
+        let rest = stem[digits..].trim_start_matches([' ', '_']);
+        if rest.len() < stem.len() - digits && !rest.trim().is_empty() {
+            return rest.to_string();
+        }
+    }
+    stem.to_string()
+}
+
+/// 优先按文件名中的章节号排序（“0012”“第十二章”），其余按自然顺序。
+pub(super) fn compare_chapter_files(a: &str, b: &str) -> Ordering {
+    match (chapter_number(a), chapter_number(b)) {
+        (Some(x), Some(y)) if x != y => x.cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        _ => natural_cmp(a, b),
+    }
+}
+
+/// 解析文件名开头的章节号：阿拉伯数字或“第……章/回/节”中的中文数字。
+pub fn chapter_number(name: &str) -> Option<u64> {
+    let name = name.trim();
+    let digits: String = name.chars().take_while(|ch| ch.is_ascii_digit()).collect();
+    if !digits.is_empty() {
+        return digits.parse().ok();
+    }
+
+    let rest = name.strip_prefix('第')?;
+    let end = rest.find(['章', '回', '节', '卷'])?;
+    let number = rest[..end].trim();
+    number.parse().ok().or_else(|| parse_chinese_number(number))
+}
+
+fn parse_chinese_number(text: &str) -> Option<u64> {
+    if text.is_empty() {
+        return None;
+    }
+    let mut total = 0;
+    let mut section = 0;
+    let mut digit = 0;
+    for ch in text.chars() {
+        let value = match ch {
+            '零' | '〇' => Some(0),
+            '一' | '壹' => Some(1),
+            '二' | '两' | '贰' => Some(2),
+            '三' | '叁' => Some(3),
+            '四' | '肆' => Some(4),
+            '五' | '伍' => Some(5),
+            '六' | '陆' => Some(6),
+            '七' | '柒' => Some(7),
+            '八' | '捌' => Some(8),
+            '九' | '玖' => Some(9),
+            _ => None,
+        };
+        if let Some(value) = value {
+            digit = value;
+            continue;
+        }
+        let unit = match ch {
+            '十' | '拾' => 10,
+            '百' | '佰' => 100,
+            '千' | '仟' => 1000,
+            '万' => 10_000,
+            _ => return None,
+        };
+        if unit == 10_000 {
+            total += (section + digit) * unit;
+            section = 0;
+        } else {
+            // “十二”中省略的“一”
+            let digit_value = if digit == 0 && unit == 10 { 1 } else { digit };
+            section += digit_value * unit;
+        }
+        digit = 0;
+    }
+    Some(total + section + digit)
+}
+
+/// 自然排序：连续数字按数值比较，其余按字符比较。
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut left = a.chars().peekable();
+    let mut right = b.chars().peekable();
+    loop {
+        match (left.peek().copied(), right.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |iter: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(ch) = iter.peek().copied().filter(char::is_ascii_digit) {
+                        digits.push(ch);
+                        iter.next();
+                    }
+                    digits
+                };
+                let x = take_number(&mut left);
+                let y = take_number(&mut right);
+                let x_trimmed = x.trim_start_matches('0');
+                let y_trimmed = y.trim_start_matches('0');
+                let ordering = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                left.next();
+                right.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_files_by_chapter_number() {
+        let mut names = vec![
+            "第十二章 雪夜",
+            "第2章 出发",
+            "第一章 序",
+            "附录",
+            "10 终章",
+        ];
+        names.sort_by(|a, b| compare_chapter_files(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "第一章 序",
+                "第2章 出发",
+                "10 终章",
+                "第十二章 雪夜",
+                "附录"
+            ]
+        );
+        assert_eq!(chapter_number("第一百零三章"), Some(103));
+        assert_eq!(natural_cmp("卷2", "卷10"), Ordering::Less);
+    }
+
+    #[test]
+    fn loads_folder_as_single_document() {
+        let dir = std::env::temp_dir().join(format!("moyu-reader-folder-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("0002.txt"), "第二段").unwrap();
+        fs::write(dir.join("0001.txt"), "0001\n第一段").unwrap();
+        let (gbk, _, _) = encoding_rs::GBK.encode("第十段，来自 GBK 编码的文件。");
+        fs::write(dir.join("0010.txt"), gbk).unwrap();
+        fs::write(dir.join("00011 第十一章 归途.txt"), "第十一段").unwrap();
+        fs::write(dir.join("cover.jpg"), [0u8, 1, 2]).unwrap();
+
+        let source = load_folder(&dir).unwrap();
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["0001", "0002", "0010", "第十一章 归途"]);
+        assert!(source.text.starts_with("0001\n第一段\n\n0002\n\n第二段"));
+        let third = source.chapters[2].offset;
+        let tail: String = source.text.chars().skip(third).collect();
+        assert!(tail.starts_with("0010\n\n第十段，来自 GBK 编码的文件。"));
+    }
+}
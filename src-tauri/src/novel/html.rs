@@ -0,0 +1,296 @@
+use scraper::{ElementRef, Html, Node};
+
+use super::{Chapter, SourceText};
+use crate::layout::is_wide;
+
+/// 内容整段丢弃的元素。
+const SKIPPED_TAGS: [&str; 6] = ["head", "script", "style", "noscript", "template", "svg"];
+
+/// 开始或结束时需要换行的块级元素。
+const BLOCK_TAGS: [&str; 25] = [
+    "p",
+    "div",
+    "hr",
+    "li",
+    "ul",
+    "ol",
+    "tr",
+    "table",
+    "blockquote",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "pre",
+    "dt",
+    "dd",
+    "figure",
+    "figcaption",
+    "body",
+    "html",
+    "main",
+    "aside",
+    "h4",
+    "h5",
+    "h6",
+];
+
+/// 将 HTML 转为阅读用的纯文本：块级元素分段，`<h1>`–`<h3>` 记为章节。
+///
+/// 没有标题元素时（常见于 MOBI），按 `<mbp:pagebreak>` 分章并以首行作为章节名。
+pub fn html_to_source(html: &str) -> SourceText {
+    let document = Html::parse_document(html);
+    elements_to_source([document.root_element()], |_| false)
+}
+
+/// 依次转换若干元素的子树；`skip` 为真的元素连同子树一起丢弃。
+pub(super) fn elements_to_source<'a>(
+    elements: impl IntoIterator<Item = ElementRef<'a>>,
+    skip: impl Fn(ElementRef) -> bool,
+) -> SourceText {
+    let mut converter = Converter {
+        writer: TextWriter::default(),
+        heading: None,
+        page_breaks: vec![0],
+        skip: &skip,
+    };
+    for element in elements {
+        converter.element(element);
+    }
+    let page_breaks = converter.page_breaks;
+    let mut source = converter.writer.finish();
+    if source.chapters.is_empty() && page_breaks.len() > 2 {
+        source.chapters = chapters_from_breaks(&source.text, &page_breaks);
+    }
+    source
+}
+
+struct Converter<'s> {
+    writer: TextWriter,
+    /// 正在输出的标题起点：字符偏移与字节位置
+    heading: Option<(usize, usize)>,
+    page_breaks: Vec<usize>,
+    skip: &'s dyn Fn(ElementRef) -> bool,
+}
+
+impl Converter<'_> {
+    fn element(&mut self, element: ElementRef) {
+        let name = element.value().name();
+        if SKIPPED_TAGS.contains(&name) || (self.skip)(element) {
+            return;
+        }
+        let writer = &mut self.writer;
+        match name {
+            "h1" | "h2" | "h3" => {
+                writer.break_paragraph();
+                self.heading = Some((writer.len, writer.text.len()));
+            }
+            "pre" => {
+                writer.break_paragraph();
+                writer.preformatted = true;
+            }
+            // 解析器不认自闭合写法，之后的内容都会落在分页元素里，只在开头记录分页
+            "mbp:pagebreak" => {
+                writer.break_paragraph();
+                self.page_breaks.push(writer.len);
+            }
+            "br" => writer.break_line(),
+            "img" => {
+                if let Some(alt) = element
+                    .value()
+                    .attr("alt")
+                    .filter(|alt| !alt.trim().is_empty())
+                {
+                    writer.push_text(alt);
+                }
+            }
+            name if BLOCK_TAGS.contains(&name) => writer.break_paragraph(),
+            _ => {}
+        }
+
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.writer.push_text(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let writer = &mut self.writer;
+        match name {
+            "h1" | "h2" | "h3" => {
+                writer.break_paragraph();
+                if let Some((offset, byte_start)) = self.heading.take() {
+                    let title = writer.text[byte_start..].trim().to_string();
+                    if !title.is_empty() {
+                        writer.chapters.push(Chapter { title, offset });
+                    }
+                }
+            }
+            "pre" => {
+                writer.break_paragraph();
+                writer.preformatted = false;
+            }
+            "td" | "th" => writer.push_text(" "),
+            name if BLOCK_TAGS.contains(&name) => writer.break_paragraph(),
+            _ => {}
+        }
+    }
+}
+
+fn chapters_from_breaks(text: &str, breaks: &[usize]) -> Vec<Chapter> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut chapters: Vec<Chapter> = Vec::new();
+    for &offset in breaks {
+        let offset = offset.min(chars.len());
+        if chapters.last().map(|last| last.offset) == Some(offset) {
+            continue;
+        }
+        let title: String = chars[offset..]
+            .iter()
+            .skip_while(|ch| ch.is_whitespace())
+            .take_while(|ch| **ch != '\n')
+            .take(40)
+            .collect();
+        let title = title.trim().to_string();
+        if !title.is_empty() {
+            chapters.push(Chapter { title, offset });
+        }
+    }
+    chapters
+}
+
+/// 逐段写出文本，`len` 为已写出的字符数，供章节偏移使用。
+#[derive(Default)]
+struct TextWriter {
+    text: String,
+    len: usize,
+    chapters: Vec<Chapter>,
+    preformatted: bool,
+    /// 待输出的空白；`Some(true)` 表示只来自源码换行
+    pending_space: Option<bool>,
+}
+
+impl TextWriter {
+    fn push_text(&mut self, text: &str) {
+        if self.preformatted {
+            self.push_str(text);
+            return;
+        }
+        let mut rest = text;
+        while !rest.is_empty() {
+            let word_start = rest
+                .find(|ch: char| !ch.is_whitespace())
+                .unwrap_or(rest.len());
+            let gap = &rest[..word_start];
+            if !gap.is_empty() {
+                // 多段空白取最“弱”的一种：只要出现过空格就保留空格
+                let wrap = gap.chars().all(|ch| ch == '\n' || ch == '\r');
+                self.pending_space = Some(match self.pending_space {
+                    Some(previous) => previous && wrap,
+                    None => wrap,
+                });
+            }
+            rest = &rest[word_start..];
+            if rest.is_empty() {
+                break;
+            }
+            let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let word = &rest[..word_end];
+            if let Some(wrap) = self.pending_space.take() {
+                if self.needs_space_before(word, wrap) {
+                    self.push_str(" ");
+                }
+            }
+            self.push_str(word);
+            rest = &rest[word_end..];
+        }
+    }
+
+    /// 源码换行产生的空白在中文之间没有意义，只在西文单词之间保留。
+    fn needs_space_before(&self, word: &str, wrap: bool) -> bool {
+        let Some(last) = self.text.chars().next_back() else {
+            return false;
+        };
+        if last == '\n' || last == ' ' {
+            return false;
+        }
+        let first = word.chars().next().unwrap_or(' ');
+        !(wrap && is_wide(last) && is_wide(first))
+    }
+
+    fn push_str(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.len += text.chars().count();
+    }
+
+    fn break_line(&mut self) {
+        self.trim_trailing_space();
+        self.pending_space = None;
+        if !self.text.is_empty() && !self.text.ends_with("\n\n") {
+            self.push_str("\n");
+        }
+    }
+
+    fn break_paragraph(&mut self) {
+        self.trim_trailing_space();
+        self.pending_space = None;
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.push_str("\n");
+        }
+    }
+
+    fn trim_trailing_space(&mut self) {
+        while self.text.ends_with(' ') {
+            self.text.pop();
+            self.len -= 1;
+        }
+    }
+
+    fn finish(mut self) -> SourceText {
+        self.break_paragraph();
+        SourceText {
+            text: self.text,
+            chapters: self.chapters,
+            ..SourceText::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_blocks_headings_and_entities() {
+        let html = r#"<html><head><title>忽略</title><style>p{}</style></head>
+<body><h1>第一章 <em>起</em></h1><p>他说：&ldquo;走吧。&rdquo;</p>
+<p>Tom &amp;
+   Jerry<br/>second line</p><!-- 注释 --><h2>第二章</h2><p>完</p></body></html>"#;
+        let source = html_to_source(html);
+        assert_eq!(
+            source.text,
+            "第一章 起\n他说：“走吧。”\nTom & Jerry\nsecond line\n第二章\n完\n"
+        );
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一章 起", "第二章"]);
+        let second: String = source
+            .text
+            .chars()
+            .skip(source.chapters[1].offset)
+            .collect();
+        assert!(second.starts_with("第二章"));
+    }
+
+    #[test]
+    fn page_breaks_become_chapters_without_headings() {
+        let html = "<p>序</p><mbp:pagebreak/><p>楔子</p><p>正文</p><mbp:pagebreak/><p>尾声</p>";
+        let source = html_to_source(html);
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["序", "楔子", "尾声"]);
+    }
+}
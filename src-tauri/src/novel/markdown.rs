@@ -0,0 +1,196 @@
+use anyhow::Result;
+use pulldown_cmark::{Event, HeadingLevel, MetadataBlockKind, Options, Parser, Tag, TagEnd};
+
+use super::html::html_to_source;
+use super::{decode_bytes, BookMeta, Chapter, SourceText};
+use crate::layout::is_wide;
+
+/// 解析 Markdown 文件：渲染为纯文本，一到三级标题记为章节，YAML 头中的 title/author 作为书籍信息。
+pub fn parse_markdown(bytes: &[u8]) -> Result<SourceText> {
+    Ok(markdown_to_source(&decode_bytes(bytes)?))
+}
+
+pub fn markdown_to_source(markdown: &str) -> SourceText {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS;
+    let mut writer = MarkdownWriter::default();
+    let mut heading: Option<usize> = None;
+    let mut metadata: Option<String> = None;
+    let mut list_depth = 0usize;
+
+    for event in Parser::new_ext(markdown, options) {
+        if let Some(block) = metadata.as_mut() {
+            match event {
+                Event::Text(text) => block.push_str(&text),
+                Event::End(TagEnd::MetadataBlock(_)) => {
+                    writer.meta = front_matter(block);
+                    metadata = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(Tag::MetadataBlock(MetadataBlockKind::YamlStyle)) => {
+                metadata = Some(String::new());
+            }
+            Event::Start(Tag::Heading { level, .. }) => {
+                writer.break_paragraph();
+                if level <= HeadingLevel::H3 {
+                    heading = Some(writer.text.len());
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(start) = heading.take() {
+                    let title = writer.text[start..].trim().to_string();
+                    if !title.is_empty() {
+                        let offset = writer.text[..start].chars().count();
+                        writer.chapters.push(Chapter { title, offset });
+                    }
+                }
+                writer.break_paragraph();
+            }
+            Event::Start(Tag::List(_)) => {
+                writer.break_paragraph();
+                list_depth += 1;
+            }
+            Event::End(TagEnd::List(_)) => {
+                writer.break_paragraph();
+                list_depth = list_depth.saturating_sub(1);
+            }
+            Event::Start(Tag::Item) => {
+                writer.break_paragraph();
+                writer.push_str(&"　".repeat(list_depth.saturating_sub(1)));
+                writer.push_str("• ");
+            }
+            Event::TaskListMarker(done) => writer.push_str(if done { "☑ " } else { "☐ " }),
+            Event::Start(Tag::CodeBlock(_)) => {
+                writer.break_paragraph();
+                writer.preformatted = true;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                writer.preformatted = false;
+                writer.break_paragraph();
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                writer.break_paragraph();
+                writer.push_str(&format!("[{}] ", label));
+            }
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Item
+                | TagEnd::BlockQuote(_)
+                | TagEnd::TableRow
+                | TagEnd::TableHead
+                | TagEnd::FootnoteDefinition,
+            ) => writer.break_paragraph(),
+            Event::End(TagEnd::TableCell) => writer.push_str(" "),
+            Event::Text(text) | Event::Code(text) => writer.push_text(&text),
+            Event::InlineMath(text) | Event::DisplayMath(text) => writer.push_text(&text),
+            Event::FootnoteReference(label) => writer.push_str(&format!("[{}]", label)),
+            Event::SoftBreak => writer.soft_break = true,
+            Event::HardBreak => writer.break_paragraph(),
+            // 块级 HTML 按网页规则转成文字，行内标签直接丢弃
+            Event::Html(html) => {
+                writer.break_paragraph();
+                writer.push_str(html_to_source(&html).text.trim_end());
+                writer.break_paragraph();
+            }
+            _ => {}
+        }
+    }
+    writer.break_paragraph();
+    SourceText {
+        text: writer.text,
+        chapters: writer.chapters,
+        meta: writer.meta,
+        ..SourceText::default()
+    }
+}
+
+#[derive(Default)]
+struct MarkdownWriter {
+    text: String,
+    chapters: Vec<Chapter>,
+    meta: BookMeta,
+    preformatted: bool,
+    /// 源码中的软换行：西文单词之间补空格，中文之间直接相连
+    soft_break: bool,
+}
+
+impl MarkdownWriter {
+    fn push_text(&mut self, text: &str) {
+        if self.preformatted {
+            self.text.push_str(text);
+            return;
+        }
+        if std::mem::take(&mut self.soft_break) {
+            let last = self.text.chars().next_back();
+            let first = text.chars().next();
+            if let (Some(last), Some(first)) = (last, first) {
+                if last != '\n' && !(is_wide(last) && is_wide(first)) {
+                    self.text.push(' ');
+                }
+            }
+        }
+        self.text.push_str(text);
+    }
+
+    fn push_str(&mut self, text: &str) {
+        self.soft_break = false;
+        self.text.push_str(text);
+    }
+
+    fn break_paragraph(&mut self) {
+        self.soft_break = false;
+        while self.text.ends_with(' ') {
+            self.text.pop();
+        }
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+}
+
+/// 只读取 YAML 头中的单行 `title:` 与 `author:`，不做完整的 YAML 解析。
+fn front_matter(block: &str) -> BookMeta {
+    let value = |key: &str| {
+        block.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let value = value.trim().trim_matches(['"', '\'']).trim();
+            (name.trim().eq_ignore_ascii_case(key) && !value.is_empty()).then(|| value.to_string())
+        })
+    };
+    BookMeta {
+        title: value("title"),
+        author: value("author"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_plain_text_with_heading_chapters() {
+        let markdown = "---\ntitle: \"雪夜行\"\nauthor: 佚名\n---\n\n# 第一章 雪夜\n\n那一夜的雪**下得很大**，\n他独自走在[回家](https://example.com)的路上。\nThe snow\nwas deep.\n\n- 积雪\n- [x] 灯火\n\n```\nlet x = 1;\n```\n\n第二章 归人\n---\n\n> 清晨，村口站着一个人。\n";
+        let source = markdown_to_source(markdown);
+        assert_eq!(source.meta.title.as_deref(), Some("雪夜行"));
+        assert_eq!(source.meta.author.as_deref(), Some("佚名"));
+        assert_eq!(
+            source.text,
+            "第一章 雪夜\n那一夜的雪下得很大，他独自走在回家的路上。 The snow was deep.\n• 积雪\n• ☑ 灯火\nlet x = 1;\n第二章 归人\n清晨，村口站着一个人。\n"
+        );
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一章 雪夜", "第二章 归人"]);
+        let second: String = source
+            .text
+            .chars()
+            .skip(source.chapters[1].offset)
+            .collect();
+        assert!(second.starts_with("第二章 归人"));
+    }
+}
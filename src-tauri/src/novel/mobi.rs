@@ -0,0 +1,966 @@
+use anyhow::{bail, Context, Result};
+
+use super::html::html_to_source;
+use super::{BookMeta, Chapter, SourceText};
+
+const COMPRESSION_NONE: u16 = 1;
+const COMPRESSION_PALMDOC: u16 = 2;
+const COMPRESSION_HUFFCDIC: u16 = 17480;
+
+const EXTH_AUTHOR: u32 = 100;
+const EXTH_KF8_BOUNDARY: u32 = 121;
+const EXTH_TITLE: u32 = 503;
+
+/// 解析未加密的 MOBI / AZW3（KF8）文件，HTML 正文转为纯文本并按标题或分页符分章。
+///
+/// 同时包含旧格式与 KF8 的合订文件优先读取 KF8 部分。KF8 正文按骨架与片段索引
+/// 还原为原来的各个 XHTML 文件再逐个转换。
+pub fn parse_mobi(bytes: &[u8]) -> Result<SourceText> {
+    let pdb = PalmDb::parse(bytes)?;
+    match pdb.kind.as_slice() {
+        b"BOOKMOBI" | b"TEXtREAd" => {}
+        b"TPZ3" | b"TPZ0" => bail!("不支持 Topaz 格式的 Kindle 文件"),
+        _ => bail!("不是有效的 MOBI/AZW3 文件"),
+    }
+
+    let mut header = MobiHeader::parse(&pdb, 0)?;
+    if let Some(boundary) = header.exth_u32(EXTH_KF8_BOUNDARY) {
+        // 合订文件中 KF8 头位于边界记录之后；KF8 部分损坏时退回旧格式
+        if let Ok(kf8) = MobiHeader::parse(&pdb, boundary as usize) {
+            header = kf8;
+        }
+    }
+    if header.encryption != 0 {
+        bail!("该电子书受 DRM 保护，无法打开；请使用未加密的 MOBI/AZW3 文件");
+    }
+
+    let text = header.read_text(&pdb)?;
+    let mut source = match header.kf8_parts(&pdb, &text)? {
+        Some(parts) => merge_parts(
+            parts
+                .iter()
+                .map(|part| html_to_source(&header.decode(part))),
+        ),
+        None => html_to_source(&header.decode(&text)),
+    };
+    if source.text.trim().is_empty() {
+        bail!("电子书中没有可读取的正文");
+    }
+    source.meta = BookMeta {
+        title: header
+            .exth_string(EXTH_TITLE)
+            .or_else(|| header.full_name.clone()),
+        author: header.exth_string(EXTH_AUTHOR),
+    };
+    Ok(source)
+}
+
+/// 依次拼接各文件的文字，章节偏移随之平移。
+fn merge_parts(parts: impl Iterator<Item = SourceText>) -> SourceText {
+    let mut source = SourceText::default();
+    let mut offset = 0;
+    for part in parts {
+        if part.text.trim().is_empty() {
+            continue;
+        }
+        source
+            .chapters
+            .extend(part.chapters.into_iter().map(|chapter| Chapter {
+                offset: offset + chapter.offset,
+                ..chapter
+            }));
+        let mut text = part.text;
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        offset += text.chars().count();
+        source.text.push_str(&text);
+    }
+    source
+}
+
+/// Palm 数据库容器：78 字节文件头加记录偏移表。
+struct PalmDb<'a> {
+    kind: Vec<u8>,
+    records: Vec<&'a [u8]>,
+}
+
+impl<'a> PalmDb<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self> {
+        if bytes.len() < 78 {
+            bail!("不是有效的 MOBI/AZW3 文件");
+        }
+        let kind = bytes[60..68].to_vec();
+        let count = read_u16(bytes, 76)? as usize;
+        let mut offsets = Vec::with_capacity(count);
+        for index in 0..count {
+            offsets.push(read_u32(bytes, 78 + index * 8)? as usize);
+        }
+        let mut records = Vec::with_capacity(count);
+        for (index, &start) in offsets.iter().enumerate() {
+            let end = offsets.get(index + 1).copied().unwrap_or(bytes.len());
+            let record = bytes
+                .get(start..end.max(start))
+                .context("MOBI 记录偏移超出文件范围")?;
+            records.push(record);
+        }
+        Ok(PalmDb { kind, records })
+    }
+
+    fn record(&self, index: usize) -> Result<&'a [u8]> {
+        self.records
+            .get(index)
+            .copied()
+            .with_context(|| format!("MOBI 文件缺少第 {} 条记录", index))
+    }
+}
+
+struct MobiHeader {
+    /// 头部所在的记录号，正文与 HUFF 记录号都相对于它
+    base: usize,
+    compression: u16,
+    text_length: usize,
+    text_records: usize,
+    encryption: u16,
+    encoding: u32,
+    version: u32,
+    full_name: Option<String>,
+    huff_record: usize,
+    huff_count: usize,
+    extra_flags: u16,
+    /// KF8 的 FDST、片段与骨架索引记录号，同样相对于头部
+    fdst_record: Option<usize>,
+    fragment_index: Option<usize>,
+    skeleton_index: Option<usize>,
+    exth: Vec<(u32, Vec<u8>)>,
+}
+
+impl MobiHeader {
+    fn parse(pdb: &PalmDb, base: usize) -> Result<Self> {
+        let record = pdb.record(base)?;
+        let mut header = MobiHeader {
+            base,
+            compression: read_u16(record, 0)?,
+            text_length: read_u32(record, 4)? as usize,
+            text_records: read_u16(record, 8)? as usize,
+            encryption: read_u16(record, 12)?,
+            encoding: 1252,
+            version: 0,
+            full_name: None,
+            huff_record: 0,
+            huff_count: 0,
+            extra_flags: 0,
+            fdst_record: None,
+            fragment_index: None,
+            skeleton_index: None,
+            exth: Vec::new(),
+        };
+        // 纯 PalmDOC（TEXtREAd）没有 MOBI 头
+        if record.get(16..20) != Some(b"MOBI".as_slice()) {
+            if base != 0 {
+                bail!("KF8 头无效");
+            }
+            return Ok(header);
+        }
+
+        let header_len = read_u32(record, 20)? as usize;
+        header.encoding = read_u32(record, 28)?;
+        header.version = read_u32(record, 36)?;
+        let name_offset = read_u32(record, 84)? as usize;
+        let name_len = read_u32(record, 88)? as usize;
+        header.full_name = record
+            .get(name_offset..name_offset + name_len)
+            .map(|name| header.decode(name))
+            .filter(|name| !name.trim().is_empty());
+        header.huff_record = read_u32(record, 112)? as usize;
+        header.huff_count = read_u32(record, 116)? as usize;
+        if header_len >= 0xE4 {
+            header.extra_flags = read_u16(record, 16 + 0xE2)?;
+        }
+        if header.version >= 8 && header_len >= 0xF0 {
+            header.fdst_record = record_number(read_u32(record, 0xC0)?);
+            header.fragment_index = record_number(read_u32(record, 0xF8)?);
+            header.skeleton_index = record_number(read_u32(record, 0xFC)?);
+        }
+
+        let exth_flags = read_u32(record, 128)?;
+        if exth_flags & 0x40 != 0 {
+            header.exth = parse_exth(record.get(16 + header_len..).unwrap_or(&[]));
+        }
+        Ok(header)
+    }
+
+    fn exth_u32(&self, kind: u32) -> Option<u32> {
+        self.exth
+            .iter()
+            .find(|(record, _)| *record == kind)
+            .and_then(|(_, data)| read_u32(data, 0).ok())
+    }
+
+    fn exth_string(&self, kind: u32) -> Option<String> {
+        self.exth
+            .iter()
+            .find(|(record, _)| *record == kind)
+            .map(|(_, data)| self.decode(data).trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        let encoding = if self.encoding == 65001 {
+            encoding_rs::UTF_8
+        } else {
+            encoding_rs::WINDOWS_1252
+        };
+        encoding.decode_without_bom_handling(bytes).0.into_owned()
+    }
+
+    /// 逐条解压正文记录，拼接为未解码的完整正文。
+    fn read_text(&self, pdb: &PalmDb) -> Result<Vec<u8>> {
+        let mut huff = match self.compression {
+            COMPRESSION_HUFFCDIC => Some(HuffCdic::load(
+                pdb,
+                self.base + self.huff_record,
+                self.huff_count,
+            )?),
+            COMPRESSION_NONE | COMPRESSION_PALMDOC => None,
+            other => bail!("不支持的 MOBI 压缩方式: {}", other),
+        };
+
+        let mut text = Vec::with_capacity(self.text_length);
+        for index in 1..=self.text_records {
+            let record = pdb.record(self.base + index)?;
+            let record = strip_trailing_entries(record, self.extra_flags);
+            match (&mut huff, self.compression) {
+                (Some(huff), _) => text.extend(huff.unpack(record, 0)?),
+                (None, COMPRESSION_PALMDOC) => text.extend(palmdoc_decompress(record)),
+                _ => text.extend_from_slice(record),
+            }
+        }
+        text.truncate(self.text_length);
+        Ok(text)
+    }
+
+    /// 还原 KF8 的各个 XHTML 文件：每个骨架之后紧跟属于它的片段，片段按插入位置
+    /// 填回骨架。旧格式返回 `None`。
+    fn kf8_parts(&self, pdb: &PalmDb, text: &[u8]) -> Result<Option<Vec<Vec<u8>>>> {
+        if self.version < 8 {
+            return Ok(None);
+        }
+        // FDST 把正文分为若干流，第一个是 XHTML，其余是样式表与 SVG
+        let text = match self.fdst_record {
+            Some(record) => first_flow(pdb.record(self.base + record)?, text)?,
+            None => text,
+        };
+        let (Some(skeleton_index), Some(fragment_index)) =
+            (self.skeleton_index, self.fragment_index)
+        else {
+            return Ok(Some(vec![text.to_vec()]));
+        };
+        let skeletons = read_index(pdb, self.base + skeleton_index)?;
+        let mut fragments = read_index(pdb, self.base + fragment_index)?.into_iter();
+
+        let mut parts = Vec::with_capacity(skeletons.len());
+        for skeleton in &skeletons {
+            let start = skeleton.value(6, 0)?;
+            let mut cursor = start.saturating_add(skeleton.value(6, 1)?);
+            let mut part = text
+                .get(start..cursor)
+                .context("KF8 骨架超出正文范围")?
+                .to_vec();
+            for _ in 0..skeleton.value(1, 0)? {
+                let fragment = fragments.next().context("KF8 片段索引不完整")?;
+                let length = fragment.value(6, 1)?;
+                let data = text
+                    .get(cursor..cursor.saturating_add(length))
+                    .context("KF8 片段超出正文范围")?;
+                // 插入位置以骨架在正文中的起点为基准，且计入此前已插入的片段
+                let position = std::str::from_utf8(&fragment.key)
+                    .ok()
+                    .and_then(|key| key.parse::<usize>().ok())
+                    .and_then(|position| position.checked_sub(start))
+                    .filter(|position| *position <= part.len())
+                    .context("KF8 片段插入位置无效")?;
+                part.splice(position..position, data.iter().copied());
+                cursor += length;
+            }
+            parts.push(part);
+        }
+        Ok(Some(parts))
+    }
+}
+
+/// 头部中的记录号，`0xFFFFFFFF` 表示没有该记录。
+fn record_number(value: u32) -> Option<usize> {
+    (value != u32::MAX).then_some(value as usize)
+}
+
+fn first_flow<'a>(fdst: &[u8], text: &'a [u8]) -> Result<&'a [u8]> {
+    if fdst.get(0..4) != Some(b"FDST".as_slice()) {
+        bail!("FDST 记录无效");
+    }
+    if read_u32(fdst, 8)? == 0 {
+        return Ok(text);
+    }
+    let start = read_u32(fdst, 12)? as usize;
+    let end = (read_u32(fdst, 16)? as usize).min(text.len());
+    text.get(start..end).context("FDST 流超出正文范围")
+}
+
+/// INDX 索引中的一条：文字键与 TAGX 所描述各标签的取值。
+struct IndexEntry {
+    key: Vec<u8>,
+    tags: Vec<(u8, Vec<usize>)>,
+}
+
+impl IndexEntry {
+    fn parse(entry: &[u8], tags: &[[u8; 4]], control_bytes: usize) -> Result<Self> {
+        let key_len = usize::from(*entry.first().context("KF8 索引条目为空")?);
+        let key = entry.get(1..1 + key_len).context("KF8 索引条目越界")?;
+        let controls = entry
+            .get(1 + key_len..1 + key_len + control_bytes)
+            .context("KF8 索引条目越界")?;
+        let mut data = &entry[1 + key_len + control_bytes..];
+
+        // 控制字节给出每个标签的取值组数；掩码各位全为 1 时改由变长整数给出字节数
+        let mut layouts = Vec::new();
+        let mut control = 0;
+        for &[tag, per_entry, mask, end] in tags {
+            if end & 1 != 0 {
+                control += 1;
+                continue;
+            }
+            let value = controls.get(control).context("KF8 索引条目越界")? & mask;
+            if value == 0 {
+                continue;
+            }
+            let layout = if value == mask && mask.count_ones() > 1 {
+                TagLayout::Bytes(read_varint(&mut data)?)
+            } else {
+                TagLayout::Count(
+                    usize::from(value >> mask.trailing_zeros()) * usize::from(per_entry),
+                )
+            };
+            layouts.push((tag, layout));
+        }
+
+        let mut values = Vec::with_capacity(layouts.len());
+        for (tag, layout) in layouts {
+            let mut tag_values = Vec::new();
+            match layout {
+                TagLayout::Count(count) => {
+                    for _ in 0..count {
+                        tag_values.push(read_varint(&mut data)?);
+                    }
+                }
+                TagLayout::Bytes(bytes) => {
+                    let end = data.len().saturating_sub(bytes);
+                    while data.len() > end {
+                        tag_values.push(read_varint(&mut data)?);
+                    }
+                }
+            }
+            values.push((tag, tag_values));
+        }
+        Ok(IndexEntry {
+            key: key.to_vec(),
+            tags: values,
+        })
+    }
+
+    fn value(&self, tag: u8, position: usize) -> Result<usize> {
+        self.tags
+            .iter()
+            .find(|(kind, _)| *kind == tag)
+            .and_then(|(_, values)| values.get(position).copied())
+            .with_context(|| format!("KF8 索引缺少标签 {}", tag))
+    }
+}
+
+/// 标签取值的个数，或取值合计占用的字节数。
+enum TagLayout {
+    Count(usize),
+    Bytes(usize),
+}
+
+/// 读取一个 INDX 索引：首条记录含 TAGX 标签表，其后若干条记录存放条目。
+fn read_index(pdb: &PalmDb, first: usize) -> Result<Vec<IndexEntry>> {
+    let header = pdb.record(first)?;
+    if header.get(0..4) != Some(b"INDX".as_slice()) {
+        bail!("KF8 索引记录无效");
+    }
+    let tagx = header
+        .get(read_u32(header, 4)? as usize..)
+        .filter(|tagx| tagx.starts_with(b"TAGX"))
+        .context("KF8 索引缺少 TAGX")?;
+    let tagx_len = read_u32(tagx, 4)? as usize;
+    let control_bytes = read_u32(tagx, 8)? as usize;
+    let tags: Vec<[u8; 4]> = tagx
+        .get(12..tagx_len)
+        .context("KF8 索引缺少 TAGX")?
+        .chunks_exact(4)
+        .map(|tag| [tag[0], tag[1], tag[2], tag[3]])
+        .collect();
+
+    let mut entries = Vec::new();
+    for index in 1..=read_u32(header, 24)? as usize {
+        let record = pdb.record(first + index)?;
+        if record.get(0..4) != Some(b"INDX".as_slice()) {
+            bail!("KF8 索引记录无效");
+        }
+        // IDXT 表给出各条目的起点，最后一条止于 IDXT 本身
+        let idxt = read_u32(record, 20)? as usize;
+        let mut positions = (0..read_u32(record, 24)? as usize)
+            .map(|entry| read_u16(record, idxt + 4 + entry * 2).map(usize::from))
+            .collect::<Result<Vec<_>>>()?;
+        positions.push(idxt);
+        for pair in positions.windows(2) {
+            let entry = record
+                .get(pair[0]..pair[1].max(pair[0]))
+                .context("KF8 索引条目越界")?;
+            entries.push(IndexEntry::parse(entry, &tags, control_bytes)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// 正序变长整数，最高位为 1 的字节是最后一个字节。
+fn read_varint(data: &mut &[u8]) -> Result<usize> {
+    let mut value = 0usize;
+    loop {
+        let (&byte, rest) = data.split_first().context("KF8 索引条目越界")?;
+        *data = rest;
+        value = value << 7 | usize::from(byte & 0x7F);
+        if byte & 0x80 != 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn parse_exth(data: &[u8]) -> Vec<(u32, Vec<u8>)> {
+    let mut records = Vec::new();
+    if data.get(0..4) != Some(b"EXTH".as_slice()) {
+        return records;
+    }
+    let Ok(count) = read_u32(data, 8) else {
+        return records;
+    };
+    let mut position = 12;
+    for _ in 0..count {
+        let (Ok(kind), Ok(len)) = (read_u32(data, position), read_u32(data, position + 4)) else {
+            break;
+        };
+        let len = len as usize;
+        let Some(value) = data.get(position + 8..position + len.max(8)) else {
+            break;
+        };
+        records.push((kind, value.to_vec()));
+        position += len.max(8);
+    }
+    records
+}
+
+/// 去掉正文记录末尾的附加数据（多字节字符溢出与索引信息），由 MOBI 头的 extra flags 描述。
+fn strip_trailing_entries(record: &[u8], flags: u16) -> &[u8] {
+    let mut size = record.len();
+    let mut bits = flags >> 1;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            size = size.saturating_sub(trailing_entry_size(&record[..size]));
+        }
+        bits >>= 1;
+    }
+    if flags & 1 != 0 && size > 0 {
+        size = size.saturating_sub(usize::from(record[size - 1] & 0x3) + 1);
+    }
+    &record[..size]
+}
+
+/// 附加数据的长度以逆序变长整数写在末尾，最高位为 1 的字节是最后一个字节。
+fn trailing_entry_size(data: &[u8]) -> usize {
+    let mut result = 0usize;
+    let mut shift = 0;
+    for &byte in data.iter().rev().take(4) {
+        result |= usize::from(byte & 0x7F) << shift;
+        shift += 7;
+        if byte & 0x80 != 0 {
+            break;
+        }
+    }
+    result
+}
+
+/// PalmDOC 的 LZ77 变体。
+fn palmdoc_decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() * 2);
+    let mut index = 0;
+    while index < data.len() {
+        let byte = data[index];
+        index += 1;
+        match byte {
+            0x00 | 0x09..=0x7F => output.push(byte),
+            0x01..=0x08 => {
+                let end = (index + byte as usize).min(data.len());
+                output.extend_from_slice(&data[index..end]);
+                index = end;
+            }
+            0x80..=0xBF => {
+                let Some(&next) = data.get(index) else {
+                    break;
+                };
+                index += 1;
+                let pair = (u16::from(byte) << 8 | u16::from(next)) & 0x3FFF;
+                let distance = (pair >> 3) as usize;
+                let length = (pair & 0x07) as usize + 3;
+                if distance == 0 || distance > output.len() {
+                    continue;
+                }
+                let start = output.len() - distance;
+                for offset in 0..length {
+                    output.push(output[start + offset]);
+                }
+            }
+            0xC0..=0xFF => {
+                output.push(b' ');
+                output.push(byte ^ 0x80);
+            }
+        }
+    }
+    output
+}
+
+/// HUFF/CDIC 压缩：HUFF 记录给出码表，CDIC 记录给出短语字典，短语本身可能仍是压缩的。
+struct HuffCdic {
+    dict1: Vec<(u32, bool, u64)>,
+    min_codes: [u64; 33],
+    max_codes: [u64; 33],
+    phrases: Vec<(Vec<u8>, bool)>,
+}
+
+impl HuffCdic {
+    fn load(pdb: &PalmDb, first: usize, count: usize) -> Result<Self> {
+        let huff = pdb.record(first)?;
+        if huff.get(0..8) != Some(b"HUFF\x00\x00\x00\x18".as_slice()) {
+            bail!("HUFF 记录无效");
+        }
+        let table1 = read_u32(huff, 8)? as usize;
+        let table2 = read_u32(huff, 12)? as usize;
+
+        let mut dict1 = Vec::with_capacity(256);
+        for index in 0..256 {
+            let value = read_u32(huff, table1 + index * 4)?;
+            let code_len = value & 0x1F;
+            let terminal = value & 0x80 != 0;
+            let max_code = u64::from(value >> 8);
+            if code_len == 0 {
+                bail!("HUFF 码表无效");
+            }
+            dict1.push((code_len, terminal, ((max_code + 1) << (32 - code_len)) - 1));
+        }
+
+        let mut min_codes = [0u64; 33];
+        let mut max_codes = [0u64; 33];
+        for code_len in 1..=32usize {
+            let min = u64::from(read_u32(huff, table2 + (code_len - 1) * 8)?);
+            let max = u64::from(read_u32(huff, table2 + (code_len - 1) * 8 + 4)?);
+            min_codes[code_len] = min << (32 - code_len);
+            max_codes[code_len] = ((max + 1) << (32 - code_len)).wrapping_sub(1);
+        }
+
+        let mut phrases = Vec::new();
+        for index in 1..count {
+            let cdic = pdb.record(first + index)?;
+            if cdic.get(0..8) != Some(b"CDIC\x00\x00\x00\x10".as_slice()) {
+                bail!("CDIC 记录无效");
+            }
+            let total = read_u32(cdic, 8)? as usize;
+            let bits = read_u32(cdic, 12)?;
+            let n = (1usize << bits.min(31)).min(total.saturating_sub(phrases.len()));
+            for entry in 0..n {
+                let offset = read_u16(cdic, 16 + entry * 2)? as usize;
+                let header = read_u16(cdic, 16 + offset)?;
+                let len = (header & 0x7FFF) as usize;
+                let data = cdic
+                    .get(18 + offset..18 + offset + len)
+                    .context("CDIC 短语越界")?;
+                phrases.push((data.to_vec(), header & 0x8000 != 0));
+            }
+        }
+        Ok(HuffCdic {
+            dict1,
+            min_codes,
+            max_codes,
+            phrases,
+        })
+    }
+
+    fn unpack(&mut self, data: &[u8], depth: usize) -> Result<Vec<u8>> {
+        if depth > 32 {
+            bail!("HUFF/CDIC 短语嵌套过深");
+        }
+        let mut padded = data.to_vec();
+        padded.extend_from_slice(&[0; 8]);
+        let read_u64 = |position: usize| {
+            u64::from_be_bytes(padded[position..position + 8].try_into().expect("长度为 8"))
+        };
+
+        let mut bits_left = data.len() as i64 * 8;
+        let mut position = 0;
+        let mut window = read_u64(position);
+        let mut n: i64 = 32;
+        let mut output = Vec::new();
+        loop {
+            if n <= 0 {
+                position += 4;
+                if position + 8 > padded.len() {
+                    break;
+                }
+                window = read_u64(position);
+                n += 32;
+            }
+            let code = (window >> n) & 0xFFFF_FFFF;
+            let (mut code_len, terminal, mut max_code) = self.dict1[(code >> 24) as usize];
+            if !terminal {
+                while code_len < 32 && code < self.min_codes[code_len as usize] {
+                    code_len += 1;
+                }
+                max_code = self.max_codes[code_len as usize];
+            }
+            n -= i64::from(code_len);
+            bits_left -= i64::from(code_len);
+            if bits_left < 0 {
+                break;
+            }
+            let index = (max_code.checked_sub(code).context("HUFF/CDIC 数据损坏")?
+                >> (32 - code_len)) as usize;
+            let (phrase, expanded) = self
+                .phrases
+                .get(index)
+                .cloned()
+                .context("HUFF/CDIC 短语索引越界")?;
+            if expanded {
+                output.extend_from_slice(&phrase);
+            } else {
+                let phrase = self.unpack(&phrase, depth + 1)?;
+                output.extend_from_slice(&phrase);
+                self.phrases[index] = (phrase, true);
+            }
+        }
+        Ok(output)
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .context("MOBI 文件结构不完整")?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("MOBI 文件结构不完整")?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按 PalmDOC 规则压缩：只用字面量与空格组合，足以验证解压与容器解析。
+    fn palmdoc_compress(data: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut index = 0;
+        while index < data.len() {
+            let byte = data[index];
+            if byte == b' ' && matches!(data.get(index + 1), Some(0x40..=0x7F)) {
+                output.push(data[index + 1] ^ 0x80);
+                index += 2;
+            } else if byte == 0 || (0x09..=0x7F).contains(&byte) {
+                output.push(byte);
+                index += 1;
+            } else {
+                let end = (index + 8).min(data.len());
+                output.push((end - index) as u8);
+                output.extend_from_slice(&data[index..end]);
+                index = end;
+            }
+        }
+        output
+    }
+
+    fn set_u32(record: &mut [u8], offset: usize, value: u32) {
+        record[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    /// 带 EXTH 作者与书名的 MOBI 头，KF8 索引记录号默认为空。
+    fn record0(compression: u16, text_len: usize, text_records: usize, encryption: u16) -> Vec<u8> {
+        let mut exth = b"EXTH".to_vec();
+        let author = "曹雪芹".as_bytes();
+        exth.extend_from_slice(&(12 + 8 + author.len() as u32).to_be_bytes());
+        exth.extend_from_slice(&1u32.to_be_bytes());
+        exth.extend_from_slice(&EXTH_AUTHOR.to_be_bytes());
+        exth.extend_from_slice(&(8 + author.len() as u32).to_be_bytes());
+        exth.extend_from_slice(author);
+
+        let mut record0 = vec![0u8; 16 + 0x108];
+        record0[0..2].copy_from_slice(&compression.to_be_bytes());
+        set_u32(&mut record0, 4, text_len as u32);
+        record0[8..10].copy_from_slice(&(text_records as u16).to_be_bytes());
+        record0[12..14].copy_from_slice(&encryption.to_be_bytes());
+        record0[16..20].copy_from_slice(b"MOBI");
+        set_u32(&mut record0, 20, 0x108);
+        set_u32(&mut record0, 28, 65001);
+        set_u32(&mut record0, 36, 6);
+        set_u32(&mut record0, 128, 0x40);
+        for offset in [0xC0, 0xF8, 0xFC] {
+            set_u32(&mut record0, offset, u32::MAX);
+        }
+        record0.extend_from_slice(&exth);
+        let name = "红楼梦".as_bytes();
+        let name_offset = record0.len() as u32;
+        set_u32(&mut record0, 84, name_offset);
+        set_u32(&mut record0, 88, name.len() as u32);
+        record0.extend_from_slice(name);
+        record0
+    }
+
+    fn palm_db(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = vec![0u8; 78];
+        file[60..68].copy_from_slice(b"BOOKMOBI");
+        file[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        let mut offset = 78 + records.len() * 8 + 2;
+        for record in records {
+            file.extend_from_slice(&(offset as u32).to_be_bytes());
+            file.extend_from_slice(&[0; 4]);
+            offset += record.len();
+        }
+        file.extend_from_slice(&[0, 0]);
+        for record in records {
+            file.extend_from_slice(record);
+        }
+        file
+    }
+
+    fn build_mobi(html: &str, encryption: u16) -> Vec<u8> {
+        let text = html.as_bytes();
+        let chunks: Vec<Vec<u8>> = text.chunks(4096).map(palmdoc_compress).collect();
+        let mut records = vec![record0(
+            COMPRESSION_PALMDOC,
+            text.len(),
+            chunks.len(),
+            encryption,
+        )];
+        records.extend(chunks);
+        palm_db(&records)
+    }
+
+    /// 每个码字固定 8 位，码字 `c` 对应第 `255 - c` 个短语。
+    fn huff_record() -> Vec<u8> {
+        let mut record = b"HUFF\x00\x00\x00\x18".to_vec();
+        record.extend_from_slice(&24u32.to_be_bytes());
+        record.extend_from_slice(&(24 + 256 * 4u32).to_be_bytes());
+        record.resize(24, 0);
+        for _ in 0..256 {
+            record.extend_from_slice(&(255u32 << 8 | 0x80 | 8).to_be_bytes());
+        }
+        record.extend_from_slice(&[0; 32 * 8]);
+        record
+    }
+
+    fn cdic_record(phrases: &[(&[u8], bool)]) -> Vec<u8> {
+        let mut record = b"CDIC\x00\x00\x00\x10".to_vec();
+        record.extend_from_slice(&(phrases.len() as u32).to_be_bytes());
+        record.extend_from_slice(&8u32.to_be_bytes());
+        let mut body = Vec::new();
+        for (phrase, literal) in phrases {
+            let offset = phrases.len() * 2 + body.len();
+            record.extend_from_slice(&(offset as u16).to_be_bytes());
+            let flag = if *literal { 0x8000 } else { 0 };
+            body.extend_from_slice(&(phrase.len() as u16 | flag).to_be_bytes());
+            body.extend_from_slice(phrase);
+        }
+        record.extend_from_slice(&body);
+        record
+    }
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = vec![(value & 0x7F) as u8 | 0x80];
+        value >>= 7;
+        while value > 0 {
+            bytes.insert(0, (value & 0x7F) as u8);
+            value >>= 7;
+        }
+        bytes
+    }
+
+    /// 单个数据记录的 INDX 索引；每个标签占控制字节中的一位，各出现一组取值。
+    fn index_records(tags: &[(u8, u8)], entries: &[(String, Vec<usize>)]) -> Vec<Vec<u8>> {
+        let mut header = vec![0u8; 0xC0];
+        header[0..4].copy_from_slice(b"INDX");
+        set_u32(&mut header, 4, 0xC0);
+        set_u32(&mut header, 24, 1);
+        header.extend_from_slice(b"TAGX");
+        header.extend_from_slice(&(12 + 4 * (tags.len() as u32 + 1)).to_be_bytes());
+        header.extend_from_slice(&1u32.to_be_bytes());
+        for (bit, (tag, per_entry)) in tags.iter().enumerate() {
+            header.extend_from_slice(&[*tag, *per_entry, 1 << bit, 0]);
+        }
+        header.extend_from_slice(&[0, 0, 0, 1]);
+
+        let mut data = vec![0u8; 0xC0];
+        data[0..4].copy_from_slice(b"INDX");
+        let mut positions = Vec::new();
+        for (key, values) in entries {
+            positions.push(data.len() as u16);
+            data.push(key.len() as u8);
+            data.extend_from_slice(key.as_bytes());
+            data.push((1u8 << tags.len()) - 1);
+            for &value in values {
+                data.extend(varint(value));
+            }
+        }
+        let idxt = data.len() as u32;
+        set_u32(&mut data, 20, idxt);
+        set_u32(&mut data, 24, entries.len() as u32);
+        data.extend_from_slice(b"IDXT");
+        for position in positions {
+            data.extend_from_slice(&position.to_be_bytes());
+        }
+        vec![header, data]
+    }
+
+    #[test]
+    fn reads_palmdoc_mobi_with_chapters_and_meta() {
+        let mut html = String::from("<html><body>");
+        for chapter in 1..=3 {
+            html.push_str(&format!("<h2>第{}回</h2>", chapter));
+            for line in 0..60 {
+                html.push_str(&format!(
+                    "<p>满纸荒唐言，一把辛酸泪 line {} of chapter {}.</p>",
+                    line, chapter
+                ));
+            }
+            html.push_str("<mbp:pagebreak/>");
+        }
+        html.push_str("</body></html>");
+
+        let source = parse_mobi(&build_mobi(&html, 0)).unwrap();
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第1回", "第2回", "第3回"]);
+        assert!(source
+            .text
+            .contains("满纸荒唐言，一把辛酸泪 line 59 of chapter 3."));
+        assert_eq!(source.meta.title.as_deref(), Some("红楼梦"));
+        assert_eq!(source.meta.author.as_deref(), Some("曹雪芹"));
+
+        let error = parse_mobi(&build_mobi(&html, 2)).err().unwrap();
+        assert!(error.to_string().contains("DRM"));
+    }
+
+    #[test]
+    fn strips_multibyte_and_index_trailers() {
+        // 末尾依次为：多字节溢出 1 字节（标志位 0），以及长度为 3 的索引附加数据（标志位 1）
+        let record = [b'a', b'b', 0xE4, 0x01, 0xAA, 0xBB, 0x83];
+        assert_eq!(strip_trailing_entries(&record, 0b11), b"ab");
+    }
+
+    #[test]
+    fn reads_huffcdic_records_with_nested_phrases() {
+        let phrases: [(&[u8], bool); 6] = [
+            ("满纸".as_bytes(), true),
+            ("荒唐言".as_bytes(), true),
+            (b"<p>", true),
+            (b"</p>", true),
+            // 未展开的短语本身也是 HUFF/CDIC 压缩数据
+            (&[255 - 2, 255, 255 - 1, 255 - 3], false),
+            ("<h2>第一回</h2>".as_bytes(), true),
+        ];
+        let text_records = vec![vec![255 - 5, 255 - 4, 255 - 4], vec![255 - 4]];
+        let html = "<h2>第一回</h2><p>满纸荒唐言</p><p>满纸荒唐言</p><p>满纸荒唐言</p>";
+
+        let mut header = record0(COMPRESSION_HUFFCDIC, html.len(), text_records.len(), 0);
+        set_u32(&mut header, 112, 1 + text_records.len() as u32);
+        set_u32(&mut header, 116, 2);
+        let mut records = vec![header];
+        records.extend(text_records);
+        records.push(huff_record());
+        records.push(cdic_record(&phrases));
+
+        let source = parse_mobi(&palm_db(&records)).unwrap();
+        assert_eq!(source.text, "第一回\n满纸荒唐言\n满纸荒唐言\n满纸荒唐言\n");
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一回"]);
+    }
+
+    #[test]
+    fn reassembles_kf8_fragments_into_skeletons() {
+        let skeletons: [(&str, Vec<(&str, &str)>); 2] = [
+            (
+                "<html><head><title>一</title></head><body><div></div></body></html>",
+                vec![("<div>", "<h2>第一回</h2><p>甲</p>")],
+            ),
+            (
+                "<html><head><title>二</title></head><body><div></div><div></div></body></html>",
+                vec![
+                    ("<div>", "<h2>第二回</h2><p>乙</p>"),
+                    ("</div><div>", "<p>丙</p>"),
+                ],
+            ),
+        ];
+        // 正文中骨架之后紧跟它的片段；插入位置按已插入片段后的骨架计算
+        let mut flow = String::new();
+        let mut skeleton_entries = Vec::new();
+        let mut fragment_entries = Vec::new();
+        for (number, (skeleton, fragments)) in skeletons.iter().enumerate() {
+            let start = flow.len();
+            flow.push_str(skeleton);
+            skeleton_entries.push((
+                format!("SKEL{:010}", number),
+                vec![fragments.len(), start, skeleton.len()],
+            ));
+            let mut assembled = skeleton.to_string();
+            let mut search_from = 0;
+            for (anchor, fragment) in fragments {
+                let position =
+                    assembled[search_from..].find(anchor).unwrap() + search_from + anchor.len();
+                assembled.insert_str(position, fragment);
+                search_from = position + fragment.len();
+                fragment_entries.push((
+                    (start + position).to_string(),
+                    vec![start + position, fragment.len()],
+                ));
+                flow.push_str(fragment);
+            }
+        }
+        let css = "p { text-indent: 2em; }";
+        let text = format!("{}{}", flow, css);
+
+        let mut fdst = b"FDST".to_vec();
+        fdst.extend_from_slice(&12u32.to_be_bytes());
+        fdst.extend_from_slice(&2u32.to_be_bytes());
+        for value in [0, flow.len(), flow.len(), text.len()] {
+            fdst.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+
+        let mut header = record0(COMPRESSION_NONE, text.len(), 1, 0);
+        set_u32(&mut header, 36, 8);
+        set_u32(&mut header, 0xC0, 2);
+        set_u32(&mut header, 0xFC, 3);
+        set_u32(&mut header, 0xF8, 5);
+        let mut records = vec![header, text.as_bytes().to_vec(), fdst];
+        records.extend(index_records(&[(1, 1), (6, 2)], &skeleton_entries));
+        records.extend(index_records(&[(6, 2)], &fragment_entries));
+
+        let source = parse_mobi(&palm_db(&records)).unwrap();
+        assert_eq!(source.text, "第一回\n甲\n第二回\n乙\n丙\n");
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一回", "第二回"]);
+        let second: String = source
+            .text
+            .chars()
+            .skip(source.chapters[1].offset)
+            .collect();
+        assert!(second.starts_with("第二回"));
+    }
+}
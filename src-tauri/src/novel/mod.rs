@@ -0,0 +1,302 @@
+mod archive;
+mod convert;
+mod docx;
+mod epub;
+mod fb2;
+mod folder;
+mod html;
+mod markdown;
+mod mobi;
+mod normalize;
+mod offset_map;
+mod pdf;
+mod readability;
+mod reflow;
+mod replace;
+mod umd;
+
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+use serde::Serialize;
+
+pub use archive::ArchiveEntry;
+pub use convert::ScriptConversion;
+pub use offset_map::OffsetMap;
+use offset_map::Segment;
+pub use replace::RuleReport;
+
+use crate::rules::ReplaceRule;
+use crate::settings::ReadingConfig;
+
+/// 读取并解码后的原文，章节偏移为原文偏移。
+#[derive(Clone, Default)]
+pub struct SourceText {
+    pub text: String,
+    pub chapters: Vec<Chapter>,
+    pub pages: Vec<PageMark>,
+    pub meta: BookMeta,
+}
+
+/// 书籍文件自带的书名与作者，纯文本文件没有这些信息。
+#[derive(Clone, Default)]
+pub struct BookMeta {
+    pub title: Option<String>,
+    pub author: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Chapter {
+    pub title: String,
+    pub offset: usize,
+}
+
+/// 分页格式（PDF）中一页的起始位置，`label` 为书上印刷的页码，`index` 为从 0 开始的物理页序。
+#[derive(Clone, Serialize)]
+pub struct PageMark {
+    pub index: usize,
+    pub label: String,
+    pub offset: usize,
+}
+
+/// 经过处理管线后用于展示的文档，`offsets` 记录展示位置与原文位置的对应关系。
+///
+/// `chapters` 与 `pages` 中的偏移已映射为展示偏移。
+#[derive(Clone, Default)]
+pub struct Document {
+    pub text: String,
+    pub offsets: OffsetMap,
+    pub chapters: Vec<Chapter>,
+    pub pages: Vec<PageMark>,
+    pub meta: BookMeta,
+}
+
+/// 处理管线的全部输入：全局阅读设置，以及当前书籍的替换规则与简繁转换目标。
+#[derive(Clone, Default)]
+pub struct PipelineOptions {
+    pub reading: ReadingConfig,
+    pub rules: Vec<ReplaceRule>,
+    pub conversion: ScriptConversion,
+}
+
+pub fn load_document<P: AsRef<Path>>(path: P, options: &PipelineOptions) -> Result<Document> {
+    let source = load_source(path)?;
+    Ok(prepare_document(source, options))
+}
+
+/// 按路径类型读取原文：目录按分章文件合并，压缩包读取其中的书籍文件，其余按扩展名解析。
+pub fn load_source<P: AsRef<Path>>(path: P) -> Result<SourceText> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        return folder::load_folder(path);
+    }
+    if let Some((archive, entry)) = archive::split_entry_path(path) {
+        return archive::load_entry(&archive, &entry);
+    }
+    if archive::is_archive(path) {
+        return archive::load_archive(path);
+    }
+    if !path.exists() {
+        bail!("文件不存在: {}", path.display());
+    }
+    let bytes = fs::read(path).with_context(|| format!("读取文件失败: {}", path.display()))?;
+    parse_book(path, &bytes)
+}
+
+/// 按扩展名选择解析器，压缩包内的文件同样经过这里；未知扩展名按纯文本解码。
+fn parse_book(name: &Path, bytes: &[u8]) -> Result<SourceText> {
+    match book_extension(name).as_deref() {
+        Some("fb2") => fb2::parse_fb2(bytes),
+        Some("mobi" | "azw" | "azw3" | "prc") => mobi::parse_mobi(bytes),
+        Some("umd") => umd::parse_umd(bytes),
+        Some("docx") => docx::parse_docx(bytes),
+        Some("epub") => epub::parse_epub(bytes),
+        Some("pdf") => pdf::parse_pdf(bytes),
+        Some("html" | "htm" | "xhtml") => readability::parse_html(bytes),
+        Some("md" | "markdown") => markdown::parse_markdown(bytes),
+        _ => Ok(SourceText {
+            text: decode_bytes(bytes)?,
+            ..SourceText::default()
+        }),
+    }
+}
+
+const BOOK_EXTENSIONS: [&str; 16] = [
+    "txt", "text", "fb2", "mobi", "azw", "azw3", "prc", "umd", "docx", "epub", "pdf", "html",
+    "htm", "xhtml", "md", "markdown",
+];
+
+fn book_extension(name: &Path) -> Option<String> {
+    name.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+}
+
+/// 是否为可直接解析的书籍文件（不含压缩包）。
+pub fn is_book_file(name: &Path) -> bool {
+    book_extension(name)
+        .map(|ext| BOOK_EXTENSIONS.contains(&ext.as_str()))
+        .unwrap_or(false)
+}
+
+impl Document {
+    /// 接收一个处理阶段的输出，未改动时保持原样。
+    fn apply_stage(&mut self, output: Option<(String, Vec<Segment>)>) {
+        if let Some((text, segments)) = output {
+            if !segments.is_empty() {
+                self.text = text;
+                self.offsets.push_layer(segments);
+            }
+        }
+    }
+}
+
+/// EPUB 书脊中各文件在原文中的起始偏移，用于与按文件定位的阅读器互换进度。
+pub fn epub_spine_offsets(path: &Path) -> Result<Vec<usize>> {
+    let bytes = fs::read(path).with_context(|| format!("读取文件失败: {}", path.display()))?;
+    epub::spine_offsets(&bytes)
+}
+
+pub fn list_archive_entries<P: AsRef<Path>>(path: P) -> Result<Vec<ArchiveEntry>> {
+    archive::list_entries(path.as_ref())
+}
+
+/// 书籍路径对应的磁盘文件：压缩包内的文件返回压缩包本身。
+pub fn physical_path(path: &Path) -> PathBuf {
+    archive::split_entry_path(path)
+        .map(|(archive, _)| archive)
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+pub fn source_exists(path: &Path) -> bool {
+    physical_path(path).exists()
+}
+
+/// 对解码后的原文依次执行各处理阶段。
+pub fn prepare_document(source: SourceText, options: &PipelineOptions) -> Document {
+    let reading = &options.reading;
+    let mut document = Document {
+        text: source.text,
+        meta: source.meta,
+        ..Document::default()
+    };
+
+    normalize_document(&mut document, reading);
+
+    for rule in &options.rules {
+        match replace::apply_rule(&document.text, rule) {
+            Ok((_, output)) => document.apply_stage(Some(output)),
+            Err(err) => eprintln!("跳过替换规则: {:#}", err),
+        }
+    }
+
+    let output = convert::convert(&document.text, options.conversion);
+    document.apply_stage(output);
+
+    if reading.reflow {
+        let output = reflow::reflow(&document.text);
+        document.apply_stage(output);
+    }
+
+    document.chapters = source
+        .chapters
+        .into_iter()
+        .map(|chapter| Chapter {
+            offset: document.offsets.to_display(chapter.offset),
+            ..chapter
+        })
+        .collect();
+    document.pages = source
+        .pages
+        .into_iter()
+        .map(|page| PageMark {
+            offset: document.offsets.to_display(page.offset),
+            ..page
+        })
+        .collect();
+    document
+}
+
+/// 按印刷页码查找页面的展示偏移；页码不存在时退回按物理页序（从 1 开始）查找。
+pub fn find_page(pages: &[PageMark], label: &str) -> Option<usize> {
+    let label = label.trim();
+    pages
+        .iter()
+        .find(|page| page.label == label)
+        .or_else(|| {
+            let number: usize = label.parse().ok()?;
+            pages.iter().find(|page| page.index + 1 == number)
+        })
+        .map(|page| page.offset)
+}
+
+fn normalize_document(document: &mut Document, reading: &ReadingConfig) {
+    let output = normalize::normalize_chars(&document.text, &reading.normalize);
+    document.apply_stage(output);
+    let output = normalize::normalize_lines(&document.text, &reading.normalize);
+    document.apply_stage(output);
+}
+
+/// 对文件试运行替换规则，统计每条规则在清理后的文本上的命中次数。
+pub fn dry_run_rules<P: AsRef<Path>>(
+    path: P,
+    reading: &ReadingConfig,
+    rules: &[ReplaceRule],
+) -> Result<Vec<RuleReport>> {
+    let mut document = Document {
+        text: load_source(path)?.text,
+        ..Document::default()
+    };
+    normalize_document(&mut document, reading);
+    Ok(replace::dry_run(&document.text, rules))
+}
+
+pub fn load_text<P: AsRef<Path>>(path: P) -> Result<String> {
+    let path = path.as_ref();
+    if !path.exists() {
+        bail!("文件不存在: {}", path.display());
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    decode_bytes(&buffer)
+}
+
+/// 把网页片段转成分段的纯文本，供在线书源的正文使用。
+pub fn html_to_text(html: &str) -> String {
+    html::html_to_source(html).text
+}
+
+/// 自动检测编码并解码为 UTF-8 文本。
+pub fn decode_bytes(buffer: &[u8]) -> Result<String> {
+    let encoding = detect_encoding(buffer);
+    let (cow, _, had_errors) = encoding.decode(buffer);
+    if had_errors {
+        bail!("文本转换失败，可能包含无效编码");
+    }
+    Ok(cow.into_owned())
+}
+
+fn detect_encoding(buffer: &[u8]) -> &'static Encoding {
+    let mut detector = EncodingDetector::new();
+    detector.feed(buffer, true);
+    detector.guess(None, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn load_text_reads_utf8() {
+        let tmp = std::env::temp_dir().join("moyu-reader-load-text.txt");
+        fs::write(&tmp, "123\n456").unwrap();
+        let text = load_text(&tmp).unwrap();
+        assert_eq!(text.trim(), "123\n456");
+    }
+}
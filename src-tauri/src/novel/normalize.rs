@@ -0,0 +1,139 @@
+use super::offset_map::{Rewriter, Segment};
+use crate::settings::NormalizeConfig;
+
+/// 统一后的段首缩进：两个全角空格。
+const INDENT: &str = "\u{3000}\u{3000}";
+/// 短于该字符数的段落不参与去重，避免误删“嗯。”“……”之类的正常重复。
+const MIN_DEDUPE_CHARS: usize = 8;
+
+/// 字符级清理：统一换行符、去除零宽字符。
+pub fn normalize_chars(text: &str, config: &NormalizeConfig) -> Option<(String, Vec<Segment>)> {
+    if !config.unify_line_endings && !config.strip_zero_width {
+        return None;
+    }
+
+    let mut rewriter = Rewriter::with_capacity(text.len());
+    let mut pending = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, ch)) = chars.next() {
+        let replacement = match ch {
+            '\r' if config.unify_line_endings => {
+                rewriter.keep(&text[pending..index]);
+                if matches!(chars.peek(), Some((_, '\n'))) {
+                    chars.next();
+                    rewriter.replace("\r\n", "\n");
+                    pending = index + 2;
+                    continue;
+                }
+                "\n"
+            }
+            _ if config.strip_zero_width && is_zero_width(ch) => {
+                rewriter.keep(&text[pending..index]);
+                ""
+            }
+            _ => continue,
+        };
+        rewriter.replace(&text[index..index + ch.len_utf8()], replacement);
+        pending = index + ch.len_utf8();
+    }
+    rewriter.keep(&text[pending..]);
+    Some(rewriter.finish())
+}
+
+/// 行级清理：统一段首缩进、合并连续空行、删除紧邻重复的段落。
+pub fn normalize_lines(text: &str, config: &NormalizeConfig) -> Option<(String, Vec<Segment>)> {
+    if !config.normalize_indent && !config.collapse_blank_lines && !config.dedupe_paragraphs {
+        return None;
+    }
+
+    let mut rewriter = Rewriter::with_capacity(text.len());
+    // 开头的空行一并去掉
+    let mut previous_blank = true;
+    let mut previous_paragraph: Option<&str> = None;
+    for line in text.split_inclusive('\n') {
+        let body = line.trim_end_matches(['\n', '\r']);
+        let content = body.trim();
+
+        if content.is_empty() {
+            if config.collapse_blank_lines && previous_blank {
+                rewriter.replace(line, "");
+            } else {
+                rewriter.keep(line);
+            }
+            previous_blank = true;
+            continue;
+        }
+
+        if config.dedupe_paragraphs
+            && previous_paragraph == Some(content)
+            && content.chars().count() >= MIN_DEDUPE_CHARS
+        {
+            rewriter.replace(line, "");
+            continue;
+        }
+        previous_paragraph = Some(content);
+        previous_blank = false;
+
+        let indent_len = body.len() - body.trim_start().len();
+        if config.normalize_indent && indent_len > 0 {
+            rewriter.replace(&body[..indent_len], INDENT);
+            rewriter.keep(&line[indent_len..]);
+        } else {
+            rewriter.keep(line);
+        }
+    }
+    Some(rewriter.finish())
+}
+
+fn is_zero_width(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}' | '\u{00AD}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::novel::offset_map::OffsetMap;
+
+    #[test]
+    fn cleans_up_scraped_text_and_keeps_offsets() {
+        let source = "\r\n第一章\u{200B}\r\n\r\n\r\n \u{3000}\u{3000}\u{3000}天色已晚，众人各自散去。\r\n\r\n\u{3000}\u{3000}天色已晚，众人各自散去。\r\n\u{3000}\u{3000}他独自留下。";
+        let config = NormalizeConfig {
+            dedupe_paragraphs: true,
+            ..NormalizeConfig::default()
+        };
+
+        let mut map = OffsetMap::default();
+        let (text, segments) = normalize_chars(source, &config).unwrap();
+        map.push_layer(segments);
+        let (text, segments) = normalize_lines(&text, &config).unwrap();
+        map.push_layer(segments);
+
+        assert_eq!(
+            text,
+            "第一章\n\n\u{3000}\u{3000}天色已晚，众人各自散去。\n\n\u{3000}\u{3000}他独自留下。"
+        );
+
+        let source_chars: Vec<char> = source.chars().collect();
+        let display_chars: Vec<char> = text.chars().collect();
+        let target = source_chars.iter().position(|ch| *ch == '他').unwrap();
+        let mapped = map.to_display(target);
+        assert_eq!(display_chars[mapped], '他');
+        assert_eq!(map.to_source(mapped), target);
+    }
+
+    #[test]
+    fn disabled_rules_leave_text_untouched() {
+        let config = NormalizeConfig {
+            unify_line_endings: false,
+            strip_zero_width: false,
+            normalize_indent: false,
+            collapse_blank_lines: false,
+            dedupe_paragraphs: false,
+        };
+        assert!(normalize_chars("a\r\nb", &config).is_none());
+        assert!(normalize_lines("a\n\n\nb", &config).is_none());
+    }
+}
@@ -0,0 +1,160 @@
+/// 文本处理各阶段之间的偏移映射。
+///
+/// 偏移一律按字符计。阅读进度、书签等都保存为“源偏移”（解码后原文中的位置），
+/// 展示时再映射到处理后的文本，因此开关任意处理规则都不会让进度失效。
+#[derive(Clone, Default)]
+pub struct OffsetMap {
+    layers: Vec<Vec<Segment>>,
+}
+
+/// 一段连续的映射：长度相同视为逐字对应，否则视为整体替换。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub src: usize,
+    pub dst: usize,
+    pub src_len: usize,
+    pub dst_len: usize,
+}
+
+impl OffsetMap {
+    /// 追加一层映射，层按处理顺序从源文本指向最终文本。
+    pub fn push_layer(&mut self, segments: Vec<Segment>) {
+        if !segments.is_empty() {
+            self.layers.push(segments);
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn to_display(&self, source: usize) -> usize {
+        self.layers
+            .iter()
+            .fold(source, |offset, layer| map_forward(layer, offset))
+    }
+
+    pub fn to_source(&self, display: usize) -> usize {
+        self.layers
+            .iter()
+            .rev()
+            .fold(display, |offset, layer| map_backward(layer, offset))
+    }
+}
+
+fn map_forward(layer: &[Segment], offset: usize) -> usize {
+    let index = layer.partition_point(|segment| segment.src <= offset);
+    match index.checked_sub(1).map(|index| layer[index]) {
+        Some(segment) => segment.dst + (offset - segment.src).min(segment.dst_len),
+        None => offset,
+    }
+}
+
+fn map_backward(layer: &[Segment], offset: usize) -> usize {
+    let index = layer.partition_point(|segment| segment.dst <= offset);
+    match index.checked_sub(1).map(|index| layer[index]) {
+        Some(segment) => segment.src + (offset - segment.dst).min(segment.src_len),
+        None => offset,
+    }
+}
+
+/// 边改写边记录映射的输出缓冲，各处理阶段共用。
+#[derive(Default)]
+pub struct Rewriter {
+    text: String,
+    segments: Vec<Segment>,
+    src_pos: usize,
+    dst_pos: usize,
+    changed: bool,
+}
+
+impl Rewriter {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            text: String::with_capacity(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// 原样保留一段文本。
+    pub fn keep(&mut self, text: &str) {
+        let len = text.chars().count();
+        self.text.push_str(text);
+        self.record(len, len, false);
+    }
+
+    /// 用 `replacement` 替换原文中的 `original`。
+    pub fn replace(&mut self, original: &str, replacement: &str) {
+        if original == replacement {
+            self.keep(original);
+            return;
+        }
+        let src_len = original.chars().count();
+        let dst_len = replacement.chars().count();
+        self.text.push_str(replacement);
+        self.record(src_len, dst_len, true);
+    }
+
+    pub fn finish(self) -> (String, Vec<Segment>) {
+        if self.changed {
+            (self.text, self.segments)
+        } else {
+            (self.text, Vec::new())
+        }
+    }
+
+    fn record(&mut self, src_len: usize, dst_len: usize, replaced: bool) {
+        if src_len == 0 && dst_len == 0 {
+            return;
+        }
+        self.changed |= replaced;
+        let is_copy = !replaced || src_len == dst_len;
+        match self.segments.last_mut() {
+            // 相邻的逐字段合并，保持映射表紧凑
+            Some(last) if is_copy && last.src_len == last.dst_len => {
+                last.src_len += src_len;
+                last.dst_len += dst_len;
+            }
+            _ => self.segments.push(Segment {
+                src: self.src_pos,
+                dst: self.dst_pos,
+                src_len,
+                dst_len,
+            }),
+        }
+        self.src_pos += src_len;
+        self.dst_pos += dst_len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_round_trip_through_layers() {
+        let mut first = Rewriter::default();
+        first.keep("第一章");
+        first.replace("\r\n", "\n");
+        first.keep("正文");
+        let (text, layer) = first.finish();
+        assert_eq!(text, "第一章\n正文");
+
+        let mut second = Rewriter::default();
+        second.keep("第一章\n");
+        second.replace("", "　　");
+        second.keep("正文");
+        let (text, second_layer) = second.finish();
+        assert_eq!(text, "第一章\n　　正文");
+
+        let mut map = OffsetMap::default();
+        map.push_layer(layer);
+        map.push_layer(second_layer);
+
+        // 源文本中“正”位于 5（第一章 + \r\n）
+        assert_eq!(map.to_display(5), 6);
+        assert_eq!(map.to_source(6), 5);
+        assert_eq!(map.to_display(1), 1);
+        assert_eq!(map.to_source(1), 1);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+
+use super::reflow::{display_width, needs_space, starts_paragraph};
+use super::{PageMark, SourceText};
+
+/// 平均每页有效字符少于该值时视为没有文字层（扫描版）。
+const MIN_CHARS_PER_PAGE: usize = 10;
+/// 每页顶部、底部各检查的行数，页眉页脚只会出现在这里。
+const EDGE_LINES: usize = 2;
+/// 宽度不低于“满行宽度”的该百分比即视为满行，下一行属于同一段。
+const FULL_LINE_PERCENT: usize = 85;
+
+/// 提取 PDF 文字层：去掉重复的页眉页脚与页码，跨页重建段落，并记录每页的起始位置。
+pub fn parse_pdf(bytes: &[u8]) -> Result<SourceText> {
+    // pdf-extract 遇到少见的字体编码时可能直接 panic，这里统一转成错误
+    let pages = panic::catch_unwind(AssertUnwindSafe(|| {
+        pdf_extract::extract_text_from_mem_by_pages(bytes)
+    }))
+    .map_err(|_| anyhow!("PDF 解析失败：文件结构或字体编码不受支持"))?
+    .map_err(|err| anyhow!("PDF 解析失败: {}", err))?;
+    assemble_pages(&pages)
+}
+
+fn assemble_pages(pages: &[String]) -> Result<SourceText> {
+    let visible: usize = pages
+        .iter()
+        .map(|page| page.chars().filter(|ch| !ch.is_whitespace()).count())
+        .sum();
+    if pages.is_empty() || visible < pages.len() * MIN_CHARS_PER_PAGE {
+        bail!("该 PDF 没有可提取的文字层（可能是扫描版），暂不支持");
+    }
+
+    let mut lines: Vec<Vec<&str>> = pages
+        .iter()
+        .map(|page| {
+            page.lines()
+                .map(str::trim_end)
+                .filter(|line| !line.trim().is_empty())
+                .collect()
+        })
+        .collect();
+    let numbers = strip_page_furniture(&mut lines);
+    let labels = page_labels(&numbers);
+
+    let full_width = full_line_width(&lines);
+    let flat: Vec<(usize, &str)> = lines
+        .iter()
+        .enumerate()
+        .flat_map(|(page, lines)| lines.iter().map(move |line| (page, *line)))
+        .collect();
+
+    let mut source = SourceText::default();
+    let mut len = 0;
+    for (index, &(page, line)) in flat.iter().enumerate() {
+        let previous_joined = index > 0 && !source.text.ends_with('\n');
+        let content = if previous_joined {
+            line.trim_start()
+        } else {
+            line
+        };
+        mark_pages(&mut source.pages, &labels, page + 1, len);
+        source.text.push_str(content);
+        len += content.chars().count();
+
+        let next = flat.get(index + 1).map(|(_, next)| *next);
+        let join = next.is_some_and(|next| {
+            display_width(line.trim()) * 100 >= full_width * FULL_LINE_PERCENT
+                && !starts_paragraph(next)
+        });
+        let separator = match next {
+            Some(next) if join => {
+                if needs_space(line.trim_end(), next) {
+                    " "
+                } else {
+                    ""
+                }
+            }
+            _ => "\n",
+        };
+        source.text.push_str(separator);
+        len += separator.chars().count();
+    }
+    mark_pages(&mut source.pages, &labels, pages.len(), len);
+    Ok(source)
+}
+
+/// 为第 `until` 页之前尚未记录的页补上起点。空白页、整页插图没有文字，起点即下一页
+/// 文字的位置，按印刷页码跳转时也能找到。
+fn mark_pages(marks: &mut Vec<PageMark>, labels: &[String], until: usize, offset: usize) {
+    let start = marks.len();
+    marks.extend(
+        labels[start..until]
+            .iter()
+            .enumerate()
+            .map(|(index, label)| PageMark {
+                index: start + index,
+                label: label.clone(),
+                offset,
+            }),
+    );
+}
+
+/// 删除各页顶部与底部重复出现的页眉页脚和页码行，返回每页识别到的印刷页码。
+fn strip_page_furniture(pages: &mut [Vec<&str>]) -> Vec<Option<u32>> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for lines in pages.iter() {
+        let mut keys: Vec<String> = edge_lines(lines).map(furniture_key).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            *counts.entry(key).or_default() += 1;
+        }
+    }
+    let repeated = |line: &str| {
+        pages.len() >= 3
+            && counts.get(&furniture_key(line)).copied().unwrap_or(0) * 2 >= pages.len()
+    };
+
+    let mut numbers = vec![None; pages.len()];
+    let repeated_lines: Vec<Vec<bool>> = pages
+        .iter()
+        .map(|lines| lines.iter().map(|line| repeated(line)).collect())
+        .collect();
+    for (index, lines) in pages.iter_mut().enumerate() {
+        let flags = &repeated_lines[index];
+        // 从页面边缘向内剥离，遇到第一行正文即停止，避免误删正文中恰好重复的句子
+        let mut furniture = |position: usize| match page_number(lines[position]) {
+            Some(number) => {
+                numbers[index] = Some(number);
+                true
+            }
+            None => flags[position],
+        };
+        let mut start = 0;
+        while start < lines.len().min(EDGE_LINES) && furniture(start) {
+            start += 1;
+        }
+        let mut end = lines.len();
+        while end > start && lines.len() - end < EDGE_LINES && furniture(end - 1) {
+            end -= 1;
+        }
+        lines.truncate(end);
+        lines.drain(..start);
+    }
+    numbers
+}
+
+fn edge_lines<'a>(lines: &'a [&'a str]) -> impl Iterator<Item = &'a str> {
+    let head = lines.iter().take(EDGE_LINES);
+    let tail = lines
+        .iter()
+        .skip(lines.len().saturating_sub(EDGE_LINES).max(EDGE_LINES));
+    head.chain(tail).copied()
+}
+
+/// 页眉页脚常带变化的数字（页码、章节号），比较时把数字统一掉。
+fn furniture_key(line: &str) -> String {
+    let mut key = String::new();
+    for ch in line.chars().filter(|ch| !ch.is_whitespace()) {
+        if ch.is_ascii_digit() {
+            if !key.ends_with('#') {
+                key.push('#');
+            }
+        } else {
+            key.push(ch);
+        }
+    }
+    key
+}
+
+fn page_number(line: &str) -> Option<u32> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(
+            r"(?i)^\s*(?:[-–—]\s*(\d+)\s*[-–—]|第\s*(\d+)\s*页(?:\s*/?\s*共\s*\d+\s*页)?|(?:page\s+)?(\d+)(?:\s*(?:/|of)\s*\d+)?)\s*$",
+        )
+        .expect("页码正则有效")
+    });
+    let captures = pattern.captures(line)?;
+    (1..=3)
+        .find_map(|group| captures.get(group))
+        .and_then(|number| number.as_str().parse().ok())
+}
+
+/// 为每页确定印刷页码：识别到的直接使用，其余按最近的已识别页推算，都没有时用物理页序。
+fn page_labels(numbers: &[Option<u32>]) -> Vec<String> {
+    let known: Vec<(usize, u32)> = numbers
+        .iter()
+        .enumerate()
+        .filter_map(|(index, number)| number.map(|number| (index, number)))
+        .collect();
+    (0..numbers.len())
+        .map(|index| {
+            let nearest = known
+                .iter()
+                .min_by_key(|(known_index, _)| known_index.abs_diff(index));
+            let label = nearest.and_then(|&(known_index, number)| {
+                let value = i64::from(number) + index as i64 - known_index as i64;
+                (value >= 1).then_some(value)
+            });
+            label.unwrap_or(index as i64 + 1).to_string()
+        })
+        .collect()
+}
+
+/// 取行宽的第 80 百分位作为满行宽度，避开标题、段尾等短行。
+fn full_line_width(pages: &[Vec<&str>]) -> usize {
+    let mut widths: Vec<usize> = pages
+        .iter()
+        .flatten()
+        .map(|line| display_width(line.trim()))
+        .collect();
+    if widths.is_empty() {
+        return 0;
+    }
+    widths.sort_unstable();
+    widths[(widths.len() * 80 / 100).min(widths.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_furniture_and_joins_across_pages() {
+        let pages: Vec<String> = ["一", "二", "三", "四"]
+            .iter()
+            .enumerate()
+            .map(|(page, day)| {
+                format!(
+                    "雪山飞狐 · 第{}章\n　　第{}天，天色渐暗，山路上的积雪被风卷起，打在脸上，他裹紧了衣服\n继续向前走去，脚下的积雪吱吱作响，四周一片寂静，只有风声呼啸\n而过，远处隐约传来几声狼嚎，令人毛骨悚然，他不由得加快了脚步\n- {} -",
+                    page + 1,
+                    day,
+                    page + 11
+                )
+            })
+            .collect();
+        let source = assemble_pages(&pages).unwrap();
+        assert!(!source.text.contains("雪山飞狐"));
+        assert!(!source.text.contains("- 11 -"));
+        // 页尾满行与下一页开头不是新段落时拼接为同一段；缩进开头另起一段
+        assert!(source.text.contains("他裹紧了衣服继续向前走去"));
+        assert_eq!(source.text.matches('\n').count(), 4);
+
+        let labels: Vec<&str> = source.pages.iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(labels, vec!["11", "12", "13", "14"]);
+        let third: String = source.text.chars().skip(source.pages[2].offset).collect();
+        assert!(third.starts_with("　　第三天"));
+    }
+
+    #[test]
+    fn marks_pages_without_text() {
+        let text = "　　天色渐暗，山路上的积雪被风卷起，打在脸上生疼，他裹紧了衣服。";
+        let pages = vec![
+            format!("{}\n1", text),
+            "2".to_string(),
+            "　　继续向前走去，脚下的积雪吱吱作响，四周一片寂静。\n3".to_string(),
+            String::new(),
+        ];
+        let source = assemble_pages(&pages).unwrap();
+        let marks: Vec<(usize, &str, usize)> = source
+            .pages
+            .iter()
+            .map(|page| (page.index, page.label.as_str(), page.offset))
+            .collect();
+        let second = text.chars().count() + 1;
+        let end = source.text.chars().count();
+        assert_eq!(
+            marks,
+            vec![
+                (0, "1", 0),
+                (1, "2", second),
+                (2, "3", second),
+                (3, "4", end)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_pdf_without_text_layer() {
+        let pages = vec![String::new(), " \n".to_string(), "3".to_string()];
+        let error = assemble_pages(&pages).err().unwrap();
+        assert!(error.to_string().contains("扫描版"));
+        assert_eq!(page_number("第 12 页 / 共 300 页"), Some(12));
+        assert_eq!(page_number("Page 7 of 90"), Some(7));
+        assert_eq!(page_number("第12章"), None);
+    }
+}
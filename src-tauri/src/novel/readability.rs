@@ -0,0 +1,401 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use encoding_rs::Encoding;
+use scraper::{ElementRef, Html, Node};
+
+use super::html::{elements_to_source, html_to_source};
+use super::{decode_bytes, BookMeta, SourceText};
+
+/// 内容不参与正文判断的元素。
+const RAW_TAGS: [&str; 5] = ["script", "style", "noscript", "template", "svg"];
+
+/// 整个子树都不可能是正文的元素。
+const UNLIKELY_TAGS: [&str; 9] = [
+    "head", "nav", "aside", "footer", "form", "iframe", "button", "select", "menu",
+];
+
+/// class 或 id 中出现这些词的元素视为导航、广告或评论区，整体丢弃。
+const NEGATIVE_HINTS: [&str; 24] = [
+    "ad",
+    "ads",
+    "advert",
+    "advertisement",
+    "banner",
+    "breadcrumb",
+    "breadcrumbs",
+    "comment",
+    "comments",
+    "disqus",
+    "footer",
+    "login",
+    "menu",
+    "nav",
+    "navbar",
+    "pagination",
+    "popup",
+    "promo",
+    "recommend",
+    "related",
+    "share",
+    "sidebar",
+    "social",
+    "sponsor",
+];
+
+/// class 或 id 中出现这些词的元素更可能是正文容器。
+const POSITIVE_HINTS: [&str; 10] = [
+    "article",
+    "body",
+    "booktext",
+    "chapter",
+    "chaptercontent",
+    "content",
+    "entry",
+    "main",
+    "post",
+    "story",
+];
+
+/// 这些元素中的文字按段落计分，得分记在其父元素上。
+const PARAGRAPH_TAGS: [&str; 6] = ["p", "pre", "blockquote", "li", "td", "dd"];
+
+/// 少于该字数的文字不参与计分，避免按钮、署名等碎片干扰。
+const MIN_PARAGRAPH_CHARS: usize = 25;
+const HINT_WEIGHT: f64 = 25.0;
+
+/// 解析网页文件：按 `<meta charset>` 解码，提取正文后转为纯文本。
+pub fn parse_html(bytes: &[u8]) -> Result<SourceText> {
+    let html = match declared_charset(bytes) {
+        Some(encoding) => encoding.decode(bytes).0.into_owned(),
+        None => decode_bytes(bytes)?,
+    };
+    let source = extract_article(&html);
+    if source.text.trim().is_empty() {
+        bail!("网页中没有可阅读的正文");
+    }
+    Ok(source)
+}
+
+fn declared_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(2048)]).to_ascii_lowercase();
+    let rest = &head[head.find("charset=")? + "charset=".len()..];
+    let label: String = rest
+        .trim_start_matches(['"', '\''])
+        .chars()
+        .take_while(|ch| ch.is_ascii_alphanumeric() || *ch == '-' || *ch == '_')
+        .collect();
+    Encoding::for_label(label.as_bytes())
+}
+
+/// 参照 Readability 的做法找出正文容器：按段落文字量与逗号数给祖先元素计分，
+/// 按链接密度降权，再并入得分接近的兄弟元素；找不到时整页转换。
+pub fn extract_article(html: &str) -> SourceText {
+    let document = Html::parse_document(html);
+    let page = Page::parse(&document);
+    let meta = BookMeta {
+        title: page.title(),
+        author: None,
+    };
+    let source = page
+        .best_candidate()
+        .map(|best| page.render_article(best))
+        .filter(|source| !source.text.trim().is_empty())
+        .unwrap_or_else(|| html_to_source(html));
+    SourceText { meta, ..source }
+}
+
+struct Element<'a> {
+    node: ElementRef<'a>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// 自身或祖先已被判定为非正文
+    excluded: bool,
+    positive: bool,
+    /// 直接包含的非链接文字字数与逗号数
+    own_text: usize,
+    own_commas: usize,
+    /// 子树内的文字总数与其中的链接文字数
+    text: usize,
+    links: usize,
+    score: f64,
+}
+
+struct Page<'a> {
+    /// 按文档顺序排列，下标 0 为 `<html>`
+    elements: Vec<Element<'a>>,
+    title: String,
+    first_heading: String,
+}
+
+impl<'a> Element<'a> {
+    fn new(node: ElementRef<'a>, parent: Option<usize>, excluded: bool, positive: bool) -> Self {
+        Element {
+            node,
+            parent,
+            children: Vec::new(),
+            excluded,
+            positive,
+            own_text: 0,
+            own_commas: 0,
+            text: 0,
+            links: 0,
+            score: 0.0,
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.node.value().name()
+    }
+
+    fn link_density(&self) -> f64 {
+        if self.text == 0 {
+            0.0
+        } else {
+            self.links as f64 / self.text as f64
+        }
+    }
+
+    /// 最终得分：段落分加上 class/id 提示，再按链接密度降权。
+    fn final_score(&self) -> f64 {
+        let mut score = self.score;
+        if self.positive && score > 0.0 {
+            score += HINT_WEIGHT;
+        }
+        score * (1.0 - self.link_density())
+    }
+}
+
+impl<'a> Page<'a> {
+    fn parse(document: &'a Html) -> Self {
+        let root = document.root_element();
+        let mut page = Page {
+            elements: vec![Element::new(root, None, false, false)],
+            title: String::new(),
+            first_heading: String::new(),
+        };
+        page.visit(root, 0, false);
+        page.score();
+        page
+    }
+
+    fn visit(&mut self, node: ElementRef<'a>, index: usize, in_link: bool) {
+        for child in node.children() {
+            let element = match child.value() {
+                Node::Text(text) => {
+                    self.add_text(index, text, in_link);
+                    continue;
+                }
+                Node::Element(_) => match ElementRef::wrap(child) {
+                    Some(element) => element,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            let name = element.value().name();
+            if RAW_TAGS.contains(&name) {
+                continue;
+            }
+            if name == "title" {
+                self.title.extend(element.text());
+                continue;
+            }
+            if name == "h1" && self.first_heading.is_empty() {
+                self.first_heading = element.text().collect();
+            }
+
+            let hints = hint_words(element);
+            let positive = hints
+                .iter()
+                .any(|word| POSITIVE_HINTS.contains(&word.as_str()));
+            let unlikely = UNLIKELY_TAGS.contains(&name)
+                || is_hidden(element)
+                || (hints
+                    .iter()
+                    .any(|word| NEGATIVE_HINTS.contains(&word.as_str()))
+                    && !positive);
+            let excluded = unlikely || self.elements[index].excluded;
+            let child_index = self.elements.len();
+            self.elements
+                .push(Element::new(element, Some(index), excluded, positive));
+            self.elements[index].children.push(child_index);
+            self.visit(element, child_index, in_link || name == "a");
+        }
+    }
+
+    fn add_text(&mut self, index: usize, text: &str, in_link: bool) {
+        let element = &mut self.elements[index];
+        if element.excluded {
+            return;
+        }
+        let count = text.chars().filter(|ch| !ch.is_whitespace()).count();
+        if in_link {
+            element.links += count;
+        } else {
+            element.own_text += count;
+            element.own_commas += text
+                .chars()
+                .filter(|ch| matches!(ch, ',' | '，' | '、'))
+                .count();
+        }
+        element.text += count;
+    }
+
+    fn score(&mut self) {
+        for index in 1..self.elements.len() {
+            let element = &self.elements[index];
+            if element.excluded || element.own_text < MIN_PARAGRAPH_CHARS {
+                continue;
+            }
+            let points = 1.0 + element.own_commas as f64 + (element.own_text / 100).min(3) as f64;
+            let holder = if PARAGRAPH_TAGS.contains(&element.name()) {
+                element.parent
+            } else {
+                Some(index)
+            };
+            if let Some(holder) = holder {
+                self.elements[holder].score += points;
+                if let Some(grandparent) = self.elements[holder].parent {
+                    self.elements[grandparent].score += points / 2.0;
+                }
+            }
+        }
+        // 子元素总在父元素之后，倒序累加即可汇总整棵子树
+        for index in (1..self.elements.len()).rev() {
+            let (text, links) = (self.elements[index].text, self.elements[index].links);
+            if let Some(parent) = self.elements[index].parent {
+                self.elements[parent].text += text;
+                self.elements[parent].links += links;
+            }
+        }
+    }
+
+    fn best_candidate(&self) -> Option<usize> {
+        (1..self.elements.len())
+            .filter(|&index| !self.elements[index].excluded)
+            .map(|index| (index, self.elements[index].final_score()))
+            .filter(|(_, score)| *score > 0.0)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+
+    /// 转换正文容器及得分相近的兄弟元素，跳过其中被判定为非正文的子树。
+    fn render_article(&self, best: usize) -> SourceText {
+        let threshold = (self.elements[best].final_score() * 0.2).max(10.0);
+        let siblings = match self.elements[best].parent {
+            Some(parent) if parent != 0 => self.elements[parent].children.clone(),
+            _ => vec![best],
+        };
+        let included = siblings.into_iter().filter(|&sibling| {
+            let element = &self.elements[sibling];
+            sibling == best
+                || (!element.excluded
+                    && (element.final_score() >= threshold
+                        || (element.name() == "p"
+                            && element.text > 80
+                            && element.link_density() < 0.25)))
+        });
+        let excluded: HashSet<_> = self
+            .elements
+            .iter()
+            .filter(|element| element.excluded)
+            .map(|element| element.node.id())
+            .collect();
+        elements_to_source(included.map(|index| self.elements[index].node), |element| {
+            excluded.contains(&element.id())
+        })
+    }
+
+    /// 网页标题通常带站点名后缀，只取第一段；没有 `<title>` 时用第一个 `<h1>`。
+    fn title(&self) -> Option<String> {
+        let title = self
+            .title
+            .split(['|', '_'])
+            .next()
+            .and_then(|title| title.split(" - ").next())
+            .unwrap_or("")
+            .trim();
+        let title = if title.is_empty() {
+            self.first_heading.trim()
+        } else {
+            title
+        };
+        (!title.is_empty()).then(|| title.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+fn hint_words(element: ElementRef) -> Vec<String> {
+    let element = element.value();
+    [element.attr("class"), element.attr("id")]
+        .into_iter()
+        .flatten()
+        .flat_map(|value| value.split(|ch: char| !ch.is_ascii_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .collect()
+}
+
+fn is_hidden(element: ElementRef) -> bool {
+    let element = element.value();
+    element.attr("aria-hidden") == Some("true")
+        || element
+            .attr("style")
+            .map(|style| style.replace(' ', "").contains("display:none"))
+            .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_article_and_drops_navigation_ads_and_comments() {
+        let html = r##"<!DOCTYPE html><html><head><meta charset="utf-8"><title>雪夜行 | 某某文学网</title>
+<script>var ad = "<p>不是正文</p>";</script></head><body>
+<nav class="site-nav"><a href="/">首页</a><a href="/list">书库</a><a href="/rank">排行榜</a></nav>
+<div class="layout">
+  <div class="sidebar-ad"><p>限时特惠，点击领取会员礼包，每天都有新惊喜，千万不要错过这个机会哦！</p></div>
+  <article class="post">
+    <h1>第一章 雪夜</h1>
+    <p>那一夜的雪下得很大，他独自走在回家的路上，四周一片寂静，只听得见脚下积雪的声响。</p>
+    <p>远处的灯火忽明忽暗，像是有人在等他，又像是什么都没有，他不由得加快了脚步，心里却越发不安。</p>
+    <div class="share-bar"><a href="#">分享到微博</a></div>
+    <h2>第二章 归人</h2>
+    <p>第二天清晨，村口的老槐树下站着一个陌生人，身上落满了雪，看样子已经等了很久很久。
+  </article>
+  <section id="comments"><p>写得真好，期待更新，作者大大加油，一定要坚持写下去啊，我会一直追的！</p></section>
+</div>
+<footer>版权所有 © 某某文学网</footer></body></html>"##;
+        let source = extract_article(html);
+        assert_eq!(source.meta.title.as_deref(), Some("雪夜行"));
+        for unwanted in [
+            "首页",
+            "限时特惠",
+            "分享到微博",
+            "期待更新",
+            "版权所有",
+            "不是正文",
+        ] {
+            assert!(!source.text.contains(unwanted), "{}", unwanted);
+        }
+        assert!(source.text.starts_with("第一章 雪夜\n那一夜的雪"));
+        assert!(source.text.contains("站着一个陌生人"));
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一章 雪夜", "第二章 归人"]);
+    }
+
+    #[test]
+    fn prefers_line_broken_text_container_over_link_lists() {
+        let html = r#"<html><head><meta http-equiv="Content-Type" content="text/html; charset=gbk"></head><body>
+<div class="links"><a href="1">上一章</a> <a href="2">目录</a> <a href="3">下一章</a></div>
+<div id="content">　　天色渐暗，山路上的积雪被风卷起，打在脸上生疼，他裹紧了衣服。<br/><br/>　　继续向前走去，脚下的积雪吱吱作响，四周一片寂静，只有风声呼啸而过。<br/></div>
+<ul class="list"><li><a href="4">热门小说一：一个很长很长的书名在这里出现</a></li><li><a href="5">热门小说二：另一个很长很长的书名也在这里</a></li></ul>
+</body></html>"#;
+        let (bytes, _, _) = encoding_rs::GBK.encode(html);
+        let source = parse_html(&bytes).unwrap();
+        assert!(source.text.starts_with("天色渐暗"));
+        assert!(source.text.contains("风声呼啸而过。"));
+        assert!(!source.text.contains("下一章"));
+        assert!(!source.text.contains("热门小说"));
+    }
+}
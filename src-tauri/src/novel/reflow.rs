@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use super::offset_map::{Rewriter, Segment};
+use crate::layout::is_wide;
+
+/// 固定宽度换行最少要达到的宽度（半角单位），低于此值的短行视为正常分行。
+const MIN_WRAP_WIDTH: usize = 40;
+/// 判定为硬换行所需的“满行”占比（百分比）。
+const MIN_FULL_LINE_RATIO: usize = 40;
+const MIN_SAMPLE_LINES: usize = 20;
+/// 行宽与换行宽度相差不超过该值即视为满行。
+const WIDTH_TOLERANCE: usize = 2;
+
+/// 检测文本是否按固定宽度硬换行，返回换行宽度（半角单位，全角字符计 2）。
+pub fn detect_wrap_width(text: &str) -> Option<usize> {
+    let widths: Vec<usize> = text
+        .split('\n')
+        .map(|line| display_width(line.trim_end()))
+        .filter(|width| *width > 0)
+        .collect();
+    if widths.len() < MIN_SAMPLE_LINES {
+        return None;
+    }
+
+    let mut histogram: HashMap<usize, usize> = HashMap::new();
+    for width in &widths {
+        *histogram.entry(*width).or_default() += 1;
+    }
+    // 取出现次数最多的宽度；并列时取较宽者，更接近真实的换行列
+    let (wrap_width, _) = histogram
+        .iter()
+        .filter(|(width, _)| **width >= MIN_WRAP_WIDTH)
+        .max_by_key(|(width, count)| (**count, **width))?;
+
+    let full_lines = widths
+        .iter()
+        .filter(|width| is_full(**width, *wrap_width))
+        .count();
+    let overflowing = widths
+        .iter()
+        .filter(|width| **width > wrap_width + WIDTH_TOLERANCE)
+        .count();
+    // 大量超长行说明并非固定宽度换行
+    if full_lines * 100 < widths.len() * MIN_FULL_LINE_RATIO || overflowing * 20 > widths.len() {
+        return None;
+    }
+    Some(*wrap_width)
+}
+
+/// 将硬换行的行重新拼接成段落；未检测到硬换行时返回 `None`。
+///
+/// 满行后接的行会并入同一段，但缩进开头、对话开头和空行始终另起一段。
+pub fn reflow(text: &str) -> Option<(String, Vec<Segment>)> {
+    let wrap_width = detect_wrap_width(text)?;
+
+    let lines: Vec<&str> = text.split('\n').collect();
+    let mut rewriter = Rewriter::with_capacity(text.len());
+    for (index, line) in lines.iter().enumerate() {
+        let Some(next) = lines.get(index + 1) else {
+            rewriter.keep(line);
+            break;
+        };
+
+        let content = line.trim_end_matches('\r');
+        let join =
+            is_full(display_width(content.trim_end()), wrap_width) && !starts_paragraph(next);
+        if !join {
+            rewriter.keep(line);
+            rewriter.keep("\n");
+            continue;
+        }
+
+        let body = content.trim_end();
+        let separator = if needs_space(body, next) { " " } else { "" };
+        rewriter.keep(body);
+        rewriter.replace(&format!("{}\n", &line[body.len()..]), separator);
+    }
+    let (reflowed, segments) = rewriter.finish();
+    Some((reflowed, segments))
+}
+
+fn is_full(width: usize, wrap_width: usize) -> bool {
+    width + WIDTH_TOLERANCE >= wrap_width
+}
+
+pub(super) fn starts_paragraph(line: &str) -> bool {
+    let line = line.trim_end_matches('\r');
+    let Some(first) = line.chars().next() else {
+        return true;
+    };
+    line.trim().is_empty()
+        || line.starts_with("  ")
+        || matches!(first, '\u{3000}' | '\t')
+        || "“「『\"'‘—".contains(first)
+}
+
+/// 两侧都是英文时补一个空格，避免拼接后单词粘连。
+pub(super) fn needs_space(previous: &str, next: &str) -> bool {
+    let last = previous.chars().last();
+    let first = next.trim_start().chars().next();
+    matches!(
+        (last, first),
+        (Some(a), Some(b)) if (a.is_ascii_alphanumeric() || ",.;:!?".contains(a)) && b.is_ascii_alphanumeric()
+    )
+}
+
+pub(super) fn display_width(line: &str) -> usize {
+    line.chars().map(|ch| if is_wide(ch) { 2 } else { 1 }).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::novel::offset_map::OffsetMap;
+
+    const SENTENCE: &str =
+        "他抬头望向远方的雪山，风从山谷里呼啸而来，卷起漫天的雪花，落在他的肩头和眉梢。";
+
+    fn wrapped_sample() -> String {
+        let paragraph = SENTENCE.repeat(3);
+        let chars: Vec<char> = paragraph.chars().collect();
+        let mut lines: Vec<String> = Vec::new();
+        for _ in 0..8 {
+            lines.push("　　".to_string() + &chars[..28].iter().collect::<String>());
+            for chunk in chars[28..].chunks(30) {
+                lines.push(chunk.iter().collect());
+            }
+            lines.push("“走吧。”他说。".to_string());
+        }
+        lines.join("\r\n")
+    }
+
+    #[test]
+    fn detects_and_rejoins_hard_wrapped_paragraphs() {
+        let text = wrapped_sample();
+        assert_eq!(detect_wrap_width(&text), Some(60));
+
+        let (reflowed, segments) = reflow(&text).expect("should reflow");
+        let paragraphs: Vec<&str> = reflowed.split("\r\n").collect();
+        assert_eq!(paragraphs.len(), 16);
+        assert_eq!(paragraphs[0], format!("　　{}", SENTENCE.repeat(3)));
+        assert_eq!(paragraphs[1], "“走吧。”他说。");
+
+        let mut map = OffsetMap::default();
+        map.push_layer(segments);
+        let source: Vec<char> = text.chars().collect();
+        let display: Vec<char> = reflowed.chars().collect();
+        let target = text
+            .find("落在")
+            .map(|byte| text[..byte].chars().count())
+            .unwrap();
+        let mapped = map.to_display(target);
+        assert_eq!(display[mapped], source[target]);
+        assert_eq!(map.to_source(mapped), target);
+    }
+
+    #[test]
+    fn leaves_short_lines_alone() {
+        let text = (1..=200)
+            .map(|n| format!("{:03}", n))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(reflow(&text).is_none());
+    }
+}
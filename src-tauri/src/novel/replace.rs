@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Serialize;
+
+use super::offset_map::{Rewriter, Segment};
+use crate::rules::ReplaceRule;
+
+/// 单条规则的试运行结果。
+#[derive(Clone, Serialize)]
+pub struct RuleReport {
+    pub rule_id: String,
+    pub name: String,
+    pub hits: usize,
+    pub error: Option<String>,
+}
+
+pub fn compile(rule: &ReplaceRule) -> Result<Regex> {
+    let pattern = if rule.is_regex {
+        rule.pattern.clone()
+    } else {
+        regex::escape(&rule.pattern)
+    };
+    Regex::new(&pattern).with_context(|| format!("规则 `{}` 的正则表达式无效", rule.name))
+}
+
+/// 执行一条规则，返回命中次数与改写结果。
+pub fn apply_rule(text: &str, rule: &ReplaceRule) -> Result<(usize, (String, Vec<Segment>))> {
+    let regex = compile(rule)?;
+    let mut rewriter = Rewriter::with_capacity(text.len());
+    let mut hits = 0;
+    let mut pending = 0;
+    for captures in regex.captures_iter(text) {
+        let matched = captures.get(0).expect("捕获组 0 总是存在");
+        if matched.as_str().is_empty() {
+            continue;
+        }
+        hits += 1;
+        let mut replacement = String::new();
+        if rule.is_regex {
+            captures.expand(&rule.replacement, &mut replacement);
+        } else {
+            replacement.push_str(&rule.replacement);
+        }
+        rewriter.keep(&text[pending..matched.start()]);
+        rewriter.replace(matched.as_str(), &replacement);
+        pending = matched.end();
+    }
+    rewriter.keep(&text[pending..]);
+    Ok((hits, rewriter.finish()))
+}
+
+/// 按顺序模拟执行全部规则，统计每条规则的命中次数而不保留结果。
+pub fn dry_run(text: &str, rules: &[ReplaceRule]) -> Vec<RuleReport> {
+    let mut current = text.to_string();
+    rules
+        .iter()
+        .map(|rule| {
+            let (hits, error) = match apply_rule(&current, rule) {
+                Ok((hits, (text, _))) => {
+                    current = text;
+                    (hits, None)
+                }
+                Err(err) => (0, Some(format!("{:#}", err))),
+            };
+            RuleReport {
+                rule_id: rule.id.clone(),
+                name: rule.name.clone(),
+                hits,
+                error,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::novel::offset_map::OffsetMap;
+    use crate::rules::{builtin_presets, RuleSet};
+
+    fn literal(pattern: &str, replacement: &str) -> ReplaceRule {
+        ReplaceRule {
+            id: pattern.to_string(),
+            name: pattern.to_string(),
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            is_regex: false,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn presets_strip_watermarks_and_keep_offsets() {
+        let text = "他推门而入。本章未完，请点击下一页继续阅读。\n笔趣阁 www.biquge.com 最快更新\n屋里一片漆黑。";
+        let rules = RuleSet {
+            enabled_presets: builtin_presets().into_iter().map(|rule| rule.id).collect(),
+            ..RuleSet::default()
+        }
+        .rules_for("any");
+
+        let mut current = text.to_string();
+        let mut map = OffsetMap::default();
+        for rule in &rules {
+            let (_, (next, segments)) = apply_rule(&current, rule).unwrap();
+            current = next;
+            map.push_layer(segments);
+        }
+        assert_eq!(current, "他推门而入。\n\n屋里一片漆黑。");
+
+        let target = text.chars().position(|ch| ch == '屋').unwrap();
+        assert_eq!(map.to_display(target), 8);
+        assert_eq!(map.to_source(8), target);
+    }
+
+    #[test]
+    fn dry_run_counts_hits_in_order() {
+        let rules = vec![
+            literal("**", "杀"),
+            literal("杀人", "**"),
+            ReplaceRule {
+                pattern: "(".to_string(),
+                is_regex: true,
+                ..literal("broken", "")
+            },
+        ];
+        let reports = dry_run("他**人如麻，**了三人。", &rules);
+        assert_eq!(reports[0].hits, 2);
+        assert_eq!(reports[1].hits, 1);
+        assert_eq!(reports[2].hits, 0);
+        assert!(reports[2].error.is_some());
+    }
+}
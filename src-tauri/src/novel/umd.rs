@@ -0,0 +1,262 @@
+use std::io::Read;
+
+use anyhow::{bail, Context, Result};
+use flate2::read::ZlibDecoder;
+
+use super::{BookMeta, Chapter, SourceText};
+
+const MAGIC: [u8; 4] = [0x89, 0x9B, 0x9A, 0xDE];
+
+const CHUNK_HEADER: u16 = 0x01;
+const CHUNK_TITLE: u16 = 0x02;
+const CHUNK_AUTHOR: u16 = 0x03;
+const CHUNK_CHAPTER_OFFSETS: u16 = 0x83;
+const CHUNK_CHAPTER_TITLES: u16 = 0x84;
+/// 这些功能块之后紧跟一个附属数据块；其余数据块都是正文
+const CHUNKS_WITH_DATA: [u16; 5] = [0x81, 0x82, 0x83, 0x84, 0x87];
+
+/// 解析文本版 UMD：`#` 开头的功能块描述元数据与章节表，`$` 开头的数据块为 zlib 压缩的
+/// UTF-16LE 正文，章节偏移按正文字节计。
+pub fn parse_umd(bytes: &[u8]) -> Result<SourceText> {
+    if bytes.get(0..4) != Some(MAGIC.as_slice()) {
+        bail!("不是有效的 UMD 文件");
+    }
+
+    let mut meta = BookMeta::default();
+    let mut byte_offsets: Vec<usize> = Vec::new();
+    let mut titles: Vec<String> = Vec::new();
+    let mut content: Vec<u8> = Vec::new();
+    let mut pending: Option<u16> = None;
+    let mut position = 4;
+
+    while position < bytes.len() {
+        match bytes[position] {
+            b'#' => {
+                let header = bytes
+                    .get(position..position + 5)
+                    .context("UMD 功能块不完整")?;
+                let id = u16::from_le_bytes([header[1], header[2]]);
+                let len = usize::from(header[4]);
+                let data = bytes
+                    .get(position + 5..position + len.max(5))
+                    .context("UMD 功能块不完整")?;
+                match id {
+                    CHUNK_HEADER if data.first() == Some(&2) => {
+                        bail!("不支持图片版 UMD 文件")
+                    }
+                    CHUNK_TITLE => meta.title = Some(decode_utf16(data)),
+                    CHUNK_AUTHOR => meta.author = Some(decode_utf16(data)),
+                    _ => {}
+                }
+                pending = CHUNKS_WITH_DATA.contains(&id).then_some(id);
+                position += len.max(5);
+            }
+            b'$' => {
+                let header = bytes
+                    .get(position..position + 9)
+                    .context("UMD 数据块不完整")?;
+                let len = u32::from_le_bytes([header[5], header[6], header[7], header[8]]) as usize;
+                let data = bytes
+                    .get(position + 9..position + len.max(9))
+                    .context("UMD 数据块不完整")?;
+                match pending.take() {
+                    Some(CHUNK_CHAPTER_OFFSETS) => {
+                        byte_offsets = data
+                            .chunks_exact(4)
+                            .map(|raw| {
+                                u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as usize
+                            })
+                            .collect();
+                    }
+                    Some(CHUNK_CHAPTER_TITLES) => titles = parse_titles(data),
+                    Some(_) => {}
+                    None => {
+                        ZlibDecoder::new(data)
+                            .read_to_end(&mut content)
+                            .context("UMD 正文解压失败")?;
+                    }
+                }
+                position += len.max(9);
+            }
+            other => bail!(
+                "UMD 文件结构错误: 位置 {} 处的标记 0x{:02x}",
+                position,
+                other
+            ),
+        }
+    }
+
+    if content.is_empty() {
+        bail!("UMD 文件中没有正文");
+    }
+    let units: Vec<u16> = content
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    Ok(SourceText {
+        meta,
+        ..build_chapters(&units, &byte_offsets, &titles)
+    })
+}
+
+/// 按章节表切分正文；正文开头没有章节名时补上，与分章目录的处理一致。
+fn build_chapters(units: &[u16], byte_offsets: &[usize], titles: &[String]) -> SourceText {
+    let mut source = SourceText::default();
+    let starts: Vec<usize> = byte_offsets
+        .iter()
+        .map(|offset| (offset / 2).min(units.len()))
+        .collect();
+    if starts.is_empty() {
+        source.text = decode_text(units);
+        return source;
+    }
+
+    let mut offset = 0;
+    if starts[0] > 0 {
+        let preface = decode_text(&units[..starts[0]]);
+        offset += preface.chars().count();
+        source.text.push_str(&preface);
+    }
+    for (index, &start) in starts.iter().enumerate() {
+        let end = starts
+            .get(index + 1)
+            .copied()
+            .unwrap_or(units.len())
+            .max(start);
+        let body = decode_text(&units[start..end]);
+        let title = titles
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| format!("第{}章", index + 1));
+
+        let mut section = String::new();
+        if !source.text.is_empty() && !source.text.ends_with('\n') {
+            section.push('\n');
+        }
+        let chapter_offset = offset + section.chars().count();
+        let first_line = body.lines().find(|line| !line.trim().is_empty());
+        if first_line.map(str::trim) != Some(title.trim()) {
+            section.push_str(&title);
+            section.push('\n');
+        }
+        section.push_str(&body);
+
+        source.chapters.push(Chapter {
+            title,
+            offset: chapter_offset,
+        });
+        offset += section.chars().count();
+        source.text.push_str(&section);
+    }
+    source
+}
+
+fn parse_titles(data: &[u8]) -> Vec<String> {
+    let mut titles = Vec::new();
+    let mut position = 0;
+    while let Some(&len) = data.get(position) {
+        let Some(raw) = data.get(position + 1..position + 1 + usize::from(len)) else {
+            break;
+        };
+        titles.push(decode_utf16(raw).trim().to_string());
+        position += 1 + usize::from(len);
+    }
+    titles
+}
+
+fn decode_utf16(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// UMD 以 U+2029（段落分隔符）换行。
+fn decode_text(units: &[u16]) -> String {
+    String::from_utf16_lossy(units)
+        .replace('\u{2029}', "\n")
+        .replace("\r\n", "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn chunk(id: u16, data: &[u8]) -> Vec<u8> {
+        let mut chunk = vec![b'#'];
+        chunk.extend_from_slice(&id.to_le_bytes());
+        chunk.push(0);
+        chunk.push((data.len() + 5) as u8);
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    fn block(data: &[u8]) -> Vec<u8> {
+        let mut block = vec![b'$'];
+        block.extend_from_slice(&0x1234u32.to_le_bytes());
+        block.extend_from_slice(&((data.len() + 9) as u32).to_le_bytes());
+        block.extend_from_slice(data);
+        block
+    }
+
+    #[test]
+    fn reads_chapters_from_offsets_and_titles() {
+        let first = "第一章 开端\u{2029}少年离开了家乡。\u{2029}";
+        let second = "路上风雪交加。\u{2029}";
+        let content = utf16(&format!("{}{}", first, second));
+
+        let mut offsets = Vec::new();
+        offsets.extend_from_slice(&0u32.to_le_bytes());
+        offsets.extend_from_slice(&(utf16(first).len() as u32).to_le_bytes());
+        let mut titles = Vec::new();
+        for title in ["第一章 开端", "第二章 风雪"] {
+            let raw = utf16(title);
+            titles.push(raw.len() as u8);
+            titles.extend_from_slice(&raw);
+        }
+
+        let mut file = MAGIC.to_vec();
+        file.extend(chunk(CHUNK_HEADER, &[1, 0, 0]));
+        file.extend(chunk(CHUNK_TITLE, &utf16("雪中行")));
+        file.extend(chunk(CHUNK_AUTHOR, &utf16("佚名")));
+        file.extend(chunk(CHUNK_CHAPTER_OFFSETS, &[0; 4]));
+        file.extend(block(&offsets));
+        file.extend(chunk(CHUNK_CHAPTER_TITLES, &[0; 4]));
+        file.extend(block(&titles));
+        // 正文分成三个压缩块，块边界不与段落对齐
+        let parts: Vec<&[u8]> = content.chunks(20).collect();
+        assert_eq!(parts.len(), 3);
+        for part in parts {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(part).unwrap();
+            file.extend(block(&encoder.finish().unwrap()));
+        }
+        file.extend(chunk(0x81, &[0; 4]));
+        file.extend(block(&[0; 8]));
+        file.extend(chunk(0x0C, &[0; 4]));
+
+        let source = parse_umd(&file).unwrap();
+        assert_eq!(source.meta.title.as_deref(), Some("雪中行"));
+        assert_eq!(source.meta.author.as_deref(), Some("佚名"));
+        assert_eq!(
+            source.text,
+            "第一章 开端\n少年离开了家乡。\n第二章 风雪\n路上风雪交加。\n"
+        );
+        let titles: Vec<&str> = source.chapters.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["第一章 开端", "第二章 风雪"]);
+        let second: String = source
+            .text
+            .chars()
+            .skip(source.chapters[1].offset)
+            .collect();
+        assert!(second.starts_with("第二章 风雪\n路上"));
+    }
+}
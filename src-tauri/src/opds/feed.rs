@@ -0,0 +1,343 @@
+use anyhow::{Context, Result};
+use roxmltree::{Document, Node};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use super::percent_encode;
+use crate::novel;
+
+const ACQUISITION_REL: &str = "http://opds-spec.org/acquisition";
+
+/// 一页目录：OPDS 1.2（Atom）与 OPDS 2.0（JSON）解析为同一结构。
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Feed {
+    pub url: String,
+    pub title: String,
+    pub entries: Vec<Entry>,
+    pub next: Option<String>,
+    pub previous: Option<String>,
+    /// 搜索链接：OpenSearch 描述文件或带 `{searchTerms}`/`{?query}` 的模板
+    pub search: Option<String>,
+}
+
+/// 目录中的一项：导航项（指向下一级目录）或书籍（带下载链接）。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Entry {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub summary: String,
+    pub navigation: Option<String>,
+    pub acquisitions: Vec<Acquisition>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Acquisition {
+    pub href: String,
+    pub mime: String,
+}
+
+/// 按内容类型或内容本身判断格式并解析，链接均补全为绝对地址。
+pub fn parse_feed(url: &str, content_type: &str, body: &str) -> Result<Feed> {
+    let base = Url::parse(url).with_context(|| format!("无效的目录地址: {}", url))?;
+    let body = body.trim_start_matches('\u{feff}').trim_start();
+    let mut feed = if content_type.contains("json") || body.starts_with('{') {
+        let value: Value = serde_json::from_str(body).context("OPDS 目录不是有效的 JSON")?;
+        parse_json(&value, &base)
+    } else {
+        let document = Document::parse(body).context("OPDS 目录不是有效的 XML")?;
+        parse_atom(document.root_element(), &base)
+    };
+    feed.url = url.to_string();
+    Ok(feed)
+}
+
+fn parse_atom(root: Node, base: &Url) -> Feed {
+    let mut feed = Feed {
+        title: child_text(root, "title"),
+        ..Feed::default()
+    };
+    for link in children(root, "link") {
+        let Some(href) = link.attribute("href").and_then(|href| resolve(base, href)) else {
+            continue;
+        };
+        match link.attribute("rel").unwrap_or("") {
+            "next" => feed.next = Some(href),
+            "previous" | "prev" => feed.previous = Some(href),
+            "search" if feed.search.is_none() || is_atom(link.attribute("type")) => {
+                feed.search = Some(href)
+            }
+            _ => {}
+        }
+    }
+
+    for node in children(root, "entry") {
+        let mut entry = Entry {
+            title: child_text(node, "title"),
+            authors: children(node, "author")
+                .map(|author| child_text(author, "name"))
+                .filter(|name| !name.is_empty())
+                .collect(),
+            summary: plain_text(&{
+                let summary = child_text(node, "summary");
+                if summary.is_empty() {
+                    child_text(node, "content")
+                } else {
+                    summary
+                }
+            }),
+            ..Entry::default()
+        };
+        for link in children(node, "link") {
+            let Some(href) = link.attribute("href").and_then(|href| resolve(base, href)) else {
+                continue;
+            };
+            let rel = link.attribute("rel").unwrap_or("");
+            let mime = link.attribute("type").unwrap_or("");
+            if rel.starts_with(ACQUISITION_REL) {
+                entry.acquisitions.push(Acquisition {
+                    href,
+                    mime: mime.to_string(),
+                });
+            } else if entry.navigation.is_none()
+                && is_atom(Some(mime))
+                && !mime.contains("type=entry")
+                && rel != "alternate"
+            {
+                entry.navigation = Some(href);
+            }
+        }
+        feed.entries.push(entry);
+    }
+    feed
+}
+
+fn parse_json(root: &Value, base: &Url) -> Feed {
+    let mut feed = Feed {
+        title: json_str(&root["metadata"]["title"]),
+        ..Feed::default()
+    };
+    for link in json_array(&root["links"]) {
+        let Some(href) = link["href"].as_str().and_then(|href| resolve(base, href)) else {
+            continue;
+        };
+        for rel in rels(link) {
+            match rel.as_str() {
+                "next" => feed.next = Some(href.clone()),
+                "previous" | "prev" => feed.previous = Some(href.clone()),
+                "search" => feed.search = Some(href.clone()),
+                _ => {}
+            }
+        }
+    }
+
+    let groups = std::iter::once(root).chain(json_array(&root["groups"]));
+    for group in groups {
+        for navigation in json_array(&group["navigation"]) {
+            if let Some(href) = navigation["href"]
+                .as_str()
+                .and_then(|href| resolve(base, href))
+            {
+                feed.entries.push(Entry {
+                    title: json_str(&navigation["title"]),
+                    navigation: Some(href),
+                    ..Entry::default()
+                });
+            }
+        }
+        for publication in json_array(&group["publications"]) {
+            let metadata = &publication["metadata"];
+            let acquisitions = json_array(&publication["links"])
+                .filter(|link| {
+                    rels(link)
+                        .iter()
+                        .any(|rel| rel.starts_with(ACQUISITION_REL))
+                })
+                .filter_map(|link| {
+                    Some(Acquisition {
+                        href: resolve(base, link["href"].as_str()?)?,
+                        mime: json_str(&link["type"]),
+                    })
+                })
+                .collect();
+            feed.entries.push(Entry {
+                title: json_str(&metadata["title"]),
+                authors: json_contributors(&metadata["author"]),
+                summary: plain_text(&json_str(&metadata["description"])),
+                navigation: None,
+                acquisitions,
+            });
+        }
+    }
+    feed
+}
+
+/// 从 OpenSearch 描述文件中取出返回 Atom/OPDS 结果的地址模板。
+pub fn open_search_template(base: &str, body: &str) -> Result<String> {
+    let base = Url::parse(base).with_context(|| format!("无效的地址: {}", base))?;
+    let document = Document::parse(body.trim_start()).context("搜索描述文件不是有效的 XML")?;
+    let urls: Vec<Node> = document
+        .descendants()
+        .filter(|node| node.tag_name().name() == "Url")
+        .collect();
+    urls.iter()
+        .find(|node| is_atom(node.attribute("type")))
+        .or(urls.first())
+        .and_then(|node| node.attribute("template"))
+        .and_then(|template| resolve(&base, template))
+        .context("搜索描述文件中没有可用的地址模板")
+}
+
+/// 填入搜索词：支持 OpenSearch 的 `{searchTerms}` 与 OPDS 2.0 的 `{?query}`，
+/// 其余可选参数（如 `{startPage?}`）留空。
+pub fn fill_search_template(template: &str, query: &str) -> String {
+    let encoded = percent_encode(query);
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            rest = &rest[start..];
+            break;
+        };
+        let expression = &rest[start + 1..end];
+        if let Some(names) = expression.strip_prefix('?') {
+            if names.split(',').any(|name| name.trim() == "query") {
+                result.push_str("?query=");
+                result.push_str(&encoded);
+            }
+        } else if expression.trim_end_matches('?') == "searchTerms" {
+            result.push_str(&encoded);
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// 模板展开前的大括号会被 URL 解析器转义，这里先还原。
+fn resolve(base: &Url, href: &str) -> Option<String> {
+    let joined = base.join(href.trim()).ok()?.to_string();
+    Some(joined.replace("%7B", "{").replace("%7D", "}"))
+}
+
+fn is_atom(mime: Option<&str>) -> bool {
+    mime.is_some_and(|mime| mime.contains("atom+xml") || mime.contains("opds"))
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn child_text(node: Node, name: &'static str) -> String {
+    children(node, name)
+        .next()
+        .map(|child| {
+            child
+                .descendants()
+                .filter(|node| node.is_text())
+                .filter_map(|node| node.text())
+                .collect::<String>()
+        })
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default()
+}
+
+/// 简介常以 HTML 给出，转成纯文本。
+fn plain_text(text: &str) -> String {
+    if text.contains('<') {
+        novel::html_to_text(text).trim().to_string()
+    } else {
+        text.trim().to_string()
+    }
+}
+
+fn rels(link: &Value) -> Vec<String> {
+    match &link["rel"] {
+        Value::String(rel) => vec![rel.clone()],
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|rel| rel.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn json_array(value: &Value) -> impl Iterator<Item = &Value> {
+    value.as_array().into_iter().flatten()
+}
+
+fn json_str(value: &Value) -> String {
+    value.as_str().unwrap_or("").trim().to_string()
+}
+
+/// 作者可以是字符串、`{"name": …}` 或它们的数组。
+fn json_contributors(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(name) => vec![name.trim().to_string()],
+        Value::Object(_) => vec![json_str(&value["name"])],
+        Value::Array(items) => items.iter().flat_map(json_contributors).collect(),
+        _ => Vec::new(),
+    }
+    .into_iter()
+    .filter(|name| !name.is_empty())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_opds2_publications_and_templated_search() {
+        let body = r#"{
+            "metadata": {"title": "书库"},
+            "links": [
+                {"rel": "self", "href": "/v2/new?page=1"},
+                {"rel": ["next"], "href": "/v2/new?page=2"},
+                {"rel": "search", "href": "/v2/search{?query}", "type": "application/opds+json", "templated": true}
+            ],
+            "navigation": [{"href": "/v2/authors", "title": "按作者"}],
+            "groups": [{
+                "metadata": {"title": "最新"},
+                "publications": [{
+                    "metadata": {"title": "雪中行", "author": [{"name": "佚名"}, "无名"], "description": "<p>一个<b>少年</b>的故事。</p>"},
+                    "links": [
+                        {"rel": "http://opds-spec.org/acquisition", "href": "/get/1.epub", "type": "application/epub+zip"},
+                        {"rel": "http://opds-spec.org/image", "href": "/cover/1.jpg", "type": "image/jpeg"}
+                    ]
+                }]
+            }]
+        }"#;
+        let feed = parse_feed("https://nas.local/v2/new", "application/opds+json", body).unwrap();
+        assert_eq!(feed.title, "书库");
+        assert_eq!(
+            feed.next.as_deref(),
+            Some("https://nas.local/v2/new?page=2")
+        );
+        let template = feed.search.unwrap();
+        assert_eq!(template, "https://nas.local/v2/search{?query}");
+        assert_eq!(
+            fill_search_template(&template, "雪 中"),
+            "https://nas.local/v2/search?query=%E9%9B%AA%20%E4%B8%AD"
+        );
+
+        assert_eq!(feed.entries.len(), 2);
+        assert_eq!(
+            feed.entries[0].navigation.as_deref(),
+            Some("https://nas.local/v2/authors")
+        );
+        let book = &feed.entries[1];
+        assert_eq!(book.authors, vec!["佚名", "无名"]);
+        assert_eq!(book.summary, "一个少年的故事。");
+        assert_eq!(book.acquisitions.len(), 1);
+        assert_eq!(book.acquisitions[0].href, "https://nas.local/get/1.epub");
+    }
+}
@@ -0,0 +1,486 @@
+mod feed;
+mod server;
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::library;
+use crate::novel;
+use crate::settings::write_atomic;
+
+pub use feed::{Acquisition, Entry, Feed};
+pub use server::{new_token, LibrarySource, OpdsServer, ServerStatus};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+const ACCEPT: &str =
+    "application/opds+json, application/atom+xml;q=0.9, application/xml;q=0.8, */*;q=0.5";
+/// 目录与搜索描述文件的大小上限
+const MAX_FEED_BYTES: u64 = 8 * 1024 * 1024;
+const MAX_BOOK_BYTES: u64 = 256 * 1024 * 1024;
+
+/// 下载格式的优先顺序：纯文本最忠实于原文，其次是 EPUB，其余按解析质量排列。
+const FORMATS: [(&str, &str); 10] = [
+    ("text/plain", "txt"),
+    ("application/epub+zip", "epub"),
+    ("application/x-fictionbook+xml", "fb2"),
+    ("application/fb2+xml", "fb2"),
+    ("application/x-mobipocket-ebook", "mobi"),
+    ("application/vnd.amazon.ebook", "azw3"),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    ("application/pdf", "pdf"),
+    ("text/html", "html"),
+    ("application/xhtml+xml", "xhtml"),
+];
+
+/// 用户添加的 OPDS 书库，用户名为空时不发送认证信息。认证信息只发往与书库地址
+/// 协议、主机和端口都相同的链接。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Catalog {
+    pub name: String,
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+struct Fetched {
+    url: String,
+    content_type: String,
+    /// Content-Disposition 中给出的文件名
+    file_name: Option<String>,
+    bytes: Vec<u8>,
+}
+
+impl Fetched {
+    fn text(&self) -> Result<String> {
+        match std::str::from_utf8(&self.bytes) {
+            Ok(text) => Ok(text.to_string()),
+            Err(_) => novel::decode_bytes(&self.bytes),
+        }
+    }
+}
+
+pub fn load_catalogs(path: &Path) -> Vec<Catalog> {
+    if let Ok(bytes) = fs::read(path) {
+        if let Ok(catalogs) = serde_json::from_slice::<Vec<Catalog>>(&bytes) {
+            return catalogs;
+        }
+    }
+    Vec::new()
+}
+
+pub fn save_catalogs(path: &Path, catalogs: &[Catalog]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("创建 OPDS 书库目录失败: {}", parent.display()))?;
+    }
+    let data = serde_json::to_vec_pretty(catalogs)?;
+    write_atomic(path, &data).with_context(|| format!("写入 OPDS 书库失败: {}", path.display()))
+}
+
+pub fn default_catalogs_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("moyu-reader-opds.json")
+}
+
+pub fn default_download_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("moyu-reader-downloads")
+}
+
+/// 读取一页目录；`url` 为空时读取书库首页。翻页使用返回的 `next`/`previous`。
+pub fn browse(catalog: &Catalog, url: Option<&str>) -> Result<Feed> {
+    let fetched = fetch(catalog, url.unwrap_or(&catalog.url), MAX_FEED_BYTES)?;
+    feed::parse_feed(&fetched.url, &fetched.content_type, &fetched.text()?)
+}
+
+/// 按目录给出的搜索链接搜索；未提供时使用书库首页的搜索链接。
+pub fn search(catalog: &Catalog, search_link: Option<&str>, query: &str) -> Result<Feed> {
+    if query.trim().is_empty() {
+        bail!("搜索词不能为空");
+    }
+    let link = match search_link {
+        Some(link) => link.to_string(),
+        None => browse(catalog, None)?
+            .search
+            .with_context(|| format!("书库“{}”不支持搜索", catalog.name))?,
+    };
+    let template = if link.contains('{') {
+        link
+    } else {
+        let description = fetch(catalog, &link, MAX_FEED_BYTES)?;
+        feed::open_search_template(&description.url, &description.text()?)?
+    };
+    browse(catalog, Some(&feed::fill_search_template(&template, query)))
+}
+
+/// 选出最合适的下载链接及其扩展名；没有可打开的格式时返回 `None`。
+pub fn pick_acquisition(entry: &Entry) -> Option<(&Acquisition, String)> {
+    entry
+        .acquisitions
+        .iter()
+        .filter_map(|acquisition| {
+            let mime = acquisition
+                .mime
+                .split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_ascii_lowercase();
+            if let Some(rank) = FORMATS.iter().position(|(kind, _)| *kind == mime) {
+                return Some((rank, acquisition, FORMATS[rank].1.to_string()));
+            }
+            // 类型不明确（如 application/octet-stream）时看链接的扩展名
+            let path = acquisition.href.split(['?', '#']).next().unwrap_or("");
+            let ext = Path::new(path)
+                .extension()?
+                .to_string_lossy()
+                .to_lowercase();
+            novel::is_book_file(Path::new(path)).then_some((FORMATS.len(), acquisition, ext))
+        })
+        .min_by_key(|(rank, _, _)| *rank)
+        .map(|(_, acquisition, ext)| (acquisition, ext))
+}
+
+/// 下载书籍到 `dir`，返回本地路径。文件名优先使用服务器给出的名字。
+pub fn download(catalog: &Catalog, entry: &Entry, dir: &Path) -> Result<PathBuf> {
+    let (acquisition, ext) = pick_acquisition(entry).with_context(|| {
+        format!(
+            "《{}》没有可打开的格式（支持 txt、epub、fb2、mobi、pdf 等）",
+            entry.title
+        )
+    })?;
+    let fetched = fetch(catalog, &acquisition.href, MAX_BOOK_BYTES)?;
+    if fetched.bytes.is_empty() {
+        bail!("下载的文件为空: {}", fetched.url);
+    }
+    let file_name = fetched
+        .file_name
+        .map(|name| library::safe_file_name(&name))
+        .filter(|name| novel::is_book_file(Path::new(name)))
+        .unwrap_or_else(|| {
+            let title = library::safe_file_name(&entry.title);
+            let title = if title.is_empty() {
+                "未命名"
+            } else {
+                &title
+            };
+            format!("{}.{}", title, ext)
+        });
+    fs::create_dir_all(dir).with_context(|| format!("创建下载目录失败: {}", dir.display()))?;
+    let path = unique_download_path(dir, &file_name, &fetched.bytes);
+    fs::write(&path, &fetched.bytes)
+        .with_context(|| format!("保存下载的书籍失败: {}", path.display()))?;
+    Ok(path)
+}
+
+/// 同名文件已存在时改用“书名 (2).txt”等名称；内容完全相同则视为同一本书，沿用原文件。
+fn unique_download_path(dir: &Path, name: &str, bytes: &[u8]) -> PathBuf {
+    let path = Path::new(name);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let mut index = 1;
+    loop {
+        let candidate = if index == 1 {
+            dir.join(name)
+        } else {
+            dir.join(format!("{} ({}){}", stem, index, ext))
+        };
+        match fs::read(&candidate) {
+            Ok(existing) if existing != bytes => index += 1,
+            _ => return candidate,
+        }
+    }
+}
+
+fn fetch(catalog: &Catalog, url: &str, limit: u64) -> Result<Fetched> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+    let mut call = agent.get(url).set("Accept", ACCEPT).set(
+        "User-Agent",
+        concat!("moyu-reader/", env!("CARGO_PKG_VERSION")),
+    );
+    if !catalog.username.is_empty() && same_origin(&catalog.url, url) {
+        let credentials = format!("{}:{}", catalog.username, catalog.password);
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        call = call.set("Authorization", &format!("Basic {}", encoded));
+    }
+    let response = call.call().map_err(|err| match err {
+        ureq::Error::Status(401, _) | ureq::Error::Status(403, _) => {
+            anyhow!("书库“{}”拒绝访问，请检查用户名和密码", catalog.name)
+        }
+        ureq::Error::Status(code, _) => anyhow!("请求失败（HTTP {}）: {}", code, url),
+        other => anyhow!("请求失败: {}: {}", url, other),
+    })?;
+
+    let file_name = response
+        .header("Content-Disposition")
+        .and_then(disposition_file_name);
+    let fetched_url = response.get_url().to_string();
+    let content_type = response.content_type().to_ascii_lowercase();
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(limit + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("读取响应失败: {}", fetched_url))?;
+    if bytes.len() as u64 > limit {
+        bail!("文件过大，已停止下载: {}", fetched_url);
+    }
+    Ok(Fetched {
+        url: fetched_url,
+        content_type,
+        file_name,
+        bytes,
+    })
+}
+
+/// 目录中的下载、翻页与搜索链接可能指向 CDN 或其他站点，这些请求不能带上书库的密码。
+fn same_origin(catalog_url: &str, url: &str) -> bool {
+    match (Url::parse(catalog_url), Url::parse(url)) {
+        (Ok(catalog_url), Ok(url)) => catalog_url.origin() == url.origin(),
+        _ => false,
+    }
+}
+
+/// 解析 `attachment; filename*=UTF-8''%E9%9B%AA.txt` 或 `filename="雪.txt"`。
+fn disposition_file_name(header: &str) -> Option<String> {
+    let params: Vec<(&str, &str)> = header
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+        .collect();
+    let extended = params
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("filename*"))
+        .and_then(|(_, value)| value.split_once("''"))
+        .map(|(_, encoded)| percent_decode(encoded));
+    extended
+        .or_else(|| {
+            params
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case("filename"))
+                .map(|(_, value)| value.to_string())
+        })
+        .filter(|name| !name.trim().is_empty())
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.trim().bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_http::{self, TestResponse};
+    use std::sync::{Arc, Mutex};
+
+    const ATOM: &str = "application/atom+xml;profile=opds-catalog";
+
+    fn stand_in() -> String {
+        test_http::serve(|request| {
+            // "reader:secret"
+            if request.header("Authorization") != Some("Basic cmVhZGVyOnNlY3JldA==") {
+                return TestResponse::status(401);
+            }
+            match (request.path(), request.query()) {
+                ("/opds", _) => TestResponse::ok(
+                    ATOM,
+                    r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>家里的书库</title>
+  <link rel="search" type="application/opensearchdescription+xml" href="/opds/search.xml"/>
+  <entry>
+    <title>最新书籍</title>
+    <link rel="subsection" type="application/atom+xml;profile=opds-catalog;kind=acquisition" href="/opds/new"/>
+  </entry>
+</feed>"#,
+                ),
+                ("/opds/new", "") => TestResponse::ok(
+                    ATOM,
+                    r#"<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>最新书籍</title>
+  <link rel="next" type="application/atom+xml" href="/opds/new?page=2"/>
+  <entry>
+    <title>雪中行</title>
+    <author><name>佚名</name></author>
+    <summary type="text">一个少年的故事。</summary>
+    <link rel="http://opds-spec.org/image" type="image/jpeg" href="/covers/1.jpg"/>
+    <link rel="http://opds-spec.org/acquisition" type="application/epub+zip" href="/books/1.epub"/>
+    <link rel="http://opds-spec.org/acquisition/open-access" type="text/plain; charset=utf-8" href="/books/1.txt"/>
+  </entry>
+</feed>"#,
+                ),
+                ("/opds/new", "page=2") => TestResponse::ok(
+                    ATOM,
+                    r#"<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>最新书籍</title>
+  <link rel="previous" type="application/atom+xml" href="/opds/new"/>
+  <entry>
+    <title>画册</title>
+    <link rel="http://opds-spec.org/acquisition" type="application/vnd.comicbook+zip" href="/books/2.cbz"/>
+  </entry>
+</feed>"#,
+                ),
+                ("/opds/search.xml", _) => TestResponse::ok(
+                    "application/opensearchdescription+xml",
+                    r#"<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+  <Url type="text/html" template="/web/search?q={searchTerms}"/>
+  <Url type="application/atom+xml" template="/opds/search?q={searchTerms}&amp;start={startPage?}"/>
+</OpenSearchDescription>"#,
+                ),
+                ("/opds/search", "q=%E9%9B%AA&start=") => TestResponse::ok(
+                    ATOM,
+                    r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>搜索：雪</title>
+<entry><title>雪中行</title><link rel="http://opds-spec.org/acquisition" type="application/epub+zip" href="/books/1.epub"/></entry>
+</feed>"#,
+                ),
+                ("/books/1.txt", _) => {
+                    TestResponse::ok("text/plain; charset=utf-8", "第一章 雪夜\n那一夜的雪下得很大。\n")
+                        .with_header(
+                            "Content-Disposition",
+                            "attachment; filename=\"1.txt\"; filename*=UTF-8''%E9%9B%AA%E4%B8%AD%E8%A1%8C.txt",
+                        )
+                }
+                _ => TestResponse::status(404),
+            }
+        })
+    }
+
+    #[test]
+    fn browses_searches_and_downloads_with_basic_auth() {
+        let base = stand_in();
+        let mut catalog = Catalog {
+            name: "家里".to_string(),
+            url: format!("{}/opds", base),
+            username: "reader".to_string(),
+            password: "wrong".to_string(),
+        };
+        let err = browse(&catalog, None).err().unwrap();
+        assert!(err.to_string().contains("拒绝访问"));
+        catalog.password = "secret".to_string();
+
+        let root = browse(&catalog, None).unwrap();
+        assert_eq!(root.title, "家里的书库");
+        let section = root.entries[0].navigation.clone().unwrap();
+        assert_eq!(section, format!("{}/opds/new", base));
+
+        let page = browse(&catalog, Some(&section)).unwrap();
+        let book = &page.entries[0];
+        assert_eq!(book.authors, vec!["佚名"]);
+        assert_eq!(book.summary, "一个少年的故事。");
+        assert!(book.navigation.is_none());
+        let (best, ext) = pick_acquisition(book).unwrap();
+        assert_eq!(best.href, format!("{}/books/1.txt", base));
+        assert_eq!(ext, "txt");
+
+        let next = browse(&catalog, page.next.as_deref()).unwrap();
+        assert_eq!(next.previous.as_deref(), Some(section.as_str()));
+        assert!(next.next.is_none());
+        assert!(pick_acquisition(&next.entries[0]).is_none());
+
+        let found = search(&catalog, None, "雪").unwrap();
+        assert_eq!(found.title, "搜索：雪");
+        assert_eq!(
+            found.entries[0].acquisitions[0].mime,
+            "application/epub+zip"
+        );
+
+        let dir = std::env::temp_dir().join(format!("moyu-reader-opds-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = download(&catalog, book, &dir).unwrap();
+        assert_eq!(path, dir.join("雪中行.txt"));
+        // 再次下载同一本书沿用原文件，同名的另一本书不会覆盖它
+        assert_eq!(download(&catalog, book, &dir).unwrap(), path);
+        fs::write(&path, "另一本同名的书").unwrap();
+        let path = download(&catalog, book, &dir).unwrap();
+        assert_eq!(path, dir.join("雪中行 (2).txt"));
+        let source = novel::load_source(&path).unwrap();
+        assert!(source.text.contains("那一夜的雪下得很大。"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sends_credentials_only_to_the_catalog_origin() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mirror = {
+            let seen = Arc::clone(&seen);
+            test_http::serve(move |request| {
+                seen.lock()
+                    .unwrap()
+                    .push(request.header("Authorization").map(str::to_string));
+                TestResponse::ok("text/plain; charset=utf-8", "第一章 雪夜\n")
+            })
+        };
+        let feed = format!(
+            r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>镜像</title>
+<entry><title>雪中行</title><link rel="http://opds-spec.org/acquisition" type="text/plain" href="{}/books/1.txt"/></entry>
+</feed>"#,
+            mirror
+        );
+        let base = test_http::serve(move |request| {
+            if request.header("Authorization") != Some("Basic cmVhZGVyOnNlY3JldA==") {
+                return TestResponse::status(401);
+            }
+            TestResponse::ok(ATOM, feed.clone())
+        });
+        let catalog = Catalog {
+            name: "家里".to_string(),
+            url: format!("{}/opds", base),
+            username: "reader".to_string(),
+            password: "secret".to_string(),
+        };
+
+        let page = browse(&catalog, None).unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("moyu-reader-opds-mirror-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        download(&catalog, &page.entries[0], &dir).unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![None]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,662 @@
+use std::cmp::Reverse;
+use std::fs::File;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Result};
+use base64::Engine;
+use serde::Serialize;
+use tiny_http::{Header, Response, ResponseBox, Server};
+
+use super::{percent_decode, percent_encode, FORMATS};
+use crate::library::{self, BookRecord, Library};
+use crate::novel;
+use crate::settings::OpdsServerConfig;
+
+const NAVIGATION: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+const ACQUISITION: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+const PAGE_SIZE: usize = 50;
+const UNKNOWN_AUTHOR: &str = "未知作者";
+
+/// 每次请求时取得书库的最新内容。
+pub type LibrarySource = Arc<dyn Fn() -> Library + Send + Sync>;
+
+/// 内置 OPDS 服务，按配置启动或停止，同一时间只运行一个实例。
+#[derive(Default)]
+pub struct OpdsServer {
+    running: Mutex<Option<Running>>,
+}
+
+struct Running {
+    server: Arc<Server>,
+    handle: JoinHandle<()>,
+    address: SocketAddr,
+    lan: bool,
+    token: String,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    pub running: bool,
+    /// 可在阅读器中填写的目录地址（已带访问令牌）
+    pub urls: Vec<String>,
+}
+
+impl OpdsServer {
+    /// 按配置重启服务；未启用时只停止。
+    pub fn apply(&self, config: &OpdsServerConfig, library: LibrarySource) -> Result<()> {
+        let mut running = self.running.lock().expect("OPDS 服务状态锁已损坏");
+        if let Some(previous) = running.take() {
+            previous.server.unblock();
+            let _ = previous.handle.join();
+        }
+        if !config.enabled {
+            return Ok(());
+        }
+        if config.token.is_empty() {
+            return Err(anyhow!("OPDS 服务缺少访问令牌"));
+        }
+
+        let host = if config.lan { "0.0.0.0" } else { "127.0.0.1" };
+        let server = Server::http((host, config.port))
+            .map_err(|err| anyhow!("无法在端口 {} 启动 OPDS 服务: {}", config.port, err))?;
+        let address = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| anyhow!("OPDS 服务地址无效"))?;
+        let server = Arc::new(server);
+        let token = config.token.clone();
+        let handle = {
+            let server = Arc::clone(&server);
+            let token = token.clone();
+            thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let library = Arc::clone(&library);
+                    let token = token.clone();
+                    // 下载大文件时不阻塞其他请求
+                    thread::spawn(move || {
+                        let authorization = request
+                            .headers()
+                            .iter()
+                            .find(|header| header.field.equiv("Authorization"))
+                            .map(|header| header.value.to_string());
+                        let response =
+                            handle(request.url(), authorization.as_deref(), &library(), &token);
+                        let _ = request.respond(response);
+                    });
+                }
+            })
+        };
+        *running = Some(Running {
+            server,
+            handle,
+            address,
+            lan: config.lan,
+            token,
+        });
+        Ok(())
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        let running = self.running.lock().expect("OPDS 服务状态锁已损坏");
+        let Some(running) = running.as_ref() else {
+            return ServerStatus {
+                running: false,
+                urls: Vec::new(),
+            };
+        };
+        let port = running.address.port();
+        let mut hosts = vec![IpAddr::from([127, 0, 0, 1])];
+        if running.lan {
+            hosts.extend(lan_address());
+        }
+        ServerStatus {
+            running: true,
+            urls: hosts
+                .into_iter()
+                .map(|host| {
+                    format!(
+                        "http://{}/opds?token={}",
+                        SocketAddr::new(host, port),
+                        percent_encode(&running.token)
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// 生成随机访问令牌。
+pub fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|err| anyhow!("生成访问令牌失败: {}", err))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// 本机在局域网中的地址：向外“连接”一个 UDP 套接字（不会发出数据）后读取本地地址。
+fn lan_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_loopback() && !ip.is_unspecified()).then_some(ip)
+}
+
+/// 一次请求的身份：通过查询参数带令牌的客户端，生成的链接也要带上令牌。
+struct Session<'a> {
+    token_param: Option<&'a str>,
+}
+
+impl Session<'_> {
+    fn link(&self, path: &str, params: &[(&str, &str)]) -> String {
+        let mut query: Vec<String> = params
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, percent_encode(value)))
+            .collect();
+        if let Some(token) = self.token_param {
+            query.push(format!("token={}", percent_encode(token)));
+        }
+        if query.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}?{}", path, query.join("&"))
+        }
+    }
+}
+
+fn handle(url: &str, authorization: Option<&str>, library: &Library, token: &str) -> ResponseBox {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let params: Vec<(String, String)> = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), percent_decode(&value.replace('+', " "))))
+        .collect();
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let session = if param("token") == Some(token) {
+        Session {
+            token_param: Some(token),
+        }
+    } else if authorization.is_some_and(|value| authorized(value, token)) {
+        Session { token_param: None }
+    } else {
+        return Response::from_string("需要访问令牌")
+            .with_status_code(401)
+            .with_header(header("WWW-Authenticate", "Basic realm=\"moyu-reader\""))
+            .boxed();
+    };
+
+    let page = param("page")
+        .and_then(|page| page.parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let mut books: Vec<&BookRecord> = library
+        .books
+        .iter()
+        .filter(|book| novel::source_exists(&book.path))
+        .collect();
+    books.sort_by_key(|book| Reverse(book.last_opened));
+
+    let xml = match path.trim_end_matches('/') {
+        "" | "/opds" => root_feed(&session),
+        "/opds/recent" => acquisition_feed(&session, "最近阅读", "/opds/recent", &[], &books, page),
+        "/opds/authors" => authors_feed(&session, &books),
+        "/opds/author" => {
+            let name = param("name").unwrap_or("");
+            let books: Vec<&BookRecord> = books
+                .into_iter()
+                .filter(|book| author_of(book) == name)
+                .collect();
+            acquisition_feed(
+                &session,
+                name,
+                "/opds/author",
+                &[("name", name)],
+                &books,
+                page,
+            )
+        }
+        "/opds/search" => {
+            let q = param("q").unwrap_or("");
+            let query = q.trim().to_lowercase();
+            let books: Vec<&BookRecord> = books
+                .into_iter()
+                .filter(|book| {
+                    !query.is_empty()
+                        && (book.title.to_lowercase().contains(&query)
+                            || book.author.to_lowercase().contains(&query))
+                })
+                .collect();
+            let title = format!("搜索：{}", q);
+            acquisition_feed(&session, &title, "/opds/search", &[("q", q)], &books, page)
+        }
+        "/opds/search.xml" => {
+            let template = session.link("/opds/search", &[]);
+            let separator = if template.contains('?') { '&' } else { '?' };
+            let body = format!(
+                concat!(
+                    r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                    r#"<OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">"#,
+                    "<ShortName>摸鱼阅读</ShortName><Description>搜索书名或作者</Description>",
+                    r#"<InputEncoding>UTF-8</InputEncoding><Url type="{}" template="{}"/>"#,
+                    "</OpenSearchDescription>"
+                ),
+                ACQUISITION,
+                escape(&format!("{}{}q={{searchTerms}}", template, separator)),
+            );
+            return xml_response(body, "application/opensearchdescription+xml");
+        }
+        path => match path.strip_prefix("/opds/books/") {
+            Some(id) => return book_response(library.find(id)),
+            None => {
+                return Response::from_string("未找到")
+                    .with_status_code(404)
+                    .boxed()
+            }
+        },
+    };
+    xml_response(xml, "application/atom+xml;charset=utf-8")
+}
+
+/// 接受 `Bearer <令牌>`，或用户名任意、密码为令牌的 HTTP Basic 认证（多数阅读器只支持后者）。
+fn authorized(value: &str, token: &str) -> bool {
+    let value = value.trim();
+    if let Some(bearer) = value.strip_prefix("Bearer ") {
+        return bearer.trim() == token;
+    }
+    value
+        .strip_prefix("Basic ")
+        .and_then(|encoded| {
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .ok()
+        })
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|credentials| {
+            credentials
+                .split_once(':')
+                .map(|(_, password)| password == token)
+        })
+        .unwrap_or(false)
+}
+
+fn root_feed(session: &Session) -> String {
+    let mut xml = feed_head(
+        session,
+        "urn:moyu-reader:root",
+        "摸鱼阅读书库",
+        "/opds",
+        NAVIGATION,
+    );
+    xml.push_str(&format!(
+        r#"<link rel="search" type="application/opensearchdescription+xml" href="{}"/>"#,
+        escape(&session.link("/opds/search.xml", &[]))
+    ));
+    for (id, title, path, content, kind) in [
+        (
+            "recent",
+            "最近阅读",
+            "/opds/recent",
+            "按最近打开时间排列",
+            ACQUISITION,
+        ),
+        (
+            "authors",
+            "按作者",
+            "/opds/authors",
+            "按作者浏览书库",
+            NAVIGATION,
+        ),
+    ] {
+        let href = session.link(path, &[]);
+        navigation_entry(&mut xml, id, title, &href, content, kind);
+    }
+    xml.push_str("</feed>");
+    xml
+}
+
+fn authors_feed(session: &Session, books: &[&BookRecord]) -> String {
+    let mut authors: Vec<(&str, usize)> = Vec::new();
+    for book in books {
+        let author = author_of(book);
+        match authors.iter_mut().find(|(name, _)| *name == author) {
+            Some((_, count)) => *count += 1,
+            None => authors.push((author, 1)),
+        }
+    }
+    authors.sort();
+    let mut xml = feed_head(
+        session,
+        "urn:moyu-reader:authors",
+        "按作者",
+        "/opds/authors",
+        NAVIGATION,
+    );
+    for (author, count) in authors {
+        navigation_entry(
+            &mut xml,
+            &format!("author:{}", author),
+            author,
+            &session.link("/opds/author", &[("name", author)]),
+            &format!("{} 本", count),
+            ACQUISITION,
+        );
+    }
+    xml.push_str("</feed>");
+    xml
+}
+
+fn acquisition_feed(
+    session: &Session,
+    title: &str,
+    path: &str,
+    params: &[(&str, &str)],
+    books: &[&BookRecord],
+    page: usize,
+) -> String {
+    let page_link = |page: usize| {
+        let page = page.to_string();
+        let mut params = params.to_vec();
+        params.push(("page", &page));
+        session.link(path, &params)
+    };
+    let mut xml = feed_head(
+        session,
+        &format!("urn:moyu-reader:{}", path.trim_start_matches('/')),
+        title,
+        &page_link(page),
+        ACQUISITION,
+    );
+    if page > 1 {
+        xml.push_str(&format!(
+            r#"<link rel="previous" type="{}" href="{}"/>"#,
+            ACQUISITION,
+            escape(&page_link(page - 1))
+        ));
+    }
+    if books.len() > page * PAGE_SIZE {
+        xml.push_str(&format!(
+            r#"<link rel="next" type="{}" href="{}"/>"#,
+            ACQUISITION,
+            escape(&page_link(page + 1))
+        ));
+    }
+    for book in books.iter().skip((page - 1) * PAGE_SIZE).take(PAGE_SIZE) {
+        let (mime, _) = book_format(&book.path);
+        xml.push_str(&format!(
+            concat!(
+                "<entry><id>urn:moyu-reader:book:{}</id><title>{}</title>",
+                "<updated>{}</updated><author><name>{}</name></author>",
+                r#"<link rel="http://opds-spec.org/acquisition" type="{}" href="{}"/></entry>"#
+            ),
+            book.id,
+            escape(&book.title),
+            timestamp(book.last_opened),
+            escape(author_of(book)),
+            mime,
+            escape(&session.link(&format!("/opds/books/{}", book.id), &[])),
+        ));
+    }
+    xml.push_str("</feed>");
+    xml
+}
+
+fn feed_head(session: &Session, id: &str, title: &str, self_link: &str, kind: &str) -> String {
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:opds="http://opds-spec.org/2010/catalog">"#,
+            "<id>{}</id><title>{}</title><updated>{}</updated>",
+            r#"<link rel="self" type="{}" href="{}"/>"#,
+            r#"<link rel="start" type="{}" href="{}"/>"#
+        ),
+        escape(id),
+        escape(title),
+        timestamp(library::now_secs()),
+        kind,
+        escape(self_link),
+        NAVIGATION,
+        escape(&session.link("/opds", &[])),
+    )
+}
+
+/// 指向子目录的条目，`kind` 为子目录的类型。
+fn navigation_entry(
+    xml: &mut String,
+    id: &str,
+    title: &str,
+    href: &str,
+    content: &str,
+    kind: &str,
+) {
+    xml.push_str(&format!(
+        concat!(
+            "<entry><id>urn:moyu-reader:{}</id><title>{}</title><updated>{}</updated>",
+            r#"<content type="text">{}</content><link rel="subsection" type="{}" href="{}"/></entry>"#
+        ),
+        escape(id),
+        escape(title),
+        timestamp(library::now_secs()),
+        escape(content),
+        kind,
+        escape(href),
+    ));
+}
+
+/// 单个书籍文件原样提供；目录、压缩包内的书等合并为纯文本后提供。
+fn book_response(book: Option<&BookRecord>) -> ResponseBox {
+    let Some(book) = book else {
+        return Response::from_string("书库中没有这本书")
+            .with_status_code(404)
+            .boxed();
+    };
+    let (mime, ext) = book_format(&book.path);
+    let title = library::safe_file_name(&book.title);
+    let file_name = format!(
+        "{}.{}",
+        if title.is_empty() { &book.id } else { &title },
+        ext
+    );
+    let disposition = format!(
+        "attachment; filename=\"{}.{}\"; filename*=UTF-8''{}",
+        book.id,
+        ext,
+        percent_encode(&file_name)
+    );
+    let response = if is_plain_file(&book.path) {
+        match File::open(&book.path) {
+            Ok(file) => Response::from_file(file).boxed(),
+            Err(err) => return server_error(&format!("读取文件失败: {}", err)),
+        }
+    } else {
+        match novel::load_source(&book.path) {
+            Ok(source) => Response::from_data(source.text.into_bytes()).boxed(),
+            Err(err) => return server_error(&format!("读取书籍失败: {}", err)),
+        }
+    };
+    response
+        .with_header(header("Content-Type", mime))
+        .with_header(header("Content-Disposition", &disposition))
+}
+
+fn is_plain_file(path: &Path) -> bool {
+    path.is_file() && novel::is_book_file(path)
+}
+
+/// 提供给客户端的类型与扩展名。
+fn book_format(path: &Path) -> (&'static str, String) {
+    if !is_plain_file(path) {
+        return ("text/plain; charset=utf-8", "txt".to_string());
+    }
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mime = FORMATS
+        .iter()
+        .find(|(_, known)| *known == ext)
+        .map(|(mime, _)| *mime)
+        .unwrap_or("application/octet-stream");
+    (mime, ext)
+}
+
+fn author_of(book: &BookRecord) -> &str {
+    match book.author.trim() {
+        "" => UNKNOWN_AUTHOR,
+        author => author,
+    }
+}
+
+fn xml_response(body: String, content_type: &str) -> ResponseBox {
+    Response::from_string(body)
+        .with_header(header("Content-Type", content_type))
+        .boxed()
+}
+
+fn server_error(message: &str) -> ResponseBox {
+    Response::from_string(message).with_status_code(500).boxed()
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("响应头只含 ASCII 字符")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Unix 秒数转为 RFC 3339（UTC）。
+fn timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rest = secs % 86_400;
+    // 公历日期换算，见 Howard Hinnant 的 civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opds::{self, Catalog};
+    use std::fs;
+
+    #[test]
+    fn serves_library_to_opds_clients_with_token() {
+        let dir =
+            std::env::temp_dir().join(format!("moyu-reader-opds-server-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let snow = dir.join("snow.txt");
+        let rain = dir.join("rain.txt");
+        fs::write(&snow, "第一章 雪夜\n那一夜的雪下得很大。\n").unwrap();
+        fs::write(&rain, "第一章 雨\n雨下了一整天。\n").unwrap();
+
+        let mut library = Library::default();
+        let record = library.touch(&snow);
+        record.title = "雪中行".to_string();
+        record.author = "佚名".to_string();
+        record.last_opened = 200;
+        let record = library.touch(&rain);
+        record.title = "雨夜".to_string();
+        record.last_opened = 100;
+        library.touch(&dir.join("已删除.txt"));
+
+        let server = OpdsServer::default();
+        let config = OpdsServerConfig {
+            enabled: true,
+            port: 0,
+            lan: false,
+            token: "s3cret".to_string(),
+        };
+        let source: LibrarySource = Arc::new(move || library.clone());
+        server.apply(&config, source.clone()).unwrap();
+        let url = server.status().urls[0].clone();
+        assert!(url.ends_with("/opds?token=s3cret"));
+
+        let locked = Catalog {
+            url: url.replace("token=s3cret", "token=wrong"),
+            ..Catalog::default()
+        };
+        assert!(opds::browse(&locked, None).is_err());
+        let basic = Catalog {
+            url: url.replace("?token=s3cret", ""),
+            username: "kobo".to_string(),
+            password: "s3cret".to_string(),
+            ..Catalog::default()
+        };
+        assert_eq!(opds::browse(&basic, None).unwrap().title, "摸鱼阅读书库");
+
+        let catalog = Catalog {
+            url,
+            ..Catalog::default()
+        };
+        let root = opds::browse(&catalog, None).unwrap();
+        let recent = opds::browse(&catalog, root.entries[0].navigation.as_deref()).unwrap();
+        let titles: Vec<&str> = recent.entries.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, vec!["雪中行", "雨夜"]);
+        assert_eq!(recent.entries[1].authors, vec![UNKNOWN_AUTHOR]);
+
+        let authors = opds::browse(&catalog, root.entries[1].navigation.as_deref()).unwrap();
+        assert_eq!(authors.entries.len(), 2);
+        let anonymous = authors
+            .entries
+            .iter()
+            .find(|entry| entry.title == "佚名")
+            .unwrap();
+        let by_author = opds::browse(&catalog, anonymous.navigation.as_deref()).unwrap();
+        assert_eq!(by_author.entries.len(), 1);
+        assert_eq!(by_author.entries[0].title, "雪中行");
+
+        let found = opds::search(&catalog, None, "雨").unwrap();
+        assert_eq!(found.entries.len(), 1);
+        let download_dir = dir.join("downloads");
+        let path = opds::download(&catalog, &found.entries[0], &download_dir).unwrap();
+        assert_eq!(path, download_dir.join("雨夜.txt"));
+        assert_eq!(fs::read(&path).unwrap(), fs::read(&rain).unwrap());
+
+        server
+            .apply(
+                &OpdsServerConfig {
+                    enabled: false,
+                    ..config
+                },
+                source,
+            )
+            .unwrap();
+        assert!(!server.status().running);
+        assert!(opds::browse(&catalog, None).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn formats_timestamps_as_rfc3339() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(1_709_164_800 + 3_723), "2024-02-29T01:02:03Z");
+    }
+}
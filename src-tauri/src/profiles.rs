@@ -0,0 +1,245 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::settings::{self, AppConfig, SyncConfig};
+use crate::vault::{self, Keyring};
+
+/// 旧版配置没有方案名，视为默认方案
+pub const DEFAULT_PROFILE: &str = "默认";
+const MAX_NAME_CHARS: usize = 32;
+
+#[derive(Clone, Serialize)]
+pub struct ProfileList {
+    pub active: String,
+    pub profiles: Vec<String>,
+}
+
+/// 未启用的方案存放在配置目录下的 `profiles/<名称>.json`，启用中的方案就是主配置文件。
+pub fn default_profiles_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("profiles")
+}
+
+pub fn profile_name(config: &AppConfig) -> &str {
+    if config.profile.is_empty() {
+        DEFAULT_PROFILE
+    } else {
+        &config.profile
+    }
+}
+
+/// 启动参数 `--profile <名称>` 指定使用的配置方案，之后一直沿用到再次切换。
+pub fn launch_profile() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
+
+/// 校验方案名，名称会直接用作文件名。
+pub fn validate_name(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("方案名称不能为空");
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        bail!("方案名称不能超过 {} 个字符", MAX_NAME_CHARS);
+    }
+    if name.starts_with('.')
+        || name
+            .chars()
+            .any(|c| c.is_control() || r#"/\:*?"<>|"#.contains(c))
+    {
+        bail!("方案名称不能以点开头，也不能包含 / \\ : * ? \" < > |");
+    }
+    Ok(name.to_string())
+}
+
+fn profile_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.json", name))
+}
+
+/// 方案目录中保存的全部方案（不含启用中的方案）的名称与文件路径。
+pub fn profile_files(dir: &Path) -> Vec<(String, PathBuf)> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    let name = name.strip_suffix(".json")?;
+                    (!name.starts_with('.')).then(|| (name.to_string(), entry.path()))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 列出全部方案，启用中的方案排在最前。
+pub fn list_profiles(dir: &Path, active: &AppConfig) -> ProfileList {
+    let active = profile_name(active).to_string();
+    let mut profiles: Vec<String> = profile_files(dir)
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| name != &active)
+        .collect();
+    profiles.sort();
+    profiles.insert(0, active.clone());
+    ProfileList { active, profiles }
+}
+
+pub fn profile_exists(dir: &Path, active: &AppConfig, name: &str) -> bool {
+    name == profile_name(active) || profile_path(dir, name).is_file()
+}
+
+/// 保存一个未启用的方案，同名方案已存在时报错。
+pub fn create_profile(
+    dir: &Path,
+    active: &AppConfig,
+    name: &str,
+    config: &AppConfig,
+    keyring: Option<&Keyring>,
+) -> Result<()> {
+    let name = validate_name(name)?;
+    if profile_exists(dir, active, &name) {
+        bail!("方案“{}”已存在", name);
+    }
+    let config = AppConfig {
+        profile: name.clone(),
+        ..config.clone()
+    };
+    settings::save_config(&profile_path(dir, &name), &config, keyring)
+}
+
+/// 读取方案的完整配置，启用中的方案直接取当前配置。
+pub fn load_profile(
+    dir: &Path,
+    active: &AppConfig,
+    name: &str,
+    keyring: Option<&Keyring>,
+) -> Result<AppConfig> {
+    if name == profile_name(active) {
+        return Ok(active.clone());
+    }
+    let path = profile_path(dir, &validate_name(name)?);
+    let Some(bytes) = vault::read_file(&path, keyring)? else {
+        bail!("方案“{}”不存在", name);
+    };
+    let mut config: AppConfig =
+        serde_json::from_slice(&bytes).with_context(|| format!("方案“{}”已损坏", name))?;
+    config.profile = name.to_string();
+    Ok(config)
+}
+
+pub fn delete_profile(dir: &Path, active: &AppConfig, name: &str) -> Result<()> {
+    if name == profile_name(active) {
+        bail!("不能删除正在使用的方案");
+    }
+    let path = profile_path(dir, &validate_name(name)?);
+    fs::remove_file(&path).with_context(|| format!("删除方案“{}”失败", name))
+}
+
+/// 切换到另一个方案：当前方案存入方案目录，目标方案交给 `save` 写成主配置后才从方案目录移除，
+/// 中途失败不会丢失任何一个方案。
+///
+/// 打开的书和阅读位置属于共享的书库，设备 ID 属于本机，都不随方案切换。
+pub fn switch_profile(
+    dir: &Path,
+    active: &AppConfig,
+    name: &str,
+    keyring: Option<&Keyring>,
+    save: impl FnOnce(&AppConfig) -> Result<()>,
+) -> Result<AppConfig> {
+    let name = validate_name(name)?;
+    if name == profile_name(active) {
+        return Ok(active.clone());
+    }
+    let target = load_profile(dir, active, &name, keyring)?;
+    let current = AppConfig {
+        profile: profile_name(active).to_string(),
+        ..active.clone()
+    };
+    settings::save_config(&profile_path(dir, &current.profile), &current, keyring)?;
+
+    let mut kosync = target.kosync.clone();
+    kosync.device_id = active.kosync.device_id.clone();
+    let config = AppConfig {
+        last_file: active.last_file.clone(),
+        last_page: active.last_page,
+        last_offset: active.last_offset,
+        kosync,
+        sync: SyncConfig {
+            device_id: active.sync.device_id.clone(),
+            ..target.sync.clone()
+        },
+        ..target
+    };
+    save(&config)?;
+    let _ = fs::remove_file(profile_path(dir, &name));
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_profiles_swaps_configs_and_keeps_shared_state() {
+        let dir = std::env::temp_dir().join(format!("moyu-reader-profiles-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut home = AppConfig::default();
+        home.appearance.font_size = 22;
+        home.last_file = Some(PathBuf::from("/books/雪中行.txt"));
+        home.last_offset = 1200;
+        home.sync.device_id = "DEVICE".to_string();
+        assert_eq!(list_profiles(&dir, &home).profiles, vec![DEFAULT_PROFILE]);
+
+        let mut work = AppConfig::default();
+        work.appearance.window_opacity = 30;
+        work.privacy.boss_action = "disguise".to_string();
+        create_profile(&dir, &home, "工作", &work, None).unwrap();
+        assert!(create_profile(&dir, &home, "工作", &work, None).is_err());
+        assert!(create_profile(&dir, &home, "../工作", &work, None).is_err());
+        assert!(create_profile(&dir, &home, DEFAULT_PROFILE, &work, None).is_err());
+
+        let mut saved = None;
+        let active = switch_profile(&dir, &home, "工作", None, |config| {
+            saved = Some(config.clone());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(saved.unwrap().profile, "工作");
+        assert_eq!(active.appearance.window_opacity, 30);
+        assert_eq!(active.appearance.font_size, 16);
+        assert_eq!(active.last_offset, 1200);
+        assert_eq!(active.last_file, home.last_file);
+        assert_eq!(active.sync.device_id, "DEVICE");
+
+        // 启用中的方案只存在于主配置，原来的默认方案进入方案目录
+        assert_eq!(
+            list_profiles(&dir, &active).profiles,
+            vec!["工作", DEFAULT_PROFILE]
+        );
+        assert!(delete_profile(&dir, &active, "工作").is_err());
+        let restored = load_profile(&dir, &active, DEFAULT_PROFILE, None).unwrap();
+        assert_eq!(restored.appearance.font_size, 22);
+
+        // 主配置写入失败时不影响任何方案
+        let err = switch_profile(&dir, &active, DEFAULT_PROFILE, None, |_| bail!("磁盘已满"));
+        assert!(err.is_err());
+        assert!(profile_exists(&dir, &active, DEFAULT_PROFILE));
+
+        delete_profile(&dir, &active, DEFAULT_PROFILE).unwrap();
+        assert_eq!(list_profiles(&dir, &active).profiles, vec!["工作"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
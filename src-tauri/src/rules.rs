@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::write_atomic;
+
+/// 替换规则集：内置预设、全局规则与按书籍 ID 划分的单书规则，按此顺序依次执行。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuleSet {
+    pub enabled_presets: Vec<String>,
+    pub global: Vec<ReplaceRule>,
+    pub books: HashMap<String, Vec<ReplaceRule>>,
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplaceRule {
+    pub id: String,
+    pub name: String,
+    pub pattern: String,
+    pub replacement: String,
+    /// 为 false 时按字面文本匹配，替换文本中的 `$` 也不做展开。
+    pub is_regex: bool,
+    pub enabled: bool,
+}
+
+impl RuleSet {
+    /// 返回作用于某本书的全部启用规则，顺序即执行顺序。
+    pub fn rules_for(&self, book_id: &str) -> Vec<ReplaceRule> {
+        let presets = builtin_presets()
+            .into_iter()
+            .filter(|preset| self.enabled_presets.contains(&preset.id));
+        let book_rules = self.books.get(book_id).into_iter().flatten().cloned();
+        presets
+            .chain(self.global.iter().cloned())
+            .chain(book_rules)
+            .filter(|rule| rule.enabled && !rule.pattern.is_empty())
+            .collect()
+    }
+}
+
+/// 常见盗版站水印的内置规则，默认不启用，由用户在规则列表中勾选。
+pub fn builtin_presets() -> Vec<ReplaceRule> {
+    let preset = |id: &str, name: &str, pattern: &str| ReplaceRule {
+        id: format!("preset:{}", id),
+        name: name.to_string(),
+        pattern: pattern.to_string(),
+        replacement: String::new(),
+        is_regex: true,
+        enabled: true,
+    };
+    vec![
+        preset(
+            "next-page",
+            "本章未完提示",
+            r"[（(]?本章未完[，,]?\s*请点击下一页继续阅读[。！!]?[）)]?",
+        ),
+        preset(
+            "site-domain",
+            "网址水印",
+            r"(?i)(?:https?://)?(?:www|m|wap)\s*[.．。]\s*[a-z0-9-]+\s*[.．。]\s*(?:com|net|org|cc|la|info|cn|tw|me|co)\b",
+        ),
+        preset(
+            "site-name",
+            "小说站推广语",
+            r"(?m)^.{0,10}(?:笔趣阁|顶点小说|八一中文网|新笔趣阁|书趣阁|69书吧)[^\n]{0,30}(?:最快更新|首发|免费阅读|无弹窗)[^\n]*$",
+        ),
+        preset(
+            "remember-site",
+            "记住本站地址",
+            r"(?:天才一秒记住本站地址|请记住本书首发域名|一秒记住)[:：][^\n]*",
+        ),
+        preset(
+            "mobile-hint",
+            "手机阅读提示",
+            r"手机用户请浏览[^\n]*阅读[^\n]*",
+        ),
+    ]
+}
+
+pub fn load_rules(path: &Path) -> RuleSet {
+    let Ok(bytes) = fs::read(path) else {
+        return RuleSet::default();
+    };
+    match serde_json::from_slice::<RuleSet>(&bytes) {
+        Ok(rules) => rules,
+        Err(err) => {
+            eprintln!(
+                "替换规则文件已损坏，暂用默认规则 {}: {}",
+                path.display(),
+                err
+            );
+            RuleSet::default()
+        }
+    }
+}
+
+pub fn save_rules(path: &Path, rules: &RuleSet) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("创建规则目录失败: {}", parent.display()))?;
+    }
+    let data = serde_json::to_vec_pretty(rules)?;
+    write_atomic(path, &data).with_context(|| format!("写入替换规则失败: {}", path.display()))
+}
+
+pub fn default_rules_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("moyu-reader-rules.json")
+}
@@ -0,0 +1,395 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::library::{BookRecord, Library};
+use crate::novel::{load_source, physical_path};
+use crate::vault::{self, Keyring};
+
+/// 每个索引块覆盖的字符数，倒排表只记录块号以控制索引体积。
+const BLOCK_CHARS: usize = 2048;
+const SNIPPET_CONTEXT: usize = 24;
+
+/// 书库全文索引：按字符二元组（bigram）建立倒排表，命中块后回到原文逐字校验。
+///
+/// 每本书在索引目录下对应 `<id>.json`（倒排表与源文件指纹）和 `<id>.txt`（解码后的全文），
+/// 启用加密后与书库使用同一密钥加密保存。
+#[derive(Clone)]
+pub struct SearchIndex {
+    inner: Arc<IndexInner>,
+}
+
+struct IndexInner {
+    dir: PathBuf,
+    cache: Mutex<HashMap<String, Arc<LoadedIndex>>>,
+    pending: Mutex<HashSet<String>>,
+    keyring: RwLock<Option<Keyring>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BookIndex {
+    book_id: String,
+    file_len: u64,
+    modified: u64,
+    char_len: usize,
+    grams: HashMap<String, Vec<u32>>,
+}
+
+struct LoadedIndex {
+    meta: BookIndex,
+    chars: Vec<char>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct SearchHit {
+    pub book_id: String,
+    pub file_path: String,
+    pub title: String,
+    pub offset: usize,
+    pub snippet: String,
+}
+
+impl SearchIndex {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            inner: Arc::new(IndexInner {
+                dir,
+                cache: Mutex::new(HashMap::new()),
+                pending: Mutex::new(HashSet::new()),
+                keyring: RwLock::new(None),
+            }),
+        }
+    }
+
+    /// 解锁后设置读写索引使用的密钥。
+    pub fn set_keyring(&self, keyring: Option<Keyring>) {
+        *self.inner.keyring.write().expect("索引密钥锁已损坏") = keyring;
+    }
+
+    /// 启用、关闭加密或更换口令后清空索引，之后按新的密钥重新建立。
+    pub fn reset(&self, keyring: Option<Keyring>) {
+        self.set_keyring(keyring);
+        self.inner.cache.lock().expect("索引缓存锁已损坏").clear();
+        let _ = fs::remove_dir_all(&self.inner.dir);
+    }
+
+    /// 在后台线程中为尚未索引或已变更的书籍建立索引。
+    pub fn schedule(&self, books: Vec<BookRecord>) {
+        let books: Vec<BookRecord> = {
+            let mut pending = self.inner.pending.lock().expect("索引队列锁已损坏");
+            books
+                .into_iter()
+                .filter(|book| pending.insert(book.id.clone()))
+                .collect()
+        };
+        if books.is_empty() {
+            return;
+        }
+
+        let index = self.clone();
+        thread::spawn(move || {
+            for book in books {
+                if !index.is_fresh(&book.id, &book.path) {
+                    let result = load_source(&book.path)
+                        .and_then(|source| index.build(&book.id, &book.path, &source.text));
+                    if let Err(err) = result {
+                        eprintln!("建立索引失败 {}: {}", book.path.display(), err);
+                    }
+                }
+                index
+                    .inner
+                    .pending
+                    .lock()
+                    .expect("索引队列锁已损坏")
+                    .remove(&book.id);
+            }
+        });
+    }
+
+    pub fn is_fresh(&self, book_id: &str, path: &Path) -> bool {
+        let Some((file_len, modified)) = file_fingerprint(path) else {
+            return false;
+        };
+        match self.load(book_id) {
+            Some(index) => index.meta.file_len == file_len && index.meta.modified == modified,
+            None => false,
+        }
+    }
+
+    pub fn build(&self, book_id: &str, path: &Path, text: &str) -> Result<()> {
+        let (file_len, modified) = file_fingerprint(path).unwrap_or_default();
+        let chars: Vec<char> = text.chars().collect();
+        let mut grams: HashMap<String, BTreeSet<u32>> = HashMap::new();
+        for (position, pair) in chars.windows(2).enumerate() {
+            if pair.iter().any(|ch| ch.is_whitespace()) {
+                continue;
+            }
+            let gram: String = pair.iter().map(|ch| ch.to_ascii_lowercase()).collect();
+            grams
+                .entry(gram)
+                .or_default()
+                .insert((position / BLOCK_CHARS) as u32);
+        }
+
+        let meta = BookIndex {
+            book_id: book_id.to_string(),
+            file_len,
+            modified,
+            char_len: chars.len(),
+            grams: grams
+                .into_iter()
+                .map(|(gram, blocks)| (gram, blocks.into_iter().collect()))
+                .collect(),
+        };
+
+        fs::create_dir_all(&self.inner.dir)
+            .with_context(|| format!("创建索引目录失败: {}", self.inner.dir.display()))?;
+        {
+            let keyring = self.inner.keyring.read().expect("索引密钥锁已损坏");
+            vault::write_file(&self.text_path(book_id), text.as_bytes(), keyring.as_ref())
+                .with_context(|| format!("写入索引全文失败: {}", book_id))?;
+            vault::write_file(
+                &self.meta_path(book_id),
+                &serde_json::to_vec(&meta)?,
+                keyring.as_ref(),
+            )
+            .with_context(|| format!("写入索引失败: {}", book_id))?;
+        }
+
+        self.inner
+            .cache
+            .lock()
+            .expect("索引缓存锁已损坏")
+            .insert(book_id.to_string(), Arc::new(LoadedIndex { meta, chars }));
+        Ok(())
+    }
+
+    pub fn remove(&self, book_id: &str) {
+        self.inner
+            .cache
+            .lock()
+            .expect("索引缓存锁已损坏")
+            .remove(book_id);
+        let _ = fs::remove_file(self.meta_path(book_id));
+        let _ = fs::remove_file(self.text_path(book_id));
+    }
+
+    /// 在书库所有已索引书籍中查找，按最近打开顺序返回命中。
+    pub fn search(&self, library: &Library, query: &str, limit: usize) -> Vec<SearchHit> {
+        let needle: Vec<char> = query
+            .trim()
+            .chars()
+            .map(|ch| ch.to_ascii_lowercase())
+            .collect();
+        if needle.is_empty() || limit == 0 {
+            return Vec::new();
+        }
+
+        let mut books: Vec<&BookRecord> = library.books.iter().collect();
+        books.sort_by_key(|book| std::cmp::Reverse(book.last_opened));
+
+        let mut hits = Vec::new();
+        for book in books {
+            let Some(index) = self.load(&book.id) else {
+                continue;
+            };
+            for offset in find_in_book(&index, &needle) {
+                hits.push(SearchHit {
+                    book_id: book.id.clone(),
+                    file_path: book.path.display().to_string(),
+                    title: book.title.clone(),
+                    offset,
+                    snippet: snippet(&index.chars, offset, needle.len()),
+                });
+                if hits.len() >= limit {
+                    return hits;
+                }
+            }
+        }
+        hits
+    }
+
+    fn load(&self, book_id: &str) -> Option<Arc<LoadedIndex>> {
+        if let Some(index) = self
+            .inner
+            .cache
+            .lock()
+            .expect("索引缓存锁已损坏")
+            .get(book_id)
+        {
+            return Some(index.clone());
+        }
+
+        let (meta, text) = {
+            let keyring = self.inner.keyring.read().expect("索引密钥锁已损坏");
+            let meta = vault::read_file(&self.meta_path(book_id), keyring.as_ref()).ok()??;
+            let text = vault::read_file(&self.text_path(book_id), keyring.as_ref()).ok()??;
+            (meta, text)
+        };
+        let meta: BookIndex = serde_json::from_slice(&meta).ok()?;
+        let chars: Vec<char> = std::str::from_utf8(&text).ok()?.chars().collect();
+        if chars.len() != meta.char_len {
+            return None;
+        }
+        let index = Arc::new(LoadedIndex { meta, chars });
+        self.inner
+            .cache
+            .lock()
+            .expect("索引缓存锁已损坏")
+            .insert(book_id.to_string(), index.clone());
+        Some(index)
+    }
+
+    fn meta_path(&self, book_id: &str) -> PathBuf {
+        self.inner.dir.join(format!("{}.json", book_id))
+    }
+
+    fn text_path(&self, book_id: &str) -> PathBuf {
+        self.inner.dir.join(format!("{}.txt", book_id))
+    }
+}
+
+fn find_in_book(index: &LoadedIndex, needle: &[char]) -> Vec<usize> {
+    let total_blocks = index.chars.len().div_ceil(BLOCK_CHARS);
+    let grams: Vec<String> = needle
+        .windows(2)
+        .filter(|pair| !pair.iter().any(|ch| ch.is_whitespace()))
+        .map(|pair| pair.iter().collect())
+        .collect();
+
+    // 单字或全是空白时没有可用的二元组，只能逐块扫描。
+    let blocks: Vec<usize> = if grams.is_empty() {
+        (0..total_blocks).collect()
+    } else {
+        let mut candidates: Option<BTreeSet<usize>> = None;
+        for gram in &grams {
+            let Some(posting) = index.meta.grams.get(gram) else {
+                return Vec::new();
+            };
+            // 匹配可能跨越块边界，二元组出现在后一块时也要回看前一块。
+            let mut blocks: BTreeSet<usize> = BTreeSet::new();
+            for block in posting {
+                let block = *block as usize;
+                blocks.insert(block);
+                if block > 0 {
+                    blocks.insert(block - 1);
+                }
+            }
+            candidates = Some(match candidates {
+                Some(existing) => existing.intersection(&blocks).copied().collect(),
+                None => blocks,
+            });
+        }
+        candidates.unwrap_or_default().into_iter().collect()
+    };
+
+    let mut offsets = Vec::new();
+    for block in blocks {
+        let start = block * BLOCK_CHARS;
+        let end = ((block + 1) * BLOCK_CHARS).min(index.chars.len());
+        for position in start..end {
+            let Some(window) = index.chars.get(position..position + needle.len()) else {
+                break;
+            };
+            if window
+                .iter()
+                .zip(needle)
+                .all(|(ch, expected)| ch.to_ascii_lowercase() == *expected)
+            {
+                offsets.push(position);
+            }
+        }
+    }
+    offsets
+}
+
+fn snippet(chars: &[char], offset: usize, len: usize) -> String {
+    let start = offset.saturating_sub(SNIPPET_CONTEXT);
+    let end = (offset + len + SNIPPET_CONTEXT).min(chars.len());
+    chars[start..end]
+        .iter()
+        .map(|ch| if ch.is_whitespace() { ' ' } else { *ch })
+        .collect()
+}
+
+fn file_fingerprint(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(physical_path(path)).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    Some((metadata.len(), modified))
+}
+
+pub fn default_index_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join("moyu-reader-index")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::Library;
+
+    #[test]
+    fn search_finds_hits_across_books() {
+        let base = std::env::temp_dir().join(format!("moyu-reader-index-{}", std::process::id()));
+        let _ = fs::create_dir_all(&base);
+        let first = base.join("first.txt");
+        let second = base.join("second.txt");
+        let filler = "平平无奇的一段话。".repeat(400);
+        let first_text = format!("{}林动抬头看向远方。{}", filler, filler);
+        let second_text = "雪山之巅，万籁俱寂。".to_string();
+        fs::write(&first, &first_text).unwrap();
+        fs::write(&second, &second_text).unwrap();
+
+        let mut library = Library::default();
+        library.touch(&first);
+        library.touch(&second);
+
+        let index = SearchIndex::new(base.join("index"));
+        let first_id = crate::library::book_id(&first);
+        let second_id = crate::library::book_id(&second);
+        index.build(&first_id, &first, &first_text).unwrap();
+        index.build(&second_id, &second, &second_text).unwrap();
+        assert!(index.is_fresh(&first_id, &first));
+
+        let hits = index.search(&library, "林动", 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].book_id, first_id);
+        assert_eq!(hits[0].offset, filler.chars().count());
+        assert!(hits[0].snippet.contains("林动抬头"));
+
+        // 重新从磁盘加载同样可以命中
+        let reloaded = SearchIndex::new(base.join("index"));
+        let hits = reloaded.search(&library, "雪山", 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].offset, 0);
+
+        // 启用加密后清空并用密钥重建，磁盘上不再有明文
+        let kdf = vault::KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let (_, keyring) = vault::VaultHeader::create("摸鱼口令", kdf).unwrap();
+        reloaded.reset(Some(keyring.clone()));
+        assert!(!reloaded.is_fresh(&second_id, &second));
+        reloaded.build(&second_id, &second, &second_text).unwrap();
+        let sealed = fs::read(base.join("index").join(format!("{}.txt", second_id))).unwrap();
+        assert!(vault::is_sealed(&sealed));
+
+        let locked = SearchIndex::new(base.join("index"));
+        assert!(locked.search(&library, "雪山", 10).is_empty());
+        locked.set_keyring(Some(keyring));
+        assert_eq!(locked.search(&library, "雪山", 10).len(), 1);
+        let _ = fs::remove_dir_all(&base);
+    }
+}
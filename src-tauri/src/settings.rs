@@ -1,12 +1,18 @@
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::vault::{self, Keyring};
 
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct AppConfig {
+    /// 配置方案名称，为空表示默认方案
+    pub profile: String,
     pub last_file: Option<PathBuf>,
     pub last_page: usize,
     pub last_offset: usize,
@@ -17,6 +23,11 @@ pub struct AppConfig {
     pub privacy: PrivacyConfig,
     pub keybindings: KeybindingsConfig,
     pub system: SystemConfig,
+    pub opds_server: OpdsServerConfig,
+    pub kosync: KosyncConfig,
+    pub sync: SyncConfig,
+    /// 同步范围内的设置最后一次修改的时间（Unix 秒）
+    pub settings_updated: u64,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -37,6 +48,20 @@ pub struct AppearanceConfig {
 pub struct ReadingConfig {
     pub smart_break: bool,
     pub auto_save_interval: String,
+    /// 自动识别按固定宽度硬换行的 txt，并重新拼接成段落。
+    pub reflow: bool,
+    pub normalize: NormalizeConfig,
+}
+
+/// 解码后的文本清理规则，每项可单独开关。
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalizeConfig {
+    pub unify_line_endings: bool,
+    pub strip_zero_width: bool,
+    pub normalize_indent: bool,
+    pub collapse_blank_lines: bool,
+    pub dedupe_paragraphs: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -63,9 +88,73 @@ pub struct SystemConfig {
     pub dev_mode: bool,
 }
 
+/// 内置 OPDS 服务，供电子书阅读器或手机从书库下载书籍。
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpdsServerConfig {
+    pub enabled: bool,
+    pub port: u16,
+    /// 允许局域网访问；关闭时只监听本机。
+    pub lan: bool,
+    /// 访问令牌，为空时在启用服务时自动生成。
+    pub token: String,
+}
+
+/// KOReader 进度同步（kosync）服务器与账号。
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KosyncConfig {
+    pub server: String,
+    pub username: String,
+    /// 密码的 MD5，与 KOReader 保存的形式相同，不保存明文密码。
+    pub userkey: String,
+    /// `binary` 按文件内容识别同一本书，`filename` 按文件名识别，需与 KOReader 的设置一致。
+    pub document_matching: String,
+    pub device: String,
+    /// 首次同步时生成。
+    pub device_id: String,
+}
+
+/// 多台设备间同步设置、阅读进度与书签。
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// 在同步数据中标明进度来自哪台设备，为空时使用主机名。
+    pub device_name: String,
+    /// 本机在共享文件夹中的日志文件名，首次同步时生成。
+    pub device_id: String,
+    /// 后台自动同步的间隔（分钟），0 表示只手动同步。
+    pub interval_minutes: u32,
+    pub webdav: WebdavConfig,
+    pub folder: FolderSyncConfig,
+}
+
+/// WebDAV 同步（坚果云、Nextcloud 等）。
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebdavConfig {
+    pub enabled: bool,
+    /// WebDAV 根地址，如 `https://dav.jianguoyun.com/dav/`
+    pub url: String,
+    pub username: String,
+    /// 应用密码；多数网盘要求使用单独生成的应用密码而非登录密码。
+    pub password: String,
+    /// 同步数据所在的子目录
+    pub folder: String,
+}
+
+/// 共享文件夹同步（OneDrive、Syncthing 等同步盘），每台设备只写自己的日志文件。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FolderSyncConfig {
+    pub enabled: bool,
+    pub path: String,
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            profile: String::new(),
             last_file: None,
             last_page: 0,
             last_offset: 0,
@@ -76,6 +165,10 @@ impl Default for AppConfig {
             privacy: PrivacyConfig::default(),
             keybindings: KeybindingsConfig::default(),
             system: SystemConfig::default(),
+            opds_server: OpdsServerConfig::default(),
+            kosync: KosyncConfig::default(),
+            sync: SyncConfig::default(),
+            settings_updated: 0,
         }
     }
 }
@@ -100,6 +193,20 @@ impl Default for ReadingConfig {
         Self {
             smart_break: true,
             auto_save_interval: "instant".to_string(),
+            reflow: true,
+            normalize: NormalizeConfig::default(),
+        }
+    }
+}
+
+impl Default for NormalizeConfig {
+    fn default() -> Self {
+        Self {
+            unify_line_endings: true,
+            strip_zero_width: true,
+            normalize_indent: true,
+            collapse_blank_lines: true,
+            dedupe_paragraphs: false,
         }
     }
 }
@@ -134,6 +241,54 @@ impl Default for SystemConfig {
     }
 }
 
+impl Default for OpdsServerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8732,
+            lan: false,
+            token: String::new(),
+        }
+    }
+}
+
+impl Default for KosyncConfig {
+    fn default() -> Self {
+        Self {
+            server: "https://sync.koreader.rocks".to_string(),
+            username: String::new(),
+            userkey: String::new(),
+            document_matching: "binary".to_string(),
+            device: "moyu-reader".to_string(),
+            device_id: String::new(),
+        }
+    }
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            device_name: String::new(),
+            device_id: String::new(),
+            interval_minutes: 10,
+            webdav: WebdavConfig::default(),
+            folder: FolderSyncConfig::default(),
+        }
+    }
+}
+
+impl Default for WebdavConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            username: String::new(),
+            password: String::new(),
+            folder: "moyu-reader".to_string(),
+        }
+    }
+}
+
 fn default_boss_key() -> String {
     #[cfg(target_os = "macos")]
     {
@@ -146,22 +301,51 @@ fn default_boss_key() -> String {
     }
 }
 
-pub fn load_config(path: &Path) -> AppConfig {
-    if let Ok(bytes) = fs::read(path) {
-        if let Ok(config) = serde_json::from_slice::<AppConfig>(&bytes) {
-            return config;
-        }
-    }
-    AppConfig::default()
+pub fn load_config(path: &Path, keyring: Option<&Keyring>) -> AppConfig {
+    read_config(path, keyring)
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// 读取配置，文件不存在时为 `None`；解密或解析失败时报错，不回退到默认配置。
+pub fn read_config(path: &Path, keyring: Option<&Keyring>) -> Result<Option<AppConfig>> {
+    let Some(bytes) = vault::read_file(path, keyring)? else {
+        return Ok(None);
+    };
+    let config = serde_json::from_slice(&bytes)
+        .with_context(|| format!("配置文件已损坏: {}", path.display()))?;
+    Ok(Some(config))
 }
 
-pub fn save_config(path: &Path, config: &AppConfig) -> Result<()> {
+pub fn save_config(path: &Path, config: &AppConfig, keyring: Option<&Keyring>) -> Result<()> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
             .with_context(|| format!("创建配置目录失败: {}", parent.display()))?;
     }
-    let data = serde_json::to_vec_pretty(config)?;
-    fs::write(path, data).with_context(|| format!("写入配置失败: {}", path.display()))
+    let data = Zeroizing::new(serde_json::to_vec_pretty(config)?);
+    vault::write_file(path, &data, keyring)
+        .with_context(|| format!("写入配置失败: {}", path.display()))
+}
+
+/// 先写入同目录下的临时文件再改名替换，中途退出或同步盘同时读取时不会看到写了一半的文件。
+pub fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    let result = fs::File::create(&tmp).and_then(|mut file| {
+        file.write_all(data)?;
+        file.sync_all()
+    });
+    match result.and_then(|_| fs::rename(&tmp, path)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = fs::remove_file(&tmp);
+            Err(err)
+        }
+    }
 }
 
 pub fn default_config_path(config_dir: PathBuf) -> PathBuf {
@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use super::{SyncData, SyncReport};
+use crate::settings::write_atomic;
+
+/// 共享文件夹中存放各设备日志的子目录
+const DIR_NAME: &str = "moyu-reader-sync";
+
+/// 与共享文件夹同步：读取所有设备的日志合并后交给 `merge`，再只改写本机自己的日志。
+///
+/// 各设备从不写同一个文件，同步盘不会因同时写入产生冲突副本；万一出现（如同一设备的日志
+/// 在两处被改写），冲突副本也参与合并，合并写回后才删除本机日志的冲突副本，进度不会丢失。
+pub fn sync_folder(
+    root: &Path,
+    device_id: &str,
+    merge: impl FnOnce(&mut SyncData) -> Result<SyncReport>,
+) -> Result<SyncReport> {
+    if !root.is_dir() {
+        bail!("共享文件夹不存在: {}", root.display());
+    }
+    let dir = root.join(DIR_NAME);
+    fs::create_dir_all(&dir).with_context(|| format!("创建同步目录失败: {}", dir.display()))?;
+    let own = dir.join(format!("{}.json", device_id));
+
+    let mut combined = SyncData::default();
+    let mut own_copies = Vec::new();
+    let entries =
+        fs::read_dir(&dir).with_context(|| format!("读取同步目录失败: {}", dir.display()))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // 临时文件以点开头，写完才改名为日志
+        if name.starts_with('.') || !name.to_ascii_lowercase().ends_with(".json") {
+            continue;
+        }
+        // 同步盘可能还没传完，读不出来的日志等下次再合并
+        let Some(data) = fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<SyncData>(&bytes).ok())
+        else {
+            continue;
+        };
+        combined.absorb(data);
+        // 同步盘生成的冲突副本以原文件名开头，如 `<ID> (冲突副本).json`
+        if path != own && name.starts_with(device_id) {
+            own_copies.push(path);
+        }
+    }
+
+    let report = merge(&mut combined)?;
+    let data = serde_json::to_vec_pretty(&combined)?;
+    if fs::read(&own).ok().as_deref() != Some(data.as_slice()) {
+        write_atomic(&own, &data)
+            .with_context(|| format!("写入同步日志失败: {}", own.display()))?;
+    }
+    for copy in own_copies {
+        let _ = fs::remove_file(copy);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::Library;
+    use crate::settings::AppConfig;
+    use crate::sync::{merge, SyncedBook};
+    use std::collections::HashMap;
+
+    struct Device {
+        id: &'static str,
+        config: AppConfig,
+        library: Library,
+        keys: HashMap<String, String>,
+    }
+
+    impl Device {
+        fn new(id: &'static str, offset: usize, progress_at: u64) -> Self {
+            let mut library = Library::default();
+            let record = library.add(Path::new(&format!("/{}/雪中行.txt", id)));
+            record.offset = offset;
+            record.progress_at = progress_at;
+            let keys = HashMap::from([(record.id.clone(), "k1".to_string())]);
+            Device {
+                id,
+                config: AppConfig::default(),
+                library,
+                keys,
+            }
+        }
+
+        fn sync(&mut self, root: &Path) -> SyncReport {
+            sync_folder(root, self.id, |data| {
+                merge(
+                    &mut self.config,
+                    &mut self.library,
+                    &self.keys,
+                    data,
+                    self.id,
+                )
+            })
+            .unwrap()
+        }
+    }
+
+    #[test]
+    fn merges_device_journals_and_absorbs_conflicted_copies() {
+        let root =
+            std::env::temp_dir().join(format!("moyu-reader-folder-sync-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let dir = root.join(DIR_NAME);
+
+        let mut office = Device::new("office", 500, 100);
+        let mut home = Device::new("home", 0, 0);
+        office.sync(&root);
+        // 同步盘只传了一半的日志不影响合并
+        fs::write(dir.join("laptop.json"), "{\"version\": 1, \"books\": {").unwrap();
+        home.sync(&root);
+        assert_eq!(home.library.books[0].offset, 500);
+        assert!(dir.join("home.json").is_file());
+
+        // 家里的日志在同步盘上出现了冲突副本，里面是更新的进度
+        let mut newer = SyncData::default();
+        newer.books.insert(
+            "k1".to_string(),
+            SyncedBook {
+                offset: 800,
+                updated: 300,
+                device: "home".to_string(),
+                ..SyncedBook::default()
+            },
+        );
+        let copy = dir.join("home (冲突副本 2024-05-01).json");
+        fs::write(&copy, serde_json::to_vec(&newer).unwrap()).unwrap();
+        let report = home.sync(&root);
+        assert_eq!(report.books_updated, vec![home.library.books[0].id.clone()]);
+        assert_eq!(home.library.books[0].offset, 800);
+        assert!(!copy.exists());
+
+        office.sync(&root);
+        assert_eq!(office.library.books[0].offset, 800);
+        let names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(names.iter().all(|name| !name.ends_with(".tmp")));
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,488 @@
+mod folder;
+mod webdav;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::kosync;
+use crate::library::{now_secs, BookRecord, Bookmark, Library};
+use crate::settings::{AppConfig, SyncConfig};
+
+pub use folder::sync_folder;
+pub use webdav::sync_webdav;
+
+/// 同步数据的格式版本，读到更高版本时拒绝合并以免丢失字段。
+const FORMAT_VERSION: u32 = 1;
+
+/// 参与同步的设置项。打开的文件、开机启动、OPDS 服务和各类账号只属于本机。
+const SYNCED_SETTINGS: [&str; 6] = [
+    "boss_key",
+    "max_chars_per_page",
+    "appearance",
+    "reading",
+    "privacy",
+    "keybindings",
+];
+
+/// 各设备共享的同步数据，每条记录各自带有修改时间，合并时以较新者为准。
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncData {
+    pub version: u32,
+    pub settings: Option<SyncedSettings>,
+    /// 以 [`book_key`] 为键，同一本书在不同设备上的路径可以不同
+    pub books: BTreeMap<String, SyncedBook>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncedSettings {
+    pub updated: u64,
+    pub device: String,
+    pub values: Map<String, Value>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncedBook {
+    pub title: String,
+    pub author: String,
+    /// 原文偏移
+    pub offset: usize,
+    pub updated: u64,
+    pub device: String,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+impl SyncData {
+    /// 并入另一份同步数据（如另一台设备的日志），每条记录取较新的一份。
+    pub fn absorb(&mut self, other: SyncData) {
+        self.version = self.version.max(other.version);
+        if let Some(settings) = other.settings {
+            let newer = self
+                .settings
+                .as_ref()
+                .is_none_or(|current| settings.updated > current.updated);
+            if newer {
+                self.settings = Some(settings);
+            }
+        }
+        for (key, mut book) in other.books {
+            let Some(current) = self.books.get_mut(&key) else {
+                self.books.insert(key, book);
+                continue;
+            };
+            // 时间相同时取较靠后的位置，各设备合并结果一致
+            if (book.updated, book.offset) > (current.updated, current.offset) {
+                current.offset = book.offset;
+                current.updated = book.updated;
+                current.device = std::mem::take(&mut book.device);
+            }
+            merge_bookmarks(&mut current.bookmarks, &mut book.bookmarks);
+            if current.title.is_empty() {
+                current.title = book.title;
+                current.author = book.author;
+            }
+        }
+    }
+}
+
+/// 远端进度较新却比本机靠前，需要用户确认后才回退。
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressConflict {
+    pub book_id: String,
+    pub title: String,
+    pub local_offset: usize,
+    pub remote_offset: usize,
+    pub remote_device: String,
+    pub remote_updated: u64,
+}
+
+#[derive(Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    /// 采用了其他设备的设置
+    pub settings_applied: bool,
+    /// 本机的进度或书签有变化的书（书库 ID）
+    pub books_updated: Vec<String>,
+    pub conflicts: Vec<ProgressConflict>,
+    /// 同步数据需要写回
+    #[serde(skip)]
+    pub changed: bool,
+}
+
+impl SyncReport {
+    /// 合并另一次同步的结果。
+    pub fn combine(&mut self, other: SyncReport) {
+        self.settings_applied |= other.settings_applied;
+        for id in other.books_updated {
+            if !self.books_updated.contains(&id) {
+                self.books_updated.push(id);
+            }
+        }
+        for conflict in other.conflicts {
+            self.conflicts
+                .retain(|known| known.book_id != conflict.book_id);
+            self.conflicts.push(conflict);
+        }
+        self.changed |= other.changed;
+    }
+}
+
+/// 跨设备识别同一本书：文件按内容计算（与 KOReader 相同的部分 MD5），
+/// 文件夹等其他来源按书名与作者。
+pub fn book_key(book: &BookRecord) -> Option<String> {
+    if book.path.is_file() {
+        kosync::document_hash(&book.path, "binary").ok()
+    } else if book.title.is_empty() {
+        None
+    } else {
+        Some(format!("title:{}\n{}", book.title, book.author))
+    }
+}
+
+/// 计算书库中每本书的同步键（书库 ID → 同步键），会读取书籍文件，应在锁外调用。
+pub fn book_keys(library: &Library) -> HashMap<String, String> {
+    library
+        .books
+        .iter()
+        .filter_map(|book| Some((book.id.clone(), book_key(book)?)))
+        .collect()
+}
+
+/// 本机在同步数据中的名称。
+pub fn device_name(config: &SyncConfig) -> String {
+    if !config.device_name.trim().is_empty() {
+        return config.device_name.trim().to_string();
+    }
+    ["COMPUTERNAME", "HOSTNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        .unwrap_or_else(|| "moyu-reader".to_string())
+}
+
+/// 把同步数据与本机的设置和书库双向合并：较新的记录覆盖较旧的，
+/// 但远端进度比本机靠前时只记为冲突，两边都不修改。
+pub fn merge(
+    config: &mut AppConfig,
+    library: &mut Library,
+    keys: &HashMap<String, String>,
+    remote: &mut SyncData,
+    device: &str,
+) -> Result<SyncReport> {
+    if remote.version > FORMAT_VERSION {
+        bail!("同步数据由更新版本的摸鱼阅读器写入，请先升级");
+    }
+    let mut report = SyncReport::default();
+    if remote.version < FORMAT_VERSION {
+        remote.version = FORMAT_VERSION;
+        report.changed = true;
+    }
+    merge_settings(config, remote, device, &mut report);
+
+    for book in &mut library.books {
+        let Some(key) = keys.get(&book.id) else {
+            continue;
+        };
+        let Some(entry) = remote.books.get_mut(key) else {
+            remote.books.insert(
+                key.clone(),
+                SyncedBook {
+                    title: book.title.clone(),
+                    author: book.author.clone(),
+                    offset: book.offset,
+                    updated: book.progress_at,
+                    device: device.to_string(),
+                    bookmarks: book.bookmarks.clone(),
+                },
+            );
+            report.changed = true;
+            continue;
+        };
+
+        let mut book_changed = false;
+        if entry.updated > book.progress_at {
+            if entry.offset >= book.offset {
+                book_changed |= entry.offset != book.offset;
+                book.offset = entry.offset;
+                book.progress_at = entry.updated;
+            } else {
+                report.conflicts.push(ProgressConflict {
+                    book_id: book.id.clone(),
+                    title: book.title.clone(),
+                    local_offset: book.offset,
+                    remote_offset: entry.offset,
+                    remote_device: entry.device.clone(),
+                    remote_updated: entry.updated,
+                });
+            }
+        } else if book.progress_at > entry.updated {
+            entry.offset = book.offset;
+            entry.updated = book.progress_at;
+            entry.device = device.to_string();
+            report.changed = true;
+        }
+
+        let (local_changed, remote_changed) =
+            merge_bookmarks(&mut book.bookmarks, &mut entry.bookmarks);
+        book_changed |= local_changed;
+        report.changed |= remote_changed;
+        if entry.title.is_empty() && !book.title.is_empty() {
+            entry.title = book.title.clone();
+            entry.author = book.author.clone();
+            report.changed = true;
+        }
+        if book_changed {
+            report.books_updated.push(book.id.clone());
+        }
+    }
+    Ok(report)
+}
+
+fn merge_settings(
+    config: &mut AppConfig,
+    remote: &mut SyncData,
+    device: &str,
+    report: &mut SyncReport,
+) {
+    let Ok(Value::Object(mut local)) = serde_json::to_value(&*config) else {
+        return;
+    };
+    let remote_updated = remote.settings.as_ref().map(|settings| settings.updated);
+    match remote_updated {
+        Some(updated) if updated > config.settings_updated => {
+            let values = &remote.settings.as_ref().unwrap().values;
+            let mut changed = false;
+            for key in SYNCED_SETTINGS {
+                if let Some(value) = values.get(key) {
+                    changed |= local.get(key) != Some(value);
+                    local.insert(key.to_string(), value.clone());
+                }
+            }
+            if changed {
+                match serde_json::from_value::<AppConfig>(Value::Object(local)) {
+                    Ok(merged) => *config = merged,
+                    // 无法识别的设置不采用，但仍视为已同步，免得每次都重试
+                    Err(err) => eprintln!("同步的设置格式错误: {}", err),
+                }
+                report.settings_applied = true;
+            }
+            config.settings_updated = updated;
+        }
+        Some(updated) if updated == config.settings_updated => {}
+        _ => {
+            remote.settings = Some(SyncedSettings {
+                updated: config.settings_updated,
+                device: device.to_string(),
+                values: synced_settings(config),
+            });
+            report.changed = true;
+        }
+    }
+}
+
+/// 参与同步的那部分设置，用于判断设置修改是否需要同步。
+pub fn synced_settings(config: &AppConfig) -> Map<String, Value> {
+    let Ok(Value::Object(mut values)) = serde_json::to_value(config) else {
+        return Map::new();
+    };
+    SYNCED_SETTINGS
+        .iter()
+        .filter_map(|key| Some((key.to_string(), values.remove(*key)?)))
+        .collect()
+}
+
+/// 按 ID 合并书签，同一书签取修改时间较新的一份，删除标记同样参与比较。
+/// 返回（本机是否变化, 远端是否变化）。
+fn merge_bookmarks(local: &mut Vec<Bookmark>, remote: &mut Vec<Bookmark>) -> (bool, bool) {
+    let mut merged: BTreeMap<String, Bookmark> = BTreeMap::new();
+    for bookmark in local.iter().chain(remote.iter()) {
+        match merged.get(&bookmark.id) {
+            Some(existing) if existing.updated >= bookmark.updated => {}
+            _ => {
+                merged.insert(bookmark.id.clone(), bookmark.clone());
+            }
+        }
+    }
+    let differs = |side: &Vec<Bookmark>| {
+        side.len() != merged.len()
+            || side.iter().any(|bookmark| {
+                merged
+                    .get(&bookmark.id)
+                    .is_none_or(|kept| kept.updated != bookmark.updated)
+            })
+    };
+    let (local_changed, remote_changed) = (differs(local), differs(remote));
+    let merged: Vec<Bookmark> = merged.into_values().collect();
+    if local_changed {
+        *local = merged.clone();
+    }
+    if remote_changed {
+        *remote = merged;
+    }
+    (local_changed, remote_changed)
+}
+
+/// 用户确认后处理进度冲突：`accept` 为真时跳到远端位置，否则保留本机位置并在下次同步时上传。
+pub fn resolve_conflict(library: &mut Library, conflict: &ProgressConflict, accept: bool) {
+    let Some(book) = library
+        .books
+        .iter_mut()
+        .find(|book| book.id == conflict.book_id)
+    else {
+        return;
+    };
+    if accept {
+        book.offset = conflict.remote_offset;
+        book.progress_at = conflict.remote_updated;
+    } else {
+        book.progress_at = now_secs().max(conflict.remote_updated + 1);
+    }
+}
+
+/// 同步状态：同一时间只进行一次同步，并保存等待用户确认的进度冲突。
+#[derive(Default)]
+pub struct SyncService {
+    running: Mutex<()>,
+    conflicts: Mutex<Vec<ProgressConflict>>,
+}
+
+impl SyncService {
+    /// 独占执行一次同步；已有同步在进行时等待其结束。
+    pub fn exclusive<T>(&self, run: impl FnOnce() -> T) -> T {
+        let _guard = self.running.lock().unwrap_or_else(|err| err.into_inner());
+        run()
+    }
+
+    pub fn set_conflicts(&self, conflicts: Vec<ProgressConflict>) {
+        *self.conflicts.lock().unwrap_or_else(|err| err.into_inner()) = conflicts;
+    }
+
+    pub fn conflicts(&self) -> Vec<ProgressConflict> {
+        self.conflicts
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+
+    /// 取出某本书的冲突。
+    pub fn take_conflict(&self, book_id: &str) -> Option<ProgressConflict> {
+        let mut conflicts = self.conflicts.lock().unwrap_or_else(|err| err.into_inner());
+        let index = conflicts
+            .iter()
+            .position(|conflict| conflict.book_id == book_id)?;
+        Some(conflicts.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn bookmark(id: &str, offset: usize, updated: u64, deleted: bool) -> Bookmark {
+        Bookmark {
+            id: id.to_string(),
+            offset,
+            updated,
+            deleted,
+            ..Bookmark::default()
+        }
+    }
+
+    fn library_with(offset: usize, progress_at: u64) -> (Library, HashMap<String, String>) {
+        let mut library = Library::default();
+        let record = library.add(Path::new("/books/雪中行.txt"));
+        record.offset = offset;
+        record.progress_at = progress_at;
+        let keys = HashMap::from([(record.id.clone(), "k1".to_string())]);
+        (library, keys)
+    }
+
+    #[test]
+    fn newer_records_win_but_progress_never_moves_back() {
+        let mut config = AppConfig::default();
+        config.appearance.font_size = 20;
+        config.settings_updated = 100;
+        let (mut library, keys) = library_with(500, 100);
+        library.books[0].bookmarks =
+            vec![bookmark("a", 10, 50, false), bookmark("b", 20, 90, false)];
+
+        let mut remote = SyncData::default();
+        let report = merge(&mut config, &mut library, &keys, &mut remote, "office").unwrap();
+        assert!(report.changed);
+        assert_eq!(remote.books["k1"].offset, 500);
+        assert_eq!(
+            remote.settings.as_ref().unwrap().values["appearance"]["font_size"],
+            20
+        );
+        assert!(remote
+            .settings
+            .as_ref()
+            .unwrap()
+            .values
+            .get("last_offset")
+            .is_none());
+
+        // 家里读得更远、删了一个书签、改了字号
+        let mut home_config = AppConfig::default();
+        let (mut home_library, home_keys) = library_with(0, 0);
+        let mut home_remote = remote.clone();
+        merge(
+            &mut home_config,
+            &mut home_library,
+            &home_keys,
+            &mut home_remote,
+            "home",
+        )
+        .unwrap();
+        assert_eq!(home_config.appearance.font_size, 20);
+        assert_eq!(home_library.books[0].offset, 500);
+        home_library.books[0].offset = 900;
+        home_library.books[0].progress_at = 200;
+        home_library.books[0].bookmarks[1] = bookmark("b", 20, 210, true);
+        home_config.appearance.font_size = 18;
+        home_config.settings_updated = 220;
+        let report = merge(
+            &mut home_config,
+            &mut home_library,
+            &home_keys,
+            &mut home_remote,
+            "home",
+        )
+        .unwrap();
+        assert!(report.changed);
+
+        // 办公室继续同步：采用家里的进度、书签删除与设置
+        let report = merge(&mut config, &mut library, &keys, &mut home_remote, "office").unwrap();
+        assert!(report.settings_applied);
+        assert_eq!(config.appearance.font_size, 18);
+        assert_eq!(report.books_updated, vec![library.books[0].id.clone()]);
+        assert_eq!(library.books[0].offset, 900);
+        assert_eq!(library.books[0].active_bookmarks().len(), 1);
+        assert!(!report.changed);
+
+        // 另一台设备较新的进度却更靠前：不回退，等待确认
+        home_remote.books.get_mut("k1").unwrap().offset = 300;
+        home_remote.books.get_mut("k1").unwrap().updated = 300;
+        let report = merge(&mut config, &mut library, &keys, &mut home_remote, "office").unwrap();
+        assert_eq!(library.books[0].offset, 900);
+        assert_eq!(home_remote.books["k1"].offset, 300);
+        let conflict = &report.conflicts[0];
+        assert_eq!((conflict.local_offset, conflict.remote_offset), (900, 300));
+
+        resolve_conflict(&mut library, conflict, false);
+        let report = merge(&mut config, &mut library, &keys, &mut home_remote, "office").unwrap();
+        assert!(report.conflicts.is_empty());
+        assert_eq!(home_remote.books["k1"].offset, 900);
+        assert_eq!(home_remote.books["k1"].device, "office");
+
+        home_remote.version = FORMAT_VERSION + 1;
+        assert!(merge(&mut config, &mut library, &keys, &mut home_remote, "office").is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use url::Url;
+
+use super::{SyncData, SyncReport};
+use crate::settings::WebdavConfig;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+const FILE_NAME: &str = "moyu-reader-sync.json";
+/// 写入时发现其他设备抢先写入，重新读取合并的次数
+const MAX_ATTEMPTS: usize = 3;
+
+/// 与 WebDAV 上的同步数据合并：读取 → `merge` → 有变化时带 ETag 条件写回，
+/// 期间被其他设备改写则重新读取再合并，不会覆盖对方的修改。
+pub fn sync_webdav(
+    config: &WebdavConfig,
+    mut merge: impl FnMut(&mut SyncData) -> Result<SyncReport>,
+) -> Result<SyncReport> {
+    let client = Client::new(config)?;
+    let mut report = SyncReport::default();
+    for _ in 0..MAX_ATTEMPTS {
+        let (mut data, etag, exists) = match client.get()? {
+            Some((data, etag)) => (data, etag, true),
+            None => (SyncData::default(), None, false),
+        };
+        let attempt = merge(&mut data)?;
+        let changed = attempt.changed;
+        // 写回被抢先时，这次合并对本机的修改仍然有效，要一并报告
+        report.combine(attempt);
+        if !changed || client.put(&data, etag.as_deref(), exists)? {
+            return Ok(report);
+        }
+    }
+    bail!("同步数据正被其他设备频繁修改，请稍后重试")
+}
+
+struct Client {
+    folder: Url,
+    file: Url,
+    authorization: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl Client {
+    fn new(config: &WebdavConfig) -> Result<Self> {
+        let base = format!("{}/", config.url.trim().trim_end_matches('/'));
+        let base =
+            Url::parse(&base).with_context(|| format!("无效的 WebDAV 地址: {}", config.url))?;
+        if !matches!(base.scheme(), "http" | "https") {
+            bail!("无效的 WebDAV 地址: {}", config.url);
+        }
+        let folder = config.folder.trim().trim_matches('/');
+        let folder = if folder.is_empty() {
+            base
+        } else {
+            base.join(&format!("{}/", folder))
+                .with_context(|| format!("无效的同步目录: {}", config.folder))?
+        };
+        let file = folder.join(FILE_NAME)?;
+        let authorization = (!config.username.is_empty()).then(|| {
+            let credentials = format!("{}:{}", config.username, config.password);
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            )
+        });
+        Ok(Client {
+            folder,
+            file,
+            authorization,
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+        })
+    }
+
+    fn request(&self, method: &str, url: &Url) -> ureq::Request {
+        let request = self.agent.request(method, url.as_str()).set(
+            "User-Agent",
+            concat!("moyu-reader/", env!("CARGO_PKG_VERSION")),
+        );
+        match &self.authorization {
+            Some(value) => request.set("Authorization", value),
+            None => request,
+        }
+    }
+
+    /// 读取同步数据与其 ETag，文件不存在时返回 `None`。
+    fn get(&self) -> Result<Option<(SyncData, Option<String>)>> {
+        let response = match self.request("GET", &self.file).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) | Err(ureq::Error::Status(409, _)) => return Ok(None),
+            Err(err) => return Err(self.error(err)),
+        };
+        let etag = response.header("ETag").map(str::to_string);
+        let text = response
+            .into_string()
+            .with_context(|| format!("读取同步数据失败: {}", self.file))?;
+        let data = serde_json::from_str(&text)
+            .with_context(|| format!("同步数据已损坏: {}", self.file))?;
+        Ok(Some((data, etag)))
+    }
+
+    /// 写回同步数据；对方在此期间改写过文件时返回 `false`。
+    fn put(&self, data: &SyncData, etag: Option<&str>, exists: bool) -> Result<bool> {
+        let body = serde_json::to_string_pretty(data)?;
+        for attempt in 0..2 {
+            let mut request = self
+                .request("PUT", &self.file)
+                .set("Content-Type", "application/json");
+            request = match etag {
+                Some(etag) => request.set("If-Match", etag),
+                // 文件原本不存在时，防止覆盖其他设备同时创建的文件
+                None if !exists => request.set("If-None-Match", "*"),
+                None => request,
+            };
+            match request.send_string(&body) {
+                Ok(_) => return Ok(true),
+                Err(ureq::Error::Status(412, _)) => return Ok(false),
+                // 同步目录不存在
+                Err(ureq::Error::Status(404, _)) | Err(ureq::Error::Status(409, _))
+                    if attempt == 0 =>
+                {
+                    self.create_folder()?
+                }
+                Err(err) => return Err(self.error(err)),
+            }
+        }
+        bail!("无法写入同步数据: {}", self.file)
+    }
+
+    fn create_folder(&self) -> Result<()> {
+        match self.request("MKCOL", &self.folder).call() {
+            // 405 表示目录已存在
+            Ok(_) | Err(ureq::Error::Status(405, _)) => Ok(()),
+            Err(err) => Err(self.error(err)),
+        }
+    }
+
+    fn error(&self, err: ureq::Error) -> anyhow::Error {
+        match err {
+            ureq::Error::Status(401, _) | ureq::Error::Status(403, _) => {
+                anyhow!("WebDAV 拒绝访问，请检查用户名和应用密码")
+            }
+            ureq::Error::Status(code, _) => {
+                anyhow!("WebDAV 请求失败（HTTP {}）: {}", code, self.folder)
+            }
+            other => anyhow!("连接 WebDAV 失败: {}: {}", self.folder, other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::Library;
+    use crate::settings::AppConfig;
+    use crate::sync::{merge, SyncedBook};
+    use crate::test_http::{self, TestResponse};
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Default)]
+    struct Dav {
+        folder: bool,
+        file: Option<(String, u32)>,
+    }
+
+    fn serve_dav(dav: Arc<Mutex<Dav>>) -> String {
+        test_http::serve(move |request| {
+            if request.header("Authorization") != Some("Basic cmVhZGVyOnNlY3JldA==") {
+                return TestResponse::status(401);
+            }
+            let mut dav = dav.lock().unwrap();
+            match (request.method.as_str(), request.path()) {
+                ("MKCOL", "/dav/%E6%91%B8%E9%B1%BC/") => {
+                    dav.folder = true;
+                    TestResponse::status(201)
+                }
+                ("GET", "/dav/%E6%91%B8%E9%B1%BC/moyu-reader-sync.json") => match &dav.file {
+                    Some((body, version)) => TestResponse::ok("application/json", body.clone())
+                        .with_header("ETag", &format!("\"v{}\"", version)),
+                    None => TestResponse::status(404),
+                },
+                ("PUT", "/dav/%E6%91%B8%E9%B1%BC/moyu-reader-sync.json") => {
+                    if !dav.folder {
+                        return TestResponse::status(409);
+                    }
+                    let current = dav
+                        .file
+                        .as_ref()
+                        .map(|(_, version)| format!("\"v{}\"", version));
+                    let allowed =
+                        match (request.header("If-Match"), request.header("If-None-Match")) {
+                            (Some(tag), _) => current.as_deref() == Some(tag),
+                            (None, Some("*")) => current.is_none(),
+                            _ => true,
+                        };
+                    if !allowed {
+                        return TestResponse::status(412);
+                    }
+                    let version = dav.file.as_ref().map_or(1, |(_, version)| version + 1);
+                    dav.file = Some((String::from_utf8(request.body.clone()).unwrap(), version));
+                    TestResponse::status(201)
+                }
+                _ => TestResponse::status(405),
+            }
+        })
+    }
+
+    #[test]
+    fn merges_through_webdav_and_retries_after_concurrent_write() {
+        let dav: Arc<Mutex<Dav>> = Arc::default();
+        let config = WebdavConfig {
+            enabled: true,
+            url: format!("{}/dav", serve_dav(Arc::clone(&dav))),
+            username: "reader".to_string(),
+            password: "secret".to_string(),
+            folder: "摸鱼".to_string(),
+        };
+
+        let mut app_config = AppConfig::default();
+        let mut library = Library::default();
+        let record = library.add(Path::new("/books/雪中行.txt"));
+        record.offset = 500;
+        record.progress_at = 100;
+        let book_id = record.id.clone();
+        let keys = HashMap::from([(book_id.clone(), "k1".to_string())]);
+
+        // 远端已有较新的进度；第一次写入前，另一台设备抢先改写了同步文件
+        let mut initial = SyncData::default();
+        initial.books.insert(
+            "k1".to_string(),
+            SyncedBook {
+                offset: 800,
+                updated: 200,
+                ..SyncedBook::default()
+            },
+        );
+        dav.lock().unwrap().file = Some((serde_json::to_string(&initial).unwrap(), 6));
+        let mut attempts = 0;
+        let report = sync_webdav(&config, |remote| {
+            attempts += 1;
+            if attempts == 1 {
+                let mut other = SyncData::default();
+                other.books.insert(
+                    "k2".to_string(),
+                    SyncedBook {
+                        title: "雨夜".to_string(),
+                        offset: 42,
+                        updated: 50,
+                        ..SyncedBook::default()
+                    },
+                );
+                dav.lock().unwrap().file = Some((serde_json::to_string(&other).unwrap(), 7));
+            }
+            merge(&mut app_config, &mut library, &keys, remote, "office")
+        })
+        .unwrap();
+        assert_eq!(attempts, 2);
+        assert!(report.conflicts.is_empty());
+        // 第一次合并采用了远端进度，重试后仍要报告，界面才会跳转
+        assert_eq!(report.books_updated, vec![book_id]);
+        assert_eq!(library.books[0].offset, 800);
+
+        let (body, version) = dav.lock().unwrap().file.clone().unwrap();
+        assert_eq!(version, 8);
+        let stored: SyncData = serde_json::from_str(&body).unwrap();
+        assert_eq!(stored.books["k1"].offset, 800);
+        assert_eq!(stored.books["k2"].offset, 42);
+
+        // 没有变化时不再写入
+        sync_webdav(&config, |remote| {
+            merge(&mut app_config, &mut library, &keys, remote, "office")
+        })
+        .unwrap();
+        assert_eq!(dav.lock().unwrap().file.as_ref().unwrap().1, 8);
+
+        let denied = WebdavConfig {
+            password: "wrong".to_string(),
+            ..config
+        };
+        let err = sync_webdav(&denied, |_| Ok(SyncReport::default()))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("拒绝访问"));
+    }
+}
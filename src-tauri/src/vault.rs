@@ -0,0 +1,432 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::settings::write_atomic;
+
+/// 加密文件的开头，明文 JSON 不会以此开头
+const MAGIC: &[u8; 8] = b"MOYUVLT1";
+const KEY_ID_LEN: usize = 8;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const SALT_LEN: usize = 16;
+const FORMAT_VERSION: u32 = 1;
+
+/// 口令派生（Argon2id）的参数，随密钥文件保存，以后调整默认值不影响已加密的数据。
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct WrappedKey {
+    id: String,
+    /// 用口令派生的密钥加密后的数据密钥（nonce + 密文，Base64）
+    key: String,
+}
+
+/// 密钥文件：保存口令派生参数和被口令加密的数据密钥，本身不含任何阅读数据。
+///
+/// 数据文件用随机生成的数据密钥加密，口令只用来加密数据密钥。
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VaultHeader {
+    version: u32,
+    salt: String,
+    kdf: KdfParams,
+    /// 第一个是当前密钥；更换口令时旧密钥暂时保留，直到所有文件都用新密钥重新加密
+    keys: Vec<WrappedKey>,
+}
+
+#[derive(Clone)]
+struct DataKey {
+    id: [u8; KEY_ID_LEN],
+    key: Zeroizing<[u8; KEY_LEN]>,
+}
+
+/// 解锁得到的数据密钥，只保存在内存中，释放时清零。
+#[derive(Clone)]
+pub struct Keyring {
+    keys: Vec<DataKey>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockStatus {
+    /// 未启用加密
+    Off,
+    Locked,
+    Unlocked,
+}
+
+pub fn default_vault_path(config_dir: &Path) -> PathBuf {
+    config_dir.join("moyu-reader-vault.json")
+}
+
+/// 读取密钥文件，未启用加密时返回 `None`。
+pub fn load_header(path: &Path) -> Result<Option<VaultHeader>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("读取密钥文件失败: {}", path.display()))
+        }
+    };
+    let header = serde_json::from_slice(&bytes)
+        .with_context(|| format!("密钥文件已损坏: {}", path.display()))?;
+    Ok(Some(header))
+}
+
+pub fn save_header(path: &Path, header: &VaultHeader) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("创建配置目录失败: {}", parent.display()))?;
+    }
+    let data = serde_json::to_vec_pretty(header)?;
+    write_atomic(path, &data).with_context(|| format!("写入密钥文件失败: {}", path.display()))
+}
+
+impl VaultHeader {
+    /// 生成新的数据密钥，并用口令加密保存。
+    pub fn create(passphrase: &str, kdf: KdfParams) -> Result<(VaultHeader, Keyring)> {
+        let keyring = Keyring {
+            keys: vec![DataKey::generate()?],
+        };
+        let header = Self::wrap(&keyring, passphrase, kdf)?;
+        Ok((header, keyring))
+    }
+
+    /// 更换口令：生成新的数据密钥，连同旧密钥一起用新口令加密。
+    /// 所有文件用新密钥重新加密后，再调用 `retire_old_keys` 去掉旧密钥。
+    pub fn rekey(
+        keyring: &Keyring,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<(VaultHeader, Keyring)> {
+        let mut keys = vec![DataKey::generate()?];
+        keys.extend(keyring.keys.iter().cloned());
+        let keyring = Keyring { keys };
+        let header = Self::wrap(&keyring, passphrase, kdf)?;
+        Ok((header, keyring))
+    }
+
+    fn wrap(keyring: &Keyring, passphrase: &str, kdf: KdfParams) -> Result<VaultHeader> {
+        if passphrase.is_empty() {
+            bail!("口令不能为空");
+        }
+        let salt = random_bytes::<SALT_LEN>()?;
+        let kek = derive_key(passphrase, &salt, kdf)?;
+        let keys = keyring
+            .keys
+            .iter()
+            .map(|data_key| {
+                let sealed = seal_raw(&kek, &data_key.id, data_key.key.as_slice())?;
+                Ok(WrappedKey {
+                    id: hex(&data_key.id),
+                    key: base64::engine::general_purpose::STANDARD.encode(sealed),
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(VaultHeader {
+            version: FORMAT_VERSION,
+            salt: base64::engine::general_purpose::STANDARD.encode(salt),
+            kdf,
+            keys,
+        })
+    }
+
+    /// 用口令解出数据密钥，口令错误时报错。
+    pub fn unlock(&self, passphrase: &str) -> Result<Keyring> {
+        if self.version > FORMAT_VERSION {
+            bail!("密钥文件版本过新（{}），请升级摸鱼阅读器", self.version);
+        }
+        let engine = &base64::engine::general_purpose::STANDARD;
+        let salt = engine
+            .decode(&self.salt)
+            .map_err(|_| anyhow!("密钥文件已损坏"))?;
+        let kek = derive_key(passphrase, &salt, self.kdf)?;
+        let keys = self
+            .keys
+            .iter()
+            .map(|wrapped| {
+                let id = unhex(&wrapped.id).ok_or_else(|| anyhow!("密钥文件已损坏"))?;
+                let sealed = engine
+                    .decode(&wrapped.key)
+                    .map_err(|_| anyhow!("密钥文件已损坏"))?;
+                let plain = open_raw(&kek, &id, &sealed).map_err(|_| anyhow!("口令错误"))?;
+                let mut key = Zeroizing::new([0u8; KEY_LEN]);
+                if plain.len() != KEY_LEN {
+                    bail!("密钥文件已损坏");
+                }
+                key.copy_from_slice(&plain);
+                Ok(DataKey { id, key })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if keys.is_empty() {
+            bail!("密钥文件已损坏");
+        }
+        Ok(Keyring { keys })
+    }
+
+    pub fn has_old_keys(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    pub fn retire_old_keys(&mut self) {
+        self.keys.truncate(1);
+    }
+}
+
+impl DataKey {
+    fn generate() -> Result<Self> {
+        Ok(DataKey {
+            id: random_bytes::<KEY_ID_LEN>()?,
+            key: Zeroizing::new(random_bytes::<KEY_LEN>()?),
+        })
+    }
+}
+
+impl Keyring {
+    /// 用当前密钥加密：魔数 + 密钥 ID + nonce + 密文。
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let current = &self.keys[0];
+        let mut data = Vec::with_capacity(MAGIC.len() + KEY_ID_LEN + NONCE_LEN + plaintext.len());
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&current.id);
+        let sealed = seal_raw(&current.key, &data, plaintext)?;
+        data.extend(sealed);
+        Ok(data)
+    }
+
+    pub fn open(&self, data: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        let header_len = MAGIC.len() + KEY_ID_LEN;
+        if !is_sealed(data) || data.len() < header_len + NONCE_LEN {
+            bail!("不是有效的加密数据");
+        }
+        let (header, sealed) = data.split_at(header_len);
+        let id = &header[MAGIC.len()..];
+        let Some(data_key) = self.keys.iter().find(|key| key.id == id) else {
+            bail!("找不到解密所需的密钥");
+        };
+        open_raw(&data_key.key, header, sealed).map_err(|_| anyhow!("加密数据已损坏或被篡改"))
+    }
+
+    pub fn retire_old_keys(&mut self) {
+        self.keys.truncate(1);
+    }
+}
+
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn is_sealed_file(path: &Path) -> bool {
+    let mut magic = [0u8; MAGIC.len()];
+    fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut magic))
+        .is_ok()
+        && is_sealed(&magic)
+}
+
+/// 读取可能已加密的数据文件，文件不存在时返回 `None`；尚未解锁时遇到加密文件报错。
+pub fn read_file(path: &Path, keyring: Option<&Keyring>) -> Result<Option<Zeroizing<Vec<u8>>>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("读取失败: {}", path.display())),
+    };
+    if !is_sealed(&bytes) {
+        // 启用加密前写入的文件，下次保存时加密
+        return Ok(Some(Zeroizing::new(bytes)));
+    }
+    let Some(keyring) = keyring else {
+        bail!("数据已加密，请先输入口令解锁");
+    };
+    keyring
+        .open(&bytes)
+        .with_context(|| format!("解密失败: {}", path.display()))
+        .map(Some)
+}
+
+/// 写入数据文件，提供密钥时先加密。
+pub fn write_file(path: &Path, data: &[u8], keyring: Option<&Keyring>) -> Result<()> {
+    match keyring {
+        Some(keyring) => write_atomic(path, &keyring.seal(data)?)?,
+        None => write_atomic(path, data)?,
+    }
+    Ok(())
+}
+
+/// 用 `from` 读出、`to` 写回，用于启用、关闭加密和更换口令后重新加密文件。
+pub fn reseal_file(path: &Path, from: Option<&Keyring>, to: Option<&Keyring>) -> Result<()> {
+    if let Some(data) = read_file(path, from)? {
+        write_file(path, &data, to).with_context(|| format!("写入失败: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], kdf: KdfParams) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+    let params = Params::new(
+        kdf.memory_kib,
+        kdf.iterations,
+        kdf.parallelism,
+        Some(KEY_LEN),
+    )
+    .map_err(|err| anyhow!("密钥文件参数无效: {}", err))?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+        .map_err(|err| anyhow!("派生密钥失败: {}", err))?;
+    Ok(key)
+}
+
+/// XChaCha20-Poly1305 加密，返回 nonce + 密文；`aad` 参与认证但不加密。
+fn seal_raw(key: &[u8; KEY_LEN], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = random_bytes::<NONCE_LEN>()?;
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let ciphertext = cipher
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow!("加密失败"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+fn open_raw(key: &[u8; KEY_LEN], aad: &[u8], sealed: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    if sealed.len() < NONCE_LEN {
+        bail!("加密数据已损坏");
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(key.into());
+    cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| anyhow!("加密数据已损坏或被篡改"))
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|err| anyhow!("生成随机数失败: {}", err))?;
+    Ok(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<[u8; KEY_ID_LEN]> {
+    let mut id = [0u8; KEY_ID_LEN];
+    if text.len() != KEY_ID_LEN * 2 {
+        return None;
+    }
+    for (i, byte) in id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试用的低强度参数，避免派生密钥拖慢测试
+    const FAST: KdfParams = KdfParams {
+        memory_kib: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+
+    #[test]
+    fn passphrase_unlocks_and_rekey_keeps_old_files_readable() {
+        let (header, keyring) = VaultHeader::create("摸鱼口令", FAST).unwrap();
+        let sealed = keyring.seal(b"{\"last_file\": \"/books/a.txt\"}").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(5).any(|window| window == b"books"));
+
+        // 密钥文件往返后仍可解锁，口令错误时报错
+        let header: VaultHeader =
+            serde_json::from_slice(&serde_json::to_vec(&header).unwrap()).unwrap();
+        assert!(header.unlock("别的口令").is_err());
+        let unlocked = header.unlock("摸鱼口令").unwrap();
+        assert_eq!(
+            unlocked.open(&sealed).unwrap().as_slice(),
+            b"{\"last_file\": \"/books/a.txt\"}"
+        );
+
+        // 篡改密文会被发现
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(unlocked.open(&tampered).is_err());
+
+        // 更换口令后，重新加密完成前旧文件仍能读取
+        let (mut rekeyed, mut keys) = VaultHeader::rekey(&unlocked, "新口令", FAST).unwrap();
+        assert!(rekeyed.has_old_keys());
+        assert!(rekeyed.unlock("摸鱼口令").is_err());
+        let resumed = rekeyed.unlock("新口令").unwrap();
+        assert!(resumed.open(&sealed).is_ok());
+        let resealed = keys.seal(b"{}").unwrap();
+
+        rekeyed.retire_old_keys();
+        keys.retire_old_keys();
+        let finished = rekeyed.unlock("新口令").unwrap();
+        assert!(finished.open(&sealed).is_err());
+        assert_eq!(finished.open(&resealed).unwrap().as_slice(), b"{}");
+        assert!(keys.open(&resealed).is_ok());
+    }
+
+    #[test]
+    fn reads_plain_files_and_refuses_sealed_ones_while_locked() {
+        let dir = std::env::temp_dir().join(format!("moyu-reader-vault-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let (_, keyring) = VaultHeader::create("口令", FAST).unwrap();
+
+        assert!(read_file(&path, None).unwrap().is_none());
+        fs::write(&path, b"{\"a\": 1}").unwrap();
+        reseal_file(&path, None, Some(&keyring)).unwrap();
+        assert!(is_sealed_file(&path));
+        assert!(read_file(&path, None).is_err());
+        assert_eq!(
+            read_file(&path, Some(&keyring))
+                .unwrap()
+                .unwrap()
+                .as_slice(),
+            b"{\"a\": 1}"
+        );
+
+        reseal_file(&path, Some(&keyring), None).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"{\"a\": 1}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}